@@ -13,8 +13,9 @@
 //! DEL key            Delete a key (writes a tombstone)
 //! SCAN [start] [end] Range scan (inclusive start, exclusive end)
 //! FLUSH              Force flush memtable to SSTable
-//! COMPACT            Trigger manual compaction (L0 + L1 -> L1)
-//! STATS              Print engine debug info
+//! COMPACT            Trigger manual full compaction
+//! COMPACT start [end] Compact only the SSTables overlapping [start, end)
+//! STATS              Print engine debug info and statistics (JSON)
 //! EXIT / QUIT        Shut down gracefully
 //! ```
 //!
@@ -26,8 +27,17 @@
 //! RIPTIDE_WAL_PATH   WAL file path           (default: "wal.log")
 //! RIPTIDE_SST_DIR    SSTable directory       (default: "data/sst")
 //! RIPTIDE_FLUSH_KB   Flush threshold in KiB  (default: 1024 = 1 MiB)
-//! RIPTIDE_WAL_SYNC   fsync every WAL append  (default: "true")
+//! RIPTIDE_WAL_SYNC   WAL sync policy: true, false, bytes:N, interval:MS (default: "true")
+//! RIPTIDE_WAL_COMPRESSION WAL record compression: none, lz4 (default: none)
+//! RIPTIDE_WAL_RECOVERY WAL recovery mode: absolute, tolerate-tail, point-in-time, skip (default: tolerate-tail)
 //! RIPTIDE_L0_TRIGGER L0 compaction trigger   (default: 4, 0 = disabled)
+//! RIPTIDE_IO_RATE_KB Flush/compaction write limit in KiB/s (default: 0 = unlimited)
+//! RIPTIDE_COMPACTION Compaction strategy: leveled, size-tiered, merge-all, fifo (default: leveled)
+//! RIPTIDE_FIFO_MAX_MB       fifo: delete oldest SSTables above this total size (default: 0 = no limit)
+//! RIPTIDE_FIFO_MAX_AGE_SECS fifo: delete SSTables older than this (default: 0 = no limit)
+//! RIPTIDE_ENCRYPTION_KEY    Encrypt files at rest with this key: 64 hex digits (default: unset = plain)
+//! RIPTIDE_ENCRYPTION_KEY_ID Id recorded for that key in new files (default: 1)
+//! RIPTIDE_ENCRYPTION_OLD_KEYS Retired keys still needed to read old files: id:hex,... (default: none)
 //! ```
 //!
 //! ## Example
@@ -45,43 +55,176 @@
 //! > EXIT
 //! bye
 //! ```
-use anyhow::Result;
-use engine::Engine;
+use anyhow::{Context, Result};
+use engine::{
+    CompactionStrategy, Engine, FifoStrategy, Key, KeyProvider, LeveledStrategy, MergeAllStrategy,
+    RateLimiter, SizeTieredStrategy, StaticKeyProvider, WalCompression, WalRecoveryMode,
+    WalSyncPolicy,
+};
 use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Reads a configuration value from the environment, falling back to `default`.
 fn env_or(key: &str, default: &str) -> String {
     std::env::var(key).unwrap_or_else(|_| default.to_string())
 }
 
+/// Parses `RIPTIDE_WAL_SYNC`: `true`/`always`, `false`/`never`, `bytes:N`
+/// or `interval:MS`. Anything else falls back to syncing every write.
+fn parse_wal_sync(value: &str) -> WalSyncPolicy {
+    let value = value.to_lowercase();
+    if let Some(n) = value.strip_prefix("bytes:") {
+        return n
+            .parse()
+            .map_or(WalSyncPolicy::EveryWrite, WalSyncPolicy::EveryNBytes);
+    }
+    if let Some(ms) = value.strip_prefix("interval:") {
+        return ms.parse().map_or(WalSyncPolicy::EveryWrite, |ms| {
+            WalSyncPolicy::EveryInterval(Duration::from_millis(ms))
+        });
+    }
+    match value.as_str() {
+        "false" | "never" => WalSyncPolicy::Never,
+        _ => WalSyncPolicy::EveryWrite,
+    }
+}
+
+/// Builds the key provider from `RIPTIDE_ENCRYPTION_KEY` (plus its id and
+/// any retired keys), or `None` if no key is set. A malformed key is an
+/// error rather than a silent fallback to plain files.
+fn parse_encryption_keys() -> Result<Option<Arc<dyn KeyProvider>>> {
+    let hex = env_or("RIPTIDE_ENCRYPTION_KEY", "");
+    if hex.is_empty() {
+        return Ok(None);
+    }
+    let parse_key = |hex: &str| {
+        Key::from_hex(hex.trim()).context("encryption keys must be 64 hex digits (32 bytes)")
+    };
+    let id: u32 = env_or("RIPTIDE_ENCRYPTION_KEY_ID", "1")
+        .parse()
+        .context("RIPTIDE_ENCRYPTION_KEY_ID must be a number")?;
+    let mut keys = StaticKeyProvider::new(id, parse_key(&hex)?);
+    for entry in env_or("RIPTIDE_ENCRYPTION_OLD_KEYS", "")
+        .split(',')
+        .filter(|e| !e.trim().is_empty())
+    {
+        let (old_id, old_hex) = entry
+            .split_once(':')
+            .context("RIPTIDE_ENCRYPTION_OLD_KEYS entries must be id:hex")?;
+        let old_id: u32 = old_id
+            .trim()
+            .parse()
+            .context("RIPTIDE_ENCRYPTION_OLD_KEYS ids must be numbers")?;
+        keys = keys.with_key(old_id, parse_key(old_hex)?);
+    }
+    Ok(Some(Arc::new(keys)))
+}
+
 fn main() -> Result<()> {
     // Configuration via environment variables with sensible defaults.
     //
-    //  RIPTIDE_WAL_PATH   - WAL file path           (default: "wal.log")
+    //  RIPTIDE_WAL_PATH   - legacy WAL file path    (default: "wal.log")
     //  RIPTIDE_SST_DIR    - SSTable directory       (default: "data/sst")
     //  RIPTIDE_FLUSH_KB   - flush threshold in KiB  (default: 1024 = 1 MiB)
-    //  RIPTIDE_WAL_SYNC   - fsync every WAL append  (default: "true")
+    //  RIPTIDE_WAL_SYNC   - true | false | bytes:N | interval:MS (default: "true")
+    //  RIPTIDE_WAL_COMPRESSION - none | lz4 (default: none)
+    //  RIPTIDE_WAL_RECOVERY - absolute | tolerate-tail | point-in-time | skip (default: tolerate-tail)
     //  RIPTIDE_L0_TRIGGER - L0 compaction trigger   (default: 4, 0 = disabled)
+    //  RIPTIDE_IO_RATE_KB - flush/compaction write limit in KiB/s (default: 0 = unlimited)
+    //  RIPTIDE_COMPACTION - leveled | size-tiered | merge-all | fifo (default: leveled)
+    //  RIPTIDE_FIFO_MAX_MB       - fifo total size limit in MiB (default: 0 = none)
+    //  RIPTIDE_FIFO_MAX_AGE_SECS - fifo file age limit in seconds (default: 0 = none)
+    //  RIPTIDE_ENCRYPTION_KEY    - 64 hex digits; encrypts files at rest (default: unset)
+    //  RIPTIDE_ENCRYPTION_KEY_ID - id of that key (default: 1)
+    //  RIPTIDE_ENCRYPTION_OLD_KEYS - retired keys as id:hex,... (default: none)
     let wal_path = env_or("RIPTIDE_WAL_PATH", "wal.log");
     let sst_dir = env_or("RIPTIDE_SST_DIR", "data/sst");
     let flush_kb: usize = env_or("RIPTIDE_FLUSH_KB", "1024").parse().unwrap_or(1024);
     let flush_threshold = flush_kb * 1024;
-    let wal_sync: bool = env_or("RIPTIDE_WAL_SYNC", "true").parse().unwrap_or(true);
+    let wal_sync = parse_wal_sync(&env_or("RIPTIDE_WAL_SYNC", "true"));
+    let wal_compression = match env_or("RIPTIDE_WAL_COMPRESSION", "none")
+        .to_lowercase()
+        .as_str()
+    {
+        "lz4" => WalCompression::Lz4,
+        _ => WalCompression::None,
+    };
+    let wal_recovery = match env_or("RIPTIDE_WAL_RECOVERY", "tolerate-tail")
+        .to_lowercase()
+        .as_str()
+    {
+        "absolute" => WalRecoveryMode::AbsoluteConsistency,
+        "point-in-time" => WalRecoveryMode::PointInTime,
+        "skip" => WalRecoveryMode::SkipAnyCorrupted,
+        _ => WalRecoveryMode::TolerateCorruptedTail,
+    };
     let l0_trigger: usize = env_or("RIPTIDE_L0_TRIGGER", "4").parse().unwrap_or(4);
+    let io_rate_kb: u64 = env_or("RIPTIDE_IO_RATE_KB", "0").parse().unwrap_or(0);
+    let compaction = env_or("RIPTIDE_COMPACTION", "leveled");
+    let strategy: Arc<dyn CompactionStrategy> = match compaction.to_lowercase().as_str() {
+        "size-tiered" | "universal" => Arc::new(SizeTieredStrategy::new()),
+        "merge-all" => Arc::new(MergeAllStrategy),
+        "fifo" => {
+            let max_mb: u64 = env_or("RIPTIDE_FIFO_MAX_MB", "0").parse().unwrap_or(0);
+            let max_age_secs: u64 = env_or("RIPTIDE_FIFO_MAX_AGE_SECS", "0")
+                .parse()
+                .unwrap_or(0);
+            Arc::new(
+                FifoStrategy::new()
+                    .with_max_total_size_bytes(max_mb * 1024 * 1024)
+                    .with_max_age((max_age_secs > 0).then(|| Duration::from_secs(max_age_secs))),
+            )
+        }
+        _ => Arc::new(LeveledStrategy),
+    };
+
+    let encryption_keys = parse_encryption_keys()?;
 
-    let mut engine = Engine::new(&wal_path, &sst_dir, flush_threshold, wal_sync)?;
+    let mut engine = match encryption_keys {
+        Some(keys) => Engine::open_encrypted(
+            &wal_path,
+            &sst_dir,
+            flush_threshold,
+            wal_sync == WalSyncPolicy::EveryWrite,
+            wal_recovery,
+            keys,
+        )?,
+        None => Engine::open_with_wal_recovery_mode(
+            &wal_path,
+            &sst_dir,
+            flush_threshold,
+            wal_sync == WalSyncPolicy::EveryWrite,
+            wal_recovery,
+        )?,
+    };
+    engine.set_wal_sync_policy(wal_sync)?;
+    engine.set_wal_compression(wal_compression);
+    for (path, report) in engine.wal_recovery_reports() {
+        eprintln!(
+            "WARN discarded {} damaged WAL record(s) ({} bytes) from {}",
+            report.records_discarded,
+            report.bytes_discarded,
+            path.display()
+        );
+    }
     engine.set_l0_compaction_trigger(l0_trigger);
+    engine.set_compaction_strategy(strategy);
+    if io_rate_kb > 0 {
+        engine.set_rate_limiter(Some(Arc::new(RateLimiter::new(io_rate_kb * 1024))));
+    }
 
     println!(
-        "RiptideKV started (seq={}, wal={}, sst_dir={}, flush={}KiB, l0_trigger={})",
+        "RiptideKV started (seq={}, wal={}, sst_dir={}, flush={}KiB, l0_trigger={}, compaction={})",
         engine.seq(),
         wal_path,
         sst_dir,
         flush_kb,
-        l0_trigger
+        l0_trigger,
+        engine.compaction_strategy().name()
     );
     println!("Commands: SET key value | GET key | DEL key | SCAN [start] [end]");
-    println!("          COMPACT | FLUSH | STATS | EXIT");
+    println!("          COMPACT [start end] | FLUSH | STATS | EXIT");
     print!("> ");
     io::stdout().flush().ok();
 
@@ -149,14 +292,25 @@ fn main() -> Result<()> {
                         Err(e) => println!("ERR scan failed: {}", e),
                     }
                 }
-                "COMPACT" => match engine.compact() {
-                    Ok(()) => println!(
-                        "OK (L0={}, L1={})",
-                        engine.l0_sstable_count(),
-                        engine.l1_sstable_count()
-                    ),
-                    Err(e) => println!("ERR compact failed: {}", e),
-                },
+                "COMPACT" => {
+                    // COMPACT compacts everything; COMPACT start [end]
+                    // only the SSTables overlapping [start, end).
+                    let result = match parts.next() {
+                        Some(start) => {
+                            let end = parts.next().unwrap_or("");
+                            engine.compact_range(start.as_bytes(), end.as_bytes())
+                        }
+                        None => engine.compact(),
+                    };
+                    match result {
+                        Ok(()) => println!(
+                            "OK (L0={}, L1={})",
+                            engine.l0_sstable_count(),
+                            engine.l1_sstable_count()
+                        ),
+                        Err(e) => println!("ERR compact failed: {}", e),
+                    }
+                }
                 "FLUSH" => match engine.force_flush() {
                     Ok(()) => println!(
                         "OK (L0={}, L1={})",
@@ -167,6 +321,10 @@ fn main() -> Result<()> {
                 },
                 "STATS" => {
                     println!("{:?}", engine);
+                    match serde_json::to_string_pretty(&engine.stats()) {
+                        Ok(json) => println!("{}", json),
+                        Err(e) => println!("ERR stats failed: {}", e),
+                    }
                 }
                 "EXIT" | "QUIT" => {
                     println!("bye");
@@ -201,6 +201,22 @@ fn test_compaction() {
     assert!(output.contains("val10"));
 }
 
+#[test]
+fn test_range_compaction() {
+    let dir = tempdir().unwrap();
+    let wal_path = dir.path().join("wal.log");
+    let sst_dir = dir.path().join("sst");
+    fs::create_dir_all(&sst_dir).unwrap();
+
+    let commands = "SET apple 1\nFLUSH\nSET zebra 2\nFLUSH\nDEL apple\nFLUSH\n\
+                    COMPACT a b\nGET apple\nGET zebra\n";
+    let output = run_cli_command(&wal_path, &sst_dir, commands);
+
+    assert!(output.contains("(nil)"));
+    assert!(output.contains("2"));
+    assert!(!output.contains("ERR"), "unexpected error: {}", output);
+}
+
 #[test]
 fn test_tombstone_in_range_scan() {
     let dir = tempdir().unwrap();
@@ -274,6 +290,10 @@ fn test_stats_output() {
 
     // STATS should show engine info
     assert!(output.contains("Engine") || output.contains("seq") || output.contains("memtable"));
+
+    // ...followed by the structured statistics as JSON
+    assert!(output.contains("\"sets\": 2"));
+    assert!(output.contains("\"flushes\": 1"));
 }
 
 #[test]
@@ -0,0 +1,589 @@
+//! # Encryption — at-rest encryption for RiptideKV files
+//!
+//! Data files (WAL segments, SSTables, manifest logs) can be encrypted with
+//! ChaCha20-Poly1305, an authenticated cipher: tampering with or corrupting
+//! an encrypted record is detected, not just hidden.
+//!
+//! Keys come from a [`KeyProvider`], identified by a `u32` key id. Each
+//! encrypted file starts with an [encryption header](HEADER_LEN) naming the
+//! key it was written with, so keys can be rotated: new files use the
+//! provider's current key, and older files stay readable as long as the
+//! provider still has the key they name.
+//!
+//! ## Encryption header
+//!
+//! ```text
+//! [key_id: u32 LE][file_id: 16 bytes][nonce: 12 bytes][tag: 16 bytes]
+//! ```
+//!
+//! `file_id` is random and authenticated with every record of the file, so
+//! records cannot be moved between files. The tag authenticates the key id
+//! and file id under the key: a wrong key fails with
+//! [`EncryptionError::WrongKey`] before any record is read.
+//!
+//! ## Records and blocks
+//!
+//! [`FileCipher::seal`] encrypts one record (a WAL frame, a manifest edit)
+//! as `[nonce: 12 bytes][ciphertext][tag: 16 bytes]`, with a fresh random
+//! nonce. [`BlockWriter`] and [`BlockReader`] encrypt a whole byte stream
+//! (an SSTable) in fixed-size blocks sealed the same way, each bound to its
+//! index and to whether it is the last block, so blocks cannot be reordered
+//! and the stream cannot be truncated at a block boundary. `BlockReader`
+//! gives random access to the plaintext.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use encryption::{FileCipher, Key, StaticKeyProvider};
+//!
+//! let keys = StaticKeyProvider::new(1, Key::new([7; 32]));
+//! let (cipher, header) = FileCipher::create(&keys).unwrap();
+//! let sealed = cipher.seal(b"", b"secret").unwrap();
+//!
+//! let cipher = FileCipher::open(&header, Some(&keys)).unwrap();
+//! assert_eq!(cipher.open_sealed(b"", &sealed).unwrap(), b"secret");
+//! ```
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use thiserror::Error;
+
+/// Size of a key in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// Size of the random nonce stored with every sealed record.
+pub const NONCE_LEN: usize = 12;
+
+/// Size of the authentication tag stored with every sealed record.
+pub const TAG_LEN: usize = 16;
+
+/// Bytes a sealed record takes beyond its plaintext.
+pub const SEAL_OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+
+/// Size of the random file id.
+const FILE_ID_LEN: usize = 16;
+
+/// Size of the encryption header.
+pub const HEADER_LEN: usize = 4 + FILE_ID_LEN + SEAL_OVERHEAD;
+
+/// Default plaintext size of a [`BlockWriter`] block.
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// A 256-bit encryption key. Its bytes never appear in `Debug` output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; KEY_LEN]);
+
+impl Key {
+    /// Wraps raw key bytes.
+    #[must_use]
+    pub fn new(bytes: [u8; KEY_LEN]) -> Self {
+        Key(bytes)
+    }
+
+    /// Generates a random key.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EncryptionError::Random`] if the OS random source fails.
+    pub fn generate() -> Result<Self, EncryptionError> {
+        let mut bytes = [0u8; KEY_LEN];
+        random_bytes(&mut bytes)?;
+        Ok(Key(bytes))
+    }
+
+    /// Parses a key from 64 hexadecimal digits, or returns `None`.
+    #[must_use]
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.as_bytes();
+        if hex.len() != 2 * KEY_LEN {
+            return None;
+        }
+        let mut bytes = [0u8; KEY_LEN];
+        for (byte, pair) in bytes.iter_mut().zip(hex.chunks(2)) {
+            let pair = std::str::from_utf8(pair).ok()?;
+            *byte = u8::from_str_radix(pair, 16).ok()?;
+        }
+        Some(Key(bytes))
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+/// Source of encryption keys.
+///
+/// Implementations may fetch keys from a KMS, a file or the environment.
+/// Keys are looked up when a file is opened or created, never per record.
+pub trait KeyProvider: Send + Sync + fmt::Debug {
+    /// Id of the key new files are encrypted with.
+    fn current_key_id(&self) -> u32;
+
+    /// Returns the key with id `id`, or `None` if the provider does not
+    /// have it.
+    fn key(&self, id: u32) -> Option<Key>;
+}
+
+/// A [`KeyProvider`] holding its keys in memory.
+///
+/// ```rust
+/// use encryption::{Key, KeyProvider, StaticKeyProvider};
+///
+/// // rotated from key 1 to key 2: new files use key 2, old ones stay readable
+/// let keys = StaticKeyProvider::new(2, Key::new([2; 32])).with_key(1, Key::new([1; 32]));
+/// assert_eq!(keys.current_key_id(), 2);
+/// assert!(keys.key(1).is_some());
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticKeyProvider {
+    current: u32,
+    keys: BTreeMap<u32, Key>,
+}
+
+impl StaticKeyProvider {
+    /// Creates a provider whose current key is `key`, with id `id`.
+    #[must_use]
+    pub fn new(id: u32, key: Key) -> Self {
+        Self {
+            current: id,
+            keys: BTreeMap::from([(id, key)]),
+        }
+    }
+
+    /// Adds a key that is only used to read files written with it, such as
+    /// the previous key after a rotation.
+    #[must_use]
+    pub fn with_key(mut self, id: u32, key: Key) -> Self {
+        self.keys.entry(id).or_insert(key);
+        self
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn current_key_id(&self) -> u32 {
+        self.current
+    }
+
+    fn key(&self, id: u32) -> Option<Key> {
+        self.keys.get(&id).cloned()
+    }
+}
+
+/// Errors that can occur while encrypting or decrypting.
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    /// The file is encrypted but no key provider was configured.
+    #[error("file is encrypted with key {0} but no key provider is configured")]
+    NoKeyProvider(u32),
+
+    /// The key provider does not have the key the file names.
+    #[error("encryption key {0} is not available from the key provider")]
+    UnknownKey(u32),
+
+    /// The key provider returned a key that did not encrypt the file.
+    #[error("wrong encryption key for key id {0}")]
+    WrongKey(u32),
+
+    /// The encryption header is too short.
+    #[error("truncated encryption header")]
+    BadHeader,
+
+    /// A record or block failed authentication: it was corrupted or
+    /// tampered with.
+    #[error("decryption failed: data is corrupt or was tampered with")]
+    Decrypt,
+
+    /// The OS random source failed.
+    #[error("random number generation failed: {0}")]
+    Random(String),
+}
+
+impl From<EncryptionError> for io::Error {
+    fn from(e: EncryptionError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Encrypts and decrypts the records of one file.
+///
+/// Created for a new file with [`create`](FileCipher::create), which also
+/// returns the header to store, or for an existing file from its header with
+/// [`open`](FileCipher::open).
+#[derive(Clone)]
+pub struct FileCipher {
+    key_id: u32,
+    file_id: [u8; FILE_ID_LEN],
+    aead: ChaCha20Poly1305,
+}
+
+impl fmt::Debug for FileCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileCipher")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FileCipher {
+    /// Starts a new file encrypted with the provider's current key. Returns
+    /// the cipher and the header to write at the start of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EncryptionError::UnknownKey`] if the provider does not have
+    /// its current key, and [`EncryptionError::Random`] if the OS random
+    /// source fails.
+    pub fn create(keys: &dyn KeyProvider) -> Result<(Self, [u8; HEADER_LEN]), EncryptionError> {
+        let key_id = keys.current_key_id();
+        let key = keys
+            .key(key_id)
+            .ok_or(EncryptionError::UnknownKey(key_id))?;
+        let mut file_id = [0u8; FILE_ID_LEN];
+        random_bytes(&mut file_id)?;
+        let cipher = Self {
+            key_id,
+            file_id,
+            aead: ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&key.0)),
+        };
+
+        let mut header = [0u8; HEADER_LEN];
+        header[..4].copy_from_slice(&key_id.to_le_bytes());
+        header[4..4 + FILE_ID_LEN].copy_from_slice(&file_id);
+        let check = cipher.seal_raw(&header[..4 + FILE_ID_LEN], &[])?;
+        header[4 + FILE_ID_LEN..].copy_from_slice(&check);
+        Ok((cipher, header))
+    }
+
+    /// Opens a file from its encryption header (at least [`HEADER_LEN`]
+    /// bytes; the rest is ignored), checking that the key matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EncryptionError::BadHeader`] for a short header,
+    /// [`EncryptionError::NoKeyProvider`] if `keys` is `None`,
+    /// [`EncryptionError::UnknownKey`] if the provider does not have the
+    /// key the header names and [`EncryptionError::WrongKey`] if it has a
+    /// different key under that id.
+    pub fn open(header: &[u8], keys: Option<&dyn KeyProvider>) -> Result<Self, EncryptionError> {
+        let key_id = header_key_id(header).ok_or(EncryptionError::BadHeader)?;
+        let keys = keys.ok_or(EncryptionError::NoKeyProvider(key_id))?;
+        let key = keys
+            .key(key_id)
+            .ok_or(EncryptionError::UnknownKey(key_id))?;
+        let mut file_id = [0u8; FILE_ID_LEN];
+        file_id.copy_from_slice(&header[4..4 + FILE_ID_LEN]);
+        let cipher = Self {
+            key_id,
+            file_id,
+            aead: ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&key.0)),
+        };
+        cipher
+            .open_raw(
+                &header[..4 + FILE_ID_LEN],
+                &header[4 + FILE_ID_LEN..HEADER_LEN],
+            )
+            .map_err(|_| EncryptionError::WrongKey(key_id))?;
+        Ok(cipher)
+    }
+
+    /// Returns the id of the key the file is encrypted with.
+    #[must_use]
+    pub fn key_id(&self) -> u32 {
+        self.key_id
+    }
+
+    /// Encrypts `plaintext` as `[nonce][ciphertext][tag]`. `aad` is
+    /// authenticated but not stored; [`open_sealed`](FileCipher::open_sealed)
+    /// needs the same bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EncryptionError::Random`] if the OS random source fails.
+    pub fn seal(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        self.seal_raw(&self.aad(aad), plaintext)
+    }
+
+    /// Decrypts a record sealed by [`seal`](FileCipher::seal) with the same
+    /// `aad`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EncryptionError::Decrypt`] if the record fails
+    /// authentication.
+    pub fn open_sealed(&self, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        self.open_raw(&self.aad(aad), sealed)
+    }
+
+    /// Binds `aad` to this file.
+    fn aad(&self, aad: &[u8]) -> Vec<u8> {
+        [&self.file_id[..], aad].concat()
+    }
+
+    fn seal_raw(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        random_bytes(&mut nonce)?;
+        let ciphertext = self
+            .aead
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| EncryptionError::Decrypt)?;
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    fn open_raw(&self, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        if sealed.len() < SEAL_OVERHEAD {
+            return Err(EncryptionError::Decrypt);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.aead
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| EncryptionError::Decrypt)
+    }
+}
+
+/// Returns the key id an encryption header names, without checking it, or
+/// `None` if the header is too short.
+#[must_use]
+pub fn header_key_id(header: &[u8]) -> Option<u32> {
+    if header.len() < HEADER_LEN {
+        return None;
+    }
+    Some(u32::from_le_bytes([
+        header[0], header[1], header[2], header[3],
+    ]))
+}
+
+fn random_bytes(buf: &mut [u8]) -> Result<(), EncryptionError> {
+    getrandom::fill(buf).map_err(|e| EncryptionError::Random(e.to_string()))
+}
+
+/// Associated data of block `index`.
+fn block_aad(index: u64, last: bool) -> [u8; 9] {
+    let mut aad = [0u8; 9];
+    aad[..8].copy_from_slice(&index.to_le_bytes());
+    aad[8] = u8::from(last);
+    aad
+}
+
+/// Encrypts a byte stream in blocks of `block_size` plaintext bytes.
+///
+/// Blocks are written to the inner writer as they fill up;
+/// [`finish`](BlockWriter::finish) writes the last one, which is always
+/// present (possibly empty) so that truncation is detected. `Seek` only
+/// supports querying the plaintext position.
+pub struct BlockWriter<W: Write> {
+    inner: W,
+    cipher: FileCipher,
+    block_size: usize,
+    buf: Vec<u8>,
+    index: u64,
+    pos: u64,
+}
+
+impl<W: Write> BlockWriter<W> {
+    /// Creates a writer sealing blocks of `block_size` bytes (at least 1)
+    /// with `cipher`.
+    pub fn new(inner: W, cipher: FileCipher, block_size: usize) -> Self {
+        let block_size = block_size.max(1);
+        Self {
+            inner,
+            cipher,
+            block_size,
+            buf: Vec::with_capacity(block_size),
+            index: 0,
+            pos: 0,
+        }
+    }
+
+    /// Writes the last block and returns the inner writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the block cannot be written.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.seal_block(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn seal_block(&mut self, last: bool) -> io::Result<()> {
+        let sealed = self.cipher.seal(&block_aad(self.index, last), &self.buf)?;
+        self.inner.write_all(&sealed)?;
+        self.buf.clear();
+        self.index += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BlockWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(self.block_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        self.pos += n as u64;
+        if self.buf.len() == self.block_size {
+            self.seal_block(false)?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Seek for BlockWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.pos),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "encrypted streams are written sequentially",
+            )),
+        }
+    }
+}
+
+/// Random-access reader of a stream written by [`BlockWriter`].
+///
+/// The stream occupies the inner reader from `start` to its end. Blocks
+/// are decrypted on demand; the last one read is cached. A block that fails
+/// authentication is reported as an `InvalidData` error wrapping
+/// [`EncryptionError::Decrypt`].
+pub struct BlockReader<R: Read + Seek> {
+    inner: R,
+    cipher: FileCipher,
+    start: u64,
+    block_size: usize,
+    blocks: u64,
+    len: u64,
+    pos: u64,
+    cached: Option<(u64, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> BlockReader<R> {
+    /// Opens the stream of blocks of `block_size` bytes that starts at
+    /// `start` in `inner`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the stored size cannot hold a
+    /// valid sequence of blocks, and any I/O error from `inner`.
+    pub fn new(
+        mut inner: R,
+        cipher: FileCipher,
+        start: u64,
+        block_size: usize,
+    ) -> io::Result<Self> {
+        let stored = inner.seek(SeekFrom::End(0))?.saturating_sub(start);
+        let stride = (block_size + SEAL_OVERHEAD) as u64;
+        let blocks = stored.div_ceil(stride);
+        let last = stored - blocks.saturating_sub(1) * stride;
+        if block_size == 0 || blocks == 0 || last < SEAL_OVERHEAD as u64 {
+            return Err(EncryptionError::Decrypt.into());
+        }
+        let len = (blocks - 1) * block_size as u64 + last - SEAL_OVERHEAD as u64;
+        Ok(Self {
+            inner,
+            cipher,
+            start,
+            block_size,
+            blocks,
+            len,
+            pos: 0,
+            cached: None,
+        })
+    }
+
+    /// Returns the plaintext length of the stream.
+    #[must_use]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the stream holds no plaintext.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the id of the key the stream is encrypted with.
+    #[must_use]
+    pub fn key_id(&self) -> u32 {
+        self.cipher.key_id()
+    }
+
+    /// Decrypts block `index` into the cache.
+    fn load(&mut self, index: u64) -> io::Result<&[u8]> {
+        if self.cached.as_ref().map(|(i, _)| *i) != Some(index) {
+            let stride = (self.block_size + SEAL_OVERHEAD) as u64;
+            let last = index + 1 == self.blocks;
+            let stored_len = if last {
+                (self.len - index * self.block_size as u64) as usize + SEAL_OVERHEAD
+            } else {
+                stride as usize
+            };
+            let mut sealed = vec![0u8; stored_len];
+            self.inner
+                .seek(SeekFrom::Start(self.start + index * stride))?;
+            self.inner.read_exact(&mut sealed)?;
+            let block = self.cipher.open_sealed(&block_aad(index, last), &sealed)?;
+            self.cached = Some((index, block));
+        }
+        Ok(&self.cached.as_ref().expect("block just cached").1)
+    }
+}
+
+impl<R: Read + Seek> Read for BlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self.pos / self.block_size as u64;
+        let offset = (self.pos % self.block_size as u64) as usize;
+        let block = self.load(index)?;
+        let n = buf.len().min(block.len() - offset);
+        buf[..n].copy_from_slice(&block[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for BlockReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.len.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        self.pos = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,181 @@
+use super::*;
+use std::io::Cursor;
+
+// -------------------- Helpers --------------------
+
+fn keys(id: u32, byte: u8) -> StaticKeyProvider {
+    StaticKeyProvider::new(id, Key::new([byte; KEY_LEN]))
+}
+
+fn block_stream(data: &[u8], block_size: usize) -> (Vec<u8>, [u8; HEADER_LEN]) {
+    let (cipher, header) = FileCipher::create(&keys(1, 1)).unwrap();
+    let mut w = BlockWriter::new(Vec::new(), cipher, block_size);
+    w.write_all(data).unwrap();
+    assert_eq!(w.stream_position().unwrap(), data.len() as u64);
+    (w.finish().unwrap(), header)
+}
+
+fn read_stream(stored: Vec<u8>, header: &[u8]) -> io::Result<Vec<u8>> {
+    let cipher = FileCipher::open(header, Some(&keys(1, 1))).unwrap();
+    let mut r = BlockReader::new(Cursor::new(stored), cipher, 0, 16)?;
+    let mut out = Vec::new();
+    r.read_to_end(&mut out)?;
+    Ok(out)
+}
+
+// -------------------- Keys --------------------
+
+#[test]
+fn key_parses_from_hex_and_hides_its_bytes() {
+    let key = Key::from_hex(&"ab".repeat(KEY_LEN)).unwrap();
+    assert_eq!(key, Key::new([0xab; KEY_LEN]));
+    assert_eq!(format!("{:?}", key), "Key(..)");
+    assert!(Key::from_hex("abcd").is_none());
+    assert!(Key::from_hex(&"zz".repeat(KEY_LEN)).is_none());
+}
+
+#[test]
+fn generated_keys_differ() {
+    assert_ne!(Key::generate().unwrap(), Key::generate().unwrap());
+}
+
+#[test]
+fn static_provider_keeps_retired_keys_for_reading() {
+    let provider = keys(2, 2).with_key(1, Key::new([1; KEY_LEN]));
+    assert_eq!(provider.current_key_id(), 2);
+    assert_eq!(provider.key(1), Some(Key::new([1; KEY_LEN])));
+    assert_eq!(provider.key(3), None);
+}
+
+// -------------------- Header & records --------------------
+
+#[test]
+fn header_names_the_current_key() {
+    let (cipher, header) = FileCipher::create(&keys(7, 1)).unwrap();
+    assert_eq!(cipher.key_id(), 7);
+    assert_eq!(header_key_id(&header), Some(7));
+    assert_eq!(
+        FileCipher::open(&header, Some(&keys(7, 1)))
+            .unwrap()
+            .key_id(),
+        7
+    );
+}
+
+#[test]
+fn opening_fails_cleanly_without_the_right_key() {
+    let (_, header) = FileCipher::create(&keys(1, 1)).unwrap();
+    assert!(matches!(
+        FileCipher::open(&header, None),
+        Err(EncryptionError::NoKeyProvider(1))
+    ));
+    assert!(matches!(
+        FileCipher::open(&header, Some(&keys(2, 1))),
+        Err(EncryptionError::UnknownKey(1))
+    ));
+    assert!(matches!(
+        FileCipher::open(&header, Some(&keys(1, 9))),
+        Err(EncryptionError::WrongKey(1))
+    ));
+    assert!(matches!(
+        FileCipher::open(&header[..10], Some(&keys(1, 1))),
+        Err(EncryptionError::BadHeader)
+    ));
+}
+
+#[test]
+fn sealed_records_roundtrip_and_detect_tampering() {
+    let (cipher, _) = FileCipher::create(&keys(1, 1)).unwrap();
+    let sealed = cipher.seal(b"frame", b"hello").unwrap();
+    assert_eq!(sealed.len(), 5 + SEAL_OVERHEAD);
+    assert!(!sealed.windows(5).any(|w| w == b"hello"));
+    assert_eq!(cipher.open_sealed(b"frame", &sealed).unwrap(), b"hello");
+
+    // same plaintext, fresh nonce
+    assert_ne!(cipher.seal(b"frame", b"hello").unwrap(), sealed);
+
+    let mut flipped = sealed.clone();
+    flipped[NONCE_LEN] ^= 1;
+    assert!(matches!(
+        cipher.open_sealed(b"frame", &flipped),
+        Err(EncryptionError::Decrypt)
+    ));
+    assert!(cipher.open_sealed(b"other", &sealed).is_err());
+    assert!(cipher.open_sealed(b"frame", &sealed[..10]).is_err());
+}
+
+#[test]
+fn records_are_bound_to_their_file() {
+    let provider = keys(1, 1);
+    let (a, _) = FileCipher::create(&provider).unwrap();
+    let (b, _) = FileCipher::create(&provider).unwrap();
+    let sealed = a.seal(b"", b"x").unwrap();
+    assert!(b.open_sealed(b"", &sealed).is_err());
+}
+
+// -------------------- Block streams --------------------
+
+#[test]
+fn block_stream_roundtrips_at_every_length() {
+    for len in [0, 1, 15, 16, 17, 100] {
+        let data: Vec<u8> = (0..len as u8).collect();
+        let (stored, header) = block_stream(&data, 16);
+        // full blocks, then a last block holding the rest (possibly nothing)
+        assert_eq!(stored.len(), len + (len / 16 + 1) * SEAL_OVERHEAD);
+        assert_eq!(read_stream(stored, &header).unwrap(), data);
+    }
+}
+
+#[test]
+fn block_reader_seeks_randomly() {
+    let data: Vec<u8> = (0..100u8).collect();
+    let (stored, header) = block_stream(&data, 16);
+    let cipher = FileCipher::open(&header, Some(&keys(1, 1))).unwrap();
+    let mut r = BlockReader::new(Cursor::new(stored), cipher, 0, 16).unwrap();
+    assert_eq!(r.len(), 100);
+
+    let mut buf = [0u8; 10];
+    r.seek(SeekFrom::Start(40)).unwrap();
+    r.read_exact(&mut buf).unwrap();
+    assert_eq!(buf.to_vec(), (40..50).collect::<Vec<u8>>());
+    assert_eq!(r.seek(SeekFrom::End(-4)).unwrap(), 96);
+    let mut tail = Vec::new();
+    r.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, vec![96, 97, 98, 99]);
+    assert!(r.seek(SeekFrom::Current(-200)).is_err());
+}
+
+#[test]
+fn block_stream_detects_corruption_and_truncation() {
+    let data = vec![5u8; 64];
+    let (stored, header) = block_stream(&data, 16);
+
+    let mut flipped = stored.clone();
+    flipped[20] ^= 1;
+    let err = read_stream(flipped, &header).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // drop the final (empty) block: the new last block was not sealed as last
+    let stride = 16 + SEAL_OVERHEAD;
+    let truncated = stored[..stored.len() - SEAL_OVERHEAD].to_vec();
+    assert_eq!(truncated.len() % stride, 0);
+    assert!(read_stream(truncated, &header).is_err());
+
+    // swap two blocks
+    let mut swapped = stored.clone();
+    let (first, rest) = swapped.split_at_mut(stride);
+    first.swap_with_slice(&mut rest[..stride]);
+    assert!(read_stream(swapped, &header).is_err());
+}
+
+#[test]
+fn block_stream_can_start_after_a_prefix() {
+    let (stored, header) = block_stream(b"payload", 16);
+    let mut file = b"PREFIX".to_vec();
+    file.extend(stored);
+    let cipher = FileCipher::open(&header, Some(&keys(1, 1))).unwrap();
+    let mut r = BlockReader::new(Cursor::new(file), cipher, 6, 16).unwrap();
+    let mut out = Vec::new();
+    r.read_to_end(&mut out).unwrap();
+    assert_eq!(out, b"payload");
+}
@@ -1,140 +1,752 @@
-/// Compaction: merges all L0 + L1 SSTables into a single L1 SSTable.
+/// Compaction: merges SSTables down the level hierarchy.
 ///
-/// Uses [`MergeIterator`] for sorted, deduplicated streaming from multiple
-/// SSTables. Tombstone GC drops dead keys when no older SSTables remain.
-/// The result is written atomically (temp file + rename), old files are
-/// deleted, and the manifest is updated.
-use anyhow::Result;
+/// ## Automatic compaction
+///
+/// After every flush the engine asks its [`CompactionStrategy`] which files
+/// to merge and runs the returned [`CompactionJob`], repeating until the
+/// strategy is satisfied. The default [`LeveledStrategy`](crate::LeveledStrategy):
+///
+/// - **L0 -> L1** once L0 holds `l0_compaction_trigger` files. L0 files may
+///   overlap, so all of them are merged together with the L1 files their key
+///   range overlaps.
+/// - **Ln -> Ln+1** once the total size of Ln exceeds its target
+///   (`level_base_bytes * level_size_multiplier^(n-1)`). One file is picked
+///   (round-robin through the level's key space) and merged with only the
+///   files of Ln+1 it overlaps. If it overlaps none, it is moved down without
+///   being rewritten.
+///
+/// Only the picked files are rewritten, so the cost of a compaction is
+/// bounded by the level multiplier rather than by the database size. See
+/// [`strategy`](crate::strategy) for the size-tiered and merge-everything
+/// alternatives. [`Engine::compact`] remains available as a manual full
+/// compaction, and [`Engine::compact_range`] as a manual compaction of the
+/// files overlapping one key range.
+///
+/// Uses [`MergeIterator`] for sorted, deduplicated streaming from the input
+/// SSTables. Tombstones are dropped once no older file outside the job holds
+/// data in the output's key range. Outputs below L0 are split at
+/// `target_file_size_bytes` into files with disjoint key ranges. Each is
+/// written atomically (temp file + rename), then the manifest is updated and
+/// the input files are deleted. With
+/// [`max_subcompactions`](Engine::set_max_subcompactions) above 1, such a
+/// job is split into disjoint key ranges merged on parallel worker threads
+/// (see `subcompaction.rs`).
+use anyhow::{bail, Context, Result};
 use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::{Engine, MergeIterator, SSTableReader, SSTableWriter};
+use crate::events::{BackgroundJob, CompactionJobInfo, TableFileInfo, TableFileReason};
+use crate::manifest::{SstMeta, MAX_SUPPORTED_LEVELS};
+use crate::recovery::{open_sstable, sort_by_smallest_key};
+use crate::stats::Metrics;
+use crate::strategy::{creation_time_ms, file_name, full_compaction, key_range, overlaps};
+use crate::subcompaction::{subcompaction_ranges, Subcompaction, SubcompactionOutput};
+use crate::{CompactionJob, CompactionKind, CompactionStrategy, Engine, SSTableReader};
 
 impl Engine {
-    /// Compacts all SSTables into a single merged SSTable.
+    /// Compacts all SSTables into the deepest level.
     ///
-    /// Uses [`MergeIterator`] to walk all SSTables in sorted key order,
-    /// resolving duplicates by highest sequence number. The merged result is
-    /// written to a new SSTable, old SSTable files are deleted, and the
-    /// engine's SSTable list is replaced with the single merged reader.
+    /// This is a manual *full* compaction: every SSTable in every level is
+    /// merged, resolving duplicates by highest sequence number, and the
+    /// result is written to the deepest non-empty level (at least L1), split
+    /// into files of about `target_file_size_bytes`.
+    /// Automatic compaction after a flush follows the configured
+    /// [`CompactionStrategy`] instead.
     ///
-    /// Tombstone GC: since this is a full compaction (all L0 + L1 -> single
-    /// L1), tombstones are safe to drop unless the memtable still references
-    /// the key (the memtable is not part of compaction).
-    ///
-    /// # When to compact
-    ///
-    /// Called automatically when L0 count reaches `l0_compaction_trigger`
-    /// after a flush, or manually by the caller.
+    /// Tombstone GC: since every SSTable takes part, there is no older data
+    /// a tombstone could still shadow, so all tombstones are dropped. (The
+    /// memtable is always newer than any SSTable.)
     ///
     /// # Errors
     ///
-    /// Returns an error on I/O failure during merge, write, or cleanup.
+    /// Returns an error on I/O failure during merge, write, or cleanup, or if
+    /// the engine is read-only.
     pub fn compact(&mut self) -> Result<()> {
-        let total = self.l0_sstables.len() + self.l1_sstables.len();
-        if total <= 1 {
+        self.ensure_writable("compact")?;
+        if self.sstable_count() <= 1 {
             return Ok(()); // nothing to compact
         }
+        match full_compaction(&self.level_state()) {
+            Some(job) => self.run_compaction(job),
+            None => Ok(()),
+        }
+    }
+
+    /// Compacts the SSTables holding keys in `[start, end)`, leaving every
+    /// other file alone. Use it to reclaim space after deleting a key range.
+    ///
+    /// Like [`scan`](Engine::scan), `start` is inclusive, `end` exclusive,
+    /// and an empty bound is unbounded, so `compact_range(b"", b"")` covers
+    /// every key (but, unlike [`compact`](Engine::compact), still produces
+    /// one output per level it passes through).
+    ///
+    /// Level by level, starting at L0, the files overlapping the range are
+    /// merged with the overlapping files of the next level that has any
+    /// (or the bottommost level) and written there, using the same streaming
+    /// [`MergeIterator`] path as every other compaction. Data in the range
+    /// thus ends up in the bottommost level, where tombstones can be
+    /// dropped. Bottommost files in the range that no upper level pushed
+    /// data into are rewritten in place, one by one.
+    ///
+    /// Whole files are compacted, so keys just outside the range that share
+    /// a file with keys inside it are rewritten too.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error on I/O failure during merge, write, or cleanup, or if
+    /// the engine is read-only.
+    pub fn compact_range(&mut self, start: &[u8], end: &[u8]) -> Result<()> {
+        self.ensure_writable("compact_range")?;
+        let Some(bottom) = self.level_state().deepest_non_empty_level() else {
+            return Ok(()); // no SSTables at all
+        };
+        let bottom = bottom.max(1);
 
-        // Collect the paths of old SSTable files before we start.
-        let old_paths: Vec<PathBuf> = std::fs::read_dir(&self.sst_dir)?
-            .filter_map(|e| e.ok())
-            .map(|e| e.path())
-            .filter(|p| p.extension().map(|x| x == "sst").unwrap_or(false))
+        // Bottommost files in the range; those not consumed on the way
+        // down are rewritten in place at the end.
+        let bottom_files: Vec<String> = self.levels[bottom]
+            .iter()
+            .filter(|r| in_range(r, start, end))
+            .map(file_name)
             .collect();
 
-        // Move L0 + L1 into a contiguous vec for MergeIterator.
-        let mut all_sstables: Vec<SSTableReader> = Vec::new();
-        let mut l0 = std::mem::take(&mut self.l0_sstables);
-        let mut l1 = std::mem::take(&mut self.l1_sstables);
-        all_sstables.append(&mut l0);
-        all_sstables.append(&mut l1);
-
-        // Estimate total entry count for bloom filter sizing.
-        let estimated_count: usize = all_sstables.iter().map(|r| r.len()).sum();
-
-        let mut merge = MergeIterator::new(&all_sstables);
-
-        // Stram directly from MergeIterator -> SSTableWriter without
-        // materializing the entire dataset in RAM. Memory usage is bounded
-        // by the bloom filter + index, not the data volume.
-        let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
-        let sst_name = format!("sst-{:020}-{}.sst", self.seq, ts);
-        let sst_path = self.sst_dir.join(&sst_name);
-
-        // Tombstone GC: since this is a full compaction (all L0 + L1 -> single
-        // L1), there are no older SSTables that could contain shadowed values.
-        // Tombstones are therefore safe to drop — they have no older data to
-        // shadow. Also check if the memtable contains the key: if so, the
-        // tombstone must be preserved to shadow the memtable entry on recovery.
-        //
-        // Build a streaming iterator adapter from MergeIterator.
-        // MergeIterator::next() returns Result<Option<...>>, so we collect
-        // into a fallible iterator that stops on error or exhaustion.
-        let mem_ref = &self.mem;
-        let mut merge_error: Option<anyhow::Error> = None;
-        let streaming_iter = std::iter::from_fn(|| {
+        let mut level = 0;
+        while level < bottom {
+            match self.range_compaction_job(level, bottom, start, end) {
+                Some(job) => {
+                    level = job.output_level;
+                    self.run_compaction(job)?;
+                }
+                None => level += 1,
+            }
+        }
+
+        // One file at a time: compactions above may have put new files
+        // between them.
+        for name in bottom_files {
+            if self.levels[bottom].iter().any(|r| file_name(r) == name) {
+                self.run_compaction(CompactionJob {
+                    level: bottom,
+                    inputs: vec![(bottom, name)],
+                    output_level: bottom,
+                    kind: CompactionKind::Merge,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the [`compact_range`](Engine::compact_range) job pushing the
+    /// files of `level` in `[start, end)` down, or `None` if `level` has no
+    /// such file.
+    ///
+    /// L0 files may overlap, so every L0 file overlapping the picked ones is
+    /// taken too (repeated until the key range stops growing). The output
+    /// goes to the first deeper level holding overlapping files, or to
+    /// `bottom`.
+    fn range_compaction_job(
+        &self,
+        level: usize,
+        bottom: usize,
+        start: &[u8],
+        end: &[u8],
+    ) -> Option<CompactionJob> {
+        let files = &self.levels[level];
+        let mut picked: Vec<&SSTableReader> =
+            files.iter().filter(|r| in_range(r, start, end)).collect();
+        let (mut smallest, mut largest) = key_range(&picked)?;
+        if level == 0 {
             loop {
-                match merge.next_entry() {
-                    Ok(Some((key, entry))) => {
-                        // Drop tombstones unless the memtable still references
-                        // this key (the memtable is not part of compaction, so
-                        // we must keep tombstones that shadow memtable data).
-                        if entry.value.is_none() && mem_ref.contains_key(&key) {
-                            continue; // GC this tombstone
-                        }
-                        return Some((key, entry));
-                    }
-                    Ok(None) => return None,
-                    Err(e) => {
-                        merge_error = Some(e);
-                        return None;
-                    }
+                picked = files
+                    .iter()
+                    .filter(|r| overlaps(r, &smallest, &largest))
+                    .collect();
+                let (lo, hi) = key_range(&picked)?;
+                if (lo.as_slice(), hi.as_slice()) == (smallest.as_slice(), largest.as_slice()) {
+                    break;
                 }
+                (smallest, largest) = (lo, hi);
             }
+        }
+
+        let output_level = (level + 1..bottom)
+            .find(|&l| {
+                self.overlapping_files(l, &smallest, &largest)
+                    .next()
+                    .is_some()
+            })
+            .unwrap_or(bottom);
+        let mut job = CompactionJob::new(level, output_level);
+        for reader in picked {
+            job.add_input(level, reader);
+        }
+        for reader in self.overlapping_files(output_level, &smallest, &largest) {
+            job.add_input(output_level, reader);
+        }
+        Some(job)
+    }
+
+    /// Runs the jobs picked by the compaction strategy, then those picked
+    /// for tombstone-heavy or old files (see
+    /// [`pick_tombstone_compaction`](Engine::pick_tombstone_compaction)),
+    /// until neither has anything left to do. Does nothing when
+    /// `l0_compaction_trigger` is `0`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first compaction error, including a job rejected by
+    /// [`validate_job`](Engine::validate_job); earlier compactions stay
+    /// applied.
+    pub(crate) fn maybe_compact(&mut self) -> Result<()> {
+        if self.l0_compaction_trigger == 0 {
+            return Ok(());
+        }
+        let strategy: Arc<dyn CompactionStrategy> = Arc::clone(&self.compaction_strategy);
+        loop {
+            let job = strategy
+                .pick_compaction(&self.level_state())
+                .or_else(|| self.pick_tombstone_compaction());
+            match job {
+                Some(job) => self.run_compaction(job)?,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Picks a compaction for the first SSTable (L0 first) whose tombstones
+    /// make up at least `tombstone_compaction_ratio` of its entries, or
+    /// which holds tombstones and is older than `tombstone_compaction_age`.
+    ///
+    /// The file is pushed down like [`compact_range`](Engine::compact_range)
+    /// would push its key range, or rewritten in place if it is already in
+    /// the bottommost level, where its tombstones are dropped. Either way
+    /// the tombstones end up in the bottommost level, so this terminates.
+    /// Files without a tombstone count (written before SSTable v4) are
+    /// never picked.
+    fn pick_tombstone_compaction(&self) -> Option<CompactionJob> {
+        if self.tombstone_compaction_ratio <= 0.0 && self.tombstone_compaction_age.is_none() {
+            return None;
+        }
+        let cutoff = self.tombstone_compaction_age.map(|age| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis())
+                .saturating_sub(age.as_millis())
         });
+        let (level, reader) = self
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(level, files)| files.iter().map(move |r| (level, r)))
+            .find(|(_, r)| {
+                let tombstones = r.tombstone_count().unwrap_or(0);
+                if tombstones == 0 {
+                    return false;
+                }
+                let ratio = tombstones as f64 / r.len() as f64;
+                (self.tombstone_compaction_ratio > 0.0 && ratio >= self.tombstone_compaction_ratio)
+                    || cutoff.is_some_and(|c| creation_time_ms(r) < c)
+            })?;
+
+        let bottom = self.level_state().deepest_non_empty_level()?.max(1);
+        if level == bottom {
+            let mut job = CompactionJob::new(level, level);
+            job.add_input(level, reader);
+            return Some(job);
+        }
+        // `largest` followed by a zero byte is the smallest key after it,
+        // making the half-open range cover the whole file.
+        let start = reader.smallest_key()?;
+        let mut end = reader.largest_key()?.to_vec();
+        end.push(0);
+        self.range_compaction_job(level, bottom, start, &end)
+    }
 
-        let write_result =
-            SSTableWriter::write_from_iterator(&sst_path, estimated_count, streaming_iter);
+    /// Returns the files of `level` whose key range intersects
+    /// `[smallest, largest]`.
+    pub(crate) fn overlapping_files<'a>(
+        &'a self,
+        level: usize,
+        smallest: &'a [u8],
+        largest: &'a [u8],
+    ) -> impl Iterator<Item = &'a SSTableReader> + 'a {
+        self.level_state()
+            .overlapping_files(level, smallest, largest)
+    }
 
-        // Check for merge errors first, then write errors.
-        if let Some(e) = merge_error {
-            // Clean up partial write if any.
-            let _ = std::fs::remove_file(sst_path.with_extension("sst.tmp"));
-            return Err(e);
+    /// Checks that running `job` keeps every read returning the newest
+    /// version of each key:
+    ///
+    /// - all inputs exist and none is listed twice,
+    /// - data never moves to a shallower level,
+    /// - an L0 output replaces a consecutive run of L0 files,
+    /// - no older file that inputs move past on their way down (later in
+    ///   L0, or in a level above the output level) overlaps them, so no
+    ///   older file ends up above newer data,
+    /// - the output does not overlap the files left in its level.
+    ///
+    /// A [`CompactionKind::Delete`] job only needs existing, distinct inputs.
+    fn validate_job(&self, job: &CompactionJob) -> Result<()> {
+        anyhow::ensure!(!job.inputs.is_empty(), "compaction job has no inputs");
+        anyhow::ensure!(
+            job.output_level < MAX_SUPPORTED_LEVELS as usize,
+            "compaction output level L{} exceeds L{}",
+            job.output_level,
+            MAX_SUPPORTED_LEVELS - 1
+        );
+        for (i, input) in job.inputs.iter().enumerate() {
+            anyhow::ensure!(
+                !job.inputs[..i].contains(input),
+                "compaction input {} listed twice",
+                input.1
+            );
+        }
+        let inputs = self.job_inputs(job)?;
+        if job.kind == CompactionKind::Delete {
+            return Ok(());
         }
 
-        // Handle the case where all SSTables were empty.
-        if let Err(e) = write_result {
-            if e.to_string().contains("empty") {
-                drop(all_sstables);
-                for p in &old_paths {
-                    let _ = std::fs::remove_file(p);
+        let min_level = job
+            .inputs
+            .iter()
+            .map(|(level, _)| *level)
+            .min()
+            .unwrap_or(0);
+        let max_level = job
+            .inputs
+            .iter()
+            .map(|(level, _)| *level)
+            .max()
+            .unwrap_or(0);
+        anyhow::ensure!(
+            job.output_level >= max_level,
+            "compaction job would move data up from L{} to L{}",
+            max_level,
+            job.output_level
+        );
+
+        let is_input = |level: usize, r: &SSTableReader| {
+            job.inputs
+                .iter()
+                .any(|(l, name)| *l == level && *name == file_name(r))
+        };
+
+        // Inputs from shallower levels move down to the output level; no
+        // older file they pass on the way may overlap them.
+        let moving: Vec<&SSTableReader> = inputs
+            .iter()
+            .zip(&job.inputs)
+            .filter(|(_, (level, _))| *level < job.output_level)
+            .map(|(r, _)| *r)
+            .collect();
+        if let Some((smallest, largest)) = key_range(&moving) {
+            let newest_l0 = self.levels[0].iter().position(|r| is_input(0, r));
+            if let Some(newest) = newest_l0 {
+                let older = self.levels[0][newest..]
+                    .iter()
+                    .filter(|r| !is_input(0, r))
+                    .find(|r| overlaps(r, &smallest, &largest));
+                if let Some(r) = older {
+                    bail!(
+                        "compaction job skips older overlapping file {} in L0",
+                        file_name(r)
+                    );
                 }
-                self.manifest.entries.clear();
-                self.manifest.save()?;
-                return Ok(());
             }
+            for level in min_level.max(1)..job.output_level {
+                if let Some(r) = self
+                    .overlapping_files(level, &smallest, &largest)
+                    .find(|r| !is_input(level, r))
+                {
+                    bail!(
+                        "compaction job skips overlapping file {} in L{}",
+                        file_name(r),
+                        level
+                    );
+                }
+            }
+        }
+
+        if job.output_level == 0 {
+            let l0_inputs: Vec<usize> = self.levels[0]
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| is_input(0, r))
+                .map(|(i, _)| i)
+                .collect();
+            anyhow::ensure!(
+                l0_inputs
+                    .last()
+                    .zip(l0_inputs.first())
+                    .map(|(o, n)| o - n + 1)
+                    == Some(l0_inputs.len()),
+                "L0 compaction inputs must be consecutive"
+            );
+        } else if let Some((smallest, largest)) = key_range(&inputs) {
+            // The output must not overlap the rest of its level.
+            let level = job.output_level;
+            if let Some(r) = self
+                .overlapping_files(level, &smallest, &largest)
+                .find(|r| !is_input(level, r))
+            {
+                bail!(
+                    "compaction job skips overlapping file {} in L{}",
+                    file_name(r),
+                    level
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `job`, reporting a failure to event listeners.
+    pub(crate) fn run_compaction(&mut self, job: CompactionJob) -> Result<()> {
+        let result = self.validate_job(&job).and_then(|()| match job.kind {
+            CompactionKind::Merge => self.execute_compaction(&job),
+            CompactionKind::Delete => self.execute_deletion(&job),
+        });
+        if let Err(e) = &result {
+            self.notify_background_error(BackgroundJob::Compaction, e);
+        }
+        result
+    }
+
+    /// Body of [`run_compaction`](Engine::run_compaction). Notifies event
+    /// listeners as files are created and deleted.
+    ///
+    /// The input readers stay installed until the output has been written
+    /// and the manifest saved, so a failed compaction leaves the engine
+    /// serving its previous state.
+    fn execute_compaction(&mut self, job: &CompactionJob) -> Result<()> {
+        // An L0 output takes the place of its (consecutive) inputs in L0, so
+        // it stays behind every newer flush.
+        let l0_slot = self.levels[0]
+            .iter()
+            .position(|r| job.inputs.contains(&(0, file_name(r))))
+            .unwrap_or(0);
+
+        let (input_paths, bytes_read, estimated_count, cursor, drop_tombstones) = {
+            let inputs = self.job_inputs(job)?;
+            let cursor = inputs
+                .iter()
+                .zip(&job.inputs)
+                .filter(|(_, (level, _))| *level == job.level)
+                .filter_map(|(r, _)| r.largest_key())
+                .max()
+                .map(<[u8]>::to_vec);
+
+            // Tombstones can only be dropped if nothing older (i.e. deeper,
+            // or later in L0) could still hold a value for the same key.
+            let drop_tombstones = match key_range(&inputs) {
+                Some((smallest, largest)) => {
+                    let older_l0 = if job.output_level == 0 {
+                        let oldest = self.levels[0]
+                            .iter()
+                            .rposition(|r| job.inputs.contains(&(0, file_name(r))))
+                            .map_or(0, |i| i + 1);
+                        &self.levels[0][oldest..]
+                    } else {
+                        &[]
+                    };
+                    !older_l0.iter().any(|r| overlaps(r, &smallest, &largest))
+                        && (job.output_level + 1..self.levels.len()).all(|level| {
+                            self.overlapping_files(level, &smallest, &largest)
+                                .next()
+                                .is_none()
+                        })
+                }
+                None => true,
+            };
+
+            (
+                inputs
+                    .iter()
+                    .map(|r| r.path().to_path_buf())
+                    .collect::<Vec<_>>(),
+                inputs.iter().map(|r| r.file_size()).sum::<u64>(),
+                inputs.iter().map(|r| r.len()).sum::<usize>(),
+                cursor,
+                drop_tombstones,
+            )
+        };
+
+        let mut info = CompactionJobInfo {
+            input_files: job.inputs.iter().map(|(_, name)| name.clone()).collect(),
+            output_files: Vec::new(),
+            output_level: job.output_level as u32,
+            bytes_read,
+            bytes_written: 0,
+            output_entries: 0,
+            dropped_tombstones: 0,
+            filter_removed: 0,
+            filter_changed: 0,
+            subcompactions: 0,
+        };
+        self.notify(|l| l.on_compaction_begin(&info));
+
+        // Trivial move: a single input with nothing to merge against is
+        // re-labelled in the manifest instead of being rewritten. A single
+        // input compacted within its own level is rewritten, dropping its
+        // obsolete entries.
+        if let [(from_level, name)] = job.inputs.as_slice() {
+            if *from_level != job.output_level {
+                return self.trivial_move(job, *from_level, name, l0_slot, cursor, info);
+            }
+        }
+
+        // Outputs below L0 are split at the target file size so that each
+        // covers a disjoint key range. An L0 output must stay a single file
+        // to keep its place among the (overlapping) L0 files.
+        let target_file_size = if job.output_level == 0 {
+            u64::MAX
+        } else {
+            self.target_file_size_bytes
+        };
+        // Twice each file's proportional share of the entries, so the
+        // bloom filters are not undersized when the inputs compress poorly.
+        let expected_per_file = (estimated_count as u64)
+            .saturating_mul(target_file_size.min(bytes_read))
+            .checked_div(bytes_read)
+            .map_or(estimated_count, |n| n.saturating_mul(2) as usize)
+            .clamp(1, estimated_count.max(1));
+
+        let inputs = self.job_inputs(job)?;
+        let ranges = if job.output_level == 0 || self.max_subcompactions <= 1 {
+            vec![(Vec::new(), Vec::new())]
+        } else {
+            subcompaction_ranges(&inputs, self.max_subcompactions)
+        };
+        info.subcompactions = ranges.len() as u32;
+
+        let write_opts = self.sst_write_options();
+        let next_file_number = AtomicU64::new(self.manifest.next_file_number().max(1));
+        let subcompaction = Subcompaction {
+            inputs: &inputs,
+            sst_dir: &self.sst_dir,
+            write_opts: &write_opts,
+            filter: self.compaction_filter.as_ref(),
+            drop_tombstones,
+            target_file_size,
+            expected_per_file,
+            next_file_number: &next_file_number,
+        };
+        let result = subcompaction.run_all(&ranges);
+        self.manifest
+            .bump_next_file_number(next_file_number.into_inner());
+        let (outputs, stats) = result?;
+        info.dropped_tombstones = stats.dropped_tombstones;
+        info.filter_removed = stats.filter_removed;
+        info.filter_changed = stats.filter_changed;
+
+        for output in &outputs {
+            let file_size = std::fs::metadata(&output.path)?.len();
+            self.notify(|l| {
+                l.on_table_file_created(&TableFileInfo {
+                    path: output.path.clone(),
+                    file_size,
+                    reason: TableFileReason::Compaction,
+                })
+            });
+        }
+        let remove_outputs = |outputs: &[SubcompactionOutput]| {
+            for output in outputs {
+                let _ = std::fs::remove_file(&output.path);
+            }
+        };
+        let readers = match outputs
+            .iter()
+            .map(|output| open_sstable(&output.path, self.key_provider.as_ref()))
+            .collect::<Result<Vec<_>>>()
+        {
+            Ok(readers) => readers,
+            Err(e) => {
+                remove_outputs(&outputs);
+                return Err(e);
+            }
+        };
+
+        // Update the manifest atomically: swap the inputs for the outputs.
+        let input_names: Vec<&str> = job.inputs.iter().map(|(_, n)| n.as_str()).collect();
+        self.manifest.remove_files(&input_names);
+        for (i, (output, reader)) in outputs.iter().zip(&readers).enumerate() {
+            self.manifest.add_at(
+                SstMeta::from_reader(reader, job.output_level as u32, output.min_seq),
+                l0_slot + i,
+            );
+        }
+        // A failed save rolls the manifest back. The outputs stay on disk:
+        // if the record reached the log after all, a restart lists them
+        // instead of the inputs. The obsolete-file sweep removes them once
+        // the manifest is known not to.
+        if let Err(e) = self.save_manifest() {
+            drop(readers);
             return Err(e);
         }
 
-        // Update the manifest atomically: replace all entries with the
-        // single compacted L1 SSTable.
-        self.manifest.replace_all_with_l1(sst_name);
-        self.manifest.save()?;
+        // Drop the input readers (releases file handles) before deleting.
+        for (level, name) in &job.inputs {
+            self.levels[*level].retain(|r| file_name(r) != *name);
+        }
+        self.remove_compacted_files(&input_paths);
+        self.set_compact_cursor(job.level, cursor);
+
+        Metrics::add(&self.metrics.compactions, 1);
+        Metrics::add(&self.metrics.compaction_bytes_read, bytes_read);
+
+        for (i, (output, reader)) in outputs.into_iter().zip(readers).enumerate() {
+            Metrics::add(&self.metrics.compaction_bytes_written, reader.file_size());
+            info.output_files.push(output.name);
+            info.bytes_written += reader.file_size();
+            info.output_entries += reader.len() as u64;
+            self.install_sstable(job.output_level, reader, l0_slot + i);
+        }
+
+        self.notify(|l| l.on_compaction_completed(&info));
+        Ok(())
+    }
+
+    /// Deletes the inputs of a [`CompactionKind::Delete`] job: removes them
+    /// from the manifest, then from the levels and the disk.
+    fn execute_deletion(&mut self, job: &CompactionJob) -> Result<()> {
+        let (input_paths, bytes_read) = {
+            let inputs = self.job_inputs(job)?;
+            (
+                inputs
+                    .iter()
+                    .map(|r| r.path().to_path_buf())
+                    .collect::<Vec<_>>(),
+                inputs.iter().map(|r| r.file_size()).sum::<u64>(),
+            )
+        };
+        let info = CompactionJobInfo {
+            input_files: job.inputs.iter().map(|(_, name)| name.clone()).collect(),
+            output_files: Vec::new(),
+            output_level: job.output_level as u32,
+            bytes_read,
+            bytes_written: 0,
+            output_entries: 0,
+            dropped_tombstones: 0,
+            filter_removed: 0,
+            filter_changed: 0,
+            subcompactions: 0,
+        };
+        self.notify(|l| l.on_compaction_begin(&info));
 
-        // Drop old readers (releases file handles) before deleting files.
-        drop(all_sstables);
+        let input_names: Vec<&str> = job.inputs.iter().map(|(_, n)| n.as_str()).collect();
+        self.manifest.remove_files(&input_names);
+        self.save_manifest()?;
 
-        // Delete old SSTable files (but not the new one).
-        for p in &old_paths {
-            let _ = std::fs::remove_file(p);
+        for (level, name) in &job.inputs {
+            self.levels[*level].retain(|r| file_name(r) != *name);
         }
+        self.remove_compacted_files(&input_paths);
+        Metrics::add(&self.metrics.compactions, 1);
 
-        // Open the new merged SSTable into L1 (compacted = non-overlapping).
-        let reader = SSTableReader::open(&sst_path)?;
-        self.l1_sstables = vec![reader];
+        self.notify(|l| l.on_compaction_completed(&info));
+        Ok(())
+    }
+
+    /// Moves the single input of `job` to the output level by updating the
+    /// manifest, without rewriting it.
+    fn trivial_move(
+        &mut self,
+        job: &CompactionJob,
+        from_level: usize,
+        name: &str,
+        l0_slot: usize,
+        cursor: Option<Vec<u8>>,
+        mut info: CompactionJobInfo,
+    ) -> Result<()> {
+        let meta = SstMeta {
+            level: job.output_level as u32,
+            ..self
+                .manifest
+                .file(name)
+                .cloned()
+                .unwrap_or_else(|| SstMeta::new(name, 0))
+        };
+        self.manifest.remove_files(&[name]);
+        self.manifest.add_at(meta, l0_slot);
+        self.save_manifest()?;
+
+        let pos = self.levels[from_level]
+            .iter()
+            .position(|r| file_name(r) == name)
+            .context("compaction input disappeared")?;
+        let reader = self.levels[from_level].remove(pos);
+        self.install_sstable(job.output_level, reader, l0_slot);
+        self.set_compact_cursor(job.level, cursor);
 
+        info.output_files = vec![name.to_string()];
+        self.notify(|l| l.on_compaction_completed(&info));
         Ok(())
     }
+
+    /// Resolves the input filenames of `job` to the installed readers.
+    fn job_inputs(&self, job: &CompactionJob) -> Result<Vec<&SSTableReader>> {
+        job.inputs
+            .iter()
+            .map(|(level, name)| {
+                self.levels[*level]
+                    .iter()
+                    .find(|r| file_name(r) == *name)
+                    .with_context(|| format!("compaction input {} not found in L{}", name, level))
+            })
+            .collect()
+    }
+
+    /// Adds a compaction output to `level`: at position `l0_slot` of L0, or
+    /// keeping deeper levels sorted by smallest key.
+    fn install_sstable(&mut self, level: usize, reader: SSTableReader, l0_slot: usize) {
+        if self.levels.len() <= level {
+            self.levels.resize_with(level + 1, Vec::new);
+        }
+        let files = &mut self.levels[level];
+        if level == 0 {
+            files.insert(l0_slot.min(files.len()), reader);
+        } else {
+            files.push(reader);
+            sort_by_smallest_key(files);
+        }
+    }
+
+    /// Records the largest key compacted out of `level` (L1 and deeper).
+    fn set_compact_cursor(&mut self, level: usize, cursor: Option<Vec<u8>>) {
+        if level == 0 || cursor.is_none() {
+            return;
+        }
+        if self.compact_cursors.len() <= level {
+            self.compact_cursors.resize(level + 1, None);
+        }
+        self.compact_cursors[level] = cursor;
+    }
+
+    /// Deletes compaction inputs, notifying listeners of each file removed.
+    /// Failures are ignored: a leftover file is harmless once it is no
+    /// longer referenced by the manifest.
+    fn remove_compacted_files(&self, paths: &[PathBuf]) {
+        for p in paths {
+            let file_size = std::fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+            if std::fs::remove_file(p).is_ok() {
+                self.notify(|l| {
+                    l.on_table_file_deleted(&TableFileInfo {
+                        path: p.clone(),
+                        file_size,
+                        reason: TableFileReason::Compaction,
+                    })
+                });
+            }
+        }
+    }
+}
+
+/// Returns `true` if `reader` holds keys in `[start, end)`, where an empty
+/// bound is unbounded (as in [`Engine::scan`]).
+fn in_range(reader: &SSTableReader, start: &[u8], end: &[u8]) -> bool {
+    match (reader.smallest_key(), reader.largest_key()) {
+        (Some(lo), Some(hi)) => (end.is_empty() || lo < end) && (start.is_empty() || hi >= start),
+        _ => false,
+    }
 }
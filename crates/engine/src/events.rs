@@ -0,0 +1,162 @@
+/// Event listener hooks for flush, compaction, and file lifecycle.
+///
+/// Applications register an [`EventListener`] with
+/// [`Engine::add_event_listener`] to react when SSTables are created or
+/// deleted, a flushed WAL segment is deleted, or a flush/compaction fails. Callbacks run
+/// synchronously on the thread performing the operation, in registration
+/// order, so they should return quickly.
+///
+/// Every method has an empty default implementation; implement only the
+/// events you care about.
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::Engine;
+
+/// Details of a memtable flush.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlushJobInfo {
+    /// Name of the SSTable being written (basename).
+    pub sst_name: String,
+    /// Full path of the SSTable.
+    pub path: PathBuf,
+    /// Number of memtable entries flushed (including tombstones).
+    pub entries: usize,
+    /// Size of the written SSTable in bytes. Always `0` in
+    /// [`EventListener::on_flush_begin`].
+    pub file_size: u64,
+    /// Highest sequence number contained in the flush.
+    pub max_seq: u64,
+}
+
+/// Details of a compaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionJobInfo {
+    /// Input SSTable names.
+    pub input_files: Vec<String>,
+    /// Output SSTable names (empty if every entry was garbage-collected).
+    /// Always empty in [`EventListener::on_compaction_begin`].
+    pub output_files: Vec<String>,
+    /// Level the output is written to.
+    pub output_level: u32,
+    /// Total size of the input files in bytes.
+    pub bytes_read: u64,
+    /// Total size of the output files in bytes.
+    pub bytes_written: u64,
+    /// Number of entries written to the output.
+    pub output_entries: u64,
+    /// Number of tombstones dropped by tombstone GC.
+    pub dropped_tombstones: u64,
+    /// Number of records the compaction filter removed.
+    pub filter_removed: u64,
+    /// Number of records whose value the compaction filter changed.
+    pub filter_changed: u64,
+    /// Number of key ranges the job was split into and merged in parallel
+    /// (1 if it was not split, 0 for a trivial move).
+    pub subcompactions: u32,
+}
+
+/// Why a table file was created or deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFileReason {
+    /// Written by a memtable flush.
+    Flush,
+    /// Written or removed by compaction.
+    Compaction,
+    /// Removed by [`Engine::delete_obsolete_files`](crate::Engine::delete_obsolete_files)
+    /// as unreferenced.
+    Obsolete,
+}
+
+/// An SSTable that was created or deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableFileInfo {
+    /// Full path of the SSTable.
+    pub path: PathBuf,
+    /// Size of the file in bytes (`0` if unknown at deletion time).
+    pub file_size: u64,
+    /// Operation that created or deleted the file.
+    pub reason: TableFileReason,
+}
+
+/// A WAL segment was deleted after its contents were flushed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalTruncatedInfo {
+    /// Path of the deleted WAL segment.
+    pub wal_path: PathBuf,
+    /// Size of the segment when it was deleted.
+    pub bytes_truncated: u64,
+}
+
+/// The maintenance operation that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundJob {
+    /// A memtable flush (automatic or forced).
+    Flush,
+    /// A compaction (automatic or manual).
+    Compaction,
+}
+
+/// A flush or compaction failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackgroundErrorInfo {
+    /// The operation that failed.
+    pub job: BackgroundJob,
+    /// The error message (including its context chain).
+    pub error: String,
+}
+
+/// Callbacks for engine lifecycle events.
+///
+/// Listeners must be `Send + Sync` because they are shared through an `Arc`
+/// and may be cloned into other parts of an application.
+pub trait EventListener: Send + Sync {
+    /// Called before a memtable flush writes its SSTable.
+    fn on_flush_begin(&self, _info: &FlushJobInfo) {}
+
+    /// Called after a flush has written its SSTable and updated the manifest.
+    fn on_flush_completed(&self, _info: &FlushJobInfo) {}
+
+    /// Called before a compaction starts merging its inputs.
+    fn on_compaction_begin(&self, _info: &CompactionJobInfo) {}
+
+    /// Called after a compaction has installed its output and deleted its inputs.
+    fn on_compaction_completed(&self, _info: &CompactionJobInfo) {}
+
+    /// Called after a new SSTable has been durably written.
+    fn on_table_file_created(&self, _info: &TableFileInfo) {}
+
+    /// Called after an obsolete SSTable has been deleted from disk.
+    fn on_table_file_deleted(&self, _info: &TableFileInfo) {}
+
+    /// Called for each WAL segment deleted following a flush.
+    fn on_wal_truncated(&self, _info: &WalTruncatedInfo) {}
+
+    /// Called when a flush or compaction fails. The error is also returned
+    /// to the caller that triggered the operation.
+    fn on_background_error(&self, _info: &BackgroundErrorInfo) {}
+}
+
+impl Engine {
+    /// Registers a listener that is notified of flush, compaction, and file
+    /// lifecycle events. Listeners are called in registration order.
+    pub fn add_event_listener(&mut self, listener: Arc<dyn EventListener>) {
+        self.listeners.push(listener);
+    }
+
+    /// Invokes `f` on every registered listener.
+    pub(crate) fn notify(&self, f: impl Fn(&dyn EventListener)) {
+        for listener in &self.listeners {
+            f(listener.as_ref());
+        }
+    }
+
+    /// Reports a failed flush or compaction to the listeners.
+    pub(crate) fn notify_background_error(&self, job: BackgroundJob, err: &anyhow::Error) {
+        let info = BackgroundErrorInfo {
+            job,
+            error: format!("{:#}", err),
+        };
+        self.notify(|l| l.on_background_error(&info));
+    }
+}
@@ -0,0 +1,63 @@
+/// Compaction filters: application logic applied to every record a
+/// compaction rewrites.
+///
+/// Register a [`CompactionFilter`] with [`Engine::set_compaction_filter`]
+/// to drop or rewrite records as they stream from the `MergeIterator` into
+/// the output SSTable, e.g. to remove the data of deleted tenants or strip
+/// deprecated fields. The filter runs on the thread performing the
+/// compaction, once per live key (after duplicates are resolved to the
+/// newest version), so it should be cheap. With
+/// [`Engine::set_max_subcompactions`] above 1 it is called concurrently from
+/// several worker threads, each handling a disjoint key range.
+///
+/// Only records that a compaction actually rewrites are filtered: the
+/// memtable, flushes, and files moved to another level without being
+/// rewritten (trivial moves) bypass the filter. Tombstones are never passed
+/// to it.
+use memtable::ValueEntry;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::Engine;
+
+/// What a [`CompactionFilter`] wants done with a record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterDecision {
+    /// Write the record unchanged.
+    Keep,
+    /// Delete the record. If older data for the key could still exist
+    /// outside the compaction, a tombstone is written in its place so the
+    /// older value does not reappear; otherwise the key is dropped.
+    Remove,
+    /// Write the record with this value instead, keeping its sequence
+    /// number.
+    ChangeValue(Vec<u8>),
+}
+
+/// Decides, per record, whether a compaction keeps, removes or rewrites it.
+pub trait CompactionFilter: Debug + Send + Sync {
+    /// Short name for logs and `Debug` output.
+    fn name(&self) -> &'static str;
+
+    /// Called for every live `(key, entry)` a compaction writes. `entry`
+    /// always holds a value.
+    fn filter(&self, key: &[u8], entry: &ValueEntry) -> FilterDecision;
+}
+
+impl Engine {
+    /// Returns the compaction filter, if one is set.
+    #[must_use]
+    pub fn compaction_filter(&self) -> Option<&Arc<dyn CompactionFilter>> {
+        self.compaction_filter.as_ref()
+    }
+
+    /// Sets (or with `None`, removes) the filter applied to records
+    /// rewritten by compaction, including [`compact`](Engine::compact) and
+    /// [`compact_range`](Engine::compact_range).
+    ///
+    /// Changed values are subject to [`MAX_VALUE_SIZE`](crate::MAX_VALUE_SIZE):
+    /// a larger value fails the compaction, leaving its inputs in place.
+    pub fn set_compaction_filter(&mut self, filter: Option<Arc<dyn CompactionFilter>>) {
+        self.compaction_filter = filter;
+    }
+}
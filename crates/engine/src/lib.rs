@@ -19,12 +19,12 @@
 //! │              v                                │
 //! │           flush() → new SSTable               │
 //! │              |                                │
-//! │              |  (L0 count >= trigger?)        │
+//! │              |  (L0 full / level too big?)    │
 //! │              |            yes                 │
 //! │              v                                │
-//! │           compact() → merged L1 SST           │
+//! │     leveled compaction → Ln+1 SSTs            │
 //! │                                               │
-//! │ read.rs → Memtable → L0 SSTs → L1 SSTs        │
+//! │ read.rs → Memtable → L0 SSTs → L1 … Ln SSTs   │
 //! │            (first match wins)                 │
 //! └───────────────────────────────────────────────┘
 //! ```
@@ -34,11 +34,18 @@
 //! | Module        | Purpose                                               |
 //! |--------------|-------------------------------------------------------|
 //! | [`lib.rs`]   | `Engine` struct, constructor, accessors, `Debug`, `Drop` |
-//! | [`recovery`] | WAL replay, SSTable loading, tmp file cleanup          |
+//! | [`recovery`] | WAL segment replay, SSTable loading, tmp file cleanup  |
+//! | [`secondary`] | `try_catch_up()` for read-only instances tailing a primary |
 //! | [`write`]    | `set()`, `del()`, `force_flush()`, internal `flush()`   |
 //! | [`read`]     | `get()`, `scan()`                                      |
-//! | [`compaction`] | `compact()` with streaming merge + tombstone GC     |
-//! | [`manifest`] | Persistent L0/L1 level tracking (atomic file ops)      |
+//! | [`compaction`] | Runs compaction jobs, `compact()`, tombstone GC      |
+//! | [`strategy`] | `CompactionStrategy`: leveled, size-tiered, merge-all, FIFO |
+//! | [`subcompaction`] | Splits a compaction into key ranges merged in parallel |
+//! | [`events`]   | `EventListener` hooks for flush/compaction/file events |
+//! | [`filter`]   | `CompactionFilter` hook to drop/rewrite compacted records |
+//! | [`manifest`] | Version-edit log of levels and per-file metadata      |
+//! | [`obsolete`] | Sweep of unreferenced SSTables and stale manifest files |
+//! | [`stats`]    | Counters + latency histograms, `stats()` snapshot      |
 //!
 //! ## Levels
 //!
@@ -48,29 +55,60 @@
 //! ├────────────────────────────┤  ← from flushes (may overlap)
 //! │ L0 SSTables                │
 //! ├────────────────────────────┤  ← from compaction (no overlap)
-//! │ L1 SSTables                │
+//! │ L1 SSTables                │     target: level_base_bytes
+//! ├────────────────────────────┤
+//! │ L2 SSTables                │     target: L1 × level_size_multiplier
+//! ├────────────────────────────┤
+//! │ ... up to L(max_levels-1)  │
 //! └────────────────────────────┘
 //! ```
 //!
 //! ## Crash Safety
 //!
-//! Every write is appended to the WAL **before** the Memtable update. The WAL
-//! is only truncated **after** a successful flush + manifest update. SSTables
-//! are written atomically via temp file + rename. The manifest uses the same
-//! atomic write pattern. See [`ARCHITECTURE.md`] for the full crash matrix.
+//! Every write is appended to the WAL **before** the Memtable update. A WAL
+//! segment is only deleted **after** a successful flush + manifest update. SSTables
+//! are written atomically via temp file + rename. The manifest is an append-only
+//! log whose torn last record is ignored on recovery. See [`ARCHITECTURE.md`] for the full crash matrix.
 mod compaction;
+mod events;
+mod filter;
 mod manifest;
+mod obsolete;
 mod read;
 mod recovery;
+mod secondary;
+mod stats;
+pub mod strategy;
+mod subcompaction;
 mod write;
 
 use anyhow::Result;
+pub use encryption::{EncryptionError, Key, KeyProvider, StaticKeyProvider};
+pub use events::{
+    BackgroundErrorInfo, BackgroundJob, CompactionJobInfo, EventListener, FlushJobInfo,
+    TableFileInfo, TableFileReason, WalTruncatedInfo,
+};
+pub use filter::{CompactionFilter, FilterDecision};
 use manifest::Manifest;
 use memtable::Memtable;
+pub use memtable::ValueEntry;
+pub use obsolete::ObsoleteFilesReport;
 pub use recovery::replay_wal_and_build;
-use sstable::{MergeIterator, SSTableReader, SSTableWriter};
+pub use sstable::RateLimiter;
+pub use sstable::SSTableReader;
+use sstable::{SSTableWriteOptions, SSTableWriter};
+use stats::Metrics;
+pub use stats::{EngineStats, HistogramSnapshot, SstableStats};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+pub use strategy::{
+    CompactionJob, CompactionKind, CompactionStrategy, FifoStrategy, LevelState, LeveledStrategy,
+    MergeAllStrategy, SizeTieredStrategy,
+};
 use wal::WalWriter;
+pub use wal::{WalCompression, WalRecoveryMode, WalRecoveryReport, WalSyncPolicy};
+pub use write::{WriteBatch, WriteOptions};
 
 /// Maximum allowed key size in bytes (64 KiB).
 pub const MAX_KEY_SIZE: usize = 64 * 1024;
@@ -80,10 +118,23 @@ pub const MAX_VALUE_SIZE: usize = 10 * 1024 * 1024;
 /// Default number of L0 SSTables that triggers automatic compaction.
 ///
 /// When the L0 count reaches this threshold after a flush, the engine
-/// automatically runs compaction to merge L0 + L1 into a single L1 SSTable.
-/// Set to `0` to disable auto-compaction.
+/// automatically merges all L0 SSTables into L1 (together with the L1 files
+/// they overlap). Set to `0` to disable auto-compaction.
 pub const DEFAULT_L0_COMPACTION_TRIGGER: usize = 4;
 
+/// Default number of levels (L0 through L6).
+pub const DEFAULT_MAX_LEVELS: usize = 7;
+
+/// Default target total size of L1 (10 MiB).
+pub const DEFAULT_LEVEL_BASE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Default ratio between the target sizes of consecutive levels.
+pub const DEFAULT_LEVEL_SIZE_MULTIPLIER: u64 = 10;
+
+/// Default size at which compaction outputs below L0 are split into a new
+/// SSTable (2 MiB).
+pub const DEFAULT_TARGET_FILE_SIZE_BYTES: u64 = 2 * 1024 * 1024;
+
 /// The central storage engine orchestrating Memtable, WAL, and SSTables.
 ///
 /// # Write Path
@@ -92,29 +143,36 @@ pub const DEFAULT_L0_COMPACTION_TRIGGER: usize = 4;
 /// 2. Append the record to the WAL (crash-safe durability).
 /// 3. Apply the mutation to the in-memory Memtable.
 /// 4. If `approx_size >= flush_threshold`, flush the Memtable to a new SSTable,
-///    truncate the WAL, and reset the Memtable.
+///    delete the WAL segments it covered, and reset the Memtable.
 ///
 /// # Read Path
 ///
 /// 1. Check the Memtable (freshest data, includes tombstones).
-/// 2. Check SSTables from newest to oldest.
+/// 2. Check L0 SSTables from newest to oldest, then at most one SSTable per
+///    deeper level.
 /// 3. First match wins; tombstones shadow older values.
 ///
 /// # Recovery
 ///
-/// On construction ([`Engine::new`]), the WAL is replayed into a fresh Memtable
+/// On construction ([`Engine::new`]), the live WAL segments are replayed into a fresh Memtable
 /// and existing `.sst` files are loaded from the SST directory.
 pub struct Engine {
     pub(crate) mem: Memtable,
-    /// Level 0: SSTables from memtable flushes (may have overlapping key ranges).
-    /// Ordered newest-first.
-    pub(crate) l0_sstables: Vec<SSTableReader>,
-    /// Level 1: SSTables from compaction (non-overlapping key ranges).
-    /// Ordered newest-first.
-    pub(crate) l1_sstables: Vec<SSTableReader>,
+    /// SSTables by level. `levels[0]` (L0) holds memtable flushes, ordered
+    /// newest-first, whose key ranges may overlap. Every deeper level holds
+    /// compaction outputs with non-overlapping key ranges, sorted by
+    /// smallest key. Always at least `max_levels` long.
+    pub(crate) levels: Vec<Vec<SSTableReader>>,
+    /// Single-file WAL written by older versions. Replayed as segment `0`
+    /// until the first flush, then deleted.
     pub(crate) wal_path: PathBuf,
+    /// Holds the SSTables, the manifest and the WAL segments.
     pub(crate) sst_dir: PathBuf,
-    pub(crate) wal_writer: WalWriter,
+    /// WAL writer, or `None` when opened with [`Engine::open_read_only`].
+    pub(crate) wal_writer: Option<WalWriter>,
+    /// Number of the WAL segment being appended to (writable engines) or
+    /// tailed (read-only engines). `0` is the legacy WAL at `wal_path`.
+    pub(crate) wal_number: u64,
     /// Persistent manifest tracking which SSTable files belong to which level.
     /// Updated atomically on flush and compaction so that L0/L1 assignments
     /// survive restarts.
@@ -130,8 +188,76 @@ pub struct Engine {
     /// Set to `0` to disable auto-compaction (caller must invoke `compact()`).
     pub(crate) l0_compaction_trigger: usize,
 
-    /// If `true`, every WAL append is followed by `fsync` for durability.
-    pub(crate) wal_sync: bool,
+    /// Number of levels leveled compaction may use (L0..L{max_levels-1}).
+    pub(crate) max_levels: usize,
+
+    /// Target total size of L1 in bytes.
+    pub(crate) level_base_bytes: u64,
+
+    /// Each level below L1 targets this many times the size of the one above.
+    pub(crate) level_size_multiplier: u64,
+
+    /// Compaction outputs below L0 are split into SSTables of about this
+    /// many bytes, each covering a disjoint key range.
+    pub(crate) target_file_size_bytes: u64,
+
+    /// Maximum number of disjoint key ranges a compaction below L0 is split
+    /// into, each merged on its own thread.
+    pub(crate) max_subcompactions: usize,
+
+    /// SSTables whose tombstones make up at least this fraction of their
+    /// entries are compacted even if no strategy picks them (`0` = off).
+    pub(crate) tombstone_compaction_ratio: f64,
+
+    /// SSTables holding tombstones that are older than this are compacted
+    /// even if no strategy picks them (`None` = off).
+    pub(crate) tombstone_compaction_age: Option<Duration>,
+
+    /// Per level, the largest key of the file last compacted out of it, so
+    /// successive compactions of a level walk its key space round-robin.
+    pub(crate) compact_cursors: Vec<Option<Vec<u8>>>,
+
+    /// When the WAL is forced to disk; used for every new segment.
+    pub(crate) wal_sync_policy: WalSyncPolicy,
+
+    /// Compression of WAL records; used for every new segment.
+    pub(crate) wal_compression: WalCompression,
+
+    /// Highest sequence number made durable other than by the current WAL
+    /// writer: by a flush, or recovered when the engine was opened.
+    pub(crate) persisted_seq: u64,
+
+    /// If `true`, the engine never writes to disk and rejects all mutations.
+    pub(crate) read_only: bool,
+
+    /// Byte offset just past the last complete record replayed from WAL
+    /// segment `wal_number`. Only meaningful for read-only engines, which
+    /// resume tailing from here in [`Engine::try_catch_up`].
+    pub(crate) wal_offset: u64,
+
+    /// WAL segments that had records discarded when the engine was opened,
+    /// with what was discarded from each.
+    pub(crate) wal_recovery_reports: Vec<(PathBuf, WalRecoveryReport)>,
+
+    /// Operation counters and latency histograms reported by [`Engine::stats`].
+    pub(crate) metrics: Metrics,
+
+    /// Listeners notified of flush, compaction and file lifecycle events.
+    pub(crate) listeners: Vec<Arc<dyn EventListener>>,
+
+    /// Throttles SSTable writes from flushes and compactions. WAL appends
+    /// are never throttled.
+    pub(crate) rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// Picks the jobs automatic compaction runs after a flush.
+    pub(crate) compaction_strategy: Arc<dyn CompactionStrategy>,
+
+    /// Drops or rewrites records as compaction streams them to disk.
+    pub(crate) compaction_filter: Option<Arc<dyn CompactionFilter>>,
+
+    /// Keys for encrypting new WAL segments, SSTables and manifest logs, and
+    /// for reading encrypted ones. `None` writes (and reads) plain files.
+    pub(crate) key_provider: Option<Arc<dyn KeyProvider>>,
 }
 
 impl std::fmt::Debug for Engine {
@@ -139,14 +265,30 @@ impl std::fmt::Debug for Engine {
         f.debug_struct("Engine")
             .field("seq", &self.seq)
             .field("flush_threshold", &self.flush_threshold)
-            .field("wal_sync", &self.wal_sync)
+            .field("wal_sync_policy", &self.wal_sync_policy)
+            .field("wal_compression", &self.wal_compression)
             .field("wal_path", &self.wal_path)
             .field("sst_dir", &self.sst_dir)
             .field("memtable_size", &self.mem.approx_size())
             .field("memtable_entries", &self.mem.len())
-            .field("l0_sstable_count", &self.l0_sstables.len())
-            .field("l1_sstable_count", &self.l1_sstables.len())
+            .field("level_sstable_counts", &self.level_sstable_counts())
             .field("l0_compaction_trigger", &self.l0_compaction_trigger)
+            .field("max_levels", &self.max_levels)
+            .field("compaction_strategy", &self.compaction_strategy.name())
+            .field(
+                "compaction_filter",
+                &self.compaction_filter.as_ref().map(|f| f.name()),
+            )
+            .field("read_only", &self.read_only)
+            .field("event_listeners", &self.listeners.len())
+            .field(
+                "rate_limit_bytes_per_sec",
+                &self.rate_limiter.as_ref().map(|r| r.bytes_per_sec()),
+            )
+            .field(
+                "encryption_key_id",
+                &self.key_provider.as_ref().map(|k| k.current_key_id()),
+            )
             .finish()
     }
 }
@@ -157,24 +299,112 @@ impl Engine {
     ///
     /// # Arguments
     ///
-    /// * `wal_path` — path to the write-ahead log file.
-    /// * `sst_dir` — directory where SSTable files are stored.
+    /// * `wal_path` — path of the single-file WAL written by older versions.
+    ///   It is replayed if it exists and deleted after the next flush; new
+    ///   writes go to numbered WAL segments (`000012.log`) in `sst_dir`.
+    /// * `sst_dir` — directory where SSTables, the manifest and the WAL
+    ///   segments are stored.
     /// * `flush_threshold` — memtable byte-size threshold that triggers flush.
-    /// * `wal_sync` — if `true`, every WAL append calls `fsync`.
+    /// * `wal_sync` — if `true`, every WAL append calls `fsync`
+    ///   ([`WalSyncPolicy::EveryWrite`]); otherwise the WAL is only synced
+    ///   on request ([`WalSyncPolicy::Never`]). See
+    ///   [`Engine::set_wal_sync_policy`] for other policies.
     ///
     /// # Recovery Steps
     ///
     /// 1. Create the SST directory if it does not exist.
     /// 2. Clean up leftover `.sst.tmp` files from interrupted flushes.
-    /// 3. Replay the WAL into a fresh Memtable.
-    /// 4. Open the WAL writer in append mode.
+    /// 3. Replay every live WAL segment, oldest first, into a fresh Memtable.
+    /// 4. Open the WAL writer: append to the newest segment if it ends on a
+    ///    record boundary, otherwise start a new segment.
     /// 5. Load SSTables from the manifest (or scan directory for legacy DBs).
-    /// 6. Determine the highest sequence number across WAL and SSTables.
+    /// 6. Determine the highest sequence number across the WAL, the SSTables
+    ///    and the last sequence number recorded in the manifest.
+    /// 7. Delete obsolete files (see [`Engine::delete_obsolete_files`]).
+    ///
+    /// The WAL is replayed with [`WalRecoveryMode::TolerateCorruptedTail`];
+    /// use [`Engine::open_with_wal_recovery_mode`] to pick another mode.
     pub fn new<P1: AsRef<Path>, P2: AsRef<Path>>(
         wal_path: P1,
         sst_dir: P2,
         flush_threshold: usize,
         wal_sync: bool,
+    ) -> Result<Self> {
+        Self::open_with_wal_recovery_mode(
+            wal_path,
+            sst_dir,
+            flush_threshold,
+            wal_sync,
+            WalRecoveryMode::default(),
+        )
+    }
+
+    /// Like [`Engine::new`], but replays the WAL with `mode`, which decides
+    /// what happens to damaged records. What was discarded is available from
+    /// [`Engine::wal_recovery_reports`].
+    ///
+    /// If `mode` is [`WalRecoveryMode::PointInTime`] and replay stopped at a
+    /// defect, the recovered state is made durable before the engine is
+    /// returned: the memtable is flushed and the damaged segments are
+    /// deleted, so later writes are never hidden behind the defect on the
+    /// next restart.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error on I/O failure, or if the WAL has a defect `mode`
+    /// does not tolerate.
+    pub fn open_with_wal_recovery_mode<P1: AsRef<Path>, P2: AsRef<Path>>(
+        wal_path: P1,
+        sst_dir: P2,
+        flush_threshold: usize,
+        wal_sync: bool,
+        mode: WalRecoveryMode,
+    ) -> Result<Self> {
+        Self::open_with_keys(wal_path, sst_dir, flush_threshold, wal_sync, mode, None)
+    }
+
+    /// Like [`Engine::open_with_wal_recovery_mode`], with encryption at
+    /// rest: new WAL segments, SSTables and manifest logs are encrypted with
+    /// the current key of `keys`.
+    ///
+    /// Every encrypted file records the id of its key, so keys can be
+    /// rotated: switch the provider's current key and keep the old one in
+    /// it, and files written before stay readable while new ones use the
+    /// new key. Plain files of an existing database stay readable too; the
+    /// manifest is rewritten encrypted on the next flush, and SSTables as
+    /// compaction replaces them.
+    ///
+    /// # Errors
+    ///
+    /// As [`Engine::open_with_wal_recovery_mode`], plus an
+    /// [`EncryptionError`] if a file is encrypted under a key `keys` does
+    /// not have, or under a different key with the same id.
+    pub fn open_encrypted<P1: AsRef<Path>, P2: AsRef<Path>>(
+        wal_path: P1,
+        sst_dir: P2,
+        flush_threshold: usize,
+        wal_sync: bool,
+        mode: WalRecoveryMode,
+        keys: Arc<dyn KeyProvider>,
+    ) -> Result<Self> {
+        Self::open_with_keys(
+            wal_path,
+            sst_dir,
+            flush_threshold,
+            wal_sync,
+            mode,
+            Some(keys),
+        )
+    }
+
+    /// Opens a writable engine, encrypting new files if `keys` are given.
+    fn open_with_keys<P1: AsRef<Path>, P2: AsRef<Path>>(
+        wal_path: P1,
+        sst_dir: P2,
+        flush_threshold: usize,
+        wal_sync: bool,
+        mode: WalRecoveryMode,
+        keys: Option<Arc<dyn KeyProvider>>,
     ) -> Result<Self> {
         let wal_path = wal_path.as_ref().to_path_buf();
         let sst_dir = sst_dir.as_ref().to_path_buf();
@@ -185,95 +415,272 @@ impl Engine {
         // clean up any leftover .sst.tmp files from interrupted flushes
         Self::cleanup_tmp_files(&sst_dir);
 
-        // replay wal into memtable and obtain last seq
+        // Load or create the manifest to determine L0/L1 assignments and the
+        // oldest WAL segment still needed.
+        let mut manifest = Manifest::load_with_keys(&sst_dir, keys.clone())?;
+        let bootstrapping = manifest.is_new();
+
+        // replay live wal segments into memtable and obtain last seq
         // (must happen BEFORE opening the writer to avoid file-sharing conflicts on Windows)
         let mut mem = Memtable::new();
-        let seq = replay_wal_and_build(&wal_path, &mut mem)?;
-
-        // open wal writer in append mode (after replay is done)
-        let wal_writer = WalWriter::create(&wal_path, wal_sync)?;
-
-        // Load or create the manifest to determine L0/L1 assignments.
-        let mut manifest = Manifest::load_or_create(&sst_dir)?;
-
-        let mut l0_sstables = Vec::new();
-        let mut l1_sstables = Vec::new();
-        let mut max_sst_seq = 0u64;
-
-        // If the manifest has entries, use it to load SSTables into the
-        // correct levels. This preserves L0/L1 assignments across restarts.
-        if !manifest.entries.is_empty() {
-            for filename in manifest.l0_filenames() {
-                let path = sst_dir.join(filename);
-                if path.exists() {
-                    let reader = SSTableReader::open(&path)?;
-                    max_sst_seq = max_sst_seq.max(Self::reader_max_seq(&reader));
-                    l0_sstables.push(reader);
-                }
-            }
-            for filename in manifest.l1_filenames() {
-                let path = sst_dir.join(filename);
-                if path.exists() {
-                    let reader = SSTableReader::open(&path)?;
-                    max_sst_seq = max_sst_seq.max(Self::reader_max_seq(&reader));
-                    l1_sstables.push(reader);
-                }
+        let segments = recovery::live_wal_segments(&sst_dir, &wal_path, manifest.log_number())?;
+        let replayed =
+            recovery::replay_wal_segments(&segments, 0, 0, mode, keys.as_ref(), &mut mem)?;
+
+        // Keep appending to the newest segment unless it has a torn tail
+        // (appends after it would be unreadable) or is the legacy WAL.
+        if let Some((number, _)) = segments.last() {
+            manifest.bump_next_file_number(number + 1);
+        }
+        let wal_number = match segments.last() {
+            Some((number, path))
+                if *number > 0 && std::fs::metadata(path)?.len() == replayed.offset =>
+            {
+                *number
             }
+            _ => manifest.allocate_file_number(),
+        };
+        let wal_sync_policy = if wal_sync {
+            WalSyncPolicy::EveryWrite
         } else {
-            // No manifest yet (fresh DB or pre-manifest upgrade).
-            // Fall back to scanning the directory and loading all SSTables
-            // into L0 (conservative - compaction will sort them out).
-            let mut paths: Vec<_> = std::fs::read_dir(&sst_dir)?
-                .filter_map(|e| e.ok())
-                .map(|e| e.path())
-                .filter(|p| p.extension().map(|e| e == "sst").unwrap_or(false))
-                .collect();
-
-            // newest first (filename contains seq + timestamp)
-            paths.sort();
-            paths.reverse();
-
-            for path in &paths {
-                let reader = SSTableReader::open(path)?;
-                max_sst_seq = max_sst_seq.max(Self::reader_max_seq(&reader));
-                l0_sstables.push(reader);
-            }
+            WalSyncPolicy::Never
+        };
+        let wal_writer = write::create_wal_writer(
+            &sst_dir.join(wal_filename(wal_number)),
+            wal_sync_policy,
+            keys.as_ref(),
+        )?;
+        let (levels, max_sst_seq) = Self::load_sstables(&sst_dir, &mut manifest, keys.as_ref())?;
 
-            // Bootstrap the manifest from the discovered files.
-            if !paths.is_empty() {
-                for path in &paths {
-                    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                        manifest.add(name.to_string(), 0);
-                    }
-                }
-                manifest.save()?;
-            }
-        }
+        // seq must never go backwards: take the max of the WAL, the
+        // SSTables and the last seq the manifest recorded
+        let seq = replayed.max_seq.max(max_sst_seq).max(manifest.last_seq());
 
-        // seq must be the max of WAL seq and SSTable seq
-        let seq = seq.max(max_sst_seq);
+        // Persist the manifest bootstrapped from a pre-manifest directory.
+        if bootstrapping && !manifest.entries.is_empty() {
+            manifest.set_last_seq(seq)?;
+            manifest.save()?;
+        }
 
-        Ok(Self {
+        let mut engine = Self {
             mem,
-            l0_sstables,
-            l1_sstables,
+            levels: pad_levels(levels, DEFAULT_MAX_LEVELS),
             wal_path,
             sst_dir,
-            wal_writer,
+            wal_writer: Some(wal_writer),
+            wal_number,
             manifest,
             seq,
             flush_threshold,
             l0_compaction_trigger: DEFAULT_L0_COMPACTION_TRIGGER,
-            wal_sync,
+            max_levels: DEFAULT_MAX_LEVELS,
+            level_base_bytes: DEFAULT_LEVEL_BASE_BYTES,
+            level_size_multiplier: DEFAULT_LEVEL_SIZE_MULTIPLIER,
+            target_file_size_bytes: DEFAULT_TARGET_FILE_SIZE_BYTES,
+            max_subcompactions: 1,
+            tombstone_compaction_ratio: 0.0,
+            tombstone_compaction_age: None,
+            compact_cursors: Vec::new(),
+            wal_sync_policy,
+            wal_compression: WalCompression::None,
+            persisted_seq: seq,
+            read_only: false,
+            wal_offset: 0,
+            wal_recovery_reports: replayed.discarded,
+            metrics: Metrics::new(),
+            listeners: Vec::new(),
+            rate_limiter: None,
+            compaction_strategy: Arc::new(LeveledStrategy),
+            compaction_filter: None,
+            key_provider: keys,
+        };
+
+        if replayed.stopped_early {
+            engine.seal_recovered_wal()?;
+        }
+
+        // Remove SSTables a crash left behind without a manifest entry.
+        engine.delete_obsolete_files()?;
+        Ok(engine)
+    }
+
+    /// Opens an existing database without ever writing to disk.
+    ///
+    /// Intended for analytics and reporting processes that only need to read
+    /// a copy of the data directory. The engine serves `get` and `scan` from
+    /// the SSTables listed in the manifest plus the replayed WAL, exactly as
+    /// a writable engine would after recovery.
+    ///
+    /// Unlike [`Engine::new`], this:
+    ///
+    /// - does not create the SST directory, a WAL segment, or the manifest,
+    /// - does not remove leftover `.sst.tmp` files,
+    /// - does not persist a manifest bootstrapped from a legacy directory,
+    /// - does not flush the memtable on drop.
+    ///
+    /// All mutating calls (`set`, `del`, `force_flush`, `compact`) return an
+    /// error. A missing WAL segment is treated as an empty log.
+    ///
+    /// A read-only engine opened on the directory of a running primary acts
+    /// as a secondary instance: call [`Engine::try_catch_up`] to pick up the
+    /// primary's newer flushes, compactions and WAL appends.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `sst_dir` does not exist, or if the WAL or any
+    /// SSTable cannot be read.
+    pub fn open_read_only<P1: AsRef<Path>, P2: AsRef<Path>>(
+        wal_path: P1,
+        sst_dir: P2,
+    ) -> Result<Self> {
+        Self::open_read_only_with_keys(wal_path, sst_dir, None)
+    }
+
+    /// Like [`Engine::open_read_only`], for a database written by
+    /// [`Engine::open_encrypted`]. `keys` must hold every key the files
+    /// were written with.
+    ///
+    /// # Errors
+    ///
+    /// As [`Engine::open_read_only`], plus an [`EncryptionError`] if a key
+    /// is missing or wrong.
+    pub fn open_read_only_encrypted<P1: AsRef<Path>, P2: AsRef<Path>>(
+        wal_path: P1,
+        sst_dir: P2,
+        keys: Arc<dyn KeyProvider>,
+    ) -> Result<Self> {
+        Self::open_read_only_with_keys(wal_path, sst_dir, Some(keys))
+    }
+
+    /// Opens a read-only engine, reading encrypted files with `keys`.
+    fn open_read_only_with_keys<P1: AsRef<Path>, P2: AsRef<Path>>(
+        wal_path: P1,
+        sst_dir: P2,
+        keys: Option<Arc<dyn KeyProvider>>,
+    ) -> Result<Self> {
+        let wal_path = wal_path.as_ref().to_path_buf();
+        let sst_dir = sst_dir.as_ref().to_path_buf();
+
+        anyhow::ensure!(
+            sst_dir.is_dir(),
+            "SST directory {} does not exist",
+            sst_dir.display()
+        );
+
+        // Replay the segments the manifest names as live. If a primary
+        // flushed meanwhile, the oldest of them may be gone, with their data
+        // only visible through the newer manifest: start over.
+        let mut attempts = 0;
+        let (mut manifest, mem, replayed) = loop {
+            let manifest = Manifest::load_with_keys(&sst_dir, keys.clone())?;
+            let segments = recovery::live_wal_segments(&sst_dir, &wal_path, manifest.log_number())?;
+            let mut mem = Memtable::new();
+            let replayed = recovery::replay_wal_segments(
+                &segments,
+                0,
+                0,
+                secondary::TAIL_MODE,
+                keys.as_ref(),
+                &mut mem,
+            )?;
+            attempts += 1;
+            let reloaded = Manifest::load_with_keys(&sst_dir, keys.clone())?;
+            if reloaded.log_number() == manifest.log_number() {
+                break (manifest, mem, replayed);
+            }
+            anyhow::ensure!(
+                attempts < secondary::CATCH_UP_MAX_ATTEMPTS,
+                "primary is flushing too fast to open a consistent view"
+            );
+        };
+
+        // The manifest is only ever updated in memory: a bootstrapped
+        // manifest for a legacy directory is never saved.
+        let (levels, max_sst_seq) = Self::load_sstables(&sst_dir, &mut manifest, keys.as_ref())?;
+        let seq = replayed.max_seq.max(max_sst_seq).max(manifest.last_seq());
+
+        Ok(Self {
+            mem,
+            levels: pad_levels(levels, DEFAULT_MAX_LEVELS),
+            wal_path,
+            sst_dir,
+            wal_writer: None,
+            wal_number: replayed.number,
+            manifest,
+            seq,
+            flush_threshold: usize::MAX,
+            l0_compaction_trigger: 0,
+            max_levels: DEFAULT_MAX_LEVELS,
+            level_base_bytes: DEFAULT_LEVEL_BASE_BYTES,
+            level_size_multiplier: DEFAULT_LEVEL_SIZE_MULTIPLIER,
+            target_file_size_bytes: DEFAULT_TARGET_FILE_SIZE_BYTES,
+            max_subcompactions: 1,
+            tombstone_compaction_ratio: 0.0,
+            tombstone_compaction_age: None,
+            compact_cursors: Vec::new(),
+            wal_sync_policy: WalSyncPolicy::Never,
+            wal_compression: WalCompression::None,
+            persisted_seq: seq,
+            read_only: true,
+            wal_offset: replayed.offset,
+            wal_recovery_reports: replayed.discarded,
+            metrics: Metrics::new(),
+            listeners: Vec::new(),
+            rate_limiter: None,
+            compaction_strategy: Arc::new(LeveledStrategy),
+            compaction_filter: None,
+            key_provider: keys,
         })
     }
 
+    /// Returns `true` if the engine was opened with [`Engine::open_read_only`].
+    #[must_use]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns an error if the engine was opened in read-only mode.
+    ///
+    /// `op` names the rejected operation in the error message.
+    pub(crate) fn ensure_writable(&self, op: &str) -> Result<()> {
+        anyhow::ensure!(
+            !self.read_only,
+            "{} rejected: engine is opened in read-only mode",
+            op
+        );
+        Ok(())
+    }
+
     /// Returns the current monotonic sequence number.
     #[must_use]
     pub fn seq(&self) -> u64 {
         self.seq
     }
 
+    /// Returns the highest sequence number known to survive a crash, power
+    /// failure included: every write with a sequence number up to it is in
+    /// a synced WAL segment or a flushed SSTable. Writes recovered when the
+    /// engine was opened count as durable.
+    ///
+    /// Lags [`Engine::seq`] unless the WAL sync policy is
+    /// [`WalSyncPolicy::EveryWrite`].
+    /// Writes made with [`WriteOptions::disable_wal`] are the exception:
+    /// they stay volatile until the next flush even if their sequence
+    /// number is below this one.
+    #[must_use]
+    pub fn durable_seq(&self) -> u64 {
+        let synced = self.wal_writer.as_ref().map_or(0, |w| w.durable_seq());
+        self.persisted_seq.max(synced)
+    }
+
+    /// Returns the WAL segments that had records discarded when the engine
+    /// was opened, oldest first, with what was discarded from each. Empty
+    /// if the WAL was replayed in full.
+    #[must_use]
+    pub fn wal_recovery_reports(&self) -> &[(PathBuf, WalRecoveryReport)] {
+        &self.wal_recovery_reports
+    }
+
     /// Returns the current flush threshold in bytes.
     #[must_use]
     pub fn flush_threshold(&self) -> usize {
@@ -285,11 +692,49 @@ impl Engine {
         self.flush_threshold = threshold;
     }
 
+    /// Returns the policy deciding when the WAL is forced to disk.
+    #[must_use]
+    pub fn wal_sync_policy(&self) -> WalSyncPolicy {
+        self.wal_sync_policy
+    }
+
+    /// Changes when the WAL is forced to disk, for the current segment and
+    /// every later one. [`Engine::durable_seq`] tells which writes are safe
+    /// under a policy that does not sync every write.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the background syncer of
+    /// [`WalSyncPolicy::EveryInterval`] cannot be started.
+    pub fn set_wal_sync_policy(&mut self, policy: WalSyncPolicy) -> Result<()> {
+        if let Some(writer) = self.wal_writer.as_mut() {
+            writer.set_sync_policy(policy)?;
+        }
+        self.wal_sync_policy = policy;
+        Ok(())
+    }
+
+    /// Returns the compression applied to WAL records.
+    #[must_use]
+    pub fn wal_compression(&self) -> WalCompression {
+        self.wal_compression
+    }
+
+    /// Compresses WAL records written from now on, in the current segment
+    /// and every later one. Large, repetitive values (JSON documents, say)
+    /// shrink the bytes written and synced; replay needs no setting.
+    pub fn set_wal_compression(&mut self, compression: WalCompression) {
+        if let Some(writer) = self.wal_writer.as_mut() {
+            writer.set_compression(compression);
+        }
+        self.wal_compression = compression;
+    }
+
     /// Returns the current L0 compaction trigger threshold.
     ///
     /// When the number of L0 SSTables reaches this value after a flush,
     /// compaction is triggered automatically. A value of 0 disables
-    /// auto-compaction.
+    /// auto-compaction of every level.
     #[must_use]
     pub fn l0_compaction_trigger(&self) -> usize {
         self.l0_compaction_trigger
@@ -300,23 +745,317 @@ impl Engine {
         self.l0_compaction_trigger = trigger;
     }
 
+    /// Returns the I/O rate limiter applied to flush and compaction writes.
+    #[must_use]
+    pub fn rate_limiter(&self) -> Option<&Arc<RateLimiter>> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Sets (or with `None`, removes) the rate limiter throttling SSTable
+    /// writes from flushes and compactions.
+    ///
+    /// The limiter is shared: keep a clone of the `Arc` to change the budget
+    /// at runtime with [`RateLimiter::set_bytes_per_sec`], or pass the same
+    /// limiter to several engines to cap their combined throughput. WAL
+    /// appends bypass the limiter so foreground writes are never throttled
+    /// (though they may still wait on a throttled inline flush).
+    pub fn set_rate_limiter(&mut self, limiter: Option<Arc<RateLimiter>>) {
+        self.rate_limiter = limiter;
+    }
+
+    /// Returns the keys files are encrypted with, or `None` if the engine
+    /// was not opened with [`Engine::open_encrypted`] (or
+    /// [`Engine::open_read_only_encrypted`]).
+    #[must_use]
+    pub fn key_provider(&self) -> Option<&Arc<dyn KeyProvider>> {
+        self.key_provider.as_ref()
+    }
+
+    /// Options for SSTables written by flush and compaction.
+    pub(crate) fn sst_write_options(&self) -> SSTableWriteOptions {
+        SSTableWriteOptions {
+            rate_limiter: self.rate_limiter.clone(),
+            key_provider: self.key_provider.clone(),
+        }
+    }
+
+    /// Returns the strategy that picks automatic compactions.
+    #[must_use]
+    pub fn compaction_strategy(&self) -> &Arc<dyn CompactionStrategy> {
+        &self.compaction_strategy
+    }
+
+    /// Sets the strategy that picks automatic compactions (default:
+    /// [`LeveledStrategy`]). Takes effect from the next flush; files already
+    /// on disk are never reshuffled just because the strategy changed.
+    ///
+    /// Every job the strategy returns is validated before it runs: its
+    /// inputs must exist, data may not move to a shallower level, an L0
+    /// output must replace consecutive L0 files, inputs moving down may not
+    /// pass an older file overlapping them, and the output may not overlap
+    /// the rest of its level. A deletion job (see [`FifoStrategy`]) only
+    /// needs existing inputs. An invalid job fails the flush that triggered
+    /// it.
+    pub fn set_compaction_strategy(&mut self, strategy: Arc<dyn CompactionStrategy>) {
+        self.compaction_strategy = strategy;
+    }
+
+    /// Read-only view of the levels for the compaction strategy.
+    pub(crate) fn level_state(&self) -> LevelState<'_> {
+        LevelState {
+            levels: &self.levels,
+            compact_cursors: &self.compact_cursors,
+            max_levels: self.max_levels,
+            l0_compaction_trigger: self.l0_compaction_trigger,
+            level_base_bytes: self.level_base_bytes,
+            level_size_multiplier: self.level_size_multiplier,
+        }
+    }
+
+    /// Returns the number of levels leveled compaction may use.
+    #[must_use]
+    pub fn max_levels(&self) -> usize {
+        self.max_levels
+    }
+
+    /// Sets the number of levels (L0..L{n-1}) leveled compaction may use.
+    /// Clamped to at least 2 and at most 64, the deepest level a manifest
+    /// can record.
+    ///
+    /// Lowering it never discards data: SSTables already in deeper levels
+    /// stay readable, they just stop being compacted further.
+    pub fn set_max_levels(&mut self, max_levels: usize) {
+        self.max_levels = max_levels.clamp(2, manifest::MAX_SUPPORTED_LEVELS as usize);
+        let in_use = self
+            .levels
+            .iter()
+            .rposition(|files| !files.is_empty())
+            .map_or(0, |level| level + 1);
+        self.levels
+            .resize_with(self.max_levels.max(in_use), Vec::new);
+    }
+
+    /// Returns the size past which the manifest log is rolled over to a
+    /// snapshot.
+    #[must_use]
+    pub fn max_manifest_file_size(&self) -> u64 {
+        self.manifest.max_file_size()
+    }
+
+    /// Sets the size (at least 1 byte) past which the next manifest update
+    /// writes the full state to a new `MANIFEST-NNNNNN` log instead of
+    /// appending to the current one. Defaults to 4 MiB.
+    pub fn set_max_manifest_file_size(&mut self, bytes: u64) {
+        self.manifest.set_max_file_size(bytes);
+    }
+
+    /// Returns the target total size of L1 in bytes.
+    #[must_use]
+    pub fn level_base_bytes(&self) -> u64 {
+        self.level_base_bytes
+    }
+
+    /// Sets the target total size of L1 in bytes (at least 1).
+    pub fn set_level_base_bytes(&mut self, bytes: u64) {
+        self.level_base_bytes = bytes.max(1);
+    }
+
+    /// Returns the ratio between the target sizes of consecutive levels.
+    #[must_use]
+    pub fn level_size_multiplier(&self) -> u64 {
+        self.level_size_multiplier
+    }
+
+    /// Sets the ratio between the target sizes of consecutive levels
+    /// (at least 1).
+    pub fn set_level_size_multiplier(&mut self, multiplier: u64) {
+        self.level_size_multiplier = multiplier.max(1);
+    }
+
+    /// Returns the size at which compaction outputs are split.
+    #[must_use]
+    pub fn target_file_size_bytes(&self) -> u64 {
+        self.target_file_size_bytes
+    }
+
+    /// Sets the size in bytes at which a compaction output below L0 is cut
+    /// and continued in a new SSTable (at least 1). Files are only cut
+    /// between keys, so each covers a disjoint key range and may overshoot
+    /// the target by one record. L0 outputs are never split.
+    pub fn set_target_file_size_bytes(&mut self, bytes: u64) {
+        self.target_file_size_bytes = bytes.max(1);
+    }
+
+    /// Returns the maximum number of key ranges a compaction is split into.
+    #[must_use]
+    pub fn max_subcompactions(&self) -> usize {
+        self.max_subcompactions
+    }
+
+    /// Sets the maximum number of disjoint key ranges (at least 1, the
+    /// default) a compaction with an output below L0 is split into. Each
+    /// range is merged and written on its own thread; the job's manifest
+    /// update still happens once, after every range succeeds.
+    ///
+    /// Compactions into L0 and trivial moves are never split. Ranges are
+    /// cut at keys sampled from the inputs, so small jobs may use fewer
+    /// ranges than allowed.
+    pub fn set_max_subcompactions(&mut self, n: usize) {
+        self.max_subcompactions = n.max(1);
+    }
+
+    /// Returns the tombstone ratio that triggers a compaction (`0` = off).
+    #[must_use]
+    pub fn tombstone_compaction_ratio(&self) -> f64 {
+        self.tombstone_compaction_ratio
+    }
+
+    /// Compacts any SSTable whose tombstones make up at least `ratio`
+    /// (clamped to `0.0..=1.0`) of its entries, even if the compaction
+    /// strategy would leave it alone, e.g. because L0 is below its trigger.
+    /// `0` (the default) turns this off.
+    ///
+    /// Checked after every flush, after the strategy's own compactions, so
+    /// it is off too while auto-compaction is disabled. The file is pushed
+    /// down towards the bottommost level, or rewritten in place if already
+    /// there, which drops its tombstones and makes scans over the deleted
+    /// range cheap again.
+    pub fn set_tombstone_compaction_ratio(&mut self, ratio: f64) {
+        self.tombstone_compaction_ratio = if ratio.is_nan() {
+            0.0
+        } else {
+            ratio.clamp(0.0, 1.0)
+        };
+    }
+
+    /// Returns the age after which an SSTable holding tombstones is
+    /// compacted, if set.
+    #[must_use]
+    pub fn tombstone_compaction_age(&self) -> Option<Duration> {
+        self.tombstone_compaction_age
+    }
+
+    /// Compacts any SSTable that holds at least one tombstone and was
+    /// created more than `age` ago (`None`, the default, turns this off),
+    /// like [`set_tombstone_compaction_ratio`](Engine::set_tombstone_compaction_ratio)
+    /// does for delete-heavy files. Bounds how long deleted data keeps
+    /// taking up space in rarely compacted key ranges.
+    pub fn set_tombstone_compaction_age(&mut self, age: Option<Duration>) {
+        self.tombstone_compaction_age = age;
+    }
+
+    /// Returns the target total size in bytes of `level` (1 or deeper):
+    /// `level_base_bytes * level_size_multiplier^(level - 1)`. L0 is
+    /// bounded by file count instead and returns `0`.
+    #[must_use]
+    pub fn level_target_bytes(&self, level: usize) -> u64 {
+        self.level_state().level_target_bytes(level)
+    }
+
     /// Returns the total number of SSTables across all levels.
     #[must_use]
     pub fn sstable_count(&self) -> usize {
-        self.l0_sstables.len() + self.l1_sstables.len()
+        self.levels.iter().map(Vec::len).sum()
     }
 
     /// Returns the number of L0 SSTables (from memtable flushes).
     #[must_use]
     pub fn l0_sstable_count(&self) -> usize {
-        self.l0_sstables.len()
+        self.level_sstable_count(0)
     }
 
     /// Returns the number of L1 SSTables (from compaction).
     #[must_use]
     pub fn l1_sstable_count(&self) -> usize {
-        self.l1_sstables.len()
+        self.level_sstable_count(1)
+    }
+
+    /// Returns the number of SSTables in `level` (`0` for levels that do
+    /// not exist).
+    #[must_use]
+    pub fn level_sstable_count(&self, level: usize) -> usize {
+        self.levels.get(level).map_or(0, Vec::len)
+    }
+
+    /// Returns the number of SSTables in each level, L0 first.
+    #[must_use]
+    pub fn level_sstable_counts(&self) -> Vec<usize> {
+        self.levels.iter().map(Vec::len).collect()
     }
+
+    /// Returns the total size in bytes of the SSTables in `level`.
+    #[must_use]
+    pub fn level_size_bytes(&self, level: usize) -> u64 {
+        self.level_state().level_size_bytes(level)
+    }
+
+    /// Returns the filename for a newly allocated SSTable file number.
+    pub(crate) fn next_sst_name(&mut self) -> String {
+        sst_filename(self.manifest.allocate_file_number())
+    }
+
+    /// Records the current sequence number in the manifest and persists the
+    /// pending edits. Every flush and compaction saves through here, so the
+    /// manifest's last sequence number covers all data in its SSTables.
+    pub(crate) fn save_manifest(&mut self) -> Result<()> {
+        self.manifest.set_last_seq(self.seq)?;
+        self.manifest.save()
+    }
+}
+
+/// Returns the filename of WAL segment number `number`, e.g. `000041.log`.
+pub(crate) fn wal_filename(number: u64) -> String {
+    format!("{:06}.log", number)
+}
+
+/// Parses the segment number out of a name written by [`wal_filename`].
+pub(crate) fn parse_wal_number(name: &str) -> Option<u64> {
+    parse_file_number(name, ".log")
+}
+
+/// Returns the filename of SSTable number `number`, e.g. `000042.sst`.
+pub(crate) fn sst_filename(number: u64) -> String {
+    format!("{:06}.sst", number)
+}
+
+/// Parses the file number out of a name written by [`sst_filename`].
+/// Returns `None` for any other name, including the `sst-{seq}-{ts}.sst`
+/// names of SSTables written before file numbers were allocated.
+pub(crate) fn parse_sst_number(name: &str) -> Option<u64> {
+    parse_file_number(name, ".sst")
+}
+
+/// Returns `true` if `name` is an SSTable name the engine writes: a
+/// numbered [`sst_filename`] or a legacy `sst-{seq}-{ts}.sst` name.
+pub(crate) fn is_sst_filename(name: &str) -> bool {
+    if parse_sst_number(name).is_some() {
+        return true;
+    }
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    name.strip_prefix("sst-")
+        .and_then(|rest| rest.strip_suffix(".sst"))
+        .and_then(|stem| stem.split_once('-'))
+        .is_some_and(|(seq, ts)| all_digits(seq) && all_digits(ts))
+}
+
+/// Parses `{digits}{suffix}` file names.
+fn parse_file_number(name: &str, suffix: &str) -> Option<u64> {
+    let stem = name.strip_suffix(suffix)?;
+    if stem.is_empty() || !stem.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    stem.parse().ok()
+}
+
+/// Extends `levels` with empty levels until it has at least `min` entries.
+pub(crate) fn pad_levels(
+    mut levels: Vec<Vec<SSTableReader>>,
+    min: usize,
+) -> Vec<Vec<SSTableReader>> {
+    if levels.len() < min {
+        levels.resize_with(min, Vec::new);
+    }
+    levels
 }
 
 /// Best-effort flush on drop.
@@ -325,9 +1064,11 @@ impl Engine {
 /// to an SSTable so it is not lost. Errors during the flush are silently
 /// ignored because Drop cannot propagate errors — the data is still safe in
 /// the WAL and will be recovered on the next startup.
+///
+/// Read-only engines never flush: the memtable only mirrors the WAL on disk.
 impl Drop for Engine {
     fn drop(&mut self) {
-        if !self.mem.is_empty() {
+        if !self.read_only && !self.mem.is_empty() {
             let _ = self.flush();
         }
     }
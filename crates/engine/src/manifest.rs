@@ -1,224 +1,1023 @@
 /// # Manifest - SSTable Level Metadata
 ///
-/// Tracks which SSTable files belong to which level (L0 or L1) so that the
-/// engine can correctly reconstruct its state after a restart.
+/// Tracks which SSTable files belong to which level (L0 through Ln), plus
+/// the per-file metadata and engine counters needed to reconstruct the
+/// engine's state after a restart.
 ///
 /// ## File Format
 ///
-/// The manifest is a simple text-based file with one SSTable entry per line:
+/// The manifest is an append-only log of **version edits** stored in
+/// `MANIFEST-NNNNNN`. Each record is one atomic batch of edits:
 ///
 /// ```text
-/// L0:sst-000000000000000005-1708600000000.sst
-/// L0:sst-000000000000000003-1708599999000.sst
-/// L1:sst-000000000000000010-1708600001000.sst
+/// [len: u32][crc32: u32][body: len bytes]
+/// body = edit_count(u32) | edit*
+/// edit = tag(u8) | payload
+///   1 AddFile        level(u32) | index(u32) | filename | file_size(u64)
+///                    | smallest_key | largest_key | min_seq(u64) | max_seq(u64)
+///   2 DeleteFile     level(u32) | filename
+///   3 NextFileNumber u64
+///   4 LastSeq        u64
+///   5 LogNumber      u64
 /// ```
 ///
-/// Lines starting with `#` are comments. Empty lines are ignored.
+/// Strings and keys are `len(u32) | bytes`; all integers are little-endian.
+/// The CRC32 covers the body. The state is rebuilt by replaying every edit
+/// in order, starting from an empty manifest.
 ///
-/// ## Crash Safety
+/// A `CURRENT` file holds the name of the live log (e.g. `MANIFEST-000003`).
+///
+/// ## Encryption
 ///
-/// The manifest is rewritten atomically: write to a `.tmp` file, fsync, then
-/// rename over the existing manifest. This ensures the manifest is never
-/// partially written.
+/// When loaded with a [`KeyProvider`], new logs are encrypted: they start
+/// with the magic `RMANENC1` and the encryption header, and every body is
+/// sealed (bound to its offset in the log) before framing, so the CRC
+/// covers the sealed bytes. A plain log, or one under a key that is no
+/// longer current, is replaced by an encrypted snapshot on the next save.
 ///
-/// ## Design Rationale
+/// ## Snapshots
 ///
-/// A text format was chosen over binary for debuggability — operators can
-/// inspect the manifest with any text editor. The file is small (one line per
-/// SSTable) so parsing overhead is negligible.
-use anyhow::{bail, Context, Result};
+/// Once the log grows past [`DEFAULT_MAX_MANIFEST_FILE_SIZE`] (configurable
+/// with [`Manifest::set_max_file_size`]), the next save rolls over: the full
+/// state is written as a single snapshot record to a new log, `CURRENT` is
+/// atomically switched to it (temp file + rename), and the old log is
+/// deleted.
+///
+/// ## Crash Safety
+///
+/// Each save appends one record and fsyncs it. A crash mid-append leaves a
+/// torn last record, which recovery ignores (the save it belonged to never
+/// returned); the next save then rolls over to a fresh log. A corrupt
+/// record followed by valid data is not a torn write and fails the load.
+/// A failed save also rolls back the in-memory edits it carried, so that
+/// snapshot never records a change its caller gave up on, and a failed
+/// append is cut off the log again, so a restart does not replay it either.
+/// If that cut fails too, every later save fails until the manifest is
+/// reloaded.
+///
+/// Directories from before the binary format hold a text `MANIFEST` with one
+/// `L<n>:<filename>` line per SSTable. It is still loaded, and is replaced
+/// by a snapshot (then deleted) on the first save.
+use anyhow::{anyhow, bail, Context, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crc32fast::Hasher as Crc32;
+use encryption::{FileCipher, KeyProvider};
+use sstable::SSTableReader;
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Name of the text manifest used before the version-edit log.
+pub const LEGACY_MANIFEST_FILENAME: &str = "MANIFEST";
+
+/// Name of the file pointing at the live manifest log.
+pub const CURRENT_FILENAME: &str = "CURRENT";
+
+/// Temporary file used during atomic `CURRENT` writes.
+const CURRENT_TMP_FILENAME: &str = "CURRENT.tmp";
+
+/// Temporary file left behind by the text manifest's atomic writes.
+const LEGACY_MANIFEST_TMP_FILENAME: &str = "MANIFEST.tmp";
+
+/// Prefix of the manifest log filenames (`MANIFEST-000001`, ...).
+const MANIFEST_LOG_PREFIX: &str = "MANIFEST-";
+
+/// Size past which the next save rolls the log over to a snapshot.
+pub const DEFAULT_MAX_MANIFEST_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Number of levels a manifest may reference (`L0` through `L63`). Guards
+/// against allocating absurd level vectors from a corrupt manifest.
+pub const MAX_SUPPORTED_LEVELS: u32 = 64;
 
-/// Name of the manifest file within the SST directory.
-pub const MANIFEST_FILENAME: &str = "MANIFEST";
+/// Magic bytes at the start of an encrypted log. A plain log cannot start
+/// with them: as a record length they exceed any record.
+const ENCRYPTED_MAGIC: [u8; 8] = *b"RMANENC1";
 
-/// Temporary file used during atomic manifest writes.
-const MANIFEST_TMP_FILENAME: &str = "MANIFEST.tmp";
+/// Size of the header of an encrypted log: magic plus encryption header.
+const ENCRYPTED_HEADER_BYTES: usize = ENCRYPTED_MAGIC.len() + encryption::HEADER_LEN;
 
-/// Represents the level assignment for a single SSTable file.
+/// Bytes of the `[len][crc32]` header in front of every record.
+const RECORD_HEADER_BYTES: usize = 8;
+
+/// How often `load_or_create` re-reads `CURRENT` when the log it names was
+/// rolled over (and deleted) by another process between the two reads.
+const LOAD_ATTEMPTS: usize = 3;
+
+const TAG_ADD_FILE: u8 = 1;
+const TAG_DELETE_FILE: u8 = 2;
+const TAG_NEXT_FILE_NUMBER: u8 = 3;
+const TAG_LAST_SEQ: u8 = 4;
+const TAG_LOG_NUMBER: u8 = 5;
+
+/// The level assignment and metadata of a single SSTable file.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SstMeta {
     /// The SSTable filename (not the full path — just the basename).
     pub filename: String,
-    /// The level this SSTable belongs to (0 = L0, 1 = L1).
+    /// The level this SSTable belongs to (0 = L0, 1 = L1, ...).
     pub level: u32,
+    /// Size of the file in bytes. `0` if unknown (text manifests).
+    pub file_size: u64,
+    /// Smallest key in the file.
+    pub smallest_key: Vec<u8>,
+    /// Largest key in the file.
+    pub largest_key: Vec<u8>,
+    /// Smallest sequence number of any entry in the file.
+    pub min_seq: u64,
+    /// Largest sequence number of any entry in the file.
+    pub max_seq: u64,
+}
+
+impl SstMeta {
+    /// Creates an entry with no metadata beyond its level.
+    pub fn new(filename: impl Into<String>, level: u32) -> Self {
+        Self {
+            filename: filename.into(),
+            level,
+            file_size: 0,
+            smallest_key: Vec::new(),
+            largest_key: Vec::new(),
+            min_seq: 0,
+            max_seq: 0,
+        }
+    }
+
+    /// Describes `reader` at `level`. The footer has no minimum sequence
+    /// number, so the writer of the file supplies it.
+    pub fn from_reader(reader: &SSTableReader, level: u32, min_seq: u64) -> Self {
+        Self {
+            filename: reader
+                .path()
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            level,
+            file_size: reader.file_size(),
+            smallest_key: reader.smallest_key().unwrap_or_default().to_vec(),
+            largest_key: reader.largest_key().unwrap_or_default().to_vec(),
+            min_seq,
+            max_seq: reader.max_seq().unwrap_or(0),
+        }
+    }
+
+    /// Returns `true` if the entry carries file metadata (it was not loaded
+    /// from a text manifest or created with [`SstMeta::new`]).
+    pub fn has_metadata(&self) -> bool {
+        self.file_size > 0
+    }
+}
+
+/// One change to the manifest state, as recorded in the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionEdit {
+    /// Adds a file as the `index`-th entry of its level (clamped to the end
+    /// of the level).
+    AddFile { index: u32, meta: SstMeta },
+    /// Removes a file.
+    DeleteFile { level: u32, filename: String },
+    /// Sets the next file number to allocate.
+    NextFileNumber(u64),
+    /// Sets the last sequence number persisted in SSTables.
+    LastSeq(u64),
+    /// Sets the number of the oldest WAL still needed for recovery.
+    LogNumber(u64),
 }
 
 /// In-memory representation of the manifest.
 #[derive(Debug, Clone)]
 pub struct Manifest {
-    /// Path to the manifest file on disk.
-    path: PathBuf,
-    /// All SSTable entries, in the order they appear in the file.
+    /// Directory holding `CURRENT` and the manifest logs.
+    dir: PathBuf,
+    /// Number of the live `MANIFEST-NNNNNN` log, or `None` if there is none
+    /// yet (fresh or text-manifest directory).
+    manifest_number: Option<u64>,
+    /// Bytes of valid records in the live log.
+    log_size: u64,
+    /// Set when the next save must start a new log rather than append: the
+    /// state came from a text manifest, the log has a torn tail, or an
+    /// append failed midway.
+    needs_snapshot: bool,
+    /// Size past which the next save rolls over to a snapshot.
+    max_file_size: u64,
+    /// Edits made since the last save.
+    pending: Vec<VersionEdit>,
+    /// Keys for encrypting new logs and reading encrypted ones.
+    keys: Option<Arc<dyn KeyProvider>>,
+    /// Cipher of the live log, if it is encrypted.
+    cipher: Option<FileCipher>,
+    /// All SSTable entries, newest first within each level.
     pub entries: Vec<SstMeta>,
+    next_file_number: u64,
+    last_seq: u64,
+    log_number: u64,
+    /// `entries` as of the last load or successful save; restored when a
+    /// save fails.
+    saved_entries: Vec<SstMeta>,
+    /// `log_number` as of the last load or successful save.
+    saved_log_number: u64,
+    /// Set when a failed append could not be cut off the log: the log may
+    /// hold a record whose save failed, so no later save may succeed.
+    poisoned: bool,
+    /// Failure injected into the next append.
+    #[cfg(test)]
+    pub(crate) append_fault: Option<AppendFault>,
+}
+
+/// Failures tests inject into an append.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AppendFault {
+    /// The record is written in full, but syncing it fails.
+    Sync,
+    /// As `Sync`, and cutting the record off the log fails too.
+    SyncAndTruncate,
 }
 
 impl Manifest {
-    /// Loads an existing manifest from `sst_dir/MANIFEST`, or creates an
-    /// empty one if the file does not exist.
+    /// Loads the manifest named by `sst_dir/CURRENT`, falls back to a text
+    /// `sst_dir/MANIFEST`, or creates an empty one if neither exists.
+    ///
+    /// Nothing is written: a new log is only created by [`save`](Self::save).
     ///
     /// # Errors
     ///
-    /// Returns an error if the manifest file exists but cannot be parsed.
+    /// Returns an error if `CURRENT` is malformed or names a missing log, if
+    /// a record other than the last one is corrupt, or if a text manifest
+    /// cannot be parsed.
+    #[cfg(test)]
     pub fn load_or_create(sst_dir: &Path) -> Result<Self> {
-        let path = sst_dir.join(MANIFEST_FILENAME);
-
-        if path.exists() {
-            let file = File::open(&path)
-                .with_context(|| format!("failed to open manifest at {}", path.display()))?;
-            let reader = BufReader::new(file);
-            let mut entries = Vec::new();
-
-            for (line_num, line) in reader.lines().enumerate() {
-                let line =
-                    line.with_context(|| format!("failed to read manifest line {}", line_num + 1))?;
-                let trimmed = line.trim();
-
-                // Skip empty lines and comments.
-                if trimmed.is_empty() || trimmed.starts_with('#') {
-                    continue;
+        Self::load_with_keys(sst_dir, None)
+    }
+
+    /// Like [`load_or_create`](Self::load_or_create), but reads an
+    /// encrypted log with `keys` and, if given keys, encrypts the logs it
+    /// writes with their current key.
+    ///
+    /// # Errors
+    ///
+    /// As [`load_or_create`](Self::load_or_create), plus an
+    /// [`EncryptionError`](encryption::EncryptionError) if the live log is
+    /// encrypted and its key is missing or wrong.
+    pub fn load_with_keys(sst_dir: &Path, keys: Option<Arc<dyn KeyProvider>>) -> Result<Self> {
+        let mut manifest = Self {
+            dir: sst_dir.to_path_buf(),
+            manifest_number: None,
+            log_size: 0,
+            needs_snapshot: false,
+            max_file_size: DEFAULT_MAX_MANIFEST_FILE_SIZE,
+            pending: Vec::new(),
+            keys,
+            cipher: None,
+            entries: Vec::new(),
+            next_file_number: 0,
+            last_seq: 0,
+            log_number: 0,
+            saved_entries: Vec::new(),
+            saved_log_number: 0,
+            poisoned: false,
+            #[cfg(test)]
+            append_fault: None,
+        };
+
+        for attempt in 1..=LOAD_ATTEMPTS {
+            let Some(number) = read_current(sst_dir)? else {
+                let legacy = sst_dir.join(LEGACY_MANIFEST_FILENAME);
+                if legacy.exists() {
+                    manifest.entries = parse_legacy(&legacy)?;
+                    manifest.needs_snapshot = true;
+                }
+                manifest.mark_saved();
+                return Ok(manifest);
+            };
+            let path = sst_dir.join(log_filename(number));
+            let data = match fs::read(&path) {
+                Ok(data) => data,
+                // Rolled over by another process since we read CURRENT.
+                Err(e) if e.kind() == ErrorKind::NotFound && attempt < LOAD_ATTEMPTS => continue,
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("failed to read manifest {}", path.display()))
                 }
+            };
+            manifest.replay(&data)?;
+            manifest.manifest_number = Some(number);
+            if let Some(keys) = &manifest.keys {
+                let key_id = manifest.cipher.as_ref().map(FileCipher::key_id);
+                if key_id != Some(keys.current_key_id()) {
+                    manifest.needs_snapshot = true;
+                }
+            }
+            manifest.mark_saved();
+            return Ok(manifest);
+        }
+        unreachable!("the last attempt always returns")
+    }
 
-                // Expected format: "<level>:<filename>"
-                let (level_str, filename) = trimmed.split_once(':').ok_or_else(|| {
-                    anyhow::anyhow!(
-                        "manifest line {}: invalid format (expected 'L<n>:<filename>'): {}",
-                        line_num + 1,
-                        trimmed
-                    )
-                })?;
-
-                let level = match level_str {
-                    "L0" => 0,
-                    "L1" => 1,
-                    other => bail!(
-                        "manifest line {}: unknown level '{}' (expected L0 or L1)",
-                        line_num + 1,
-                        other
-                    ),
-                };
+    /// Applies every record of a log to this (empty) manifest. A torn last
+    /// record is ignored and flags the log for a rollover on the next save.
+    fn replay(&mut self, data: &[u8]) -> Result<()> {
+        let mut pos = 0usize;
+        if data.starts_with(&ENCRYPTED_MAGIC) {
+            let header = data
+                .get(ENCRYPTED_MAGIC.len()..ENCRYPTED_HEADER_BYTES)
+                .ok_or_else(|| anyhow!("manifest encryption header is truncated"))?;
+            self.cipher = Some(FileCipher::open(header, self.keys.as_deref())?);
+            pos = ENCRYPTED_HEADER_BYTES;
+        }
+        while pos < data.len() {
+            let rest = &data[pos..];
+            if rest.len() < RECORD_HEADER_BYTES {
+                self.needs_snapshot = true;
+                break;
+            }
+            let len = u32::from_le_bytes(rest[0..4].try_into()?) as usize;
+            let crc = u32::from_le_bytes(rest[4..8].try_into()?);
+            let Some(body) = rest.get(RECORD_HEADER_BYTES..RECORD_HEADER_BYTES + len) else {
+                self.needs_snapshot = true;
+                break;
+            };
+            let end = pos + RECORD_HEADER_BYTES + len;
+            if crc32(body) != crc {
+                if end == data.len() {
+                    self.needs_snapshot = true;
+                    break;
+                }
+                bail!(
+                    "manifest record at offset {} is corrupt (crc mismatch)",
+                    pos
+                );
+            }
+            let body = match &self.cipher {
+                Some(cipher) => cipher
+                    .open_sealed(&(pos as u64).to_le_bytes(), body)
+                    .with_context(|| {
+                        format!("manifest record at offset {} fails authentication", pos)
+                    })?,
+                None => body.to_vec(),
+            };
+            let edits = decode_edits(&body)
+                .with_context(|| format!("manifest record at offset {} is malformed", pos))?;
+            for edit in edits {
+                self.apply(edit)
+                    .with_context(|| format!("manifest record at offset {} is invalid", pos))?;
+            }
+            pos = end;
+        }
+        self.log_size = pos as u64;
+        Ok(())
+    }
 
-                entries.push(SstMeta {
-                    filename: filename.to_string(),
-                    level,
-                });
+    /// Applies one edit to the in-memory state.
+    fn apply(&mut self, edit: VersionEdit) -> Result<()> {
+        match edit {
+            VersionEdit::AddFile { index, meta } => self.insert_at(meta, index as usize),
+            VersionEdit::DeleteFile { filename, .. } => {
+                self.entries.retain(|e| e.filename != filename)
+            }
+            VersionEdit::NextFileNumber(n) => self.next_file_number = n,
+            VersionEdit::LastSeq(seq) => {
+                if seq < self.last_seq {
+                    bail!(
+                        "last sequence number goes backwards ({} -> {})",
+                        self.last_seq,
+                        seq
+                    );
+                }
+                self.last_seq = seq;
             }
+            VersionEdit::LogNumber(n) => self.log_number = n,
+        }
+        Ok(())
+    }
 
-            Ok(Self { path, entries })
-        } else {
-            Ok(Self {
-                path,
-                entries: Vec::new(),
-            })
+    /// Persists the edits made since the last save.
+    ///
+    /// The edits are appended to the live log as one fsynced record. If
+    /// there is no log yet, the state was loaded from a text manifest or a
+    /// torn log, or the log would grow past its size limit, a snapshot of
+    /// the full state is written to a new log instead and `CURRENT` is
+    /// switched to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error on I/O failure. The file additions and deletions
+    /// and the log number recorded since the last save are then rolled
+    /// back, so the in-memory state again matches the manifest on disk;
+    /// allocated file numbers and the last sequence number are kept (they
+    /// only move forward). A failed append is cut off the log and never
+    /// followed by another append: the next save writes a snapshot. If the
+    /// cut fails, the record may be replayed on the next load, so this and
+    /// every later save fail (see [`ensure_consistent`](Self::ensure_consistent)).
+    pub fn save(&mut self) -> Result<()> {
+        let saved = self.write_pending();
+        match saved {
+            Ok(()) => self.mark_saved(),
+            Err(_) => self.roll_back(),
         }
+        saved
+    }
+
+    /// Records the current state as the one on disk.
+    fn mark_saved(&mut self) {
+        self.saved_entries = self.entries.clone();
+        self.saved_log_number = self.log_number;
     }
 
-    /// Persists the current manifest state to disk.
+    /// Discards the edits made since the last save, except for the
+    /// counters, which stay pending for the next one.
+    fn roll_back(&mut self) {
+        self.entries = self.saved_entries.clone();
+        self.log_number = self.saved_log_number;
+        self.pending = vec![
+            VersionEdit::NextFileNumber(self.next_file_number),
+            VersionEdit::LastSeq(self.last_seq),
+        ];
+    }
+
+    /// Body of [`save`](Self::save): appends the pending edits or writes a
+    /// snapshot, leaving the in-memory state alone on failure.
+    fn write_pending(&mut self) -> Result<()> {
+        self.ensure_consistent()?;
+        let Some(number) = self.manifest_number else {
+            return self.write_snapshot();
+        };
+        if self.needs_snapshot {
+            return self.write_snapshot();
+        }
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let body = seal(
+            self.cipher.as_ref(),
+            self.log_size,
+            encode_edits(&self.pending)?,
+        )?;
+        let record = frame(&body);
+        if self.log_size + record.len() as u64 > self.max_file_size {
+            return self.write_snapshot();
+        }
+
+        let path = self.dir.join(log_filename(number));
+        let opened = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed to open manifest {}", path.display()));
+        let mut f = match opened {
+            Ok(f) => f,
+            Err(e) => {
+                self.needs_snapshot = true;
+                return Err(e);
+            }
+        };
+        if let Err(e) = self.append(&mut f, &record) {
+            // The log may now end in a partial or even a complete record of
+            // a save that failed: cut it off.
+            self.needs_snapshot = true;
+            if self.truncate(&f).is_err() {
+                self.poisoned = true;
+            }
+            return Err(e.into());
+        }
+        self.log_size += record.len() as u64;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Appends `record` to the live log `f` and syncs it.
+    fn append(&mut self, f: &mut File, record: &[u8]) -> std::io::Result<()> {
+        f.write_all(record)?;
+        #[cfg(test)]
+        if self.append_fault.is_some() {
+            return Err(std::io::Error::other("injected sync failure"));
+        }
+        f.sync_data()
+    }
+
+    /// Cuts the live log `f` back to its last successfully saved record.
+    fn truncate(&mut self, f: &File) -> std::io::Result<()> {
+        #[cfg(test)]
+        if self.append_fault.take() == Some(AppendFault::SyncAndTruncate) {
+            return Err(std::io::Error::other("injected truncate failure"));
+        }
+        f.set_len(self.log_size)?;
+        f.sync_data()
+    }
+
+    /// Returns an error if an earlier failed save could not be undone on
+    /// disk: the log may then hold edits the in-memory state does not, and
+    /// nothing may be saved (or deleted on the strength of the in-memory
+    /// state) until the manifest is reloaded.
     ///
-    /// On Unix-like systems this uses atomic rename (write to `.tmp`, fsync,
-    /// rename). On Windows, `rename` over an existing file can fail with
-    /// "Access is denied" if the target is still cached by the OS or antivirus,
-    /// so we fall back to a direct truncate-and-write strategy
-    /// which is still safe because the manifest is small and fsynced.
-    pub fn save(&self) -> Result<()> {
-        let tmp_path = self.path.with_file_name(MANIFEST_TMP_FILENAME);
-
-        // Write to a temp file first.
+    /// # Errors
+    ///
+    /// Returns an error if the manifest is in that state.
+    pub fn ensure_consistent(&self) -> Result<()> {
+        if self.poisoned {
+            bail!("manifest log holds a record of a failed save; reopen to recover");
+        }
+        Ok(())
+    }
+
+    /// Writes the full state to a new log, points `CURRENT` at it and
+    /// deletes the previous log (and any text manifest).
+    fn write_snapshot(&mut self) -> Result<()> {
+        let number = self.manifest_number.map_or(1, |n| n + 1);
+        let path = self.dir.join(log_filename(number));
+
+        let mut edits = vec![
+            VersionEdit::NextFileNumber(self.next_file_number),
+            VersionEdit::LastSeq(self.last_seq),
+            VersionEdit::LogNumber(self.log_number),
+        ];
+        edits.extend(self.entries.iter().map(|meta| VersionEdit::AddFile {
+            index: u32::MAX,
+            meta: meta.clone(),
+        }));
+        let mut header = Vec::new();
+        let mut cipher = None;
+        if let Some(keys) = &self.keys {
+            let (new_cipher, encryption_header) = FileCipher::create(keys.as_ref())?;
+            header.extend_from_slice(&ENCRYPTED_MAGIC);
+            header.extend_from_slice(&encryption_header);
+            cipher = Some(new_cipher);
+        }
+        let body = seal(cipher.as_ref(), header.len() as u64, encode_edits(&edits)?)?;
+        let record = frame(&body);
         {
             let mut f = OpenOptions::new()
                 .create(true)
                 .write(true)
                 .truncate(true)
-                .open(&tmp_path)
-                .with_context(|| {
-                    format!("failed to create manifest tmp at {}", tmp_path.display())
-                })?;
-
-            Self::write_manifest_contents(&mut f, &self.entries)?;
-            f.flush()?;
+                .open(&path)
+                .with_context(|| format!("failed to create manifest {}", path.display()))?;
+            f.write_all(&header)?;
+            f.write_all(&record)?;
             f.sync_all()?;
         }
+        self.write_current(number)?;
 
-        // Try atomic rename first. If it fails (common on Windows when the
-        // target file is still cached), fall back to direct overwrite.
-        if fs::rename(&tmp_path, &self.path).is_err() {
-            let mut f = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(&self.path)
-                .with_context(|| format!("failed to open manifest at {}", self.path.display()))?;
+        if let Some(old) = self.manifest_number {
+            let _ = fs::remove_file(self.dir.join(log_filename(old)));
+        }
+        let _ = fs::remove_file(self.dir.join(LEGACY_MANIFEST_FILENAME));
+        let _ = fs::remove_file(self.dir.join(LEGACY_MANIFEST_TMP_FILENAME));
 
-            Self::write_manifest_contents(&mut f, &self.entries)?;
-            f.flush()?;
-            f.sync_all()?;
+        self.manifest_number = Some(number);
+        self.cipher = cipher;
+        self.log_size = (header.len() + record.len()) as u64;
+        self.needs_snapshot = false;
+        self.pending.clear();
+        Ok(())
+    }
 
-            // Clean up the orphaned tmp file.
+    /// Atomically points `CURRENT` at log `number`.
+    ///
+    /// On Unix-like systems this uses atomic rename (write to `.tmp`, fsync,
+    /// rename, fsync the directory). On Windows, `rename` over an existing
+    /// file can fail with "Access is denied" if the target is still cached
+    /// by the OS or antivirus, so we fall back to a direct truncate-and-write
+    /// which is still safe because the file is tiny and fsynced.
+    fn write_current(&self, number: u64) -> Result<()> {
+        let contents = format!("{}\n", log_filename(number));
+        let tmp_path = self.dir.join(CURRENT_TMP_FILENAME);
+        let path = self.dir.join(CURRENT_FILENAME);
+        write_synced(&tmp_path, contents.as_bytes())?;
+        if fs::rename(&tmp_path, &path).is_err() {
+            write_synced(&path, contents.as_bytes())?;
             let _ = fs::remove_file(&tmp_path);
         }
-
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
         Ok(())
     }
 
-    /// Writes the manifest header and entries to a writer.
-    fn write_manifest_contents(f: &mut File, entries: &[SstMeta]) -> Result<()> {
-        writeln!(f, "# RiptideKV SSTable Manifest")?;
-        writeln!(f, "# Format: <level>:<filename>")?;
-        for entry in entries {
-            let level_str = match entry.level {
-                0 => "L0",
-                1 => "L1",
-                other => panic!("invalid level {}", other),
-            };
-            writeln!(f, "{}:{}", level_str, entry.filename)?;
+    /// Returns `true` if there is no manifest on disk yet: neither a log
+    /// named by `CURRENT` nor a text manifest.
+    pub fn is_new(&self) -> bool {
+        self.manifest_number.is_none() && !self.needs_snapshot
+    }
+
+    /// Returns `true` if `name` is a manifest file this manifest still
+    /// reads: `CURRENT` and the live log, or the text manifest it was
+    /// loaded from.
+    pub fn is_live_file(&self, name: &str) -> bool {
+        match self.manifest_number {
+            Some(number) => name == CURRENT_FILENAME || name == log_filename(number),
+            None => name == LEGACY_MANIFEST_FILENAME && self.needs_snapshot,
+        }
+    }
+
+    /// Returns the size limit past which the log is rolled over.
+    #[must_use]
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size
+    }
+
+    /// Sets the size limit past which the log is rolled over on the next
+    /// save. Clamped to at least 1 byte (roll over on every save).
+    pub fn set_max_file_size(&mut self, bytes: u64) {
+        self.max_file_size = bytes.max(1);
+    }
+
+    /// Returns the last sequence number recorded in the manifest.
+    #[must_use]
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Records the last sequence number handed out by the engine (does
+    /// **not** save to disk).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `seq` is below the recorded value: sequence
+    /// numbers never go backwards.
+    pub fn set_last_seq(&mut self, seq: u64) -> Result<()> {
+        if seq < self.last_seq {
+            bail!(
+                "last sequence number would go backwards ({} -> {})",
+                self.last_seq,
+                seq
+            );
+        }
+        self.last_seq = seq;
+        match self.pending.last_mut() {
+            Some(VersionEdit::LastSeq(last)) => *last = seq,
+            _ => self.pending.push(VersionEdit::LastSeq(seq)),
         }
         Ok(())
     }
 
-    /// Returns the filenames of all L0 SSTables, in manifest order (newest first).
-    pub fn l0_filenames(&self) -> Vec<&str> {
-        self.entries
-            .iter()
-            .filter(|e| e.level == 0)
-            .map(|e| e.filename.as_str())
-            .collect()
+    /// Returns the next file number recorded in the manifest.
+    #[must_use]
+    pub fn next_file_number(&self) -> u64 {
+        self.next_file_number
     }
 
-    /// Returns the filenames of all L1 SSTables, in manifest order (newest first).
-    pub fn l1_filenames(&self) -> Vec<&str> {
+    /// Records the next file number to allocate (does **not** save to disk).
+    pub fn set_next_file_number(&mut self, n: u64) {
+        self.next_file_number = n;
+        match self.pending.last_mut() {
+            Some(VersionEdit::NextFileNumber(last)) => *last = n,
+            _ => self.pending.push(VersionEdit::NextFileNumber(n)),
+        }
+    }
+
+    /// Returns a new file number and advances the next file number past it
+    /// (does **not** save to disk). Numbers start at 1 and are never handed
+    /// out twice once a later save has persisted the allocation.
+    pub fn allocate_file_number(&mut self) -> u64 {
+        let n = self.next_file_number.max(1);
+        self.set_next_file_number(n + 1);
+        n
+    }
+
+    /// Raises the next file number to at least `n`, e.g. past a number
+    /// found on disk or allocated concurrently (does **not** save to disk).
+    pub fn bump_next_file_number(&mut self, n: u64) {
+        if n > self.next_file_number {
+            self.set_next_file_number(n);
+        }
+    }
+
+    /// Returns the WAL number recorded in the manifest.
+    #[must_use]
+    pub fn log_number(&self) -> u64 {
+        self.log_number
+    }
+
+    /// Records the number of the oldest WAL still needed for recovery
+    /// (does **not** save to disk).
+    pub fn set_log_number(&mut self, n: u64) {
+        self.log_number = n;
+        self.pending.push(VersionEdit::LogNumber(n));
+    }
+
+    /// Returns the filenames of all SSTables in `level`, in manifest order
+    /// (newest first).
+    pub fn level_filenames(&self, level: u32) -> Vec<&str> {
         self.entries
             .iter()
-            .filter(|e| e.level == 1)
+            .filter(|e| e.level == level)
             .map(|e| e.filename.as_str())
             .collect()
     }
 
+    /// Returns the deepest level that holds at least one SSTable, or `None`
+    /// if the manifest is empty.
+    pub fn max_level(&self) -> Option<u32> {
+        self.entries.iter().map(|e| e.level).max()
+    }
+
+    /// Returns the entry for `filename`, if any.
+    pub fn file(&self, filename: &str) -> Option<&SstMeta> {
+        self.entries.iter().find(|e| e.filename == filename)
+    }
+
     /// Adds an SSTable entry to the manifest (does **not** save to disk).
     ///
-    /// New entries are inserted at the front (newest first) for the given level.
-    pub fn add(&mut self, filename: String, level: u32) {
+    /// New entries are inserted at the front (newest first) for their level.
+    pub fn add(&mut self, meta: SstMeta) {
         // Insert at the beginning of entries for this level to maintain
         // newest-first ordering within each level.
-        let insert_pos = self
+        self.add_at(meta, 0);
+    }
+
+    /// Adds an SSTable entry so that it becomes the `index`-th entry of its
+    /// level (clamped to the end of the level). Used to put a merged L0
+    /// file back in the place of its inputs.
+    pub fn add_at(&mut self, meta: SstMeta, index: usize) {
+        self.pending.push(VersionEdit::AddFile {
+            index: u32::try_from(index).unwrap_or(u32::MAX),
+            meta: meta.clone(),
+        });
+        self.insert_at(meta, index);
+    }
+
+    /// Inserts `meta` as the `index`-th entry of its level.
+    fn insert_at(&mut self, meta: SstMeta, index: usize) {
+        let mut in_level = self
             .entries
             .iter()
-            .position(|e| e.level == level)
-            .unwrap_or(self.entries.len());
-        self.entries.insert(insert_pos, SstMeta { filename, level });
+            .enumerate()
+            .filter(|(_, e)| e.level == meta.level)
+            .map(|(i, _)| i);
+        let insert_pos = match in_level.nth(index) {
+            Some(pos) => pos,
+            None => self
+                .entries
+                .iter()
+                .rposition(|e| e.level == meta.level)
+                .map_or(self.entries.len(), |pos| pos + 1),
+        };
+        self.entries.insert(insert_pos, meta);
     }
 
     /// Removes all entries matching the given filenames.
-    #[allow(dead_code)]
     pub fn remove_files(&mut self, filenames: &[&str]) {
+        for e in &self.entries {
+            if filenames.contains(&e.filename.as_str()) {
+                self.pending.push(VersionEdit::DeleteFile {
+                    level: e.level,
+                    filename: e.filename.clone(),
+                });
+            }
+        }
         self.entries
             .retain(|e| !filenames.contains(&e.filename.as_str()));
     }
 
-    /// Replaces all L0 and L1 entries with a single L1 entry (used after compaction).
-    pub fn replace_all_with_l1(&mut self, filename: String) {
-        self.entries.clear();
-        self.entries.push(SstMeta { filename, level: 1 });
+    /// Replaces the in-memory metadata of the entry for `meta.filename`,
+    /// keeping its level and position. Used to describe files loaded from a
+    /// text manifest; not recorded as an edit, as such a manifest is always
+    /// rewritten as a snapshot.
+    pub fn fill_metadata(&mut self, meta: SstMeta) {
+        for entries in [&mut self.entries, &mut self.saved_entries] {
+            if let Some(entry) = entries.iter_mut().find(|e| e.filename == meta.filename) {
+                *entry = SstMeta {
+                    level: entry.level,
+                    ..meta.clone()
+                };
+            }
+        }
+    }
+}
+
+/// Returns `true` if `name` is one of the files the manifest writes:
+/// `CURRENT`, a log, the text manifest, or their temp files.
+pub fn is_manifest_file(name: &str) -> bool {
+    let is_log = name
+        .strip_prefix(MANIFEST_LOG_PREFIX)
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+    is_log
+        || [
+            CURRENT_FILENAME,
+            CURRENT_TMP_FILENAME,
+            LEGACY_MANIFEST_FILENAME,
+            LEGACY_MANIFEST_TMP_FILENAME,
+        ]
+        .contains(&name)
+}
+
+/// Returns the filename of manifest log `number`.
+fn log_filename(number: u64) -> String {
+    format!("{}{:06}", MANIFEST_LOG_PREFIX, number)
+}
+
+/// Reads the log number named by `dir/CURRENT`, or `None` if there is no
+/// `CURRENT` file.
+fn read_current(dir: &Path) -> Result<Option<u64>> {
+    let path = dir.join(CURRENT_FILENAME);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    contents
+        .trim()
+        .strip_prefix(MANIFEST_LOG_PREFIX)
+        .and_then(|n| n.parse::<u64>().ok())
+        .map(Some)
+        .ok_or_else(|| anyhow!("{} is malformed: {:?}", path.display(), contents))
+}
+
+/// Writes `data` to `path`, replacing it, and fsyncs it.
+fn write_synced(path: &Path, data: &[u8]) -> Result<()> {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    f.write_all(data)?;
+    f.sync_all()?;
+    Ok(())
+}
+
+/// Parses a text manifest: one `L<n>:<filename>` line per SSTable, with
+/// `#` comments and blank lines ignored.
+fn parse_legacy(path: &Path) -> Result<Vec<SstMeta>> {
+    let file = File::open(path)
+        .with_context(|| format!("failed to open manifest at {}", path.display()))?;
+    let reader = BufReader::new(file);
+    let mut entries = Vec::new();
+
+    for (line_num, line) in reader.lines().enumerate() {
+        let line =
+            line.with_context(|| format!("failed to read manifest line {}", line_num + 1))?;
+        let trimmed = line.trim();
+
+        // Skip empty lines and comments.
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        // Expected format: "<level>:<filename>"
+        let (level_str, filename) = trimmed.split_once(':').ok_or_else(|| {
+            anyhow!(
+                "manifest line {}: invalid format (expected 'L<n>:<filename>'): {}",
+                line_num + 1,
+                trimmed
+            )
+        })?;
+
+        let level = match level_str
+            .strip_prefix('L')
+            .and_then(|n| n.parse::<u32>().ok())
+            .filter(|level| *level < MAX_SUPPORTED_LEVELS)
+        {
+            Some(level) => level,
+            None => bail!(
+                "manifest line {}: unknown level '{}' (expected L0..L{})",
+                line_num + 1,
+                level_str,
+                MAX_SUPPORTED_LEVELS - 1
+            ),
+        };
+
+        entries.push(SstMeta::new(filename, level));
+    }
+    Ok(entries)
+}
+
+/// Encrypts a record body written at `offset` in a log, if the log is
+/// encrypted.
+fn seal(cipher: Option<&FileCipher>, offset: u64, body: Vec<u8>) -> Result<Vec<u8>> {
+    match cipher {
+        Some(cipher) => Ok(cipher.seal(&offset.to_le_bytes(), &body)?),
+        None => Ok(body),
+    }
+}
+
+/// Wraps a record body in its `[len][crc32]` header.
+fn frame(body: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_BYTES + body.len());
+    record.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32(body).to_le_bytes());
+    record.extend_from_slice(body);
+    record
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut hasher = Crc32::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Serializes a batch of edits into a record body.
+pub(crate) fn encode_edits(edits: &[VersionEdit]) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    buf.write_u32::<LittleEndian>(edits.len() as u32)?;
+    for edit in edits {
+        match edit {
+            VersionEdit::AddFile { index, meta } => {
+                buf.write_u8(TAG_ADD_FILE)?;
+                buf.write_u32::<LittleEndian>(meta.level)?;
+                buf.write_u32::<LittleEndian>(*index)?;
+                write_bytes(&mut buf, meta.filename.as_bytes())?;
+                buf.write_u64::<LittleEndian>(meta.file_size)?;
+                write_bytes(&mut buf, &meta.smallest_key)?;
+                write_bytes(&mut buf, &meta.largest_key)?;
+                buf.write_u64::<LittleEndian>(meta.min_seq)?;
+                buf.write_u64::<LittleEndian>(meta.max_seq)?;
+            }
+            VersionEdit::DeleteFile { level, filename } => {
+                buf.write_u8(TAG_DELETE_FILE)?;
+                buf.write_u32::<LittleEndian>(*level)?;
+                write_bytes(&mut buf, filename.as_bytes())?;
+            }
+            VersionEdit::NextFileNumber(n) => {
+                buf.write_u8(TAG_NEXT_FILE_NUMBER)?;
+                buf.write_u64::<LittleEndian>(*n)?;
+            }
+            VersionEdit::LastSeq(seq) => {
+                buf.write_u8(TAG_LAST_SEQ)?;
+                buf.write_u64::<LittleEndian>(*seq)?;
+            }
+            VersionEdit::LogNumber(n) => {
+                buf.write_u8(TAG_LOG_NUMBER)?;
+                buf.write_u64::<LittleEndian>(*n)?;
+            }
+        }
+    }
+    Ok(buf)
+}
+
+/// Parses a record body written by [`encode_edits`].
+pub(crate) fn decode_edits(mut body: &[u8]) -> Result<Vec<VersionEdit>> {
+    let count = body.read_u32::<LittleEndian>()?;
+    let mut edits = Vec::new();
+    for _ in 0..count {
+        let edit = match body.read_u8()? {
+            TAG_ADD_FILE => {
+                let level = read_level(&mut body)?;
+                let index = body.read_u32::<LittleEndian>()?;
+                let filename = read_string(&mut body)?;
+                let file_size = body.read_u64::<LittleEndian>()?;
+                let smallest_key = read_bytes(&mut body)?;
+                let largest_key = read_bytes(&mut body)?;
+                let min_seq = body.read_u64::<LittleEndian>()?;
+                let max_seq = body.read_u64::<LittleEndian>()?;
+                VersionEdit::AddFile {
+                    index,
+                    meta: SstMeta {
+                        filename,
+                        level,
+                        file_size,
+                        smallest_key,
+                        largest_key,
+                        min_seq,
+                        max_seq,
+                    },
+                }
+            }
+            TAG_DELETE_FILE => VersionEdit::DeleteFile {
+                level: read_level(&mut body)?,
+                filename: read_string(&mut body)?,
+            },
+            TAG_NEXT_FILE_NUMBER => VersionEdit::NextFileNumber(body.read_u64::<LittleEndian>()?),
+            TAG_LAST_SEQ => VersionEdit::LastSeq(body.read_u64::<LittleEndian>()?),
+            TAG_LOG_NUMBER => VersionEdit::LogNumber(body.read_u64::<LittleEndian>()?),
+            tag => bail!("unknown version edit tag {}", tag),
+        };
+        edits.push(edit);
+    }
+    if !body.is_empty() {
+        bail!("{} trailing bytes after version edits", body.len());
+    }
+    Ok(edits)
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) -> Result<()> {
+    buf.write_u32::<LittleEndian>(bytes.len() as u32)?;
+    buf.extend_from_slice(bytes);
+    Ok(())
+}
+
+fn read_bytes(body: &mut &[u8]) -> Result<Vec<u8>> {
+    let len = body.read_u32::<LittleEndian>()? as usize;
+    if len > body.len() {
+        bail!("length {} exceeds the remaining {} bytes", len, body.len());
+    }
+    let mut bytes = vec![0; len];
+    body.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_string(body: &mut &[u8]) -> Result<String> {
+    String::from_utf8(read_bytes(body)?).context("filename is not valid UTF-8")
+}
+
+fn read_level(body: &mut &[u8]) -> Result<u32> {
+    let level = body.read_u32::<LittleEndian>()?;
+    if level >= MAX_SUPPORTED_LEVELS {
+        bail!(
+            "unknown level {} (expected 0..{})",
+            level,
+            MAX_SUPPORTED_LEVELS - 1
+        );
     }
+    Ok(level)
 }
@@ -0,0 +1,149 @@
+/// Obsolete file sweep.
+///
+/// A crash can leave files in the SST directory that nothing references: an
+/// SSTable written by a flush or compaction whose manifest update never
+/// happened, the temp file of an interrupted write, a manifest log that
+/// `CURRENT` no longer (or never) pointed at, or a WAL segment whose records
+/// were flushed just before the crash. Compaction inputs and WAL segments
+/// whose deletion failed are left for this sweep as well.
+///
+/// The sweep only ever deletes SSTables that are neither listed in the
+/// manifest nor held open by the engine, so it never touches live data,
+/// and never deletes files it does not recognise: an SSTable must have a
+/// name the engine writes (`000042.sst`, or a legacy `sst-{seq}-{ts}.sst`).
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::events::{TableFileInfo, TableFileReason};
+use crate::manifest::is_manifest_file;
+use crate::stats::Metrics;
+use crate::strategy::file_name;
+use crate::{is_sst_filename, parse_sst_number, parse_wal_number, Engine};
+
+/// Files removed by [`Engine::delete_obsolete_files`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObsoleteFilesReport {
+    /// Unreferenced SSTables that were deleted.
+    pub sst_files: Vec<PathBuf>,
+    /// WAL segments (and the legacy single-file WAL) whose records were
+    /// already flushed.
+    pub wal_files: Vec<PathBuf>,
+    /// Temp files and stale manifest files that were deleted.
+    pub other_files: Vec<PathBuf>,
+    /// Total size of the deleted files in bytes.
+    pub bytes_freed: u64,
+}
+
+impl ObsoleteFilesReport {
+    /// Returns `true` if nothing was deleted.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.sst_files.is_empty() && self.wal_files.is_empty() && self.other_files.is_empty()
+    }
+}
+
+impl Engine {
+    /// Deletes the files in the SST directory that the engine no longer
+    /// needs, and reports what was removed:
+    ///
+    /// - SSTables not listed in the manifest and not open for reads,
+    /// - `*.sst.tmp` files of interrupted writes,
+    /// - WAL segments older than the oldest live one recorded in the
+    ///   manifest, and the legacy WAL file once a flush has covered it,
+    /// - manifest logs other than the one `CURRENT` names, and leftover
+    ///   `CURRENT.tmp` / text manifest files.
+    ///
+    /// Other files, including `*.sst` files whose names the engine does not
+    /// write, are left alone.
+    ///
+    /// Any numbered SSTable or WAL segment seen is first used to raise the
+    /// next file number, so its number is never allocated again. Runs
+    /// automatically when the engine is opened; call it periodically to
+    /// clean up after failed deletions. Listeners get `on_table_file_deleted` with
+    /// [`TableFileReason::Obsolete`] for every SSTable removed. Files that
+    /// cannot be deleted are skipped and tried again on the next sweep.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the SST directory cannot be listed, the engine
+    /// is read-only, or a failed manifest save could not be undone (the
+    /// manifest on disk may then list files the engine does not know of).
+    pub fn delete_obsolete_files(&mut self) -> Result<ObsoleteFilesReport> {
+        self.ensure_writable("delete_obsolete_files")?;
+        self.manifest.ensure_consistent()?;
+        let live: HashSet<String> = self
+            .manifest
+            .entries
+            .iter()
+            .map(|e| e.filename.clone())
+            .chain(self.levels.iter().flatten().map(file_name))
+            .collect();
+
+        let mut report = ObsoleteFilesReport::default();
+        let entries = std::fs::read_dir(&self.sst_dir)
+            .with_context(|| format!("failed to list {}", self.sst_dir.display()))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if let Some(number) = parse_sst_number(name).or_else(|| parse_wal_number(name)) {
+                self.manifest.bump_next_file_number(number + 1);
+            }
+
+            let is_sst = is_sst_filename(name);
+            let wal_number = parse_wal_number(name);
+            let obsolete = if is_sst {
+                !live.contains(name)
+            } else if let Some(number) = wal_number {
+                number < self.manifest.log_number()
+            } else {
+                name.ends_with(".sst.tmp")
+                    || (is_manifest_file(name) && !self.manifest.is_live_file(name))
+            };
+            if !obsolete {
+                continue;
+            }
+
+            let file_size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            if std::fs::remove_file(&path).is_err() {
+                continue;
+            }
+            report.bytes_freed += file_size;
+            if is_sst {
+                self.notify(|l| {
+                    l.on_table_file_deleted(&TableFileInfo {
+                        path: path.clone(),
+                        file_size,
+                        reason: TableFileReason::Obsolete,
+                    })
+                });
+                report.sst_files.push(path);
+            } else if wal_number.is_some() {
+                report.wal_files.push(path);
+            } else {
+                report.other_files.push(path);
+            }
+        }
+
+        // The legacy WAL is replayed until the first flush records a segment.
+        if self.manifest.log_number() > 0 {
+            if let Ok(meta) = std::fs::metadata(&self.wal_path) {
+                if std::fs::remove_file(&self.wal_path).is_ok() {
+                    report.bytes_freed += meta.len();
+                    report.wal_files.push(self.wal_path.clone());
+                }
+            }
+        }
+
+        report.sst_files.sort();
+        report.wal_files.sort();
+        report.other_files.sort();
+        Metrics::add(
+            &self.metrics.obsolete_files_deleted,
+            (report.sst_files.len() + report.wal_files.len() + report.other_files.len()) as u64,
+        );
+        Ok(report)
+    }
+}
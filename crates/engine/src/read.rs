@@ -1,16 +1,23 @@
 /// Read path: get() and scan().
 ///
 /// Point lookups check the memtable first (freshest data), then L0 SSTables
-/// (newest-first, may overlap), then L1 SSTables (newest-first, non-overlapping).
-/// The first match wins; tombstones shadow older values.
+/// (newest-first, may overlap), then each deeper level in turn. L0 files
+/// whose key range excludes the key are skipped without touching their
+/// bloom filter. Files in L1 and below do not overlap, so at most one file
+/// per level is probed, found by binary search on key range. The first match
+/// wins; tombstones shadow older values.
 ///
 /// Range scans merge data from all sources, deduplicate by highest sequence
-/// number, and filter out tombstones before returning sorted results.
+/// number, and filter out tombstones before returning sorted results. Files
+/// that do not overlap the scanned range are skipped, and only the keys in
+/// the range are read from the others.
 use anyhow::Result;
 use memtable::ValueEntry;
 use std::collections::BTreeMap;
+use std::time::Instant;
 
-use crate::Engine;
+use crate::stats::Metrics;
+use crate::{Engine, SSTableReader};
 
 impl Engine {
     /// Looks up a key, returning `Some((seq, value))` if found and live.
@@ -23,13 +30,34 @@ impl Engine {
     ///
     /// Returns an error if any SSTable read fails (e.g. corruption, I/O).
     pub fn get(&self, key: &[u8]) -> Result<Option<(u64, Vec<u8>)>> {
+        let start = Instant::now();
+        let mut probed = 0u64;
+        let result = self.lookup(key, &mut probed);
+
+        if result.is_ok() {
+            Metrics::add(&self.metrics.gets, 1);
+            self.metrics.get_latency.record_duration(start.elapsed());
+            self.metrics.sstables_probed_per_get.record(probed);
+        }
+        result
+    }
+
+    /// Point lookup behind [`get`](Engine::get). `probed` counts the
+    /// SSTables consulted, for the probes-per-get histogram.
+    fn lookup(&self, key: &[u8], probed: &mut u64) -> Result<Option<(u64, Vec<u8>)>> {
         // 1. Check memtable FIRST (and respect tombstones)
         if let Some(entry) = self.mem.get_entry(key) {
             return Ok(entry.value.as_ref().map(|v| (entry.seq, v.clone())));
         }
 
-        // 2. Check L0 SSTables (newest -> oldest, may overlap)
-        for sst in &self.l0_sstables {
+        // 2. Check L0 SSTables (newest -> oldest, may overlap), then the
+        //    single candidate file in each deeper level (non-overlapping)
+        let l0 = self.levels[0].iter().filter(|r| contains_key(r, key));
+        let deeper = self.levels[1..]
+            .iter()
+            .filter_map(|files| file_for_key(files, key));
+        for sst in l0.chain(deeper) {
+            *probed += 1;
             match sst.get(key) {
                 Ok(Some(entry)) => {
                     return Ok(match entry.value {
@@ -42,21 +70,7 @@ impl Engine {
             }
         }
 
-        // 3. Check L1 SSTables (newest -> oldest, non-overlapping)
-        for sst in &self.l1_sstables {
-            match sst.get(key) {
-                Ok(Some(entry)) => {
-                    return Ok(match entry.value {
-                        Some(v) => Some((entry.seq, v)),
-                        None => None,
-                    });
-                }
-                Ok(None) => continue,
-                Err(e) => return Err(e),
-            }
-        }
-
-        // 4. Not found anywhere
+        // 3. Not found anywhere
         Ok(None)
     }
 
@@ -106,30 +120,14 @@ impl Engine {
             merge_entry(key.to_vec(), entry.clone());
         }
 
-        // 2. L0 SSTables (newest first, may overlap).
-        for sst in &self.l0_sstables {
-            for key_ref in sst.keys() {
-                if !start.is_empty() && key_ref < start {
-                    continue;
-                }
-                if !end.is_empty() && key_ref >= end {
-                    continue;
-                }
-                if let Ok(Some(entry)) = sst.get(key_ref) {
-                    merge_entry(key_ref.to_vec(), entry);
-                }
-            }
-        }
-
-        // 3. L1 SSTables (newest first, non-overlapping).
-        for sst in &self.l1_sstables {
-            for key_ref in sst.keys() {
-                if !start.is_empty() && key_ref < start {
-                    continue;
-                }
-                if !end.is_empty() && key_ref >= end {
-                    continue;
-                }
+        // 2. SSTables of every level that overlap the range.
+        let overlapping = self
+            .levels
+            .iter()
+            .flatten()
+            .filter(|r| overlaps_range(r, start, end));
+        for sst in overlapping {
+            for key_ref in sst.keys_in_range(start, end) {
                 if let Ok(Some(entry)) = sst.get(key_ref) {
                     merge_entry(key_ref.to_vec(), entry);
                 }
@@ -145,3 +143,34 @@ impl Engine {
         Ok(result)
     }
 }
+
+/// Returns the file in a non-overlapping level (sorted by smallest key)
+/// whose key range contains `key`, if any.
+pub(crate) fn file_for_key<'a>(
+    files: &'a [SSTableReader],
+    key: &[u8],
+) -> Option<&'a SSTableReader> {
+    let idx = files.partition_point(|r| r.largest_key().is_some_and(|k| k < key));
+    files
+        .get(idx)
+        .filter(|r| r.smallest_key().is_some_and(|k| k <= key))
+}
+
+/// Returns `true` if `key` lies within the key range of `reader`.
+fn contains_key(reader: &SSTableReader, key: &[u8]) -> bool {
+    match (reader.smallest_key(), reader.largest_key()) {
+        (Some(smallest), Some(largest)) => smallest <= key && key <= largest,
+        _ => false,
+    }
+}
+
+/// Returns `true` if the key range of `reader` intersects `[start, end)`,
+/// where an empty bound is unbounded.
+fn overlaps_range(reader: &SSTableReader, start: &[u8], end: &[u8]) -> bool {
+    match (reader.smallest_key(), reader.largest_key()) {
+        (Some(smallest), Some(largest)) => {
+            (start.is_empty() || largest >= start) && (end.is_empty() || smallest < end)
+        }
+        _ => false,
+    }
+}
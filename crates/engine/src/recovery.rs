@@ -1,46 +1,261 @@
 /// WAL replay and SSTable recovery logic.
 ///
-/// This module handles the cold-start path: replaying the WAL into a fresh
-/// memtable, loading existing SSTables from disk, and bootstrapping the
-/// manifest when upgrading from a pre-manifest database.
-use anyhow::Result;
+/// This module handles the cold-start path: replaying the live WAL segments
+/// into a fresh memtable, loading existing SSTables from disk, and
+/// bootstrapping the manifest when upgrading from a pre-manifest database.
+///
+/// The WAL is a sequence of numbered segments (`000012.log`) in the SST
+/// directory. A flush starts a new segment when it seals the memtable and
+/// records that segment's number in the manifest once the memtable is in an
+/// SSTable; segments numbered below it are obsolete. The single-file WAL of
+/// older versions is replayed as segment `0` until the first flush.
+use anyhow::{bail, Context, Result};
+use encryption::KeyProvider;
 use memtable::Memtable;
-use std::path::Path;
-use wal::{WalReader, WalRecord};
+use sstable::SSTableReadOptions;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use wal::{WalError, WalReader, WalRecord, WalRecoveryMode, WalRecoveryReport};
+
+use crate::manifest::{Manifest, SstMeta};
+use crate::{parse_sst_number, parse_wal_number, Engine, SSTableReader};
+
+/// Result of replaying a run of WAL segments.
+#[derive(Debug, Clone)]
+pub(crate) struct WalReplay {
+    /// Highest sequence number encountered.
+    pub(crate) max_seq: u64,
+    /// Number of the last segment replayed.
+    pub(crate) number: u64,
+    /// Offset just past the last complete record of that segment.
+    pub(crate) offset: u64,
+    /// Segments that had records discarded, oldest first.
+    pub(crate) discarded: Vec<(PathBuf, WalRecoveryReport)>,
+    /// `true` if replay stopped at a defect ([`WalRecoveryMode::PointInTime`])
+    /// and the segments after it were not replayed.
+    pub(crate) stopped_early: bool,
+}
+
+/// Opens a WAL segment for reading, with the keys it may be encrypted with.
+pub(crate) fn open_wal_reader(
+    path: &Path,
+    keys: Option<&Arc<dyn KeyProvider>>,
+) -> Result<WalReader<File>, WalError> {
+    let reader = WalReader::open(path)?;
+    Ok(match keys {
+        Some(keys) => reader.with_key_provider(keys.clone()),
+        None => reader,
+    })
+}
+
+/// Opens an SSTable, with the keys it may be encrypted with.
+pub(crate) fn open_sstable(
+    path: impl AsRef<Path>,
+    keys: Option<&Arc<dyn KeyProvider>>,
+) -> Result<SSTableReader> {
+    let opts = SSTableReadOptions {
+        key_provider: keys.cloned(),
+    };
+    SSTableReader::open_with_options(path, &opts)
+}
+
+/// Returns the WAL segments recovery must replay, oldest first: the legacy
+/// WAL at `legacy_path` (as number `0`) while `log_number` is still `0`,
+/// then every numbered segment in `dir` from `log_number` on.
+pub(crate) fn live_wal_segments(
+    dir: &Path,
+    legacy_path: &Path,
+    log_number: u64,
+) -> Result<Vec<(u64, PathBuf)>> {
+    let mut segments: Vec<(u64, PathBuf)> = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let number = parse_wal_number(e.file_name().to_str()?)?;
+            (number >= log_number).then(|| (number, e.path()))
+        })
+        .collect();
+    segments.sort();
+    if log_number == 0 && legacy_path.exists() {
+        segments.insert(0, (0, legacy_path.to_path_buf()));
+    }
+    Ok(segments)
+}
 
-use crate::{Engine, SSTableReader};
+/// Replays `segments` (oldest first) into `mem`, resuming at byte `offset`
+/// of segment `from` and skipping older segments. If there is nothing to
+/// replay, the returned position is `(from, offset)`.
+///
+/// Each segment is replayed with `mode`, so a defect at the tail of a
+/// segment is tolerated by [`WalRecoveryMode::TolerateCorruptedTail`] even
+/// if newer segments follow: after a crash, the torn segment is never
+/// appended to again. If `mode` stops at a defect, the later segments are
+/// not replayed and are reported as discarded in full.
+///
+/// # Errors
+///
+/// Propagates any I/O error, and any defect `mode` does not tolerate, from
+/// [`WalReader::replay_with_mode`].
+pub(crate) fn replay_wal_segments(
+    segments: &[(u64, PathBuf)],
+    from: u64,
+    offset: u64,
+    mode: WalRecoveryMode,
+    keys: Option<&Arc<dyn KeyProvider>>,
+    mem: &mut Memtable,
+) -> Result<WalReplay> {
+    let mut replay = WalReplay {
+        max_seq: 0,
+        number: from,
+        offset,
+        discarded: Vec::new(),
+        stopped_early: false,
+    };
+    for (number, path) in segments.iter().filter(|(n, _)| *n >= from) {
+        if replay.stopped_early {
+            replay
+                .discarded
+                .push((path.clone(), discard_segment(path, keys)?));
+            continue;
+        }
+        let start = if *number == from { offset } else { 0 };
+        let (max_seq, end, report) = replay_wal_from(path, start, mode, keys, mem)
+            .with_context(|| format!("failed to replay WAL segment {}", path.display()))?;
+        replay.max_seq = replay.max_seq.max(max_seq);
+        replay.number = *number;
+        replay.offset = end;
+        replay.stopped_early = report.stopped_early;
+        if !report.is_clean() {
+            replay.discarded.push((path.clone(), report));
+        }
+    }
+    Ok(replay)
+}
+
+/// Reports every record of a segment that is not replayed because an older
+/// one stopped early.
+fn discard_segment(path: &Path, keys: Option<&Arc<dyn KeyProvider>>) -> Result<WalRecoveryReport> {
+    let mut reader = match open_wal_reader(path, keys) {
+        Ok(reader) => reader,
+        Err(wal::WalError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(WalRecoveryReport::default())
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let counted = reader.replay_with_mode(WalRecoveryMode::SkipAnyCorrupted, |_| {})?;
+    Ok(WalRecoveryReport {
+        records_replayed: 0,
+        records_discarded: counted.records_replayed + counted.records_discarded,
+        bytes_discarded: std::fs::metadata(path)?.len(),
+        first_defect_offset: Some(0),
+        stopped_early: true,
+    })
+}
 
 /// Replays a WAL file into the given memtable, returning the highest sequence
 /// number encountered.
 ///
-/// If the WAL file does not exist, returns `Ok(0)` (fresh start).
+/// If the WAL file does not exist, returns `Ok(0)` (fresh start). A torn
+/// last record is ignored, as by [`WalReader::replay`].
 ///
 /// # Errors
 ///
 /// Propagates any I/O or corruption error from [`WalReader::replay`].
 pub fn replay_wal_and_build<P: AsRef<Path>>(path: P, mem: &mut Memtable) -> Result<u64> {
-    match WalReader::open(path.as_ref()) {
-        Ok(mut reader) => {
-            let mut max_seq = 0u64;
+    let mut reader = match WalReader::open(path.as_ref()) {
+        Ok(reader) => reader,
+        Err(wal::WalError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(anyhow::anyhow!(e).context("failed to open WAL for replay")),
+    };
+    let mut applier = Applier::new(mem);
+    reader.replay(|r| applier.apply(r))?;
+    applier.finish()
+}
 
-            reader.replay(|r| match r {
-                WalRecord::Put { seq, key, value } => {
-                    mem.put(key, value, seq);
-                    max_seq = max_seq.max(seq);
-                }
-                WalRecord::Del { seq, key } => {
-                    mem.delete(key, seq);
-                    max_seq = max_seq.max(seq);
-                }
-            })?;
+/// Applies replayed WAL records to a memtable, tracking the highest
+/// sequence number and the first record the engine cannot apply.
+struct Applier<'a> {
+    mem: &'a mut Memtable,
+    max_seq: u64,
+    error: Option<anyhow::Error>,
+}
 
-            Ok(max_seq)
+impl<'a> Applier<'a> {
+    fn new(mem: &'a mut Memtable) -> Self {
+        Self {
+            mem,
+            max_seq: 0,
+            error: None,
+        }
+    }
+
+    /// Applies `record`, unless an earlier record failed.
+    fn apply(&mut self, record: WalRecord) {
+        if self.error.is_none() {
+            if let Err(e) = self.apply_record(record) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn apply_record(&mut self, record: WalRecord) -> Result<()> {
+        let record_seq = record.seq();
+        match record {
+            WalRecord::Put { seq, key, value } => self.mem.put(key, value, seq),
+            WalRecord::Del { seq, key } => self.mem.delete(key, seq),
+            WalRecord::Batch { records } => {
+                return records.into_iter().try_for_each(|r| self.apply_record(r))
+            }
+            other => bail!(
+                "WAL record type {:?} is not supported by this engine",
+                other.record_type()
+            ),
+        }
+        self.max_seq = self.max_seq.max(record_seq);
+        Ok(())
+    }
+
+    /// Returns the highest sequence number applied, or the first error.
+    fn finish(self) -> Result<u64> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.max_seq),
+        }
+    }
+}
+
+/// Replays the WAL starting at byte `offset` (a record boundary) with
+/// `mode`, returning the highest sequence number encountered, the offset
+/// just past the last complete record and what was discarded.
+///
+/// Used by read-only engines to tail a WAL that a primary is still appending
+/// to. A missing WAL file is treated as empty.
+///
+/// # Errors
+///
+/// Propagates any I/O error, and any defect `mode` does not tolerate, from
+/// [`WalReader::replay_with_mode`].
+pub(crate) fn replay_wal_from<P: AsRef<Path>>(
+    path: P,
+    offset: u64,
+    mode: WalRecoveryMode,
+    keys: Option<&Arc<dyn KeyProvider>>,
+    mem: &mut Memtable,
+) -> Result<(u64, u64, WalRecoveryReport)> {
+    match open_wal_reader(path.as_ref(), keys) {
+        Ok(mut reader) => {
+            let mut applier = Applier::new(mem);
+            reader.seek_to(offset)?;
+            let report = reader.replay_with_mode(mode, |r| applier.apply(r))?;
+            let max_seq = applier.finish()?;
+
+            Ok((max_seq, reader.position(), report))
         }
         Err(e) => {
             // File doesn't exist yet -> fresh start
             if matches!(e, wal::WalError::Io(ref io_err) if io_err.kind() == std::io::ErrorKind::NotFound)
             {
-                Ok(0)
+                Ok((0, 0, WalRecoveryReport::default()))
             } else {
                 Err(anyhow::anyhow!(e).context("failed to open WAL for replay"))
             }
@@ -49,6 +264,116 @@ pub fn replay_wal_and_build<P: AsRef<Path>>(path: P, mem: &mut Memtable) -> Resu
 }
 
 impl Engine {
+    /// Opens the SSTables referenced by `manifest`, returning the readers
+    /// for each level (L0 newest first, deeper levels sorted by smallest key)
+    /// and the highest sequence number they contain.
+    ///
+    /// The sequence numbers come from the manifest's file metadata, so files
+    /// are only scanned if the manifest has no metadata for them.
+    ///
+    /// If the manifest is empty (fresh DB or pre-manifest upgrade), the
+    /// directory is scanned instead and every `.sst` file is loaded into L0
+    /// (conservative - compaction will sort them out). The discovered files
+    /// are added to `manifest` in memory only; the caller decides whether to
+    /// persist the bootstrapped manifest. Entries loaded from a text
+    /// manifest get their file metadata filled in the same way.
+    ///
+    /// Manifest entries whose file is missing on disk are skipped.
+    pub(crate) fn load_sstables(
+        sst_dir: &Path,
+        manifest: &mut Manifest,
+        keys: Option<&Arc<dyn KeyProvider>>,
+    ) -> Result<(Vec<Vec<SSTableReader>>, u64)> {
+        let mut levels: Vec<Vec<SSTableReader>> = Vec::new();
+        let mut max_sst_seq = 0u64;
+
+        // If the manifest has entries, use it to load SSTables into the
+        // correct levels. This preserves level assignments across restarts.
+        if !manifest.is_new() {
+            for level in 0..=manifest.max_level().unwrap_or(0) {
+                let mut readers = Vec::new();
+                for filename in manifest.level_filenames(level) {
+                    let path = sst_dir.join(filename);
+                    if path.exists() {
+                        readers.push(open_sstable(&path, keys)?);
+                    }
+                }
+                if level > 0 {
+                    sort_by_smallest_key(&mut readers);
+                }
+                levels.push(readers);
+            }
+            for (level, readers) in levels.iter().enumerate() {
+                for reader in readers {
+                    let name = reader.path().file_name().and_then(|n| n.to_str());
+                    let recorded = name
+                        .and_then(|name| manifest.file(name))
+                        .filter(|meta| meta.has_metadata())
+                        .map(|meta| meta.max_seq);
+                    let file_max_seq = match recorded {
+                        Some(seq) => seq,
+                        None => {
+                            let meta = Self::describe(reader, level as u32);
+                            let seq = meta.max_seq;
+                            manifest.fill_metadata(meta);
+                            seq
+                        }
+                    };
+                    max_sst_seq = max_sst_seq.max(file_max_seq);
+                }
+            }
+            return Ok((levels, max_sst_seq));
+        }
+
+        let mut paths: Vec<_> = std::fs::read_dir(sst_dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().map(|e| e == "sst").unwrap_or(false))
+            .collect();
+
+        // newest first (numbered names after the legacy
+        // `sst-{seq}-{timestamp}` ones, which sort by seq + timestamp)
+        paths.sort_by_key(|p| {
+            let name = p.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            (parse_sst_number(name), name.to_string())
+        });
+        paths.reverse();
+
+        let l0 = paths
+            .iter()
+            .map(|path| open_sstable(path, keys))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Bootstrap the manifest from the discovered files. `add` inserts at
+        // the front of L0, so walk oldest-first to keep it newest-first.
+        for reader in l0.iter().rev() {
+            let meta = Self::describe(reader, 0);
+            max_sst_seq = max_sst_seq.max(meta.max_seq);
+            manifest.add(meta);
+        }
+
+        levels.push(l0);
+
+        Ok((levels, max_sst_seq))
+    }
+
+    /// Builds the manifest entry for a file that has none, reading every
+    /// entry to find its sequence range.
+    fn describe(reader: &SSTableReader, level: u32) -> SstMeta {
+        let mut min_seq = u64::MAX;
+        let mut max_seq = 0u64;
+        for key in reader.keys() {
+            if let Ok(Some(entry)) = reader.get(key) {
+                min_seq = min_seq.min(entry.seq);
+                max_seq = max_seq.max(entry.seq);
+            }
+        }
+        SstMeta {
+            max_seq,
+            ..SstMeta::from_reader(reader, level, min_seq.min(max_seq))
+        }
+    }
+
     /// Extracts the max sequence number from an SSTable reader.
     ///
     /// Uses the v3 footer's `max_seq` for O(1) access when available.
@@ -80,3 +405,9 @@ impl Engine {
         }
     }
 }
+
+/// Sorts the SSTables of a level deeper than L0 by smallest key, the order
+/// the read path binary-searches.
+pub(crate) fn sort_by_smallest_key(readers: &mut [SSTableReader]) {
+    readers.sort_by(|a, b| a.smallest_key().cmp(&b.smallest_key()));
+}
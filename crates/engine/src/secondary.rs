@@ -0,0 +1,213 @@
+/// Secondary instances: read-only engines that follow a live primary.
+///
+/// A secondary opens the primary's directory with [`Engine::open_read_only`]
+/// and periodically calls [`Engine::try_catch_up`] to pick up newly flushed
+/// SSTables, drop compacted ones, and replay the new tail of the WAL
+/// (continuing into any segments the primary started since). This
+/// gives cheap read replicas on the same host without network replication.
+///
+/// ## Consistency
+///
+/// The primary flushes in a fixed order: start a new WAL segment, write the
+/// SSTable, save the manifest, then delete the old segments. The secondary
+/// reads in the opposite order — manifest, WAL, manifest again — and retries
+/// if the manifest changed underneath it. A deleted segment is therefore
+/// never missed without the manifest that references the flushed data.
+use anyhow::{bail, Result};
+use memtable::Memtable;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+
+use crate::manifest::Manifest;
+use crate::recovery::{live_wal_segments, open_sstable, replay_wal_segments, sort_by_smallest_key};
+use crate::{pad_levels, Engine, SSTableReader, WalRecoveryMode};
+
+/// How many times `try_catch_up` (and `open_read_only`) re-reads the
+/// manifest and WAL before giving up when the primary keeps flushing or
+/// compacting concurrently.
+pub(crate) const CATCH_UP_MAX_ATTEMPTS: usize = 8;
+
+/// Recovery mode read-only engines replay the WAL with. The primary may be
+/// in the middle of appending the last record, which must not be an error;
+/// records dropped as a damaged tail are read again on the next catch-up.
+pub(crate) const TAIL_MODE: WalRecoveryMode = WalRecoveryMode::TolerateCorruptedTail;
+
+/// State gathered by one catch-up attempt, committed only if consistent.
+struct CatchUp {
+    manifest: Manifest,
+    /// Readers for live files that were not open before this attempt.
+    new_readers: HashMap<String, SSTableReader>,
+    /// Records replayed by this attempt: the rebuilt memtable, or the new
+    /// WAL tail to add to the current one.
+    mem: Memtable,
+    /// `true` if `mem` replaces the memtable rather than adding to it.
+    rebuilt: bool,
+    wal_number: u64,
+    wal_offset: u64,
+    max_seq: u64,
+}
+
+impl Engine {
+    /// Brings a read-only engine up to date with the primary writing to the
+    /// same directory.
+    ///
+    /// Reloads the `MANIFEST`, opens SSTables the primary has flushed since
+    /// the last call, drops readers for SSTables that were compacted away,
+    /// and replays the WAL records appended since the last call. If the
+    /// primary flushed (deleting the WAL segments it flushed), the memtable
+    /// is rebuilt from the oldest live segment instead.
+    ///
+    /// On error the engine keeps serving its previous (consistent) state.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the engine is writable, on I/O or corruption
+    /// errors, or if the primary keeps changing the manifest for
+    /// `CATCH_UP_MAX_ATTEMPTS` consecutive attempts.
+    pub fn try_catch_up(&mut self) -> Result<()> {
+        anyhow::ensure!(
+            self.read_only,
+            "try_catch_up is only supported on read-only engines"
+        );
+
+        for _ in 0..CATCH_UP_MAX_ATTEMPTS {
+            if let Some(state) = self.catch_up_attempt()? {
+                self.commit_catch_up(state);
+                return Ok(());
+            }
+        }
+
+        bail!(
+            "catch-up did not converge after {} attempts: primary is changing the manifest too fast",
+            CATCH_UP_MAX_ATTEMPTS
+        )
+    }
+
+    /// Runs one catch-up attempt. Returns `Ok(None)` if the primary changed
+    /// the manifest (or deleted an SSTable) mid-attempt and it must be retried.
+    fn catch_up_attempt(&mut self) -> Result<Option<CatchUp>> {
+        let keys = self.key_provider.clone();
+        let manifest = Manifest::load_with_keys(&self.sst_dir, keys.clone())?;
+
+        // Open only the files we do not already hold a reader for.
+        let mut new_readers = HashMap::new();
+        let mut max_seq = 0u64;
+        for meta in &manifest.entries {
+            let already_open = self
+                .levels
+                .iter()
+                .flatten()
+                .any(|r| file_name(r) == Some(meta.filename.as_str()));
+            if already_open {
+                continue;
+            }
+            match open_sstable(self.sst_dir.join(&meta.filename), keys.as_ref()) {
+                Ok(reader) => {
+                    let file_max_seq = if meta.has_metadata() {
+                        meta.max_seq
+                    } else {
+                        Self::reader_max_seq(&reader)
+                    };
+                    max_seq = max_seq.max(file_max_seq);
+                    new_readers.insert(meta.filename.clone(), reader);
+                }
+                // Compacted away since we read the manifest.
+                Err(e) if is_not_found(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+
+        // A changed manifest means the primary may have flushed and deleted
+        // the segments we were tailing, and our memtable holds records now
+        // in SSTables: rebuild it from the oldest live segment. Otherwise
+        // just read on from where we stopped. Either way the records go to
+        // a scratch memtable until the attempt is committed.
+        let segments = live_wal_segments(&self.sst_dir, &self.wal_path, manifest.log_number())?;
+        let rebuilt = manifest.entries != self.manifest.entries
+            || manifest.log_number() != self.manifest.log_number();
+        let (from, offset) = if rebuilt {
+            (0, 0)
+        } else {
+            (self.wal_number, self.wal_offset)
+        };
+        let mut mem = Memtable::new();
+        let replayed =
+            replay_wal_segments(&segments, from, offset, TAIL_MODE, keys.as_ref(), &mut mem)?;
+
+        // If a flush completed while we were reading the WAL, the records
+        // in the segments it deleted are only visible through the newer
+        // manifest.
+        let reloaded = Manifest::load_with_keys(&self.sst_dir, keys)?;
+        if reloaded.entries != manifest.entries || reloaded.log_number() != manifest.log_number() {
+            return Ok(None);
+        }
+
+        let last_seq = manifest.last_seq();
+        Ok(Some(CatchUp {
+            manifest,
+            new_readers,
+            mem,
+            rebuilt,
+            wal_number: replayed.number,
+            wal_offset: replayed.offset,
+            max_seq: max_seq.max(replayed.max_seq).max(last_seq),
+        }))
+    }
+
+    /// Installs the state from a successful attempt: rebuilds the per-level
+    /// reader lists from the manifest, dropping readers for deleted files,
+    /// and installs or merges the replayed records.
+    fn commit_catch_up(&mut self, state: CatchUp) {
+        let mut readers = state.new_readers;
+        for reader in self.levels.drain(..).flatten() {
+            if let Some(name) = file_name(&reader) {
+                readers.entry(name.to_string()).or_insert(reader);
+            }
+        }
+
+        let mut take = |names: Vec<&str>| -> Vec<SSTableReader> {
+            names
+                .into_iter()
+                .filter_map(|name| readers.remove(name))
+                .collect()
+        };
+        let max_level = state.manifest.max_level().unwrap_or(0);
+        let levels = (0..=max_level)
+            .map(|level| {
+                let mut files = take(state.manifest.level_filenames(level));
+                if level > 0 {
+                    sort_by_smallest_key(&mut files);
+                }
+                files
+            })
+            .collect();
+        self.levels = pad_levels(levels, self.max_levels);
+
+        self.manifest = state.manifest;
+        if state.rebuilt {
+            self.mem = state.mem;
+        } else {
+            for (key, entry) in state.mem.iter() {
+                match &entry.value {
+                    Some(value) => self.mem.put(key.to_vec(), value.clone(), entry.seq),
+                    None => self.mem.delete(key.to_vec(), entry.seq),
+                }
+            }
+        }
+        self.wal_number = state.wal_number;
+        self.wal_offset = state.wal_offset;
+        self.seq = self.seq.max(state.max_seq);
+    }
+}
+
+/// Returns the basename of the file `reader` was opened from.
+fn file_name(reader: &SSTableReader) -> Option<&str> {
+    reader.path().file_name()?.to_str()
+}
+
+/// Returns `true` if `e` wraps an I/O "file not found" error.
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .map(|io| io.kind() == ErrorKind::NotFound)
+        .unwrap_or(false)
+}
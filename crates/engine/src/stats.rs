@@ -0,0 +1,333 @@
+/// Engine statistics: operation counters and latency histograms.
+///
+/// The engine records into a [`Metrics`] registry of atomic counters as it
+/// works, so read paths that only hold `&self` (e.g. `get`) can update it
+/// without locking. [`Engine::stats`] takes a point-in-time snapshot as a
+/// plain, serializable [`EngineStats`] value.
+///
+/// ## Histograms
+///
+/// Latencies (in microseconds) and per-get SSTable probe counts are recorded
+/// into power-of-two buckets: bucket `0` holds `0`, bucket `i` holds values in
+/// `[2^(i-1), 2^i)`. Percentiles are reported as the upper bound of the
+/// bucket they fall in, so they are accurate to within a factor of two.
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::Engine;
+
+/// Number of histogram buckets: one for zero plus one per bit of a `u64`.
+const HISTOGRAM_BUCKETS: usize = 65;
+
+/// A lock-free histogram of `u64` samples with power-of-two buckets.
+pub(crate) struct Histogram {
+    buckets: [AtomicU64; HISTOGRAM_BUCKETS],
+    count: AtomicU64,
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Histogram {
+    pub(crate) fn new() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    /// Records one sample.
+    pub(crate) fn record(&self, value: u64) {
+        let bucket = (u64::BITS - value.leading_zeros()) as usize;
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    /// Records a duration in microseconds.
+    pub(crate) fn record_duration(&self, d: Duration) {
+        self.record(d.as_micros().min(u64::MAX as u128) as u64);
+    }
+
+    pub(crate) fn snapshot(&self) -> HistogramSnapshot {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect();
+        let count: u64 = counts.iter().sum();
+        let sum = self.sum.load(Ordering::Relaxed);
+        let max = self.max.load(Ordering::Relaxed);
+
+        // Upper bound of the bucket containing the `q`-quantile sample,
+        // clamped to the largest value actually recorded.
+        let percentile = |q: f64| -> u64 {
+            if count == 0 {
+                return 0;
+            }
+            let rank = ((count as f64) * q).ceil().max(1.0) as u64;
+            let mut seen = 0u64;
+            for (i, c) in counts.iter().enumerate() {
+                seen += c;
+                if seen >= rank {
+                    let upper = if i == 0 {
+                        0
+                    } else {
+                        u64::MAX >> (u64::BITS as usize - i)
+                    };
+                    return upper.min(max);
+                }
+            }
+            max
+        };
+
+        HistogramSnapshot {
+            count,
+            sum,
+            mean: if count == 0 {
+                0.0
+            } else {
+                sum as f64 / count as f64
+            },
+            max,
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+        }
+    }
+}
+
+/// Internal metrics registry updated by the read, write, flush and
+/// compaction paths.
+pub(crate) struct Metrics {
+    pub(crate) gets: AtomicU64,
+    pub(crate) sets: AtomicU64,
+    pub(crate) deletes: AtomicU64,
+    pub(crate) flushes: AtomicU64,
+    pub(crate) bytes_flushed: AtomicU64,
+    pub(crate) compactions: AtomicU64,
+    pub(crate) compaction_bytes_read: AtomicU64,
+    pub(crate) compaction_bytes_written: AtomicU64,
+    pub(crate) obsolete_files_deleted: AtomicU64,
+    /// Foreground writes that had to wait for an inline flush (and any
+    /// compaction it triggered) before returning.
+    pub(crate) write_stalls: AtomicU64,
+    pub(crate) write_stall_micros: AtomicU64,
+    /// WAL bytes / fsyncs from writers replaced by earlier flushes. The
+    /// current writer's counters are added on top in [`Engine::stats`].
+    pub(crate) retired_wal_bytes: AtomicU64,
+    pub(crate) retired_wal_syncs: AtomicU64,
+    pub(crate) get_latency: Histogram,
+    pub(crate) set_latency: Histogram,
+    pub(crate) delete_latency: Histogram,
+    pub(crate) batch_latency: Histogram,
+    pub(crate) sstables_probed_per_get: Histogram,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        Self {
+            gets: AtomicU64::new(0),
+            sets: AtomicU64::new(0),
+            deletes: AtomicU64::new(0),
+            flushes: AtomicU64::new(0),
+            bytes_flushed: AtomicU64::new(0),
+            compactions: AtomicU64::new(0),
+            compaction_bytes_read: AtomicU64::new(0),
+            compaction_bytes_written: AtomicU64::new(0),
+            obsolete_files_deleted: AtomicU64::new(0),
+            write_stalls: AtomicU64::new(0),
+            write_stall_micros: AtomicU64::new(0),
+            retired_wal_bytes: AtomicU64::new(0),
+            retired_wal_syncs: AtomicU64::new(0),
+            get_latency: Histogram::new(),
+            set_latency: Histogram::new(),
+            delete_latency: Histogram::new(),
+            batch_latency: Histogram::new(),
+            sstables_probed_per_get: Histogram::new(),
+        }
+    }
+
+    /// Adds `n` to `counter`.
+    pub(crate) fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+/// Summary of a [`Histogram`] at the time of the snapshot.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HistogramSnapshot {
+    /// Number of recorded samples.
+    pub count: u64,
+    /// Sum of all samples.
+    pub sum: u64,
+    /// Arithmetic mean of all samples (`0.0` if empty).
+    pub mean: f64,
+    /// Largest recorded sample.
+    pub max: u64,
+    /// Median, rounded up to its bucket bound.
+    pub p50: u64,
+    /// 95th percentile, rounded up to its bucket bound.
+    pub p95: u64,
+    /// 99th percentile, rounded up to its bucket bound.
+    pub p99: u64,
+}
+
+/// Per-SSTable statistics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SstableStats {
+    /// SSTable filename (basename).
+    pub file: String,
+    /// Level the SSTable belongs to.
+    pub level: u32,
+    /// Number of entries (including tombstones).
+    pub entries: usize,
+    /// File size in bytes.
+    pub file_size: u64,
+    /// Smallest key in the file.
+    pub smallest_key: Vec<u8>,
+    /// Largest key in the file.
+    pub largest_key: Vec<u8>,
+    /// Smallest sequence number in the file (`0` if unknown).
+    pub min_seq: u64,
+    /// Largest sequence number in the file.
+    pub max_seq: u64,
+    /// Lookups the bloom filter let through.
+    pub bloom_hits: u64,
+    /// Lookups the bloom filter rejected.
+    pub bloom_misses: u64,
+    /// Bloom hits for keys that were not in the file.
+    pub bloom_false_positives: u64,
+}
+
+/// A point-in-time snapshot of the engine's statistics.
+///
+/// Returned by [`Engine::stats`]. All counters are cumulative since the
+/// engine was opened; latencies are in microseconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EngineStats {
+    /// Current sequence number.
+    pub seq: u64,
+    /// Highest sequence number guaranteed to survive a crash; see
+    /// [`Engine::durable_seq`].
+    pub durable_seq: u64,
+    /// Approximate memtable size in bytes.
+    pub memtable_bytes: usize,
+    /// Memtable entries (including tombstones).
+    pub memtable_entries: usize,
+    /// Completed `get` calls.
+    pub gets: u64,
+    /// Completed `set` calls.
+    pub sets: u64,
+    /// Completed `del` calls.
+    pub deletes: u64,
+    /// Memtable flushes.
+    pub flushes: u64,
+    /// Bytes written to SSTables by flushes.
+    pub bytes_flushed: u64,
+    /// Compactions run (automatic and manual).
+    pub compactions: u64,
+    /// Bytes read from input SSTables by compactions.
+    pub compaction_bytes_read: u64,
+    /// Bytes written to output SSTables by compactions.
+    pub bytes_compacted: u64,
+    /// Files removed by obsolete-file sweeps.
+    pub obsolete_files_deleted: u64,
+    /// Bytes appended to the WAL.
+    pub wal_bytes: u64,
+    /// WAL `fsync` calls.
+    pub wal_syncs: u64,
+    /// Writes that blocked on an inline flush/compaction.
+    pub write_stalls: u64,
+    /// Total time spent in write stalls.
+    pub write_stall_micros: u64,
+    /// `get` latency.
+    pub get_latency_micros: HistogramSnapshot,
+    /// `set` latency (including any stall).
+    pub set_latency_micros: HistogramSnapshot,
+    /// `del` latency (including any stall).
+    pub delete_latency_micros: HistogramSnapshot,
+    /// `write` (batch) latency (including any stall).
+    pub batch_latency_micros: HistogramSnapshot,
+    /// Number of SSTables consulted per `get`.
+    pub sstables_probed_per_get: HistogramSnapshot,
+    /// Per-SSTable statistics, L0 newest-first followed by each deeper level
+    /// in key order.
+    pub sstables: Vec<SstableStats>,
+}
+
+impl Engine {
+    /// Returns a snapshot of the engine's statistics.
+    ///
+    /// The snapshot is a plain value: it does not change as the engine keeps
+    /// running, and it can be serialized (e.g. to JSON) with `serde`.
+    #[must_use]
+    pub fn stats(&self) -> EngineStats {
+        let m = &self.metrics;
+        let load = |c: &AtomicU64| c.load(Ordering::Relaxed);
+
+        let (wal_bytes, wal_syncs) = match &self.wal_writer {
+            Some(w) => (w.bytes_written(), w.sync_count()),
+            None => (0, 0),
+        };
+
+        let sstables = self
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(level, readers)| {
+                readers.iter().map(move |r| {
+                    let bloom = r.bloom_stats();
+                    let file = r
+                        .path()
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    let meta = self.manifest.file(&file);
+                    SstableStats {
+                        level: level as u32,
+                        entries: r.len(),
+                        file_size: r.file_size(),
+                        smallest_key: r.smallest_key().unwrap_or_default().to_vec(),
+                        largest_key: r.largest_key().unwrap_or_default().to_vec(),
+                        min_seq: meta.map_or(0, |m| m.min_seq),
+                        max_seq: meta.map_or_else(|| r.max_seq().unwrap_or(0), |m| m.max_seq),
+                        file,
+                        bloom_hits: bloom.hits,
+                        bloom_misses: bloom.misses,
+                        bloom_false_positives: bloom.false_positives,
+                    }
+                })
+            })
+            .collect();
+
+        EngineStats {
+            seq: self.seq,
+            durable_seq: self.durable_seq(),
+            memtable_bytes: self.mem.approx_size(),
+            memtable_entries: self.mem.len(),
+            gets: load(&m.gets),
+            sets: load(&m.sets),
+            deletes: load(&m.deletes),
+            flushes: load(&m.flushes),
+            bytes_flushed: load(&m.bytes_flushed),
+            compactions: load(&m.compactions),
+            compaction_bytes_read: load(&m.compaction_bytes_read),
+            bytes_compacted: load(&m.compaction_bytes_written),
+            obsolete_files_deleted: load(&m.obsolete_files_deleted),
+            wal_bytes: load(&m.retired_wal_bytes) + wal_bytes,
+            wal_syncs: load(&m.retired_wal_syncs) + wal_syncs,
+            write_stalls: load(&m.write_stalls),
+            write_stall_micros: load(&m.write_stall_micros),
+            get_latency_micros: m.get_latency.snapshot(),
+            set_latency_micros: m.set_latency.snapshot(),
+            delete_latency_micros: m.delete_latency.snapshot(),
+            batch_latency_micros: m.batch_latency.snapshot(),
+            sstables_probed_per_get: m.sstables_probed_per_get.snapshot(),
+            sstables,
+        }
+    }
+}
@@ -0,0 +1,605 @@
+/// Compaction strategies: which SSTables to merge, and into which level.
+///
+/// After every flush the engine asks its [`CompactionStrategy`] for a
+/// [`CompactionJob`] and runs it, repeating until the strategy returns
+/// `None`. The strategy only sees a read-only [`LevelState`]; merging,
+/// tombstone GC, manifest updates and file deletion are handled by
+/// `compaction.rs` the same way for every strategy.
+///
+/// Four strategies ship with the engine:
+///
+/// | Strategy               | Shape                         | Trade-off                          |
+/// |------------------------|-------------------------------|------------------------------------|
+/// | [`LeveledStrategy`]    | L0 + non-overlapping L1..Ln   | Low read/space amp, higher write amp |
+/// | [`SizeTieredStrategy`] | sorted runs of similar size   | Low write amp, higher read/space amp |
+/// | [`MergeAllStrategy`]   | one big run                   | Simplest; rewrites everything each time |
+/// | [`FifoStrategy`]       | L0 only, oldest files dropped | No write amp; data expires         |
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::SSTableReader;
+
+/// What a [`CompactionJob`] does with its inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompactionKind {
+    /// Merge the inputs into new SSTables in the output level.
+    #[default]
+    Merge,
+    /// Delete the inputs without writing anything, dropping their data.
+    Delete,
+}
+
+/// A set of input SSTables and the level their merged output goes to.
+///
+/// Returned by [`CompactionStrategy::pick_compaction`]. The engine checks
+/// that a merge cannot resurrect older data (see
+/// [`Engine::set_compaction_strategy`](crate::Engine::set_compaction_strategy))
+/// before running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionJob {
+    /// Level the compaction was picked for. For L1 and deeper, the level's
+    /// round-robin cursor advances past the inputs taken from it.
+    pub level: usize,
+    /// `(level, filename)` of every input SSTable.
+    pub inputs: Vec<(usize, String)>,
+    /// Level the merged output is written to.
+    pub output_level: usize,
+    /// Whether the inputs are merged or deleted.
+    pub kind: CompactionKind,
+}
+
+impl CompactionJob {
+    /// Creates a merge job with no inputs yet.
+    #[must_use]
+    pub fn new(level: usize, output_level: usize) -> Self {
+        Self {
+            level,
+            inputs: Vec::new(),
+            output_level,
+            kind: CompactionKind::Merge,
+        }
+    }
+
+    /// Creates a job that deletes its inputs (added with
+    /// [`add_input`](Self::add_input)) instead of merging them. Their data
+    /// is gone for good; any older version of a key they held becomes
+    /// visible again, so strategies should delete the oldest files first.
+    #[must_use]
+    pub fn deletion(level: usize) -> Self {
+        Self {
+            level,
+            inputs: Vec::new(),
+            output_level: level,
+            kind: CompactionKind::Delete,
+        }
+    }
+
+    /// Adds `reader`, which lives in `level`, to the inputs.
+    pub fn add_input(&mut self, level: usize, reader: &SSTableReader) {
+        self.inputs.push((level, file_name(reader)));
+    }
+}
+
+/// Read-only view of the engine's levels handed to a [`CompactionStrategy`].
+#[derive(Clone, Copy)]
+pub struct LevelState<'a> {
+    pub(crate) levels: &'a [Vec<SSTableReader>],
+    pub(crate) compact_cursors: &'a [Option<Vec<u8>>],
+    pub(crate) max_levels: usize,
+    pub(crate) l0_compaction_trigger: usize,
+    pub(crate) level_base_bytes: u64,
+    pub(crate) level_size_multiplier: u64,
+}
+
+impl<'a> LevelState<'a> {
+    /// Returns the number of levels that currently exist (at least
+    /// [`max_levels`](LevelState::max_levels)).
+    #[must_use]
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Returns the configured number of levels (L0..L{max_levels-1}).
+    #[must_use]
+    pub fn max_levels(&self) -> usize {
+        self.max_levels
+    }
+
+    /// Returns the L0 file count that should trigger a compaction. Never
+    /// `0` here: the engine does not consult the strategy when
+    /// auto-compaction is disabled.
+    #[must_use]
+    pub fn l0_compaction_trigger(&self) -> usize {
+        self.l0_compaction_trigger
+    }
+
+    /// Returns the SSTables of `level`. L0 is ordered newest-first and may
+    /// overlap; deeper levels are sorted by smallest key and never overlap.
+    #[must_use]
+    pub fn files(&self, level: usize) -> &'a [SSTableReader] {
+        self.levels.get(level).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the total size in bytes of the SSTables in `level`.
+    #[must_use]
+    pub fn level_size_bytes(&self, level: usize) -> u64 {
+        self.files(level).iter().map(|r| r.file_size()).sum()
+    }
+
+    /// Returns the target total size in bytes of `level` (1 or deeper):
+    /// `level_base_bytes * level_size_multiplier^(level - 1)`. L0 is
+    /// bounded by file count instead and returns `0`.
+    #[must_use]
+    pub fn level_target_bytes(&self, level: usize) -> u64 {
+        if level == 0 {
+            return 0;
+        }
+        (1..level).fold(self.level_base_bytes, |bytes, _| {
+            bytes.saturating_mul(self.level_size_multiplier)
+        })
+    }
+
+    /// Returns the deepest level holding at least one SSTable.
+    #[must_use]
+    pub fn deepest_non_empty_level(&self) -> Option<usize> {
+        self.levels.iter().rposition(|files| !files.is_empty())
+    }
+
+    /// Returns the largest key of the file last compacted out of `level`.
+    #[must_use]
+    pub fn compact_cursor(&self, level: usize) -> Option<&'a [u8]> {
+        self.compact_cursors.get(level)?.as_deref()
+    }
+
+    /// Returns the files of `level` whose key range intersects
+    /// `[smallest, largest]`.
+    pub fn overlapping_files<'k>(
+        &self,
+        level: usize,
+        smallest: &'k [u8],
+        largest: &'k [u8],
+    ) -> impl Iterator<Item = &'a SSTableReader> + 'k
+    where
+        'a: 'k,
+    {
+        self.files(level)
+            .iter()
+            .filter(move |r| overlaps(r, smallest, largest))
+    }
+}
+
+/// Decides which SSTables to compact next.
+///
+/// Implementations must be deterministic for a given [`LevelState`] and
+/// must eventually return `None`: the engine keeps running the returned
+/// jobs until then. Every job has to make progress, e.g. by reducing the
+/// number of files in a level or moving data to a deeper level.
+pub trait CompactionStrategy: Debug + Send + Sync {
+    /// Short name for logs and `Debug` output.
+    fn name(&self) -> &'static str;
+
+    /// Returns the next compaction to run, or `None` if the levels are in
+    /// shape.
+    fn pick_compaction(&self, state: &LevelState<'_>) -> Option<CompactionJob>;
+}
+
+// --------------------- Merge everything ---------------------
+
+/// Merges every SSTable into a single file in the deepest non-empty level
+/// (at least L1) once L0 reaches the trigger.
+///
+/// This was the engine's original behaviour. Each compaction rewrites the
+/// whole database, so it only suits small data sets, but it leaves reads a
+/// single file to probe.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MergeAllStrategy;
+
+impl CompactionStrategy for MergeAllStrategy {
+    fn name(&self) -> &'static str {
+        "merge-all"
+    }
+
+    fn pick_compaction(&self, state: &LevelState<'_>) -> Option<CompactionJob> {
+        if state.files(0).len() < state.l0_compaction_trigger() {
+            return None;
+        }
+        full_compaction(state)
+    }
+}
+
+/// Returns a job merging every SSTable into the deepest non-empty level
+/// (at least L1), or `None` if there is nothing to merge.
+pub(crate) fn full_compaction(state: &LevelState<'_>) -> Option<CompactionJob> {
+    let output_level = state.deepest_non_empty_level()?.max(1);
+    let mut job = CompactionJob::new(0, output_level);
+    for level in 0..state.num_levels() {
+        for reader in state.files(level) {
+            job.add_input(level, reader);
+        }
+    }
+    Some(job)
+}
+
+// --------------------- Leveled ---------------------
+
+/// Leveled compaction (the default).
+///
+/// - **L0 -> L1** once L0 holds `l0_compaction_trigger` files. L0 files may
+///   overlap, so all of them are merged together with the L1 files their key
+///   range overlaps.
+/// - **Ln -> Ln+1** once the total size of Ln exceeds its target. One file
+///   is picked (round-robin through the level's key space) and merged with
+///   only the files of Ln+1 it overlaps.
+///
+/// The level with the highest score (L0 file count over the trigger, or
+/// level size over target) of at least `1.0` goes first. The last level is
+/// never picked since there is nowhere to push its data.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeveledStrategy;
+
+impl CompactionStrategy for LeveledStrategy {
+    fn name(&self) -> &'static str {
+        "leveled"
+    }
+
+    fn pick_compaction(&self, state: &LevelState<'_>) -> Option<CompactionJob> {
+        let mut best: Option<(f64, usize)> = None;
+        for level in 0..state.max_levels() - 1 {
+            let score = if level == 0 {
+                state.files(0).len() as f64 / state.l0_compaction_trigger() as f64
+            } else {
+                state.level_size_bytes(level) as f64 / state.level_target_bytes(level) as f64
+            };
+            if score >= 1.0 && best.is_none_or(|(s, _)| score > s) {
+                best = Some((score, level));
+            }
+        }
+
+        let (_, level) = best?;
+        let picked: Vec<&SSTableReader> = if level == 0 {
+            state.files(0).iter().collect()
+        } else {
+            vec![next_file_to_compact(state, level)?]
+        };
+
+        let (smallest, largest) = key_range(&picked)?;
+        let mut job = CompactionJob::new(level, level + 1);
+        for reader in picked {
+            job.add_input(level, reader);
+        }
+        for reader in state.overlapping_files(level + 1, &smallest, &largest) {
+            job.add_input(level + 1, reader);
+        }
+        Some(job)
+    }
+}
+
+/// Returns the first file of `level` past the level's round-robin cursor,
+/// wrapping around to the first file.
+fn next_file_to_compact<'a>(state: &LevelState<'a>, level: usize) -> Option<&'a SSTableReader> {
+    let files = state.files(level);
+    state
+        .compact_cursor(level)
+        .and_then(|c| {
+            files
+                .iter()
+                .find(|r| r.smallest_key().is_some_and(|k| k > c))
+        })
+        .or_else(|| files.first())
+}
+
+// --------------------- Size-tiered (universal) ---------------------
+
+/// Size-tiered ("universal") compaction.
+///
+/// Data is kept as a list of *sorted runs*, newest first: every L0 file is
+/// one run, followed by each non-empty deeper level as one run. Flushes add
+/// runs; once there are `l0_compaction_trigger` runs, consecutive runs are
+/// merged into one:
+///
+/// 1. **Space amplification**: if the runs newer than the oldest one add up
+///    to more than `max_size_amplification_percent` of the oldest run,
+///    everything is merged.
+/// 2. **Size ratio**: otherwise, starting from the newest run, runs are
+///    accumulated while the next run is at most `size_ratio_percent`
+///    larger than everything picked so far. The first such window of at
+///    least `min_merge_width` runs is merged.
+/// 3. Otherwise the newest runs are merged until the count drops below the
+///    trigger.
+///
+/// A merge of L0 files stays in L0, taking the place of its inputs; a merge
+/// that includes a deeper level's run is written to that level. Each byte
+/// is rewritten far less often than with leveled compaction, at the cost
+/// of more files to probe on reads and more space held by stale versions.
+#[derive(Debug, Clone, Copy)]
+pub struct SizeTieredStrategy {
+    size_ratio_percent: u64,
+    min_merge_width: usize,
+    max_size_amplification_percent: u64,
+}
+
+/// Default [`SizeTieredStrategy::size_ratio_percent`].
+pub const DEFAULT_SIZE_RATIO_PERCENT: u64 = 1;
+
+/// Default [`SizeTieredStrategy::min_merge_width`].
+pub const DEFAULT_MIN_MERGE_WIDTH: usize = 2;
+
+/// Default [`SizeTieredStrategy::max_size_amplification_percent`].
+pub const DEFAULT_MAX_SIZE_AMPLIFICATION_PERCENT: u64 = 200;
+
+impl Default for SizeTieredStrategy {
+    fn default() -> Self {
+        Self {
+            size_ratio_percent: DEFAULT_SIZE_RATIO_PERCENT,
+            min_merge_width: DEFAULT_MIN_MERGE_WIDTH,
+            max_size_amplification_percent: DEFAULT_MAX_SIZE_AMPLIFICATION_PERCENT,
+        }
+    }
+}
+
+impl SizeTieredStrategy {
+    /// Creates a strategy with the default tuning.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how much larger (in percent) the next run may be than the
+    /// runs accumulated so far to still join the merge.
+    #[must_use]
+    pub fn size_ratio_percent(&self) -> u64 {
+        self.size_ratio_percent
+    }
+
+    /// Sets the size ratio in percent.
+    #[must_use]
+    pub fn with_size_ratio_percent(mut self, percent: u64) -> Self {
+        self.size_ratio_percent = percent;
+        self
+    }
+
+    /// Returns the fewest runs a size-ratio merge combines.
+    #[must_use]
+    pub fn min_merge_width(&self) -> usize {
+        self.min_merge_width
+    }
+
+    /// Sets the fewest runs a size-ratio merge combines (at least 2).
+    #[must_use]
+    pub fn with_min_merge_width(mut self, width: usize) -> Self {
+        self.min_merge_width = width.max(2);
+        self
+    }
+
+    /// Returns the space amplification (newer runs over the oldest run, in
+    /// percent) above which everything is merged.
+    #[must_use]
+    pub fn max_size_amplification_percent(&self) -> u64 {
+        self.max_size_amplification_percent
+    }
+
+    /// Sets the space amplification limit in percent.
+    #[must_use]
+    pub fn with_max_size_amplification_percent(mut self, percent: u64) -> Self {
+        self.max_size_amplification_percent = percent;
+        self
+    }
+
+    /// Picks the window `runs[start..end]` to merge.
+    fn pick_window(&self, runs: &[SortedRun<'_>], trigger: usize) -> (usize, usize) {
+        let total: u64 = runs.iter().map(|r| r.size).sum();
+        let oldest = runs[runs.len() - 1].size;
+        let newer = total - oldest;
+        if newer.saturating_mul(100) > oldest.saturating_mul(self.max_size_amplification_percent) {
+            return (0, runs.len());
+        }
+
+        for start in 0..runs.len() {
+            let mut accumulated = runs[start].size;
+            let mut end = start + 1;
+            while end < runs.len()
+                && runs[end].size.saturating_mul(100)
+                    <= accumulated.saturating_mul(100 + self.size_ratio_percent)
+            {
+                accumulated += runs[end].size;
+                end += 1;
+            }
+            if end - start >= self.min_merge_width {
+                return (start, end);
+            }
+        }
+
+        let width = (runs.len() + 2).saturating_sub(trigger).max(2);
+        (0, width.min(runs.len()))
+    }
+}
+
+impl CompactionStrategy for SizeTieredStrategy {
+    fn name(&self) -> &'static str {
+        "size-tiered"
+    }
+
+    fn pick_compaction(&self, state: &LevelState<'_>) -> Option<CompactionJob> {
+        let runs = sorted_runs(state);
+        if runs.len() < 2 || runs.len() < state.l0_compaction_trigger() {
+            return None;
+        }
+
+        let (start, end) = self.pick_window(&runs, state.l0_compaction_trigger());
+        let window = &runs[start..end];
+        let output_level = window.iter().map(|r| r.level).max()?;
+        let mut job = CompactionJob::new(window[0].level, output_level);
+        for run in window {
+            for reader in &run.files {
+                job.add_input(run.level, reader);
+            }
+        }
+        Some(job)
+    }
+}
+
+/// One sorted run: a single L0 file, or all files of a deeper level.
+struct SortedRun<'a> {
+    level: usize,
+    files: Vec<&'a SSTableReader>,
+    size: u64,
+}
+
+/// Returns the sorted runs of `state`, newest first.
+fn sorted_runs<'a>(state: &LevelState<'a>) -> Vec<SortedRun<'a>> {
+    let l0 = state.files(0).iter().map(|r| SortedRun {
+        level: 0,
+        files: vec![r],
+        size: r.file_size(),
+    });
+    let deeper = (1..state.num_levels())
+        .filter(|&level| !state.files(level).is_empty())
+        .map(|level| SortedRun {
+            level,
+            files: state.files(level).iter().collect(),
+            size: state.level_size_bytes(level),
+        });
+    l0.chain(deeper).collect()
+}
+
+// --------------------- FIFO ---------------------
+
+/// FIFO compaction for data that is written once and expires, such as logs
+/// and metrics.
+///
+/// Files are never merged: every flush stays in L0. Once the SSTables add
+/// up to more than `max_total_size_bytes`, or the oldest were created more
+/// than `max_age` ago, the oldest files are deleted (and removed from the
+/// manifest) until both limits hold again. A limit of `0` / `None` is
+/// disabled.
+///
+/// Files are ordered oldest-first by level (deepest first, e.g. files left
+/// behind by a previous strategy), then by manifest order within L0 and by
+/// creation time within deeper levels. The creation time is the file's
+/// modification time, or the timestamp in a legacy `sst-{seq}-{ts}.sst`
+/// name.
+///
+/// Limits are checked after every flush, like any automatic compaction, so
+/// nothing expires while auto-compaction is disabled
+/// (`l0_compaction_trigger == 0`). Because reads do not hide expired data,
+/// keys should not be updated: deleting the file holding the newest
+/// version of a key would expose an older one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FifoStrategy {
+    max_total_size_bytes: u64,
+    max_age: Option<Duration>,
+}
+
+impl FifoStrategy {
+    /// Creates a strategy with no limits; set at least one.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the total SSTable size above which the oldest files are
+    /// deleted (`0` = unlimited).
+    #[must_use]
+    pub fn max_total_size_bytes(&self) -> u64 {
+        self.max_total_size_bytes
+    }
+
+    /// Sets the total size limit in bytes (`0` = unlimited).
+    #[must_use]
+    pub fn with_max_total_size_bytes(mut self, bytes: u64) -> Self {
+        self.max_total_size_bytes = bytes;
+        self
+    }
+
+    /// Returns the age after which a file is deleted, if any.
+    #[must_use]
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    /// Sets the age after which a file is deleted (`None` = never).
+    #[must_use]
+    pub fn with_max_age(mut self, age: Option<Duration>) -> Self {
+        self.max_age = age;
+        self
+    }
+}
+
+impl CompactionStrategy for FifoStrategy {
+    fn name(&self) -> &'static str {
+        "fifo"
+    }
+
+    fn pick_compaction(&self, state: &LevelState<'_>) -> Option<CompactionJob> {
+        let mut oldest_first: Vec<(usize, &SSTableReader)> = Vec::new();
+        for level in (1..state.num_levels()).rev() {
+            let mut files: Vec<&SSTableReader> = state.files(level).iter().collect();
+            files.sort_by_key(|r| creation_time_ms(r));
+            oldest_first.extend(files.into_iter().map(|r| (level, r)));
+        }
+        oldest_first.extend(state.files(0).iter().rev().map(|r| (0, r)));
+
+        let cutoff = self
+            .max_age
+            .map(|age| now_ms().saturating_sub(age.as_millis()));
+        let mut total: u64 = oldest_first.iter().map(|(_, r)| r.file_size()).sum();
+        let mut job = CompactionJob::deletion(0);
+        for (level, reader) in oldest_first {
+            let too_big = self.max_total_size_bytes > 0 && total > self.max_total_size_bytes;
+            let too_old = cutoff.is_some_and(|c| creation_time_ms(reader) < c);
+            if !too_big && !too_old {
+                break;
+            }
+            total -= reader.file_size();
+            job.add_input(level, reader);
+        }
+        (!job.inputs.is_empty()).then_some(job)
+    }
+}
+
+/// Returns when `reader`'s file was created, in milliseconds since the Unix
+/// epoch: the timestamp of a legacy `sst-{seq}-{timestamp_ms}.sst` name,
+/// else the file's modification time, else `0`.
+pub(crate) fn creation_time_ms(reader: &SSTableReader) -> u128 {
+    let name = file_name(reader);
+    name.strip_suffix(".sst")
+        .and_then(|stem| stem.strip_prefix("sst-")?.split_once('-'))
+        .and_then(|(_, ts)| ts.parse().ok())
+        .or_else(|| {
+            let modified = std::fs::metadata(reader.path()).ok()?.modified().ok()?;
+            Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis())
+        })
+        .unwrap_or(0)
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis())
+}
+
+/// Returns the basename of the file `reader` was opened from.
+pub(crate) fn file_name(reader: &SSTableReader) -> String {
+    reader
+        .path()
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Returns the smallest and largest key across `readers`, or `None` if they
+/// are all empty.
+pub(crate) fn key_range(readers: &[&SSTableReader]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let smallest = readers.iter().filter_map(|r| r.smallest_key()).min()?;
+    let largest = readers.iter().filter_map(|r| r.largest_key()).max()?;
+    Some((smallest.to_vec(), largest.to_vec()))
+}
+
+/// Returns `true` if the key range of `reader` intersects
+/// `[smallest, largest]`.
+pub(crate) fn overlaps(reader: &SSTableReader, smallest: &[u8], largest: &[u8]) -> bool {
+    match (reader.smallest_key(), reader.largest_key()) {
+        (Some(lo), Some(hi)) => lo <= largest && hi >= smallest,
+        _ => false,
+    }
+}
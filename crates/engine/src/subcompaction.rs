@@ -0,0 +1,247 @@
+/// Subcompactions: one compaction job split into disjoint key ranges.
+///
+/// With [`Engine::set_max_subcompactions`](crate::Engine::set_max_subcompactions)
+/// above 1, a compaction whose output goes below L0 is split at keys sampled
+/// from its inputs into up to that many ranges. Each range is merged on its
+/// own worker thread (scoped to the compaction) and written to its own
+/// output SSTables. Because the ranges are disjoint, so are the outputs,
+/// exactly as if one thread had written them all.
+///
+/// The outputs of every range are installed with a single manifest update
+/// once all of them succeed. If any range fails, the outputs of all ranges
+/// are deleted and the inputs stay in place.
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::filter::{CompactionFilter, FilterDecision};
+use crate::{sst_filename, SSTableReader, MAX_VALUE_SIZE};
+use memtable::ValueEntry;
+use sstable::{MergeIterator, SSTableWriteOptions, SSTableWriter};
+
+/// Number of sampled keys per range used to pick the range boundaries.
+const SAMPLES_PER_RANGE: usize = 16;
+
+/// One SSTable written by a subcompaction.
+#[derive(Debug, Clone)]
+pub(crate) struct SubcompactionOutput {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+    /// Smallest sequence number written to the file, for its manifest entry.
+    pub(crate) min_seq: u64,
+}
+
+/// Counters reported by one subcompaction.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SubcompactionStats {
+    pub(crate) dropped_tombstones: u64,
+    pub(crate) filter_removed: u64,
+    pub(crate) filter_changed: u64,
+}
+
+impl SubcompactionStats {
+    pub(crate) fn add(&mut self, other: &SubcompactionStats) {
+        self.dropped_tombstones += other.dropped_tombstones;
+        self.filter_removed += other.filter_removed;
+        self.filter_changed += other.filter_changed;
+    }
+}
+
+/// Everything a subcompaction needs, shared by all ranges of one job.
+pub(crate) struct Subcompaction<'a> {
+    pub(crate) inputs: &'a [&'a SSTableReader],
+    pub(crate) sst_dir: &'a Path,
+    pub(crate) write_opts: &'a SSTableWriteOptions,
+    pub(crate) filter: Option<&'a Arc<dyn CompactionFilter>>,
+    pub(crate) drop_tombstones: bool,
+    pub(crate) target_file_size: u64,
+    pub(crate) expected_per_file: usize,
+    /// Next file number, shared so that concurrent ranges never pick the
+    /// same name.
+    pub(crate) next_file_number: &'a AtomicU64,
+}
+
+impl Subcompaction<'_> {
+    /// Merges the input keys in `[start, end)` (empty = unbounded) into new
+    /// SSTables of about `target_file_size` bytes, pushing each output to
+    /// `outputs` before it is written so that the caller can remove it (or
+    /// its temp file) if this or another range fails.
+    pub(crate) fn run(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        outputs: &mut Vec<SubcompactionOutput>,
+    ) -> Result<SubcompactionStats> {
+        let mut merge = MergeIterator::from_refs_in_range(self.inputs.to_vec(), start, end);
+        let mut stats = SubcompactionStats::default();
+
+        // Stream directly from MergeIterator -> SSTableWriter without
+        // materializing the entire dataset in RAM. Memory usage is bounded
+        // by the bloom filter + index of one output file, not the data
+        // volume.
+        //
+        // MergeIterator::next() returns Result<Option<...>>, so we adapt it
+        // into an iterator that stops on error or exhaustion.
+        let mut merge_error: Option<anyhow::Error> = None;
+        let write_result: Result<()> = (|| {
+            let mut stream = std::iter::from_fn(|| loop {
+                match merge.next_entry() {
+                    Ok(Some((key, mut entry))) => {
+                        if let (Some(filter), Some(_)) = (self.filter, &entry.value) {
+                            match filter.filter(&key, &entry) {
+                                FilterDecision::Keep => {}
+                                FilterDecision::Remove => {
+                                    stats.filter_removed += 1;
+                                    if self.drop_tombstones {
+                                        continue;
+                                    }
+                                    // Shadow older versions outside this job.
+                                    entry.value = None;
+                                }
+                                FilterDecision::ChangeValue(value) => {
+                                    if value.len() > MAX_VALUE_SIZE {
+                                        merge_error = Some(anyhow!(
+                                            "compaction filter {} returned a {} byte value (max {})",
+                                            filter.name(),
+                                            value.len(),
+                                            MAX_VALUE_SIZE
+                                        ));
+                                        return None;
+                                    }
+                                    stats.filter_changed += 1;
+                                    entry.value = Some(value);
+                                }
+                            }
+                        } else if entry.value.is_none() && self.drop_tombstones {
+                            stats.dropped_tombstones += 1;
+                            continue; // GC this tombstone
+                        }
+                        return Some((key, entry));
+                    }
+                    Ok(None) => return None,
+                    Err(e) => {
+                        merge_error = Some(e);
+                        return None;
+                    }
+                }
+            })
+            .peekable();
+
+            // Every entry being garbage-collected is not an error: the
+            // range simply produces no output.
+            while stream.peek().is_some() {
+                let sst_name = sst_filename(self.next_file_number.fetch_add(1, Ordering::Relaxed));
+                let sst_path = self.sst_dir.join(&sst_name);
+                outputs.push(SubcompactionOutput {
+                    name: sst_name,
+                    path: sst_path.clone(),
+                    min_seq: 0,
+                });
+
+                let mut file_bytes = 0u64;
+                let mut min_seq = u64::MAX;
+                let chunk = std::iter::from_fn(|| {
+                    if file_bytes >= self.target_file_size {
+                        return None;
+                    }
+                    let (key, entry) = stream.next()?;
+                    file_bytes += encoded_len(&key, &entry);
+                    min_seq = min_seq.min(entry.seq);
+                    Some((key, entry))
+                });
+                SSTableWriter::write_from_iterator_with_options(
+                    &sst_path,
+                    self.expected_per_file,
+                    chunk,
+                    self.write_opts,
+                )?;
+                if let Some(output) = outputs.last_mut() {
+                    output.min_seq = min_seq;
+                }
+            }
+            Ok(())
+        })();
+
+        match merge_error.or(write_result.err()) {
+            Some(e) => Err(e),
+            None => Ok(stats),
+        }
+    }
+
+    /// Runs one subcompaction per range, on worker threads if there is more
+    /// than one. Returns the outputs of every range in key order, or the
+    /// first error after removing every output written by any range.
+    pub(crate) fn run_all(
+        &self,
+        ranges: &[(Vec<u8>, Vec<u8>)],
+    ) -> Result<(Vec<SubcompactionOutput>, SubcompactionStats)> {
+        let mut outputs: Vec<Vec<SubcompactionOutput>> = vec![Vec::new(); ranges.len()];
+        let results: Vec<Result<SubcompactionStats>> = match ranges {
+            [(start, end)] => vec![self.run(start, end, &mut outputs[0])],
+            _ => std::thread::scope(|scope| {
+                let handles: Vec<_> = ranges
+                    .iter()
+                    .zip(outputs.iter_mut())
+                    .map(|((start, end), out)| scope.spawn(move || self.run(start, end, out)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|h| {
+                        h.join()
+                            .unwrap_or_else(|_| Err(anyhow!("subcompaction thread panicked")))
+                    })
+                    .collect()
+            }),
+        };
+
+        let outputs: Vec<SubcompactionOutput> = outputs.into_iter().flatten().collect();
+        let mut stats = SubcompactionStats::default();
+        for result in results {
+            match result {
+                Ok(s) => stats.add(&s),
+                Err(e) => {
+                    for output in &outputs {
+                        let _ = std::fs::remove_file(&output.path);
+                        let _ = std::fs::remove_file(output.path.with_extension("sst.tmp"));
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok((outputs, stats))
+    }
+}
+
+/// Splits the key space of `inputs` into at most `max_ranges` disjoint
+/// `[start, end)` ranges holding roughly equal numbers of keys. The first
+/// range starts and the last range ends unbounded (empty key).
+pub(crate) fn subcompaction_ranges(
+    inputs: &[&SSTableReader],
+    max_ranges: usize,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let total: usize = inputs.iter().map(|r| r.len()).sum();
+    let step = (total / (max_ranges * SAMPLES_PER_RANGE).max(1)).max(1);
+    let mut samples: Vec<&[u8]> = inputs.iter().flat_map(|r| r.keys().step_by(step)).collect();
+    samples.sort_unstable();
+    samples.dedup();
+
+    // Boundaries are strictly increasing, non-empty keys, so every range is
+    // well-formed and no key falls into two ranges.
+    let ranges = max_ranges.min(samples.len()).max(1);
+    let mut bounds: Vec<Vec<u8>> = vec![Vec::new()];
+    bounds.extend((1..ranges).map(|i| samples[i * samples.len() / ranges].to_vec()));
+    bounds.push(Vec::new());
+    bounds
+        .windows(2)
+        .map(|w| (w[0].clone(), w[1].clone()))
+        .collect()
+}
+
+/// Approximate encoded size of one SSTable data record, used to cut
+/// compaction outputs at the target file size.
+fn encoded_len(key: &[u8], entry: &ValueEntry) -> u64 {
+    // crc + key_len + key + seq + present [+ val_len + val]
+    let value = entry.value.as_ref().map_or(0, |v| 4 + v.len());
+    (4 + 4 + key.len() + 8 + 1 + value) as u64
+}
@@ -391,3 +391,693 @@ fn compact_then_recovery_works() -> Result<()> {
     }
     Ok(())
 }
+
+// --------------------- Leveled compaction ---------------------
+
+/// Writes `{prefix}{i:04}` for every `i` in `range`, then flushes.
+fn flush_keys(engine: &mut Engine, prefix: &str, range: std::ops::Range<u32>) -> Result<()> {
+    for i in range {
+        engine.set(format!("{}{:04}", prefix, i).into_bytes(), b"v".to_vec())?;
+    }
+    engine.force_flush()
+}
+
+/// Basenames of the SSTables in `level`.
+fn level_files(engine: &Engine, level: usize) -> Vec<String> {
+    engine.levels[level]
+        .iter()
+        .map(|r| r.path().file_name().unwrap().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[test]
+fn level_target_sizes_grow_by_multiplier() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1024,
+        false,
+    )?;
+    assert_eq!(engine.max_levels(), DEFAULT_MAX_LEVELS);
+    assert_eq!(engine.level_sstable_counts().len(), DEFAULT_MAX_LEVELS);
+
+    engine.set_level_base_bytes(1000);
+    engine.set_level_size_multiplier(4);
+    assert_eq!(engine.level_target_bytes(0), 0);
+    assert_eq!(engine.level_target_bytes(1), 1000);
+    assert_eq!(engine.level_target_bytes(2), 4000);
+    assert_eq!(engine.level_target_bytes(3), 16000);
+    assert_eq!(engine.level_target_bytes(60), u64::MAX);
+
+    engine.set_max_levels(1);
+    assert_eq!(engine.max_levels(), 2);
+    engine.set_max_levels(10);
+    assert_eq!(engine.level_sstable_counts().len(), 10);
+    Ok(())
+}
+
+#[test]
+fn l0_compaction_only_rewrites_overlapping_l1_files() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1 << 20,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(2);
+
+    // Two disjoint L1 files: "a..." and "m...".
+    flush_keys(&mut engine, "a", 0..10)?;
+    flush_keys(&mut engine, "a", 5..15)?;
+    flush_keys(&mut engine, "m", 0..10)?;
+    flush_keys(&mut engine, "m", 5..15)?;
+    assert_eq!(engine.l0_sstable_count(), 0);
+    assert_eq!(engine.l1_sstable_count(), 2);
+    let before = level_files(&engine, 1);
+
+    // New "a" data only overlaps the first L1 file.
+    flush_keys(&mut engine, "a", 10..20)?;
+    flush_keys(&mut engine, "a", 20..30)?;
+    let after = level_files(&engine, 1);
+    assert_eq!(after.len(), 2);
+    assert!(
+        !after.contains(&before[0]),
+        "overlapping L1 file is rewritten"
+    );
+    assert!(after.contains(&before[1]), "disjoint L1 file is untouched");
+
+    for i in 0..30 {
+        assert!(engine.get(format!("a{:04}", i).as_bytes())?.is_some());
+    }
+    for i in 0..15 {
+        assert!(engine.get(format!("m{:04}", i).as_bytes())?.is_some());
+    }
+    Ok(())
+}
+
+#[test]
+fn oversized_level_pushes_one_file_down() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1 << 20,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(1);
+    engine.set_max_levels(4);
+
+    // L1 holds up to ~1.5 files, L2 up to ~4.5 files.
+    flush_keys(&mut engine, "a", 0..50)?;
+    let file_size = engine.level_size_bytes(1);
+    engine.set_level_base_bytes(file_size * 3 / 2);
+    engine.set_level_size_multiplier(3);
+
+    flush_keys(&mut engine, "b", 0..50)?;
+    // L1 exceeded its target: one file moved down, without a rewrite.
+    assert_eq!(engine.level_sstable_counts()[..3], [0, 1, 1]);
+    assert!(engine.level_size_bytes(1) <= engine.level_target_bytes(1));
+
+    for prefix in ["c", "d", "e", "f", "g", "h"] {
+        flush_keys(&mut engine, prefix, 0..50)?;
+        for level in 1..engine.max_levels() - 1 {
+            assert!(engine.level_size_bytes(level) <= engine.level_target_bytes(level));
+        }
+    }
+    assert!(
+        engine.level_sstable_count(3) > 0,
+        "data reaches the last level"
+    );
+
+    for prefix in ["a", "b", "c", "d", "e", "f", "g", "h"] {
+        for i in 0..50 {
+            let key = format!("{}{:04}", prefix, i);
+            assert!(engine.get(key.as_bytes())?.is_some(), "{} missing", key);
+        }
+    }
+    assert_eq!(engine.scan(b"", b"")?.len(), 8 * 50);
+    Ok(())
+}
+
+#[test]
+fn level_compaction_walks_files_round_robin() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1 << 20,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(1);
+    engine.set_max_levels(3);
+
+    for prefix in ["a", "b", "c"] {
+        flush_keys(&mut engine, prefix, 0..20)?;
+    }
+    assert_eq!(engine.l1_sstable_count(), 3);
+    let l1 = level_files(&engine, 1);
+
+    // Shrink the L1 budget so two files must leave, one per compaction.
+    engine.set_level_base_bytes(engine.level_size_bytes(1) / 3 + 1);
+    engine.maybe_compact()?;
+    assert_eq!(level_files(&engine, 1), vec![l1[2].clone()]);
+    assert_eq!(level_files(&engine, 2), vec![l1[0].clone(), l1[1].clone()]);
+    Ok(())
+}
+
+#[test]
+fn tombstones_survive_until_no_deeper_data() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1 << 20,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(1);
+    engine.set_max_levels(3);
+
+    // Put "k" into L2.
+    engine.set(b"k".to_vec(), b"old".to_vec())?;
+    engine.set(b"z".to_vec(), b"z".to_vec())?;
+    engine.force_flush()?;
+    engine.set_level_base_bytes(1);
+    engine.maybe_compact()?;
+    assert_eq!(engine.level_sstable_counts(), vec![0, 0, 1]);
+
+    // Delete it: L0 -> L1 must keep the tombstone to shadow L2.
+    engine.set_level_base_bytes(1 << 30);
+    engine.del(b"k".to_vec())?;
+    engine.set(b"j".to_vec(), b"j".to_vec())?;
+    engine.force_flush()?;
+    assert_eq!(engine.level_sstable_counts(), vec![0, 1, 1]);
+    assert!(engine.get(b"k")?.is_none(), "tombstone must shadow L2");
+
+    // Merging into the last level finally drops it.
+    engine.set_level_base_bytes(1);
+    engine.maybe_compact()?;
+    assert_eq!(engine.level_sstable_counts(), vec![0, 0, 1]);
+    assert!(engine.get(b"k")?.is_none());
+    assert!(engine.levels[2][0].get(b"k")?.is_none());
+    assert_eq!(engine.get(b"j")?.unwrap().1, b"j");
+    Ok(())
+}
+
+#[test]
+fn deeper_levels_probe_one_file_per_level() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1 << 20,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(1);
+    for prefix in ["a", "b", "c", "d"] {
+        flush_keys(&mut engine, prefix, 0..10)?;
+    }
+    assert_eq!(engine.l1_sstable_count(), 4);
+
+    engine.get(b"c0003")?;
+    engine.get(b"bz")?; // between files
+    let probes = engine.stats().sstables_probed_per_get;
+    assert_eq!(probes.count, 2);
+    assert_eq!(probes.max, 1);
+    assert_eq!(probes.sum, 1);
+    Ok(())
+}
+
+#[test]
+fn levels_survive_restart() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+
+    let counts = {
+        let mut engine = Engine::new(&wal, &sst, 1 << 20, false)?;
+        engine.set_l0_compaction_trigger(1);
+        engine.set_max_levels(4);
+        for prefix in ["a", "b", "c"] {
+            flush_keys(&mut engine, prefix, 0..20)?;
+        }
+        engine.set_level_base_bytes(1);
+        engine.maybe_compact()?;
+        flush_keys(&mut engine, "d", 0..20)?;
+        engine.level_sstable_counts()
+    };
+    assert!(counts[3] > 0, "expected data in L3: {:?}", counts);
+
+    let manifest = Manifest::load_or_create(&sst)?;
+    assert!(!manifest.level_filenames(3).is_empty(), "{:?}", manifest);
+
+    let engine = Engine::new(&wal, &sst, 1 << 20, false)?;
+    assert_eq!(engine.level_sstable_counts()[..4], counts[..4]);
+    for prefix in ["a", "b", "c", "d"] {
+        for i in 0..20 {
+            assert!(engine
+                .get(format!("{}{:04}", prefix, i).as_bytes())?
+                .is_some());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn failed_manifest_save_keeps_compaction_inputs() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+
+    {
+        let mut engine = Engine::new(&wal, &sst, 1 << 20, false)?;
+        engine.set_l0_compaction_trigger(0);
+        for prefix in ["a", "b", "c"] {
+            flush_keys(&mut engine, prefix, 0..20)?;
+        }
+        let before = level_files(&engine, 0);
+
+        // A directory in place of the manifest log makes the save fail.
+        let current = fs::read_to_string(sst.join(manifest::CURRENT_FILENAME))?;
+        let log = sst.join(current.trim());
+        fs::remove_file(&log)?;
+        fs::create_dir(&log)?;
+        assert!(engine.compact().is_err());
+        assert_eq!(level_files(&engine, 0), before);
+        assert_eq!(engine.manifest.level_filenames(0), before);
+
+        // The next flush snapshots the manifest without the compaction.
+        flush_keys(&mut engine, "d", 0..20)?;
+    }
+
+    let engine = Engine::new(&wal, &sst, 1 << 20, false)?;
+    for prefix in ["a", "b", "c", "d"] {
+        for i in 0..20 {
+            assert!(engine
+                .get(format!("{}{:04}", prefix, i).as_bytes())?
+                .is_some());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn failed_manifest_sync_loses_no_data_across_a_crash() -> Result<()> {
+    use manifest::AppendFault;
+
+    for fault in [AppendFault::Sync, AppendFault::SyncAndTruncate] {
+        let dir = tempdir()?;
+        let wal = dir.path().join("wal.log");
+        let sst = dir.path().join("sst");
+
+        let mut engine = Engine::new(&wal, &sst, 1 << 20, false)?;
+        engine.set_l0_compaction_trigger(0);
+        for prefix in ["a", "b", "c"] {
+            flush_keys(&mut engine, prefix, 0..20)?;
+        }
+        let inputs = level_files(&engine, 0);
+
+        // The record reaches the log before the sync fails; whether it
+        // stays there depends on `fault`.
+        engine.manifest.append_fault = Some(fault);
+        assert!(engine.compact().is_err());
+        assert_eq!(level_files(&engine, 0), inputs);
+        assert!(super::helpers::count_sst_files(&sst) > inputs.len());
+        if fault == AppendFault::SyncAndTruncate {
+            assert!(engine.delete_obsolete_files().is_err());
+        }
+        std::mem::forget(engine);
+
+        let mut engine = Engine::new(&wal, &sst, 1 << 20, false)?;
+        for prefix in ["a", "b", "c"] {
+            for i in 0..20 {
+                assert!(
+                    engine
+                        .get(format!("{}{:04}", prefix, i).as_bytes())?
+                        .is_some(),
+                    "{:?}: {}{:04} lost",
+                    fault,
+                    prefix,
+                    i
+                );
+            }
+        }
+        assert!(engine.delete_obsolete_files()?.sst_files.is_empty());
+        assert_eq!(
+            super::helpers::count_sst_files(&sst),
+            engine.level_sstable_counts().iter().sum::<usize>()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn full_compaction_writes_to_deepest_level() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1 << 20,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(1);
+    engine.set_max_levels(3);
+    flush_keys(&mut engine, "a", 0..10)?;
+    engine.set_level_base_bytes(1);
+    engine.maybe_compact()?;
+
+    engine.set_l0_compaction_trigger(0);
+    flush_keys(&mut engine, "b", 0..10)?;
+    flush_keys(&mut engine, "c", 0..10)?;
+    assert_eq!(engine.level_sstable_counts(), vec![2, 0, 1]);
+
+    engine.compact()?;
+    assert_eq!(engine.level_sstable_counts(), vec![0, 0, 1]);
+    assert_eq!(engine.scan(b"", b"")?.len(), 30);
+    Ok(())
+}
+
+// --------------------- Range compaction ---------------------
+
+#[test]
+fn compact_range_only_touches_overlapping_files() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    flush_keys(&mut engine, "a", 0..10)?;
+    flush_keys(&mut engine, "m", 0..10)?;
+    flush_keys(&mut engine, "z", 0..10)?;
+    let before = level_files(&engine, 0);
+
+    engine.compact_range(b"m0003", b"m0005")?;
+
+    let l0 = level_files(&engine, 0);
+    assert_eq!(l0, vec![before[0].clone(), before[2].clone()]);
+    assert_eq!(engine.l1_sstable_count(), 1);
+    for prefix in ["a", "m", "z"] {
+        let key = format!("{}{:04}", prefix, 7).into_bytes();
+        assert!(engine.get(&key)?.is_some());
+    }
+    Ok(())
+}
+
+#[test]
+fn compact_range_reclaims_deleted_keys() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    flush_keys(&mut engine, "k", 0..100)?;
+    flush_keys(&mut engine, "k", 100..200)?;
+    engine.compact()?;
+
+    for i in 10..50u32 {
+        engine.del(format!("k{:04}", i).into_bytes())?;
+    }
+    engine.force_flush()?;
+    engine.compact_range(b"k0010", b"k0050")?;
+
+    assert_eq!(engine.level_sstable_counts()[..2], [0, 1]);
+    assert_eq!(
+        engine.levels[1][0].len(),
+        160,
+        "tombstones and values dropped"
+    );
+    assert_eq!(engine.get(b"k0010")?, None);
+    assert!(engine.get(b"k0050")?.is_some());
+    Ok(())
+}
+
+#[test]
+fn compact_range_pushes_through_every_level() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    engine.set_max_levels(4);
+    flush_keys(&mut engine, "a", 0..20)?;
+    flush_keys(&mut engine, "b", 0..20)?;
+    engine.compact()?;
+    let deepest = level_files(&engine, 1);
+
+    // Move L1 down to L3 by hand, then stack newer data above it.
+    let mut meta = engine.manifest.file(&deepest[0]).unwrap().clone();
+    meta.level = 3;
+    engine.manifest.remove_files(&[deepest[0].as_str()]);
+    engine.manifest.add(meta);
+    engine.manifest.save()?;
+    let reader = engine.levels[1].remove(0);
+    engine.levels[3].push(reader);
+
+    engine.del(b"a0005".to_vec())?;
+    engine.set(b"b0005".to_vec(), b"new".to_vec())?;
+    engine.force_flush()?;
+
+    engine.compact_range(b"", b"")?;
+    assert_eq!(engine.level_sstable_counts(), vec![0, 0, 0, 1]);
+    assert_eq!(engine.get(b"a0005")?, None);
+    assert_eq!(engine.get(b"b0005")?.unwrap().1, b"new");
+    assert_eq!(engine.levels[3][0].len(), 39);
+    Ok(())
+}
+
+#[test]
+fn compact_range_rewrites_bottommost_files_in_place() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    flush_keys(&mut engine, "a", 0..10)?;
+    flush_keys(&mut engine, "b", 0..10)?;
+    engine.compact()?;
+    let before = level_files(&engine, 1);
+
+    engine.compact_range(b"c", b"d")?;
+    assert_eq!(level_files(&engine, 1), before, "range holds no files");
+
+    engine.compact_range(b"a", b"")?;
+    let after = level_files(&engine, 1);
+    assert_eq!(after.len(), 1);
+    assert_ne!(after, before, "file rewritten, not moved");
+    assert!(engine.get(b"b0009")?.is_some());
+    Ok(())
+}
+
+// --------------------- Output file size ---------------------
+
+#[test]
+fn compaction_output_is_split_at_target_file_size() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    assert_eq!(
+        engine.target_file_size_bytes(),
+        DEFAULT_TARGET_FILE_SIZE_BYTES
+    );
+    for batch in 0..2u32 {
+        for i in 0..200u32 {
+            let key = format!("k{:04}", i * 2 + batch).into_bytes();
+            engine.set(key, vec![b'v'; 100])?;
+        }
+        engine.force_flush()?;
+    }
+
+    engine.set_target_file_size_bytes(4096);
+    engine.compact()?;
+
+    // 400 records of ~130 bytes: about 13 files of 4 KiB of data each.
+    let files = &engine.levels[1];
+    assert!(files.len() >= 12, "got {} files", files.len());
+    for pair in files.windows(2) {
+        assert!(
+            pair[0].largest_key() < pair[1].smallest_key(),
+            "outputs must cover disjoint key ranges"
+        );
+    }
+    assert_eq!(files.iter().map(|r| r.len()).sum::<usize>(), 400);
+
+    for i in 0..400u32 {
+        let key = format!("k{:04}", i).into_bytes();
+        assert!(engine.get(&key)?.is_some());
+    }
+    let probes = engine.stats().sstables_probed_per_get;
+    assert_eq!(probes.max, 1, "one file probed per lookup");
+    Ok(())
+}
+
+#[test]
+fn split_outputs_survive_restart() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+    let files = {
+        let mut engine = Engine::new(&wal, &sst, usize::MAX, false)?;
+        engine.set_l0_compaction_trigger(0);
+        engine.set_target_file_size_bytes(512);
+        flush_keys(&mut engine, "a", 0..100)?;
+        flush_keys(&mut engine, "b", 0..100)?;
+        engine.compact()?;
+        level_files(&engine, 1)
+    };
+    assert!(files.len() > 1);
+
+    let engine = Engine::new(&wal, &sst, usize::MAX, false)?;
+    assert_eq!(level_files(&engine, 1), files);
+    assert!(engine.get(b"a0050")?.is_some());
+    assert!(engine.get(b"b0099")?.is_some());
+    Ok(())
+}
+
+#[test]
+fn l0_outputs_are_never_split() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_compaction_strategy(std::sync::Arc::new(SizeTieredStrategy::new()));
+    engine.set_l0_compaction_trigger(2);
+    engine.set_target_file_size_bytes(1);
+
+    flush_keys(&mut engine, "a", 0..50)?;
+    flush_keys(&mut engine, "b", 0..50)?;
+    assert_eq!(engine.level_sstable_counts()[..2], [1, 0]);
+    Ok(())
+}
+
+// --------------------- Tombstone-triggered compaction ---------------------
+
+/// Deletes `{prefix}{i:04}` for every `i` in `range`, then flushes.
+fn flush_deletes(engine: &mut Engine, prefix: &str, range: std::ops::Range<u32>) -> Result<()> {
+    for i in range {
+        engine.del(format!("{}{:04}", prefix, i).into_bytes())?;
+    }
+    engine.force_flush()
+}
+
+#[test]
+fn tombstone_ratio_triggers_compaction_below_l0_trigger() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    assert_eq!(engine.tombstone_compaction_ratio(), 0.0);
+    engine.set_tombstone_compaction_ratio(1.5);
+    assert_eq!(engine.tombstone_compaction_ratio(), 1.0);
+
+    flush_keys(&mut engine, "a", 0..100)?;
+    flush_keys(&mut engine, "b", 0..100)?;
+    assert_eq!(engine.level_sstable_counts()[..2], [2, 0]);
+
+    // Flushing a file that is all tombstones pushes it, and the L0 file it
+    // overlaps, down to L1 well before the L0 trigger of 4.
+    flush_deletes(&mut engine, "a", 0..80)?;
+    assert_eq!(engine.level_sstable_counts()[..2], [1, 1]);
+    let l1 = &engine.levels[1][0];
+    assert_eq!(l1.len(), 20);
+    assert_eq!(l1.tombstone_count(), Some(0), "tombstones dropped at L1");
+    assert_eq!(engine.scan(b"a", b"b")?.len(), 20);
+    Ok(())
+}
+
+#[test]
+fn low_tombstone_ratio_does_not_trigger_compaction() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_tombstone_compaction_ratio(0.5);
+    flush_keys(&mut engine, "a", 0..100)?;
+    for i in 0..10 {
+        engine.del(format!("a{:04}", i).into_bytes())?;
+    }
+    flush_keys(&mut engine, "b", 0..90)?;
+    assert_eq!(engine.levels[0][0].tombstone_count(), Some(10));
+    assert_eq!(engine.level_sstable_counts()[..2], [2, 0]);
+    Ok(())
+}
+
+#[test]
+fn old_tombstones_trigger_compaction() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_tombstone_compaction_age(Some(Duration::from_millis(300)));
+    flush_keys(&mut engine, "a", 0..100)?;
+    engine.del(b"a0001".to_vec())?;
+    flush_keys(&mut engine, "b", 0..100)?;
+    assert_eq!(engine.level_sstable_counts()[..2], [2, 0]);
+
+    thread::sleep(Duration::from_millis(400));
+    flush_keys(&mut engine, "c", 0..10)?;
+    // The file holding the tombstone went to L1 together with the older
+    // file it overlaps; the newest file holds no tombstone and stays.
+    assert_eq!(engine.level_sstable_counts()[..2], [1, 1]);
+    assert_eq!(engine.levels[1][0].tombstone_count(), Some(0));
+    assert_eq!(engine.get(b"a0001")?, None);
+    assert!(engine.get(b"b0001")?.is_some());
+    Ok(())
+}
+
+#[test]
+fn bottommost_tombstone_file_is_rewritten_in_place() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    flush_keys(&mut engine, "a", 0..10)?;
+    flush_deletes(&mut engine, "b", 0..10)?;
+    let mut job = CompactionJob::new(0, 1);
+    job.add_input(0, &engine.levels[0][0]);
+    engine.run_compaction(job)?; // trivial move keeps the tombstones
+    assert_eq!(engine.levels[1][0].tombstone_count(), Some(10));
+
+    engine.set_l0_compaction_trigger(4);
+    engine.set_tombstone_compaction_ratio(0.9);
+    engine.maybe_compact()?;
+    assert_eq!(engine.level_sstable_counts()[..2], [1, 0]);
+
+    // Rewriting the b-file dropped every entry; the a-file is untouched.
+    assert!(engine.get(b"a0005")?.is_some());
+    assert_eq!(engine.sstable_count(), 1);
+    Ok(())
+}
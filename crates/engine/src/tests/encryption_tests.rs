@@ -0,0 +1,186 @@
+use crate::*;
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn keys(id: u32, byte: u8) -> Arc<dyn KeyProvider> {
+    Arc::new(StaticKeyProvider::new(id, Key::new([byte; 32])))
+}
+
+fn open(dir: &Path, keys: Arc<dyn KeyProvider>) -> Result<Engine> {
+    Engine::open_encrypted(
+        dir.join("wal.log"),
+        dir.join("sst"),
+        usize::MAX,
+        false,
+        WalRecoveryMode::default(),
+        keys,
+    )
+}
+
+fn value(engine: &Engine, key: &[u8]) -> Result<Option<Vec<u8>>> {
+    Ok(engine.get(key)?.map(|(_, value)| value))
+}
+
+fn encryption_error(err: &anyhow::Error) -> Option<&EncryptionError> {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<EncryptionError>())
+}
+
+/// Asserts that no file under `dir` contains `needle`.
+fn assert_not_on_disk(dir: &Path, needle: &[u8]) {
+    for entry in fs::read_dir(dir).unwrap().flatten() {
+        let data = fs::read(entry.path()).unwrap();
+        assert!(
+            !data.windows(needle.len()).any(|w| w == needle),
+            "{} holds plaintext",
+            entry.path().display()
+        );
+    }
+}
+
+// --------------------- Encryption at rest ---------------------
+
+#[test]
+fn encrypted_engine_recovers_after_a_crash() -> Result<()> {
+    let dir = tempdir()?;
+    {
+        let mut engine = open(dir.path(), keys(1, 1))?;
+        engine.set(b"flushed".to_vec(), b"secret-1".to_vec())?;
+        engine.force_flush()?;
+        engine.set(b"logged".to_vec(), b"secret-2".to_vec())?;
+        engine.del(b"flushed".to_vec())?;
+        engine.set(b"batched".to_vec(), b"secret-3".to_vec())?;
+        // Skip the flush-on-drop so the last writes only live in the WAL.
+        engine.read_only = true;
+    }
+    assert_not_on_disk(&dir.path().join("sst"), b"secret");
+    assert_not_on_disk(&dir.path().join("sst"), b"flushed");
+
+    let engine = open(dir.path(), keys(1, 1))?;
+    assert_eq!(value(&engine, b"flushed")?, None);
+    assert_eq!(value(&engine, b"logged")?, Some(b"secret-2".to_vec()));
+    assert_eq!(value(&engine, b"batched")?, Some(b"secret-3".to_vec()));
+    assert_eq!(engine.seq(), 4);
+    Ok(())
+}
+
+#[test]
+fn wrong_or_missing_key_fails_cleanly() -> Result<()> {
+    let dir = tempdir()?;
+    {
+        let mut engine = open(dir.path(), keys(1, 1))?;
+        engine.set(b"k".to_vec(), b"v".to_vec())?;
+        engine.force_flush()?;
+    }
+
+    let err = open(dir.path(), keys(1, 2)).unwrap_err();
+    assert!(matches!(
+        encryption_error(&err),
+        Some(EncryptionError::WrongKey(1))
+    ));
+    let err = open(dir.path(), keys(2, 1)).unwrap_err();
+    assert!(matches!(
+        encryption_error(&err),
+        Some(EncryptionError::UnknownKey(1))
+    ));
+    let err = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1024,
+        false,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        encryption_error(&err),
+        Some(EncryptionError::NoKeyProvider(1))
+    ));
+    assert!(format!("{:#}", err).contains("key 1"));
+    Ok(())
+}
+
+#[test]
+fn rotated_key_reads_old_files_and_rewrites_them() -> Result<()> {
+    let dir = tempdir()?;
+    {
+        let mut engine = open(dir.path(), keys(1, 1))?;
+        engine.set_l0_compaction_trigger(0);
+        engine.set(b"a".to_vec(), b"1".to_vec())?;
+        engine.force_flush()?;
+        engine.set(b"b".to_vec(), b"2".to_vec())?;
+    }
+
+    let rotated: Arc<dyn KeyProvider> =
+        Arc::new(StaticKeyProvider::new(2, Key::new([2; 32])).with_key(1, Key::new([1; 32])));
+    {
+        let mut engine = open(dir.path(), rotated)?;
+        engine.set_l0_compaction_trigger(0);
+        assert_eq!(value(&engine, b"a")?, Some(b"1".to_vec()));
+        assert_eq!(value(&engine, b"b")?, Some(b"2".to_vec()));
+        engine.set(b"c".to_vec(), b"3".to_vec())?;
+        engine.force_flush()?;
+        engine.compact()?;
+        for reader in engine.levels.iter().flatten() {
+            assert_eq!(reader.key_id(), Some(2));
+        }
+    }
+
+    // everything live was rewritten under key 2
+    let engine = open(dir.path(), keys(2, 2))?;
+    assert_eq!(value(&engine, b"a")?, Some(b"1".to_vec()));
+    assert_eq!(value(&engine, b"b")?, Some(b"2".to_vec()));
+    assert_eq!(value(&engine, b"c")?, Some(b"3".to_vec()));
+    Ok(())
+}
+
+#[test]
+fn plain_database_is_encrypted_going_forward() -> Result<()> {
+    let dir = tempdir()?;
+    let (wal, sst) = (dir.path().join("wal.log"), dir.path().join("sst"));
+    {
+        let mut engine = Engine::new(&wal, &sst, usize::MAX, false)?;
+        engine.set(b"old".to_vec(), b"plain".to_vec())?;
+        engine.force_flush()?;
+    }
+
+    {
+        let mut engine = open(dir.path(), keys(1, 1))?;
+        assert_eq!(value(&engine, b"old")?, Some(b"plain".to_vec()));
+        engine.set(b"new".to_vec(), b"secret".to_vec())?;
+        engine.force_flush()?;
+    }
+    assert_not_on_disk(&sst, b"secret");
+
+    let engine = open(dir.path(), keys(1, 1))?;
+    assert_eq!(value(&engine, b"old")?, Some(b"plain".to_vec()));
+    assert_eq!(value(&engine, b"new")?, Some(b"secret".to_vec()));
+    assert!(Engine::open_read_only(&wal, &sst).is_err());
+    Ok(())
+}
+
+#[test]
+fn read_only_engine_reads_encrypted_files() -> Result<()> {
+    let dir = tempdir()?;
+    let (wal, sst) = (dir.path().join("wal.log"), dir.path().join("sst"));
+    let mut primary = open(dir.path(), keys(1, 1))?;
+    primary.set(b"a".to_vec(), b"1".to_vec())?;
+    primary.force_flush()?;
+    primary.set(b"b".to_vec(), b"2".to_vec())?;
+
+    let mut secondary = Engine::open_read_only_encrypted(&wal, &sst, keys(1, 1))?;
+    assert_eq!(value(&secondary, b"a")?, Some(b"1".to_vec()));
+    assert_eq!(value(&secondary, b"b")?, Some(b"2".to_vec()));
+
+    primary.set(b"c".to_vec(), b"3".to_vec())?;
+    primary.force_flush()?;
+    secondary.try_catch_up()?;
+    assert_eq!(value(&secondary, b"c")?, Some(b"3".to_vec()));
+
+    let err = Engine::open_read_only(&wal, &sst).unwrap_err();
+    assert!(matches!(
+        encryption_error(&err),
+        Some(EncryptionError::NoKeyProvider(1))
+    ));
+    Ok(())
+}
@@ -0,0 +1,231 @@
+use crate::*;
+use anyhow::Result;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+
+/// Records every callback as a short string, plus the full infos the tests
+/// inspect.
+#[derive(Default)]
+struct RecordingListener {
+    events: Mutex<Vec<String>>,
+    flushes: Mutex<Vec<FlushJobInfo>>,
+    compactions: Mutex<Vec<CompactionJobInfo>>,
+    errors: Mutex<Vec<BackgroundErrorInfo>>,
+}
+
+impl RecordingListener {
+    fn push(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn events(&self) -> Vec<String> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl EventListener for RecordingListener {
+    fn on_flush_begin(&self, info: &FlushJobInfo) {
+        self.push(format!("flush_begin {}", info.entries));
+    }
+
+    fn on_flush_completed(&self, info: &FlushJobInfo) {
+        self.push("flush_completed".to_string());
+        self.flushes.lock().unwrap().push(info.clone());
+    }
+
+    fn on_compaction_begin(&self, info: &CompactionJobInfo) {
+        self.push(format!("compaction_begin {}", info.input_files.len()));
+    }
+
+    fn on_compaction_completed(&self, info: &CompactionJobInfo) {
+        self.push("compaction_completed".to_string());
+        self.compactions.lock().unwrap().push(info.clone());
+    }
+
+    fn on_table_file_created(&self, info: &TableFileInfo) {
+        self.push(format!("created {:?}", info.reason));
+    }
+
+    fn on_table_file_deleted(&self, info: &TableFileInfo) {
+        self.push(format!("deleted {:?}", info.reason));
+    }
+
+    fn on_wal_truncated(&self, info: &WalTruncatedInfo) {
+        self.push(format!("wal_truncated {}", info.bytes_truncated > 0));
+    }
+
+    fn on_background_error(&self, info: &BackgroundErrorInfo) {
+        self.push(format!("error {:?}", info.job));
+        self.errors.lock().unwrap().push(info.clone());
+    }
+}
+
+fn open_with_listener(dir: &std::path::Path) -> Result<(Engine, Arc<RecordingListener>)> {
+    let mut engine = Engine::new(dir.join("wal.log"), dir.join("sst"), 1024 * 1024, false)?;
+    engine.set_l0_compaction_trigger(0);
+    let listener = Arc::new(RecordingListener::default());
+    engine.add_event_listener(listener.clone());
+    Ok((engine, listener))
+}
+
+// --------------------- Flush events ---------------------
+
+#[test]
+fn flush_emits_events_in_order() -> Result<()> {
+    let dir = tempdir()?;
+    let (mut engine, listener) = open_with_listener(dir.path())?;
+
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    engine.del(b"c".to_vec())?;
+    engine.force_flush()?;
+
+    assert_eq!(
+        listener.events(),
+        vec![
+            "flush_begin 3",
+            "created Flush",
+            "wal_truncated true",
+            "flush_completed",
+        ]
+    );
+
+    let flushes = listener.flushes.lock().unwrap();
+    let info = &flushes[0];
+    assert_eq!(info.entries, 3);
+    assert_eq!(info.max_seq, 3);
+    assert!(info.path.exists());
+    assert_eq!(
+        info.path.file_name().unwrap().to_str(),
+        Some(info.sst_name.as_str())
+    );
+    assert_eq!(info.file_size, fs::metadata(&info.path)?.len());
+    Ok(())
+}
+
+#[test]
+fn empty_flush_emits_nothing() -> Result<()> {
+    let dir = tempdir()?;
+    let (mut engine, listener) = open_with_listener(dir.path())?;
+    engine.force_flush()?;
+    assert!(listener.events().is_empty());
+    Ok(())
+}
+
+// --------------------- Compaction events ---------------------
+
+#[test]
+fn compaction_reports_inputs_outputs_and_deleted_files() -> Result<()> {
+    let dir = tempdir()?;
+    let (mut engine, listener) = open_with_listener(dir.path())?;
+
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    engine.force_flush()?;
+    engine.del(b"a".to_vec())?;
+    engine.force_flush()?;
+    // A newer value in the memtable lets compaction drop the tombstone.
+    engine.set(b"a".to_vec(), b"3".to_vec())?;
+
+    listener.events.lock().unwrap().clear();
+    engine.compact()?;
+
+    assert_eq!(
+        listener.events(),
+        vec![
+            "compaction_begin 2",
+            "created Compaction",
+            "deleted Compaction",
+            "deleted Compaction",
+            "compaction_completed",
+        ]
+    );
+
+    let compactions = listener.compactions.lock().unwrap();
+    let info = &compactions[0];
+    assert_eq!(info.input_files.len(), 2);
+    assert_eq!(info.output_files.len(), 1);
+    assert_eq!(info.output_level, 1);
+    assert_eq!(info.output_entries, 1);
+    assert_eq!(info.dropped_tombstones, 1);
+    assert!(info.bytes_read > 0);
+    assert!(info.bytes_written > 0);
+    for input in &info.input_files {
+        assert!(!dir.path().join("sst").join(input).exists());
+    }
+    assert!(dir.path().join("sst").join(&info.output_files[0]).exists());
+    Ok(())
+}
+
+#[test]
+fn auto_compaction_notifies_after_flush() -> Result<()> {
+    let dir = tempdir()?;
+    let (mut engine, listener) = open_with_listener(dir.path())?;
+    engine.set_l0_compaction_trigger(2);
+
+    for i in 0..2u8 {
+        engine.set(vec![i], vec![i])?;
+        engine.force_flush()?;
+    }
+
+    let events = listener.events();
+    let flush_done = events.iter().rposition(|e| e == "flush_completed").unwrap();
+    let compaction_begin = events
+        .iter()
+        .position(|e| e.starts_with("compaction_begin"))
+        .unwrap();
+    assert!(flush_done < compaction_begin);
+    assert_eq!(
+        events.last().map(String::as_str),
+        Some("compaction_completed")
+    );
+    Ok(())
+}
+
+// --------------------- Background errors ---------------------
+
+#[test]
+fn failed_flush_reports_background_error() -> Result<()> {
+    let dir = tempdir()?;
+    let (mut engine, listener) = open_with_listener(dir.path())?;
+
+    engine.set(b"k".to_vec(), b"v".to_vec())?;
+    fs::remove_dir_all(dir.path().join("sst"))?;
+
+    let err = engine.force_flush().unwrap_err();
+    assert_eq!(
+        listener.events(),
+        vec!["flush_begin 1", "error Flush"],
+        "a failed flush must not report completion"
+    );
+    let errors = listener.errors.lock().unwrap();
+    assert_eq!(errors[0].job, BackgroundJob::Flush);
+    assert_eq!(errors[0].error, format!("{:#}", err));
+
+    // The data is still in the memtable and WAL.
+    assert_eq!(engine.get(b"k")?.unwrap().1, b"v");
+    engine.read_only = true; // skip flush-on-drop
+    Ok(())
+}
+
+#[test]
+fn listeners_are_called_in_registration_order() -> Result<()> {
+    struct Tagged(&'static str, Arc<Mutex<Vec<&'static str>>>);
+    impl EventListener for Tagged {
+        fn on_flush_completed(&self, _info: &FlushJobInfo) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    let dir = tempdir()?;
+    let (mut engine, _) = open_with_listener(dir.path())?;
+    let order = Arc::new(Mutex::new(Vec::new()));
+    engine.add_event_listener(Arc::new(Tagged("first", order.clone())));
+    engine.add_event_listener(Arc::new(Tagged("second", order.clone())));
+
+    engine.set(b"k".to_vec(), b"v".to_vec())?;
+    engine.force_flush()?;
+    assert_eq!(*order.lock().unwrap(), vec!["first", "second"]);
+    Ok(())
+}
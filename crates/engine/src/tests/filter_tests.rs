@@ -0,0 +1,167 @@
+use crate::*;
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+
+/// Removes `tenant1/*`, upper-cases `legacy/*` values, and records every
+/// key it sees.
+#[derive(Debug, Default)]
+struct TenantFilter {
+    seen: Mutex<Vec<Vec<u8>>>,
+}
+
+impl CompactionFilter for TenantFilter {
+    fn name(&self) -> &'static str {
+        "tenant-filter"
+    }
+
+    fn filter(&self, key: &[u8], entry: &ValueEntry) -> FilterDecision {
+        self.seen.lock().unwrap().push(key.to_vec());
+        if key.starts_with(b"tenant1/") {
+            FilterDecision::Remove
+        } else if key.starts_with(b"legacy/") {
+            let value = entry.value.as_deref().unwrap_or_default();
+            FilterDecision::ChangeValue(value.to_ascii_uppercase())
+        } else {
+            FilterDecision::Keep
+        }
+    }
+}
+
+/// Replaces every value with one over the size limit.
+#[derive(Debug)]
+struct OversizedFilter;
+
+impl CompactionFilter for OversizedFilter {
+    fn name(&self) -> &'static str {
+        "oversized"
+    }
+
+    fn filter(&self, _key: &[u8], _entry: &ValueEntry) -> FilterDecision {
+        FilterDecision::ChangeValue(vec![0; MAX_VALUE_SIZE + 1])
+    }
+}
+
+/// Merges the two newest L0 files back into L0 once L0 holds three.
+#[derive(Debug)]
+struct MergeNewestPair;
+
+impl CompactionStrategy for MergeNewestPair {
+    fn name(&self) -> &'static str {
+        "merge-newest-pair"
+    }
+
+    fn pick_compaction(&self, state: &LevelState<'_>) -> Option<CompactionJob> {
+        let l0 = state.files(0);
+        if l0.len() < 3 {
+            return None;
+        }
+        let mut job = CompactionJob::new(0, 0);
+        job.add_input(0, &l0[0]);
+        job.add_input(0, &l0[1]);
+        Some(job)
+    }
+}
+
+fn new_engine(dir: &std::path::Path) -> Result<Engine> {
+    let mut engine = Engine::new(dir.join("wal.log"), dir.join("sst"), usize::MAX, false)?;
+    engine.set_l0_compaction_trigger(0);
+    Ok(engine)
+}
+
+// --------------------- Compaction filter ---------------------
+
+#[test]
+fn filter_removes_and_rewrites_records() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = new_engine(dir.path())?;
+    let filter = Arc::new(TenantFilter::default());
+    engine.set_compaction_filter(Some(filter.clone()));
+    assert!(format!("{:?}", engine).contains("tenant-filter"));
+
+    engine.set(b"tenant1/a".to_vec(), b"x".to_vec())?;
+    engine.set(b"tenant2/a".to_vec(), b"y".to_vec())?;
+    engine.force_flush()?;
+    engine.set(b"legacy/a".to_vec(), b"old-format".to_vec())?;
+    engine.set(b"tenant1/b".to_vec(), b"z".to_vec())?;
+    engine.force_flush()?;
+
+    // Flushes bypass the filter.
+    assert!(engine.get(b"tenant1/a")?.is_some());
+    assert!(filter.seen.lock().unwrap().is_empty());
+
+    engine.compact()?;
+    assert_eq!(engine.get(b"tenant1/a")?, None);
+    assert_eq!(engine.get(b"tenant1/b")?, None);
+    assert_eq!(engine.get(b"tenant2/a")?.unwrap().1, b"y");
+    let (seq, value) = engine.get(b"legacy/a")?.unwrap();
+    assert_eq!(value, b"OLD-FORMAT");
+    assert_eq!(seq, 3, "changed value keeps its sequence number");
+    assert_eq!(engine.levels[1][0].len(), 2, "removed keys are dropped");
+    Ok(())
+}
+
+#[test]
+fn filter_never_sees_tombstones() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = new_engine(dir.path())?;
+    let filter = Arc::new(TenantFilter::default());
+    engine.set_compaction_filter(Some(filter.clone()));
+
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.set(b"b".to_vec(), b"1".to_vec())?;
+    engine.force_flush()?;
+    engine.del(b"a".to_vec())?;
+    engine.force_flush()?;
+    engine.compact()?;
+
+    assert_eq!(*filter.seen.lock().unwrap(), vec![b"b".to_vec()]);
+    assert_eq!(engine.get(b"a")?, None);
+    Ok(())
+}
+
+#[test]
+fn removed_record_shadows_older_data_outside_the_job() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = new_engine(dir.path())?;
+    engine.set(b"tenant1/a".to_vec(), b"oldest".to_vec())?;
+    engine.force_flush()?;
+    engine.set(b"tenant1/a".to_vec(), b"newer".to_vec())?;
+    engine.force_flush()?;
+    engine.set(b"other".to_vec(), b"v".to_vec())?;
+    engine.force_flush()?;
+
+    engine.set_compaction_filter(Some(Arc::new(TenantFilter::default())));
+    engine.set_compaction_strategy(Arc::new(MergeNewestPair));
+    engine.set_l0_compaction_trigger(1);
+    engine.maybe_compact()?;
+
+    assert_eq!(engine.l0_sstable_count(), 2);
+    assert_eq!(
+        engine.get(b"tenant1/a")?,
+        None,
+        "the oldest file's value must not reappear"
+    );
+    Ok(())
+}
+
+#[test]
+fn oversized_changed_value_fails_compaction() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = new_engine(dir.path())?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.force_flush()?;
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    engine.force_flush()?;
+
+    engine.set_compaction_filter(Some(Arc::new(OversizedFilter)));
+    let err = engine.compact().unwrap_err();
+    assert!(err.to_string().contains("oversized"), "got: {}", err);
+    assert_eq!(engine.l0_sstable_count(), 2, "inputs stay in place");
+    assert_eq!(engine.get(b"a")?.unwrap().1, b"1");
+
+    engine.set_compaction_filter(None);
+    engine.compact()?;
+    assert_eq!(engine.sstable_count(), 1);
+    Ok(())
+}
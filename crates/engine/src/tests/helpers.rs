@@ -14,3 +14,15 @@ pub fn count_sst_files(dir: &Path) -> usize {
         })
         .count()
 }
+
+/// Returns the names of the WAL segments (`*.log`) in `dir`, sorted.
+pub fn wal_segment_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".log"))
+        .collect();
+    names.sort();
+    names
+}
@@ -2,15 +2,16 @@ use crate::*;
 use std::fs;
 use tempfile::tempdir;
 
-use manifest::MANIFEST_FILENAME;
+use manifest::{AppendFault, SstMeta, VersionEdit, CURRENT_FILENAME, LEGACY_MANIFEST_FILENAME};
 
 #[test]
 fn create_empty_manifest() -> Result<()> {
     let dir = tempdir()?;
     let m = Manifest::load_or_create(dir.path())?;
     assert!(m.entries.is_empty());
-    assert!(m.l0_filenames().is_empty());
-    assert!(m.l1_filenames().is_empty());
+    assert!(m.level_filenames(0).is_empty());
+    assert!(m.level_filenames(1).is_empty());
+    assert_eq!(m.max_level(), None);
     Ok(())
 }
 
@@ -18,14 +19,14 @@ fn create_empty_manifest() -> Result<()> {
 fn save_and_reload() -> Result<()> {
     let dir = tempdir()?;
     let mut m = Manifest::load_or_create(dir.path())?;
-    m.add("sst-001.sst".to_string(), 0);
-    m.add("sst-002.sst".to_string(), 0);
-    m.add("sst-003.sst".to_string(), 1);
+    m.add(SstMeta::new("sst-001.sst", 0));
+    m.add(SstMeta::new("sst-002.sst", 0));
+    m.add(SstMeta::new("sst-003.sst", 1));
     m.save()?;
 
     let m2 = Manifest::load_or_create(dir.path())?;
-    assert_eq!(m2.l0_filenames(), vec!["sst-002.sst", "sst-001.sst"]);
-    assert_eq!(m2.l1_filenames(), vec!["sst-003.sst"]);
+    assert_eq!(m2.level_filenames(0), vec!["sst-002.sst", "sst-001.sst"]);
+    assert_eq!(m2.level_filenames(1), vec!["sst-003.sst"]);
     Ok(())
 }
 
@@ -33,9 +34,9 @@ fn save_and_reload() -> Result<()> {
 fn remove_files() -> Result<()> {
     let dir = tempdir()?;
     let mut m = Manifest::load_or_create(dir.path())?;
-    m.add("a.sst".to_string(), 0);
-    m.add("b.sst".to_string(), 0);
-    m.add("c.sst".to_string(), 1);
+    m.add(SstMeta::new("a.sst", 0));
+    m.add(SstMeta::new("b.sst", 0));
+    m.add(SstMeta::new("c.sst", 1));
     m.remove_files(&["a.sst", "c.sst"]);
     assert_eq!(m.entries.len(), 1);
     assert_eq!(m.entries[0].filename, "b.sst");
@@ -43,36 +44,57 @@ fn remove_files() -> Result<()> {
 }
 
 #[test]
-fn replace_all_with_l1() -> Result<()> {
+fn add_at_places_entry_within_level() -> Result<()> {
     let dir = tempdir()?;
     let mut m = Manifest::load_or_create(dir.path())?;
-    m.add("old1.sst".to_string(), 0);
-    m.add("old2.sst".to_string(), 1);
-    m.replace_all_with_l1("merged.sst".to_string());
-    assert_eq!(m.entries.len(), 1);
-    assert_eq!(m.entries[0].filename, "merged.sst");
-    assert_eq!(m.entries[0].level, 1);
+    m.add(SstMeta::new("old.sst", 0));
+    m.add(SstMeta::new("new.sst", 0));
+    m.add(SstMeta::new("deep.sst", 1));
+    m.add_at(SstMeta::new("mid.sst", 0), 1);
+    m.add_at(SstMeta::new("last.sst", 0), 10);
+    assert_eq!(
+        m.level_filenames(0),
+        vec!["new.sst", "mid.sst", "old.sst", "last.sst"]
+    );
+    assert_eq!(m.level_filenames(1), vec!["deep.sst"]);
+    Ok(())
+}
+
+#[test]
+fn deeper_levels_round_trip() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.add(SstMeta::new("a.sst", 0));
+    m.add(SstMeta::new("b.sst", 2));
+    m.add(SstMeta::new("c.sst", 6));
+    m.save()?;
+
+    let m2 = Manifest::load_or_create(dir.path())?;
+    assert_eq!(m2.level_filenames(2), vec!["b.sst"]);
+    assert_eq!(m2.level_filenames(6), vec!["c.sst"]);
+    assert_eq!(m2.max_level(), Some(6));
+    assert_eq!(m2.entries, m.entries);
     Ok(())
 }
 
 #[test]
-fn comments_and_blank_lines_ignored() -> Result<()> {
+fn legacy_comments_and_blank_lines_ignored() -> Result<()> {
     let dir = tempdir()?;
-    let path = dir.path().join(MANIFEST_FILENAME);
+    let path = dir.path().join(LEGACY_MANIFEST_FILENAME);
     fs::write(
         &path,
         "# comment\n\nL0:a.sst\n\n# another comment\nL1:b.sst\n",
     )?;
     let m = Manifest::load_or_create(dir.path())?;
-    assert_eq!(m.l0_filenames(), vec!["a.sst"]);
-    assert_eq!(m.l1_filenames(), vec!["b.sst"]);
+    assert_eq!(m.level_filenames(0), vec!["a.sst"]);
+    assert_eq!(m.level_filenames(1), vec!["b.sst"]);
     Ok(())
 }
 
 #[test]
 fn invalid_format_returns_error() {
     let dir = tempdir().unwrap();
-    let path = dir.path().join(MANIFEST_FILENAME);
+    let path = dir.path().join(LEGACY_MANIFEST_FILENAME);
     fs::write(&path, "bad-line-no-colon\n").unwrap();
     let result = Manifest::load_or_create(dir.path());
     assert!(result.is_err());
@@ -81,8 +103,479 @@ fn invalid_format_returns_error() {
 #[test]
 fn unknown_level_returns_error() {
     let dir = tempdir().unwrap();
-    let path = dir.path().join(MANIFEST_FILENAME);
-    fs::write(&path, "L9:file.sst\n").unwrap();
-    let result = Manifest::load_or_create(dir.path());
-    assert!(result.is_err());
+    let path = dir.path().join(LEGACY_MANIFEST_FILENAME);
+    for line in ["X1:file.sst\n", "Lx:file.sst\n", "L64:file.sst\n"] {
+        fs::write(&path, line).unwrap();
+        let result = Manifest::load_or_create(dir.path());
+        assert!(result.is_err(), "{:?} should be rejected", line);
+    }
+}
+
+// --------------------- Version-edit log ---------------------
+
+fn meta(filename: &str, level: u32, seqs: (u64, u64)) -> SstMeta {
+    SstMeta {
+        filename: filename.to_string(),
+        level,
+        file_size: 4096,
+        smallest_key: format!("{}-a", filename).into_bytes(),
+        largest_key: format!("{}-z", filename).into_bytes(),
+        min_seq: seqs.0,
+        max_seq: seqs.1,
+    }
+}
+
+/// Returns the path of the log `CURRENT` points at.
+fn current_log(dir: &std::path::Path) -> Result<std::path::PathBuf> {
+    let name = fs::read_to_string(dir.join(CURRENT_FILENAME))?;
+    Ok(dir.join(name.trim()))
+}
+
+fn log_files(dir: &std::path::Path) -> Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<std::io::Result<_>>()?;
+    names.retain(|n| n.starts_with("MANIFEST-"));
+    names.sort();
+    Ok(names)
+}
+
+#[test]
+fn version_edits_round_trip() -> Result<()> {
+    let edits = vec![
+        VersionEdit::AddFile {
+            index: 3,
+            meta: meta("a.sst", 2, (5, 9)),
+        },
+        VersionEdit::DeleteFile {
+            level: 1,
+            filename: "b.sst".to_string(),
+        },
+        VersionEdit::NextFileNumber(17),
+        VersionEdit::LastSeq(42),
+        VersionEdit::LogNumber(7),
+    ];
+    let body = manifest::encode_edits(&edits)?;
+    assert_eq!(manifest::decode_edits(&body)?, edits);
+    assert!(manifest::decode_edits(&body[..body.len() - 1]).is_err());
+    Ok(())
+}
+
+#[test]
+fn metadata_and_counters_survive_reload() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.add(meta("a.sst", 0, (1, 10)));
+    m.add(meta("b.sst", 3, (11, 20)));
+    m.set_next_file_number(12);
+    m.set_last_seq(20)?;
+    m.set_log_number(4);
+    m.save()?;
+
+    let m2 = Manifest::load_or_create(dir.path())?;
+    assert_eq!(m2.entries, m.entries);
+    assert_eq!(m2.file("b.sst"), Some(&meta("b.sst", 3, (11, 20))));
+    assert_eq!(m2.next_file_number(), 12);
+    assert_eq!(m2.last_seq(), 20);
+    assert_eq!(m2.log_number(), 4);
+    Ok(())
+}
+
+#[test]
+fn saves_append_to_the_current_log() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.add(meta("a.sst", 0, (1, 1)));
+    m.save()?;
+    let log = current_log(dir.path())?;
+    let first_len = fs::metadata(&log)?.len();
+
+    m.add(meta("b.sst", 0, (2, 2)));
+    m.remove_files(&["a.sst"]);
+    m.add_at(meta("c.sst", 0, (3, 3)), 1);
+    m.save()?;
+    assert_eq!(current_log(dir.path())?, log);
+    assert!(fs::metadata(&log)?.len() > first_len);
+
+    let m2 = Manifest::load_or_create(dir.path())?;
+    assert_eq!(m2.level_filenames(0), vec!["b.sst", "c.sst"]);
+    assert_eq!(m2.entries, m.entries);
+    Ok(())
+}
+
+#[test]
+fn torn_last_record_is_ignored() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.add(meta("a.sst", 0, (1, 1)));
+    m.save()?;
+    let log = current_log(dir.path())?;
+    let committed = fs::metadata(&log)?.len();
+    m.add(meta("b.sst", 0, (2, 2)));
+    m.save()?;
+
+    // Cut the second record short, as a crash mid-append would.
+    let data = fs::read(&log)?;
+    for cut in [committed as usize + 3, data.len() - 1] {
+        fs::write(&log, &data[..cut])?;
+        let m2 = Manifest::load_or_create(dir.path())?;
+        assert_eq!(m2.level_filenames(0), vec!["a.sst"]);
+    }
+
+    // The next save starts a fresh log rather than appending after the
+    // torn record.
+    let mut m2 = Manifest::load_or_create(dir.path())?;
+    m2.add(meta("c.sst", 0, (3, 3)));
+    m2.save()?;
+    assert_ne!(current_log(dir.path())?, log);
+    assert!(!log.exists());
+    let m3 = Manifest::load_or_create(dir.path())?;
+    assert_eq!(m3.level_filenames(0), vec!["c.sst", "a.sst"]);
+    Ok(())
+}
+
+#[test]
+fn failed_append_is_rolled_back_before_the_snapshot() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.add(meta("a.sst", 0, (1, 1)));
+    m.set_log_number(3);
+    m.save()?;
+
+    // A directory in place of the log makes the next append fail.
+    let log = current_log(dir.path())?;
+    fs::remove_file(&log)?;
+    fs::create_dir(&log)?;
+    m.remove_files(&["a.sst"]);
+    m.add(meta("b.sst", 0, (2, 2)));
+    m.set_log_number(5);
+    let number = m.allocate_file_number();
+    assert!(m.save().is_err());
+    assert_eq!(m.level_filenames(0), vec!["a.sst"]);
+    assert_eq!(m.log_number(), 3);
+    assert_eq!(m.next_file_number(), number + 1);
+
+    // The snapshot written by the next save holds only what was kept.
+    m.add(meta("c.sst", 0, (3, 3)));
+    m.save()?;
+    assert_ne!(current_log(dir.path())?, log);
+    let m2 = Manifest::load_or_create(dir.path())?;
+    assert_eq!(m2.level_filenames(0), vec!["c.sst", "a.sst"]);
+    assert_eq!(m2.log_number(), 3);
+    assert_eq!(m2.next_file_number(), number + 1);
+    Ok(())
+}
+
+#[test]
+fn record_of_a_failed_sync_is_cut_off_the_log() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.add(meta("a.sst", 0, (1, 1)));
+    m.save()?;
+    let log = current_log(dir.path())?;
+    let committed = fs::metadata(&log)?.len();
+
+    // The record is written in full before the sync fails.
+    m.append_fault = Some(AppendFault::Sync);
+    m.add(meta("b.sst", 0, (2, 2)));
+    assert!(m.save().is_err());
+    assert_eq!(fs::metadata(&log)?.len(), committed);
+    assert_eq!(
+        Manifest::load_or_create(dir.path())?.level_filenames(0),
+        vec!["a.sst"]
+    );
+
+    m.add(meta("c.sst", 0, (3, 3)));
+    m.save()?;
+    let m2 = Manifest::load_or_create(dir.path())?;
+    assert_eq!(m2.level_filenames(0), vec!["c.sst", "a.sst"]);
+    Ok(())
+}
+
+#[test]
+fn failed_cut_fails_every_later_save() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.add(meta("a.sst", 0, (1, 1)));
+    m.save()?;
+
+    m.append_fault = Some(AppendFault::SyncAndTruncate);
+    m.add(meta("b.sst", 0, (2, 2)));
+    assert!(m.save().is_err());
+    assert_eq!(m.level_filenames(0), vec!["a.sst"]);
+
+    // The failed record is still in the log, so nothing more is saved.
+    m.add(meta("c.sst", 0, (3, 3)));
+    assert!(m.save().is_err());
+    assert!(m.ensure_consistent().is_err());
+
+    // Reloading picks it up and starts over.
+    let mut m2 = Manifest::load_or_create(dir.path())?;
+    assert_eq!(m2.level_filenames(0), vec!["b.sst", "a.sst"]);
+    m2.ensure_consistent()?;
+    m2.add(meta("c.sst", 0, (3, 3)));
+    m2.save()?;
+    Ok(())
+}
+
+#[test]
+fn corrupt_record_before_the_tail_is_an_error() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.add(meta("a.sst", 0, (1, 1)));
+    m.save()?;
+    m.add(meta("b.sst", 0, (2, 2)));
+    m.save()?;
+
+    let log = current_log(dir.path())?;
+    let mut data = fs::read(&log)?;
+    data[12] ^= 0xFF;
+    fs::write(&log, &data)?;
+    let err = Manifest::load_or_create(dir.path()).unwrap_err();
+    assert!(err.to_string().contains("corrupt"), "got: {}", err);
+    Ok(())
+}
+
+#[test]
+fn last_seq_never_goes_backwards() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.set_last_seq(10)?;
+    m.set_last_seq(10)?;
+    assert!(m.set_last_seq(9).is_err());
+    assert_eq!(m.last_seq(), 10);
+    m.save()?;
+
+    // A log whose records move the sequence number backwards is rejected.
+    let body = manifest::encode_edits(&[VersionEdit::LastSeq(3)])?;
+    let mut record = Vec::new();
+    record.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+    record.extend_from_slice(&body);
+    let log = current_log(dir.path())?;
+    let mut data = fs::read(&log)?;
+    data.extend_from_slice(&record);
+    fs::write(&log, &data)?;
+    let err = Manifest::load_or_create(dir.path()).unwrap_err();
+    assert!(format!("{:#}", err).contains("backwards"), "got: {:#}", err);
+    Ok(())
+}
+
+#[test]
+fn large_log_rolls_over_to_a_snapshot() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.set_max_file_size(512);
+    let mut logs_seen = std::collections::HashSet::new();
+    for i in 0..20 {
+        m.add(meta(&format!("{:02}.sst", i), 0, (i, i)));
+        if i % 2 == 0 {
+            m.remove_files(&[format!("{:02}.sst", i).as_str()]);
+        }
+        m.set_last_seq(i)?;
+        m.save()?;
+        logs_seen.insert(current_log(dir.path())?);
+    }
+    assert!(logs_seen.len() > 2, "rolled over {} times", logs_seen.len());
+
+    // Old logs are deleted once CURRENT points past them.
+    let logs = log_files(dir.path())?;
+    assert_eq!(logs.len(), 1);
+    assert_ne!(logs[0], "MANIFEST-000001");
+
+    let m2 = Manifest::load_or_create(dir.path())?;
+    assert_eq!(m2.entries, m.entries);
+    assert_eq!(m2.entries.len(), 10);
+    assert_eq!(m2.last_seq(), 19);
+    Ok(())
+}
+
+#[test]
+fn legacy_manifest_is_replaced_on_save() -> Result<()> {
+    let dir = tempdir()?;
+    let legacy = dir.path().join(LEGACY_MANIFEST_FILENAME);
+    fs::write(&legacy, "L0:b.sst\nL0:a.sst\nL2:c.sst\n")?;
+
+    let mut m = Manifest::load_or_create(dir.path())?;
+    assert!(!m.entries[0].has_metadata());
+    m.fill_metadata(meta("b.sst", 5, (4, 6)));
+    assert_eq!(m.entries[0], meta("b.sst", 0, (4, 6)));
+    m.save()?;
+    assert!(!legacy.exists());
+    assert_eq!(log_files(dir.path())?, vec!["MANIFEST-000001"]);
+
+    let m2 = Manifest::load_or_create(dir.path())?;
+    assert_eq!(m2.entries, m.entries);
+    assert_eq!(m2.level_filenames(0), vec!["b.sst", "a.sst"]);
+    Ok(())
+}
+
+#[test]
+fn malformed_current_returns_error() -> Result<()> {
+    let dir = tempdir()?;
+    fs::write(dir.path().join(CURRENT_FILENAME), "not-a-manifest\n")?;
+    assert!(Manifest::load_or_create(dir.path()).is_err());
+
+    fs::write(dir.path().join(CURRENT_FILENAME), "MANIFEST-000009\n")?;
+    assert!(Manifest::load_or_create(dir.path()).is_err());
+    Ok(())
+}
+
+// --------------------- Encryption ---------------------
+
+fn keys(id: u32, byte: u8) -> Arc<dyn KeyProvider> {
+    Arc::new(StaticKeyProvider::new(id, Key::new([byte; 32])))
+}
+
+#[test]
+fn encrypted_manifest_round_trips_and_hides_file_names() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_with_keys(dir.path(), Some(keys(1, 1)))?;
+    m.add(meta("secret-1.sst", 0, (1, 5)));
+    m.save()?;
+    // appended to the encrypted log
+    m.add(meta("secret-2.sst", 1, (6, 9)));
+    m.save()?;
+    assert_eq!(log_files(dir.path())?.len(), 1);
+
+    let data = fs::read(current_log(dir.path())?)?;
+    assert!(data.starts_with(b"RMANENC1"));
+    assert!(!data.windows(6).any(|w| w == b"secret"));
+
+    let reloaded = Manifest::load_with_keys(dir.path(), Some(keys(1, 1)))?;
+    assert_eq!(reloaded.entries, m.entries);
+
+    let err = Manifest::load_or_create(dir.path()).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<EncryptionError>(),
+        Some(EncryptionError::NoKeyProvider(1))
+    ));
+    let err = Manifest::load_with_keys(dir.path(), Some(keys(1, 2))).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<EncryptionError>(),
+        Some(EncryptionError::WrongKey(1))
+    ));
+    Ok(())
+}
+
+#[test]
+fn plain_or_old_key_manifest_rolls_over_on_save() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_or_create(dir.path())?;
+    m.add(meta("a.sst", 0, (1, 5)));
+    m.save()?;
+
+    let mut m = Manifest::load_with_keys(dir.path(), Some(keys(1, 1)))?;
+    m.save()?;
+    assert!(fs::read(current_log(dir.path())?)?.starts_with(b"RMANENC1"));
+
+    let rotated: Arc<dyn KeyProvider> =
+        Arc::new(StaticKeyProvider::new(2, Key::new([2; 32])).with_key(1, Key::new([1; 32])));
+    let mut m = Manifest::load_with_keys(dir.path(), Some(rotated))?;
+    m.save()?;
+    let data = fs::read(current_log(dir.path())?)?;
+    assert_eq!(encryption::header_key_id(&data[8..]), Some(2));
+
+    let m = Manifest::load_with_keys(dir.path(), Some(keys(2, 2)))?;
+    assert_eq!(m.level_filenames(0), vec!["a.sst"]);
+    assert_eq!(log_files(dir.path())?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn tampered_encrypted_record_fails_the_load() -> Result<()> {
+    let dir = tempdir()?;
+    let mut m = Manifest::load_with_keys(dir.path(), Some(keys(1, 1)))?;
+    m.add(meta("a.sst", 0, (1, 5)));
+    m.save()?;
+    m.add(meta("b.sst", 0, (6, 9)));
+    m.save()?;
+
+    // flip a ciphertext byte of the first record and fix up its CRC, so
+    // only authentication catches it
+    let path = current_log(dir.path())?;
+    let mut data = fs::read(&path)?;
+    let start = 8 + encryption::HEADER_LEN;
+    let len = u32::from_le_bytes(data[start..start + 4].try_into()?) as usize;
+    data[start + 8 + encryption::NONCE_LEN] ^= 1;
+    let crc = crc32fast::hash(&data[start + 8..start + 8 + len]);
+    data[start + 4..start + 8].copy_from_slice(&crc.to_le_bytes());
+    fs::write(&path, &data)?;
+
+    let err = Manifest::load_with_keys(dir.path(), Some(keys(1, 1))).unwrap_err();
+    assert!(format!("{:#}", err).contains("fails authentication"));
+    Ok(())
+}
+
+// --------------------- Engine integration ---------------------
+
+fn flush_range(engine: &mut Engine, keys: std::ops::Range<u32>) -> Result<()> {
+    for i in keys {
+        engine.set(format!("k{:04}", i).into_bytes(), b"v".to_vec())?;
+    }
+    engine.force_flush()
+}
+
+#[test]
+fn engine_records_file_metadata() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    flush_range(&mut engine, 0..10)?;
+    flush_range(&mut engine, 5..20)?;
+
+    let newest = &engine.manifest.entries[0];
+    assert_eq!(newest.smallest_key, b"k0005");
+    assert_eq!(newest.largest_key, b"k0019");
+    assert_eq!((newest.min_seq, newest.max_seq), (11, 25));
+    assert_eq!(newest.file_size, engine.levels[0][0].file_size());
+
+    engine.compact()?;
+    let merged = &engine.manifest.entries[0];
+    assert_eq!(merged.level, 1);
+    assert_eq!(merged.smallest_key, b"k0000");
+    assert_eq!(merged.largest_key, b"k0019");
+    assert_eq!((merged.min_seq, merged.max_seq), (1, 25));
+    Ok(())
+}
+
+#[test]
+fn engine_migrates_legacy_manifest() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+    let expected = {
+        let mut engine = Engine::new(&wal, &sst, usize::MAX, false)?;
+        engine.set_l0_compaction_trigger(0);
+        flush_range(&mut engine, 0..10)?;
+        flush_range(&mut engine, 5..15)?;
+        engine.compact()?;
+        flush_range(&mut engine, 10..20)?;
+        engine.manifest.entries.clone()
+    };
+
+    // Rewrite the directory as the text manifest would have left it.
+    let text: String = expected
+        .iter()
+        .map(|e| format!("L{}:{}\n", e.level, e.filename))
+        .collect();
+    fs::remove_file(current_log(&sst)?)?;
+    fs::remove_file(sst.join(CURRENT_FILENAME))?;
+    fs::write(sst.join(LEGACY_MANIFEST_FILENAME), text)?;
+
+    let mut engine = Engine::new(&wal, &sst, usize::MAX, false)?;
+    engine.set_l0_compaction_trigger(0);
+    assert_eq!(engine.level_sstable_counts()[..2], [1, 1]);
+    assert_eq!(engine.manifest.entries, expected);
+
+    flush_range(&mut engine, 20..30)?;
+    assert!(!sst.join(LEGACY_MANIFEST_FILENAME).exists());
+    let reloaded = Manifest::load_or_create(&sst)?;
+    assert_eq!(reloaded.entries, engine.manifest.entries);
+    Ok(())
 }
@@ -1,7 +1,17 @@
 mod helpers;
 
 mod compaction_tests;
+mod encryption_tests;
+mod events_tests;
+mod filter_tests;
 mod manifest_tests;
+mod obsolete_tests;
+mod read_only_tests;
 mod read_tests;
 mod recovery_tests;
+mod secondary_tests;
+mod stats_tests;
+mod strategy_tests;
+mod subcompaction_tests;
+mod wal_tests;
 mod write_tests;
@@ -0,0 +1,228 @@
+use crate::*;
+use anyhow::Result;
+use memtable::Memtable;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+
+use super::helpers::count_sst_files;
+
+/// Keeps the path and reason of every deleted table file.
+#[derive(Default)]
+struct DeletionRecorder {
+    deleted: Mutex<Vec<(std::path::PathBuf, TableFileReason)>>,
+}
+
+impl EventListener for DeletionRecorder {
+    fn on_table_file_deleted(&self, info: &TableFileInfo) {
+        self.deleted
+            .lock()
+            .unwrap()
+            .push((info.path.clone(), info.reason));
+    }
+}
+
+fn new_engine(dir: &Path) -> Result<Engine> {
+    let mut engine = Engine::new(dir.join("wal.log"), dir.join("sst"), usize::MAX, false)?;
+    engine.set_l0_compaction_trigger(0);
+    Ok(engine)
+}
+
+fn flush_key(engine: &mut Engine, key: &str) -> Result<()> {
+    engine.set(key.as_bytes().to_vec(), b"v".to_vec())?;
+    engine.force_flush()
+}
+
+/// Writes an SSTable the manifest knows nothing about.
+fn write_stray_sst(path: &Path) -> Result<u64> {
+    let mut mem = Memtable::new();
+    mem.put(b"stray".to_vec(), b"x".to_vec(), 1);
+    SSTableWriter::write_from_memtable(path, &mem)?;
+    Ok(fs::metadata(path)?.len())
+}
+
+fn sst_names(engine: &Engine) -> Vec<String> {
+    engine
+        .stats()
+        .sstables
+        .into_iter()
+        .map(|s| s.file)
+        .collect()
+}
+
+// --------------------- File numbers ---------------------
+
+#[test]
+fn sst_names_use_increasing_file_numbers() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = new_engine(dir.path())?;
+    // Same millisecond, same seq region: names must still be unique. WAL
+    // segments share the numbering: 1 is the segment opened with the
+    // engine, and every flush starts a new one after its SSTable.
+    for key in ["a", "b", "c"] {
+        flush_key(&mut engine, key)?;
+    }
+    assert_eq!(
+        sst_names(&engine),
+        vec!["000006.sst", "000004.sst", "000002.sst"]
+    );
+
+    engine.compact()?;
+    assert_eq!(sst_names(&engine), vec!["000008.sst"]);
+    assert_eq!(engine.manifest.next_file_number(), 9);
+    Ok(())
+}
+
+#[test]
+fn file_numbers_survive_restart() -> Result<()> {
+    let dir = tempdir()?;
+    {
+        let mut engine = new_engine(dir.path())?;
+        flush_key(&mut engine, "a")?;
+        flush_key(&mut engine, "b")?;
+        engine.compact()?;
+    }
+    // The files of the flushed SSTables are gone, but their numbers are
+    // not handed out again.
+    let mut engine = new_engine(dir.path())?;
+    assert_eq!(engine.manifest.next_file_number(), 7);
+    flush_key(&mut engine, "c")?;
+    assert_eq!(sst_names(&engine)[0], "000007.sst");
+    Ok(())
+}
+
+#[test]
+fn numbers_of_files_on_disk_are_never_reused() -> Result<()> {
+    let dir = tempdir()?;
+    {
+        let mut engine = new_engine(dir.path())?;
+        flush_key(&mut engine, "a")?;
+    }
+    // A flush whose manifest update was lost left number 7 behind.
+    write_stray_sst(&dir.path().join("sst").join("000007.sst"))?;
+
+    let mut engine = new_engine(dir.path())?;
+    flush_key(&mut engine, "b")?;
+    assert_eq!(sst_names(&engine), vec!["000008.sst", "000002.sst"]);
+    Ok(())
+}
+
+// --------------------- Obsolete file sweep ---------------------
+
+#[test]
+fn open_deletes_unreferenced_files() -> Result<()> {
+    let dir = tempdir()?;
+    let sst = dir.path().join("sst");
+    {
+        let mut engine = new_engine(dir.path())?;
+        flush_key(&mut engine, "a")?;
+    }
+    write_stray_sst(&sst.join("000009.sst"))?;
+    fs::write(sst.join("000010.sst.tmp"), b"partial")?;
+    fs::write(sst.join("MANIFEST-000042"), b"stale")?;
+    fs::write(sst.join("CURRENT.tmp"), b"MANIFEST-000042\n")?;
+    fs::write(sst.join("notes.txt"), b"not ours")?;
+
+    let engine = new_engine(dir.path())?;
+    assert_eq!(engine.get(b"a")?.unwrap().1, b"v");
+    assert_eq!(count_sst_files(&sst), 1);
+    for gone in [
+        "000009.sst",
+        "000010.sst.tmp",
+        "MANIFEST-000042",
+        "CURRENT.tmp",
+    ] {
+        assert!(!sst.join(gone).exists(), "{} should be deleted", gone);
+    }
+    assert!(sst.join("notes.txt").exists());
+    assert!(sst.join(manifest::CURRENT_FILENAME).exists());
+    assert_eq!(engine.stats().obsolete_files_deleted, 3);
+    Ok(())
+}
+
+#[test]
+fn sweep_reports_and_notifies_removed_files() -> Result<()> {
+    let dir = tempdir()?;
+    let sst = dir.path().join("sst");
+    let mut engine = new_engine(dir.path())?;
+    let recorder = Arc::new(DeletionRecorder::default());
+    engine.add_event_listener(recorder.clone());
+    flush_key(&mut engine, "a")?;
+    flush_key(&mut engine, "b")?;
+
+    let stray = sst.join("sst-00000000000000000001-1700000000000.sst");
+    let size = write_stray_sst(&stray)?;
+
+    // Compaction only ever deletes its own inputs.
+    engine.compact()?;
+    assert!(stray.exists());
+    recorder.deleted.lock().unwrap().clear();
+
+    let report = engine.delete_obsolete_files()?;
+    assert_eq!(report.sst_files, vec![stray.clone()]);
+    assert!(report.other_files.is_empty());
+    assert_eq!(report.bytes_freed, size);
+    assert!(!stray.exists());
+    assert_eq!(
+        *recorder.deleted.lock().unwrap(),
+        vec![(stray, TableFileReason::Obsolete)]
+    );
+
+    assert!(engine.delete_obsolete_files()?.is_empty());
+    assert_eq!(engine.get(b"a")?.unwrap().1, b"v");
+    Ok(())
+}
+
+#[test]
+fn sweep_keeps_sstables_it_did_not_name() -> Result<()> {
+    let dir = tempdir()?;
+    let sst = dir.path().join("sst");
+    {
+        let mut engine = new_engine(dir.path())?;
+        flush_key(&mut engine, "a")?;
+    }
+    let foreign = ["backup.sst", "42.old.sst", "sst-1.sst", "sst-x-1.sst"];
+    for name in foreign {
+        write_stray_sst(&sst.join(name))?;
+    }
+
+    let mut engine = new_engine(dir.path())?;
+    assert!(engine.delete_obsolete_files()?.is_empty());
+    for name in foreign {
+        assert!(sst.join(name).exists(), "{} should be kept", name);
+    }
+    Ok(())
+}
+
+#[test]
+fn empty_manifest_does_not_adopt_stray_sstables() -> Result<()> {
+    let dir = tempdir()?;
+    let sst = dir.path().join("sst");
+    {
+        let mut engine = new_engine(dir.path())?;
+        engine.manifest.save()?;
+    }
+    write_stray_sst(&sst.join("000003.sst"))?;
+
+    let engine = new_engine(dir.path())?;
+    assert!(engine.get(b"stray")?.is_none());
+    assert_eq!(count_sst_files(&sst), 0);
+    Ok(())
+}
+
+#[test]
+fn read_only_engine_cannot_sweep() -> Result<()> {
+    let dir = tempdir()?;
+    {
+        let mut engine = new_engine(dir.path())?;
+        flush_key(&mut engine, "a")?;
+    }
+    let stray = dir.path().join("sst").join("000009.sst");
+    write_stray_sst(&stray)?;
+
+    let mut engine = Engine::open_read_only(dir.path().join("wal.log"), dir.path().join("sst"))?;
+    assert!(engine.delete_obsolete_files().is_err());
+    assert!(stray.exists());
+    Ok(())
+}
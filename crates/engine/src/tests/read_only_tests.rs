@@ -0,0 +1,162 @@
+use crate::*;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+/// Snapshots every file under `dir` (name -> contents) so tests can assert
+/// that a read-only engine left the directory byte-for-byte untouched.
+fn snapshot_dir(dir: &Path) -> BTreeMap<String, Vec<u8>> {
+    let mut files = BTreeMap::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        for entry in fs::read_dir(&d).unwrap().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else {
+                let name = path.strip_prefix(dir).unwrap().display().to_string();
+                files.insert(name, fs::read(&path).unwrap());
+            }
+        }
+    }
+    files
+}
+
+// --------------------- Read-only open ---------------------
+
+#[test]
+fn read_only_serves_sstables_and_wal() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+
+    {
+        let mut engine = Engine::new(&wal, &sst, 1024 * 1024, false)?;
+        engine.set_l0_compaction_trigger(0);
+        engine.set(b"a".to_vec(), b"1".to_vec())?;
+        engine.set(b"b".to_vec(), b"2".to_vec())?;
+        engine.force_flush()?;
+        engine.set(b"c".to_vec(), b"3".to_vec())?;
+        engine.del(b"a".to_vec())?;
+        // Skip the flush-on-drop so "c" and the tombstone stay in the WAL.
+        engine.read_only = true;
+    }
+
+    let engine = Engine::open_read_only(&wal, &sst)?;
+    assert!(engine.is_read_only());
+    assert_eq!(engine.l0_sstable_count(), 1);
+    assert_eq!(engine.seq(), 4);
+    assert!(engine.get(b"a")?.is_none());
+    assert_eq!(engine.get(b"b")?.unwrap().1, b"2");
+    assert_eq!(engine.get(b"c")?.unwrap().1, b"3");
+    assert_eq!(
+        engine.scan(b"", b"")?,
+        vec![
+            (b"b".to_vec(), b"2".to_vec()),
+            (b"c".to_vec(), b"3".to_vec())
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn read_only_rejects_mutations() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+    drop(Engine::new(&wal, &sst, 1024 * 1024, false)?);
+
+    let mut engine = Engine::open_read_only(&wal, &sst)?;
+    let seq = engine.seq();
+
+    let err = engine.set(b"k".to_vec(), b"v".to_vec()).unwrap_err();
+    assert!(err.to_string().contains("read-only"), "got: {}", err);
+    assert!(engine.del(b"k".to_vec()).is_err());
+    assert!(engine.force_flush().is_err());
+    assert!(engine.compact().is_err());
+    assert!(engine.compact_range(b"a", b"z").is_err());
+
+    assert_eq!(engine.seq(), seq, "rejected writes must not consume a seq");
+    assert!(engine.get(b"k")?.is_none());
+    Ok(())
+}
+
+#[test]
+fn read_only_never_writes_to_disk() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+
+    {
+        let mut engine = Engine::new(&wal, &sst, 1024 * 1024, false)?;
+        engine.set(b"flushed".to_vec(), b"x".to_vec())?;
+        engine.force_flush()?;
+        engine.set(b"pending".to_vec(), b"y".to_vec())?;
+        engine.read_only = true;
+    }
+    // A leftover tmp file must not be cleaned up by a read-only open.
+    fs::write(sst.join("sst-00000000000000000009-1.sst.tmp"), b"partial")?;
+
+    let before = snapshot_dir(dir.path());
+    {
+        let engine = Engine::open_read_only(&wal, &sst)?;
+        assert_eq!(engine.get(b"pending")?.unwrap().1, b"y");
+    }
+    assert_eq!(snapshot_dir(dir.path()), before);
+    Ok(())
+}
+
+#[test]
+fn read_only_missing_wal_is_not_created() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+
+    {
+        let mut engine = Engine::new(&wal, &sst, 1024 * 1024, false)?;
+        engine.set(b"k".to_vec(), b"v".to_vec())?;
+    }
+    for name in super::helpers::wal_segment_names(&sst) {
+        fs::remove_file(sst.join(name))?;
+    }
+
+    let engine = Engine::open_read_only(&wal, &sst)?;
+    assert_eq!(engine.get(b"k")?.unwrap().1, b"v");
+    assert!(!wal.exists(), "read-only open must not create the WAL");
+    assert!(super::helpers::wal_segment_names(&sst).is_empty());
+    Ok(())
+}
+
+#[test]
+fn read_only_legacy_dir_does_not_write_manifest() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+
+    {
+        let mut engine = Engine::new(&wal, &sst, 1024 * 1024, false)?;
+        engine.set(b"k".to_vec(), b"v".to_vec())?;
+    }
+    // Simulate a pre-manifest database.
+    for entry in fs::read_dir(&sst)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "sst") {
+            fs::remove_file(path)?;
+        }
+    }
+
+    let engine = Engine::open_read_only(&wal, &sst)?;
+    assert_eq!(engine.get(b"k")?.unwrap().1, b"v");
+    assert!(!sst.join(manifest::CURRENT_FILENAME).exists());
+    Ok(())
+}
+
+#[test]
+fn read_only_requires_existing_sst_dir() {
+    let dir = tempdir().unwrap();
+    let sst = dir.path().join("missing");
+    assert!(Engine::open_read_only(dir.path().join("wal.log"), &sst).is_err());
+    assert!(!sst.exists());
+}
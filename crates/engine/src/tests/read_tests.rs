@@ -149,3 +149,78 @@ fn read_path_prefers_l0_over_l1() -> Result<()> {
     assert_eq!(val, b"new", "memtable/L0 should shadow L1");
     Ok(())
 }
+
+// --------------------- Key range pruning ---------------------
+
+/// Flushes one L0 file per prefix, each holding `{prefix}0..{prefix}9`.
+fn engine_with_disjoint_l0(dir: &std::path::Path, prefixes: &[&str]) -> Result<Engine> {
+    let mut engine = Engine::new(dir.join("wal.log"), dir.join("sst"), usize::MAX, false)?;
+    engine.set_l0_compaction_trigger(0);
+    for prefix in prefixes {
+        for i in 0..10 {
+            engine.set(format!("{}{}", prefix, i).into_bytes(), b"v".to_vec())?;
+        }
+        engine.force_flush()?;
+    }
+    Ok(engine)
+}
+
+/// Bloom filter lookups per file, keyed by the file's smallest key.
+fn bloom_lookups(engine: &Engine) -> Vec<(Vec<u8>, u64)> {
+    engine
+        .stats()
+        .sstables
+        .into_iter()
+        .map(|s| (s.smallest_key, s.bloom_hits + s.bloom_misses))
+        .collect()
+}
+
+#[test]
+fn get_skips_l0_files_outside_key_range() -> Result<()> {
+    let dir = tempdir()?;
+    let engine = engine_with_disjoint_l0(dir.path(), &["a", "b", "c"])?;
+
+    // "a5" lives in the oldest file; the two newer ones are not probed.
+    assert!(engine.get(b"a5")?.is_some());
+    assert_eq!(engine.stats().sstables_probed_per_get.max, 1);
+
+    // Keys outside every file (or between files) probe nothing.
+    for key in [&b"0"[..], b"b99", b"d"] {
+        assert!(engine.get(key)?.is_none());
+    }
+    assert_eq!(engine.stats().sstables_probed_per_get.max, 1);
+    assert_eq!(
+        bloom_lookups(&engine),
+        vec![
+            (b"c0".to_vec(), 0),
+            (b"b0".to_vec(), 0),
+            (b"a0".to_vec(), 1)
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn scan_skips_files_outside_range() -> Result<()> {
+    let dir = tempdir()?;
+    let engine = engine_with_disjoint_l0(dir.path(), &["a", "b", "c"])?;
+
+    let results = engine.scan(b"b3", b"b6")?;
+    let keys: Vec<&[u8]> = results.iter().map(|(k, _)| k.as_slice()).collect();
+    assert_eq!(keys, vec![&b"b3"[..], b"b4", b"b5"]);
+
+    // Only the overlapping file is read, and only the keys in range.
+    assert_eq!(
+        bloom_lookups(&engine),
+        vec![
+            (b"c0".to_vec(), 0),
+            (b"b0".to_vec(), 3),
+            (b"a0".to_vec(), 0)
+        ]
+    );
+
+    assert_eq!(engine.scan(b"a9", b"c")?.len(), 11);
+    assert!(engine.scan(b"d", b"")?.is_empty());
+    assert_eq!(engine.scan(b"", b"a")?.len(), 0);
+    Ok(())
+}
@@ -251,6 +251,76 @@ fn seq_recovered_from_sstables_after_wal_truncation() -> Result<()> {
     Ok(())
 }
 
+// --------------------- Persisted last sequence number ---------------------
+
+#[test]
+fn flush_records_last_seq_in_manifest() -> Result<()> {
+    let dir = tempdir()?;
+    let sst_dir = dir.path().join("sst");
+    let mut engine = Engine::new(dir.path().join("wal.log"), &sst_dir, usize::MAX, false)?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    engine.del(b"a".to_vec())?;
+    engine.force_flush()?;
+    assert_eq!(engine.manifest.last_seq(), 3);
+
+    let manifest = manifest::Manifest::load_or_create(&sst_dir)?;
+    assert_eq!(manifest.last_seq(), 3);
+    Ok(())
+}
+
+#[test]
+fn seq_does_not_go_backwards_when_newest_data_is_compacted_away() -> Result<()> {
+    let dir = tempdir()?;
+    let wal_path = dir.path().join("wal.log");
+    let sst_dir = dir.path().join("sst");
+    {
+        let mut engine = Engine::new(&wal_path, &sst_dir, usize::MAX, false)?;
+        engine.set(b"k".to_vec(), b"v".to_vec())?;
+        engine.force_flush()?;
+        engine.del(b"k".to_vec())?;
+        engine.force_flush()?;
+        // The tombstone and the value it shadows are both dropped, leaving
+        // no file that carries seq 2.
+        engine.compact()?;
+        assert_eq!(engine.seq(), 2);
+        assert_eq!(super::helpers::count_sst_files(&sst_dir), 0);
+    }
+
+    let mut engine = Engine::new(&wal_path, &sst_dir, usize::MAX, false)?;
+    assert_eq!(engine.seq(), 2);
+    engine.set(b"k".to_vec(), b"new".to_vec())?;
+    assert_eq!(engine.get(b"k")?, Some((3, b"new".to_vec())));
+    Ok(())
+}
+
+#[test]
+fn startup_uses_manifest_seq_metadata() -> Result<()> {
+    let dir = tempdir()?;
+    let wal_path = dir.path().join("wal.log");
+    let sst_dir = dir.path().join("sst");
+    {
+        let mut engine = Engine::new(&wal_path, &sst_dir, usize::MAX, false)?;
+        engine.set(b"a".to_vec(), b"1".to_vec())?;
+        engine.force_flush()?;
+    }
+
+    // Raise the recorded seq past anything in the SSTables or the WAL: the
+    // engine must continue from the manifest's value.
+    let mut manifest = manifest::Manifest::load_or_create(&sst_dir)?;
+    manifest.set_last_seq(100)?;
+    manifest.save()?;
+
+    let mut engine = Engine::new(&wal_path, &sst_dir, usize::MAX, false)?;
+    assert_eq!(engine.seq(), 100);
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    assert_eq!(engine.get(b"b")?, Some((101, b"2".to_vec())));
+
+    let read_only = Engine::open_read_only(&wal_path, &sst_dir)?;
+    assert_eq!(read_only.seq(), 101);
+    Ok(())
+}
+
 // --------------------- WAL open error propagation ---------------------
 
 #[test]
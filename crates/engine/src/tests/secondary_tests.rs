@@ -0,0 +1,138 @@
+use crate::*;
+use anyhow::Result;
+use tempfile::tempdir;
+
+// --------------------- Secondary catch-up ---------------------
+
+#[test]
+fn catch_up_replays_new_wal_records() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+
+    let mut primary = Engine::new(&wal, &sst, 1024 * 1024, false)?;
+    primary.set(b"a".to_vec(), b"1".to_vec())?;
+
+    let mut secondary = Engine::open_read_only(&wal, &sst)?;
+    assert_eq!(secondary.get(b"a")?.unwrap().1, b"1");
+
+    primary.set(b"b".to_vec(), b"2".to_vec())?;
+    primary.del(b"a".to_vec())?;
+    assert!(
+        secondary.get(b"b")?.is_none(),
+        "not visible before catch-up"
+    );
+
+    secondary.try_catch_up()?;
+    assert!(secondary.get(b"a")?.is_none());
+    assert_eq!(secondary.get(b"b")?.unwrap().1, b"2");
+    assert_eq!(secondary.seq(), primary.seq());
+
+    // Nothing new: catch-up is a no-op.
+    secondary.try_catch_up()?;
+    assert_eq!(secondary.seq(), primary.seq());
+    Ok(())
+}
+
+#[test]
+fn catch_up_picks_up_flushes() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+
+    let mut primary = Engine::new(&wal, &sst, 1024 * 1024, false)?;
+    primary.set_l0_compaction_trigger(0);
+    primary.set(b"a".to_vec(), b"1".to_vec())?;
+
+    let mut secondary = Engine::open_read_only(&wal, &sst)?;
+
+    // Flush deletes the WAL segment; new writes land in a new one.
+    primary.set(b"b".to_vec(), b"2".to_vec())?;
+    primary.force_flush()?;
+    primary.set(b"c".to_vec(), b"3".to_vec())?;
+
+    secondary.try_catch_up()?;
+    assert_eq!(secondary.l0_sstable_count(), 1);
+    assert_eq!(secondary.get(b"a")?.unwrap().1, b"1");
+    assert_eq!(secondary.get(b"b")?.unwrap().1, b"2");
+    assert_eq!(secondary.get(b"c")?.unwrap().1, b"3");
+    assert_eq!(
+        secondary.scan(b"", b"")?.len(),
+        3,
+        "flushed WAL records must not be duplicated or lost"
+    );
+    Ok(())
+}
+
+#[test]
+fn catch_up_drops_compacted_sstables() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+
+    let mut primary = Engine::new(&wal, &sst, 1024 * 1024, false)?;
+    primary.set_l0_compaction_trigger(0);
+    for i in 0..3u64 {
+        primary.set(format!("k{}", i).into_bytes(), b"old".to_vec())?;
+        primary.force_flush()?;
+    }
+
+    let mut secondary = Engine::open_read_only(&wal, &sst)?;
+    assert_eq!(secondary.l0_sstable_count(), 3);
+
+    primary.set(b"k0".to_vec(), b"new".to_vec())?;
+    primary.force_flush()?;
+    primary.compact()?;
+
+    secondary.try_catch_up()?;
+    assert_eq!(secondary.l0_sstable_count(), 0);
+    assert_eq!(secondary.l1_sstable_count(), 1);
+    assert_eq!(secondary.get(b"k0")?.unwrap().1, b"new");
+    assert_eq!(secondary.get(b"k2")?.unwrap().1, b"old");
+    Ok(())
+}
+
+#[test]
+fn failed_catch_up_keeps_the_previous_state() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+
+    let mut primary = Engine::new(&wal, &sst, 1024 * 1024, false)?;
+    primary.set(b"a".to_vec(), b"1".to_vec())?;
+    let mut secondary = Engine::open_read_only(&wal, &sst)?;
+    let seq = secondary.seq();
+
+    primary.set(b"b".to_vec(), b"value-b".to_vec())?;
+    primary.set(b"c".to_vec(), b"value-c".to_vec())?;
+    primary.set(b"d".to_vec(), b"value-d".to_vec())?;
+
+    // Damage the record of `c`: replay applies `b`, then fails on `c`.
+    let segment = sst.join(wal_filename(primary.wal_number));
+    let mut data = std::fs::read(&segment)?;
+    let pos = data
+        .windows(7)
+        .position(|w| w == b"value-c")
+        .expect("record of c");
+    data[pos] ^= 0xff;
+    std::fs::write(&segment, &data)?;
+
+    assert!(secondary.try_catch_up().is_err());
+    assert!(secondary.get(b"b")?.is_none(), "half-applied catch-up");
+    assert_eq!(secondary.get(b"a")?.unwrap().1, b"1");
+    assert_eq!(secondary.seq(), seq);
+    Ok(())
+}
+
+#[test]
+fn catch_up_rejected_on_writable_engine() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1024 * 1024,
+        false,
+    )?;
+    assert!(engine.try_catch_up().is_err());
+    Ok(())
+}
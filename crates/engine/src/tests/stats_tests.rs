@@ -0,0 +1,147 @@
+use crate::stats::Histogram;
+use crate::*;
+use anyhow::Result;
+use tempfile::tempdir;
+
+// --------------------- Histogram ---------------------
+
+#[test]
+fn histogram_percentiles_round_up_to_bucket() {
+    let h = Histogram::new();
+    assert_eq!(h.snapshot(), HistogramSnapshot::default());
+
+    for v in 1..=100u64 {
+        h.record(v);
+    }
+    let snap = h.snapshot();
+    assert_eq!(snap.count, 100);
+    assert_eq!(snap.sum, 5050);
+    assert_eq!(snap.max, 100);
+    assert!((snap.mean - 50.5).abs() < f64::EPSILON);
+    // 50 falls in [32, 64); 95 and 99 in [64, 128), clamped to max.
+    assert_eq!(snap.p50, 63);
+    assert_eq!(snap.p95, 100);
+    assert_eq!(snap.p99, 100);
+}
+
+#[test]
+fn histogram_handles_zero_and_max() {
+    let h = Histogram::new();
+    h.record(0);
+    h.record(u64::MAX);
+    let snap = h.snapshot();
+    assert_eq!(snap.count, 2);
+    assert_eq!(snap.p50, 0);
+    assert_eq!(snap.p99, u64::MAX);
+}
+
+// --------------------- Engine::stats ---------------------
+
+#[test]
+fn stats_count_operations() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1024 * 1024,
+        true,
+    )?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    engine.del(b"a".to_vec())?;
+    engine.get(b"a")?;
+    engine.get(b"b")?;
+    engine.get(b"missing")?;
+
+    let stats = engine.stats();
+    assert_eq!(stats.seq, 3);
+    assert_eq!(stats.sets, 2);
+    assert_eq!(stats.deletes, 1);
+    assert_eq!(stats.gets, 3);
+    assert_eq!(stats.get_latency_micros.count, 3);
+    assert_eq!(stats.set_latency_micros.count, 2);
+    assert_eq!(stats.delete_latency_micros.count, 1);
+    assert_eq!(stats.wal_syncs, 3, "wal_sync=true fsyncs every append");
+    assert_eq!(
+        stats.wal_bytes,
+        std::fs::metadata(dir.path().join("sst").join(wal_filename(engine.wal_number)))?.len()
+    );
+    assert_eq!(stats.flushes, 0);
+    assert_eq!(stats.write_stalls, 0);
+    assert_eq!(stats.memtable_entries, 2);
+    Ok(())
+}
+
+#[test]
+fn stats_time_write_batches() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1024 * 1024,
+        false,
+    )?;
+    let mut batch = WriteBatch::new();
+    batch
+        .put(b"a".to_vec(), b"1".to_vec())
+        .delete(b"b".to_vec());
+    engine.write(batch)?;
+    engine.write(WriteBatch::new())?;
+
+    let stats = engine.stats();
+    assert_eq!(
+        stats.batch_latency_micros.count, 1,
+        "empty batches are no-ops"
+    );
+    assert_eq!(stats.sets, 1);
+    assert_eq!(stats.deletes, 1);
+    assert_eq!(stats.set_latency_micros.count, 0);
+    Ok(())
+}
+
+#[test]
+fn stats_track_flush_compaction_and_probes() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(dir.path().join("wal.log"), dir.path().join("sst"), 1, false)?;
+    engine.set_l0_compaction_trigger(0);
+
+    // threshold=1: every write stalls on an inline flush.
+    for i in 0..3u64 {
+        engine.set(format!("k{}", i).into_bytes(), b"v".to_vec())?;
+    }
+    let stats = engine.stats();
+    assert_eq!(stats.flushes, 3);
+    assert_eq!(stats.write_stalls, 3);
+    assert!(stats.bytes_flushed > 0);
+    assert!(stats.wal_bytes > 0, "WAL bytes survive writer replacement");
+    assert_eq!(stats.sstables.len(), 3);
+    assert!(stats
+        .sstables
+        .iter()
+        .all(|s| s.level == 0 && s.entries == 1));
+
+    // k0 is in the oldest SSTable: the two newer ones are skipped by key
+    // range without consulting their bloom filters.
+    engine.get(b"k0")?;
+    let stats = engine.stats();
+    assert_eq!(stats.sstables_probed_per_get.max, 1);
+    let bloom_lookups: u64 = stats
+        .sstables
+        .iter()
+        .map(|s| s.bloom_hits + s.bloom_misses)
+        .sum();
+    assert_eq!(bloom_lookups, 1);
+    let oldest = &stats.sstables[2];
+    assert_eq!(oldest.smallest_key, b"k0");
+    assert_eq!(oldest.largest_key, b"k0");
+    assert_eq!((oldest.min_seq, oldest.max_seq), (1, 1));
+
+    engine.compact()?;
+    let stats = engine.stats();
+    assert_eq!(stats.compactions, 1);
+    assert!(stats.compaction_bytes_read > 0);
+    assert!(stats.bytes_compacted > 0);
+    assert_eq!(stats.sstables.len(), 1);
+    assert_eq!(stats.sstables[0].level, 1);
+    Ok(())
+}
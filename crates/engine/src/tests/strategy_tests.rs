@@ -0,0 +1,414 @@
+use crate::*;
+use anyhow::Result;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+/// Writes `{prefix}{i:04}` for every `i` in `range`, then flushes.
+fn flush_keys(engine: &mut Engine, prefix: &str, range: std::ops::Range<u32>) -> Result<()> {
+    for i in range {
+        engine.set(format!("{}{:04}", prefix, i).into_bytes(), b"v".to_vec())?;
+    }
+    engine.force_flush()
+}
+
+/// Basenames of the SSTables in `level`.
+fn level_files(engine: &Engine, level: usize) -> Vec<String> {
+    engine.levels[level]
+        .iter()
+        .map(|r| r.path().file_name().unwrap().to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Merges the two oldest L0 files back into L0 once L0 holds three.
+#[derive(Debug)]
+struct MergeOldestPair;
+
+impl CompactionStrategy for MergeOldestPair {
+    fn name(&self) -> &'static str {
+        "merge-oldest-pair"
+    }
+
+    fn pick_compaction(&self, state: &LevelState<'_>) -> Option<CompactionJob> {
+        let l0 = state.files(0);
+        if l0.len() < 3 {
+            return None;
+        }
+        let mut job = CompactionJob::new(0, 0);
+        for reader in &l0[l0.len() - 2..] {
+            job.add_input(0, reader);
+        }
+        Some(job)
+    }
+}
+
+/// Pushes the newest L0 file to L1, ignoring older overlapping L0 files.
+#[derive(Debug)]
+struct SkipOlderL0;
+
+impl CompactionStrategy for SkipOlderL0 {
+    fn name(&self) -> &'static str {
+        "skip-older-l0"
+    }
+
+    fn pick_compaction(&self, state: &LevelState<'_>) -> Option<CompactionJob> {
+        let l0 = state.files(0);
+        if l0.len() < 2 {
+            return None;
+        }
+        let mut job = CompactionJob::new(0, 1);
+        job.add_input(0, &l0[0]);
+        Some(job)
+    }
+}
+
+// --------------------- Strategy selection ---------------------
+
+#[test]
+fn default_strategy_is_leveled() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    assert_eq!(engine.compaction_strategy().name(), "leveled");
+
+    engine.set_compaction_strategy(Arc::new(SizeTieredStrategy::new()));
+    assert_eq!(engine.compaction_strategy().name(), "size-tiered");
+    assert!(format!("{:?}", engine).contains("size-tiered"));
+    Ok(())
+}
+
+// --------------------- Merge everything ---------------------
+
+#[test]
+fn merge_all_strategy_rewrites_everything_into_one_file() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_compaction_strategy(Arc::new(MergeAllStrategy));
+    engine.set_l0_compaction_trigger(2);
+
+    flush_keys(&mut engine, "a", 0..10)?;
+    assert_eq!(engine.level_sstable_counts()[..2], [1, 0]);
+    flush_keys(&mut engine, "b", 0..10)?;
+    assert_eq!(engine.level_sstable_counts()[..2], [0, 1]);
+
+    flush_keys(&mut engine, "c", 0..10)?;
+    flush_keys(&mut engine, "d", 0..10)?;
+    assert_eq!(engine.sstable_count(), 1, "everything merged into one file");
+    assert_eq!(engine.l1_sstable_count(), 1);
+
+    for prefix in ["a", "b", "c", "d"] {
+        let key = format!("{}{:04}", prefix, 5).into_bytes();
+        assert!(engine.get(&key)?.is_some());
+    }
+    Ok(())
+}
+
+// --------------------- Size-tiered (universal) ---------------------
+
+#[test]
+fn size_tiered_merges_runs_of_similar_size() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_compaction_strategy(Arc::new(SizeTieredStrategy::new()));
+    engine.set_l0_compaction_trigger(4);
+
+    flush_keys(&mut engine, "big", 0..200)?;
+    let big = level_files(&engine, 0);
+    for prefix in ["x", "y", "z"] {
+        flush_keys(&mut engine, prefix, 0..5)?;
+    }
+
+    // The three small runs are merged; the big one is left alone.
+    let l0 = level_files(&engine, 0);
+    assert_eq!(l0.len(), 2);
+    assert_eq!(l0[1], big[0], "oldest run is not rewritten");
+    assert_eq!(engine.sstable_count(), 2, "size-tiered stays in L0");
+
+    for prefix in ["big", "x", "y", "z"] {
+        let key = format!("{}{:04}", prefix, 3).into_bytes();
+        assert!(engine.get(&key)?.is_some());
+    }
+    Ok(())
+}
+
+#[test]
+fn size_tiered_space_amplification_merges_everything() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_compaction_strategy(Arc::new(
+        SizeTieredStrategy::new()
+            .with_size_ratio_percent(0)
+            .with_max_size_amplification_percent(50),
+    ));
+    engine.set_l0_compaction_trigger(3);
+
+    flush_keys(&mut engine, "a", 0..20)?;
+    flush_keys(&mut engine, "b", 0..10)?;
+    flush_keys(&mut engine, "c", 0..5)?;
+
+    assert_eq!(engine.sstable_count(), 1);
+    assert_eq!(engine.l0_sstable_count(), 1);
+    Ok(())
+}
+
+#[test]
+fn size_tiered_merge_with_deeper_run_writes_to_that_level() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    flush_keys(&mut engine, "a", 0..100)?;
+    flush_keys(&mut engine, "b", 0..100)?;
+    engine.compact()?;
+    assert_eq!(engine.l1_sstable_count(), 1);
+
+    engine.set_compaction_strategy(Arc::new(SizeTieredStrategy::new()));
+    engine.set_l0_compaction_trigger(2);
+    engine.del(b"a0007".to_vec())?;
+    flush_keys(&mut engine, "c", 0..3)?;
+
+    assert_eq!(engine.level_sstable_counts()[..2], [0, 1]);
+    assert_eq!(engine.get(b"a0007")?, None);
+    assert!(engine.get(b"c0001")?.is_some());
+    Ok(())
+}
+
+// --------------------- FIFO ---------------------
+
+#[test]
+fn fifo_deletes_oldest_files_over_size_limit() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+    let kept = {
+        let mut engine = Engine::new(&wal, &sst, usize::MAX, false)?;
+        engine.set_l0_compaction_trigger(0);
+        flush_keys(&mut engine, "a", 0..50)?;
+        let file_size = engine.levels[0][0].file_size();
+        engine.set_compaction_strategy(Arc::new(
+            FifoStrategy::new().with_max_total_size_bytes(file_size * 3),
+        ));
+        engine.set_l0_compaction_trigger(1);
+
+        for prefix in ["b", "c", "d", "e"] {
+            flush_keys(&mut engine, prefix, 0..50)?;
+        }
+        assert_eq!(engine.level_sstable_counts()[..2], [3, 0], "never merged");
+        assert!(engine.level_size_bytes(0) <= file_size * 3);
+        for (prefix, present) in [("a", false), ("b", false), ("c", true), ("e", true)] {
+            let key = format!("{}{:04}", prefix, 7).into_bytes();
+            assert_eq!(engine.get(&key)?.is_some(), present, "{}", prefix);
+        }
+        assert_eq!(super::helpers::count_sst_files(&sst), 3);
+        level_files(&engine, 0)
+    };
+
+    let engine = Engine::new(&wal, &sst, usize::MAX, false)?;
+    assert_eq!(level_files(&engine, 0), kept);
+    assert_eq!(engine.get(b"b0007")?, None);
+    Ok(())
+}
+
+#[test]
+fn fifo_deletes_files_older_than_max_age() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(1);
+    engine.set_compaction_strategy(Arc::new(
+        FifoStrategy::new().with_max_age(Some(std::time::Duration::from_millis(300))),
+    ));
+    flush_keys(&mut engine, "old", 0..10)?;
+    flush_keys(&mut engine, "old", 10..20)?;
+    assert_eq!(engine.l0_sstable_count(), 2);
+
+    std::thread::sleep(std::time::Duration::from_millis(400));
+    flush_keys(&mut engine, "new", 0..10)?;
+    assert_eq!(engine.l0_sstable_count(), 1);
+    assert_eq!(engine.get(b"old0005")?, None);
+    assert!(engine.get(b"new0005")?.is_some());
+    Ok(())
+}
+
+#[test]
+fn fifo_deletes_deeper_levels_first() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    flush_keys(&mut engine, "a", 0..25)?;
+    flush_keys(&mut engine, "a", 25..50)?;
+    engine.compact()?;
+    flush_keys(&mut engine, "b", 0..50)?;
+    flush_keys(&mut engine, "c", 0..50)?;
+    assert_eq!(engine.level_sstable_counts()[..2], [2, 1]);
+
+    let l0_size = engine.level_size_bytes(0);
+    engine.set_compaction_strategy(Arc::new(
+        FifoStrategy::new().with_max_total_size_bytes(l0_size),
+    ));
+    engine.set_l0_compaction_trigger(1);
+    engine.maybe_compact()?;
+    assert_eq!(engine.level_sstable_counts()[..2], [2, 0]);
+    assert_eq!(engine.get(b"a0001")?, None);
+    assert!(engine.manifest.level_filenames(1).is_empty());
+    Ok(())
+}
+
+#[test]
+fn deletion_job_rejects_unknown_inputs() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    flush_keys(&mut engine, "a", 0..10)?;
+
+    let mut job = CompactionJob::deletion(0);
+    assert_eq!(job.kind, CompactionKind::Delete);
+    job.inputs.push((0, "missing.sst".to_string()));
+    assert!(engine.run_compaction(job).is_err());
+    assert_eq!(engine.l0_sstable_count(), 1);
+    Ok(())
+}
+
+// --------------------- Custom strategies ---------------------
+
+#[test]
+fn l0_output_keeps_its_place_among_newer_files() -> Result<()> {
+    let dir = tempdir()?;
+    let wal = dir.path().join("wal.log");
+    let sst = dir.path().join("sst");
+    {
+        let mut engine = Engine::new(&wal, &sst, usize::MAX, false)?;
+        engine.set_compaction_strategy(Arc::new(MergeOldestPair));
+        engine.set_l0_compaction_trigger(1);
+
+        for version in 1..=3u8 {
+            engine.set(b"key".to_vec(), vec![version])?;
+            engine.set(format!("filler{}", version).into_bytes(), b"f".to_vec())?;
+            engine.force_flush()?;
+        }
+
+        assert_eq!(engine.l0_sstable_count(), 2);
+        assert_eq!(engine.get(b"key")?.unwrap().1, vec![3]);
+        assert!(engine.get(b"filler1")?.is_some());
+    }
+
+    let engine = Engine::new(&wal, &sst, usize::MAX, false)?;
+    assert_eq!(engine.l0_sstable_count(), 2);
+    assert_eq!(
+        engine.get(b"key")?.unwrap().1,
+        vec![3],
+        "manifest keeps the merged file behind the newer flush"
+    );
+    Ok(())
+}
+
+#[test]
+fn l0_merge_keeps_tombstones_shadowing_older_l0_files() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    engine.set(b"key".to_vec(), b"old".to_vec())?;
+    engine.force_flush()?;
+    engine.del(b"key".to_vec())?;
+    engine.force_flush()?;
+    engine.set(b"other".to_vec(), b"v".to_vec())?;
+    engine.force_flush()?;
+
+    // Merge only the two newest files; the oldest still holds "old".
+    #[derive(Debug)]
+    struct MergeNewestPair;
+    impl CompactionStrategy for MergeNewestPair {
+        fn name(&self) -> &'static str {
+            "merge-newest-pair"
+        }
+        fn pick_compaction(&self, state: &LevelState<'_>) -> Option<CompactionJob> {
+            let l0 = state.files(0);
+            if l0.len() < 3 {
+                return None;
+            }
+            let mut job = CompactionJob::new(0, 0);
+            job.add_input(0, &l0[0]);
+            job.add_input(0, &l0[1]);
+            Some(job)
+        }
+    }
+    engine.set_compaction_strategy(Arc::new(MergeNewestPair));
+    engine.set_l0_compaction_trigger(1);
+    engine.maybe_compact()?;
+
+    assert_eq!(engine.l0_sstable_count(), 2);
+    assert_eq!(
+        engine.get(b"key")?,
+        None,
+        "tombstone must survive the merge"
+    );
+    Ok(())
+}
+
+#[test]
+fn invalid_job_is_rejected() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )?;
+    engine.set_compaction_strategy(Arc::new(SkipOlderL0));
+    engine.set_l0_compaction_trigger(1);
+
+    engine.set(b"key".to_vec(), b"old".to_vec())?;
+    engine.force_flush()?;
+    engine.set(b"key".to_vec(), b"new".to_vec())?;
+    let err = engine.force_flush().unwrap_err();
+    assert!(
+        err.to_string().contains("skips older overlapping file"),
+        "unexpected error: {err}"
+    );
+
+    // The flush itself succeeded and nothing was moved.
+    assert_eq!(engine.level_sstable_counts()[..2], [2, 0]);
+    assert_eq!(engine.get(b"key")?.unwrap().1, b"new");
+    Ok(())
+}
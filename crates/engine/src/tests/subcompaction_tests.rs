@@ -0,0 +1,174 @@
+use crate::*;
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+
+use super::helpers::count_sst_files;
+
+/// Keeps the info of every completed compaction.
+#[derive(Default)]
+struct CompactionRecorder {
+    compactions: Mutex<Vec<CompactionJobInfo>>,
+}
+
+impl EventListener for CompactionRecorder {
+    fn on_compaction_completed(&self, info: &CompactionJobInfo) {
+        self.compactions.lock().unwrap().push(info.clone());
+    }
+}
+
+/// Rewrites `z*` values to one over the size limit, failing only the
+/// subcompaction that holds them.
+#[derive(Debug)]
+struct FailLastRange;
+
+impl CompactionFilter for FailLastRange {
+    fn name(&self) -> &'static str {
+        "fail-last-range"
+    }
+
+    fn filter(&self, key: &[u8], _entry: &ValueEntry) -> FilterDecision {
+        if key.starts_with(b"z") {
+            FilterDecision::ChangeValue(vec![0; MAX_VALUE_SIZE + 1])
+        } else {
+            FilterDecision::Keep
+        }
+    }
+}
+
+fn new_engine(dir: &std::path::Path) -> Result<Engine> {
+    let mut engine = Engine::new(dir.join("wal.log"), dir.join("sst"), usize::MAX, false)?;
+    engine.set_l0_compaction_trigger(0);
+    Ok(engine)
+}
+
+/// Writes `files` overlapping L0 files holding keys `k0000..k{n}`, with a
+/// tombstone for every tenth key in the newest one.
+fn fill(engine: &mut Engine, files: u32, n: u32) -> Result<()> {
+    for f in 0..files {
+        for i in (f..n).step_by(files as usize) {
+            engine.set(
+                format!("k{:04}", i).into_bytes(),
+                format!("v{}", f).into_bytes(),
+            )?;
+        }
+        engine.force_flush()?;
+    }
+    for i in (0..n).step_by(10) {
+        engine.del(format!("k{:04}", i).into_bytes())?;
+    }
+    engine.force_flush()
+}
+
+fn contents(engine: &Engine) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    engine.scan(b"", b"")
+}
+
+// --------------------- Subcompactions ---------------------
+
+#[test]
+fn max_subcompactions_defaults_to_one() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = new_engine(dir.path())?;
+    assert_eq!(engine.max_subcompactions(), 1);
+    engine.set_max_subcompactions(0);
+    assert_eq!(engine.max_subcompactions(), 1);
+    Ok(())
+}
+
+#[test]
+fn parallel_compaction_matches_single_threaded() -> Result<()> {
+    let serial_dir = tempdir()?;
+    let mut serial = new_engine(serial_dir.path())?;
+    fill(&mut serial, 3, 600)?;
+    serial.compact()?;
+
+    let dir = tempdir()?;
+    let mut engine = new_engine(dir.path())?;
+    let recorder = Arc::new(CompactionRecorder::default());
+    engine.add_event_listener(recorder.clone());
+    engine.set_max_subcompactions(4);
+    fill(&mut engine, 3, 600)?;
+    engine.compact()?;
+
+    assert_eq!(contents(&engine)?, contents(&serial)?);
+    let info = recorder.compactions.lock().unwrap()[0].clone();
+    assert_eq!(info.subcompactions, 4);
+    assert_eq!(info.dropped_tombstones, 60);
+    assert_eq!(info.output_entries, 540);
+
+    // One output per range, sorted and disjoint.
+    let files = &engine.levels[1];
+    assert_eq!(files.len(), 4);
+    for pair in files.windows(2) {
+        assert!(pair[0].largest_key() < pair[1].smallest_key());
+    }
+    assert_eq!(info.output_files.len(), 4);
+    assert_eq!(engine.manifest.level_filenames(1).len(), 4);
+    assert_eq!(count_sst_files(&dir.path().join("sst")), 4);
+    Ok(())
+}
+
+#[test]
+fn ranges_are_still_split_at_target_file_size() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = new_engine(dir.path())?;
+    engine.set_max_subcompactions(2);
+    engine.set_target_file_size_bytes(1024);
+    fill(&mut engine, 2, 400)?;
+    engine.compact()?;
+
+    let files = &engine.levels[1];
+    assert!(files.len() > 2, "got {} files", files.len());
+    for pair in files.windows(2) {
+        assert!(pair[0].largest_key() < pair[1].smallest_key());
+    }
+    assert_eq!(files.iter().map(|r| r.len()).sum::<usize>(), 360);
+    Ok(())
+}
+
+#[test]
+fn failed_range_discards_every_output() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = new_engine(dir.path())?;
+    engine.set_max_subcompactions(4);
+    fill(&mut engine, 2, 400)?;
+    engine.set(b"z".to_vec(), b"last".to_vec())?;
+    engine.force_flush()?;
+    let before = contents(&engine)?;
+    let manifest = engine.manifest.entries.clone();
+
+    engine.set_compaction_filter(Some(Arc::new(FailLastRange)));
+    let err = engine.compact().unwrap_err();
+    assert!(err.to_string().contains("fail-last-range"), "got: {}", err);
+
+    assert_eq!(engine.l0_sstable_count(), 4, "inputs stay in place");
+    assert_eq!(engine.manifest.entries, manifest);
+    assert_eq!(count_sst_files(&dir.path().join("sst")), 4);
+    assert!(std::fs::read_dir(dir.path().join("sst"))?.all(|e| !e
+        .unwrap()
+        .path()
+        .to_string_lossy()
+        .ends_with(".tmp")));
+    assert_eq!(contents(&engine)?, before);
+
+    engine.set_compaction_filter(None);
+    engine.compact()?;
+    assert_eq!(contents(&engine)?, before);
+    Ok(())
+}
+
+#[test]
+fn l0_outputs_are_not_split() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = new_engine(dir.path())?;
+    engine.set_max_subcompactions(4);
+    fill(&mut engine, 2, 200)?;
+
+    let mut job = CompactionJob::new(0, 0);
+    job.add_input(0, &engine.levels[0][0]);
+    job.add_input(0, &engine.levels[0][1]);
+    engine.run_compaction(job)?;
+    assert_eq!(engine.l0_sstable_count(), 2);
+    Ok(())
+}
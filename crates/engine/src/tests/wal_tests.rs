@@ -0,0 +1,496 @@
+use crate::*;
+use anyhow::Result;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+use wal::{WalRecord, WalWriter};
+
+use super::helpers::wal_segment_names;
+
+fn open(dir: &Path) -> Result<Engine> {
+    let mut engine = Engine::new(dir.join("wal.log"), dir.join("sst"), usize::MAX, false)?;
+    engine.set_l0_compaction_trigger(0);
+    Ok(engine)
+}
+
+/// Drops `engine` without the flush on drop, as a crash would.
+fn crash(engine: Engine) {
+    std::mem::forget(engine);
+}
+
+// --------------------- Segments ---------------------
+
+#[test]
+fn reopen_appends_to_the_newest_segment() -> Result<()> {
+    let dir = tempdir()?;
+    let sst = dir.path().join("sst");
+    let mut engine = open(dir.path())?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    crash(engine);
+
+    let mut engine = open(dir.path())?;
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    assert_eq!(wal_segment_names(&sst), vec!["000001.log"]);
+    crash(engine);
+
+    let engine = open(dir.path())?;
+    assert_eq!(engine.get(b"a")?, Some((1, b"1".to_vec())));
+    assert_eq!(engine.get(b"b")?, Some((2, b"2".to_vec())));
+    Ok(())
+}
+
+#[test]
+fn recovery_replays_all_live_segments_in_order() -> Result<()> {
+    let dir = tempdir()?;
+    let sst = dir.path().join("sst");
+    let mut engine = open(dir.path())?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.set(b"b".to_vec(), b"1".to_vec())?;
+    crash(engine);
+
+    // A torn tail: appending after it would make later records unreadable,
+    // so the next engine starts a new segment.
+    fs::OpenOptions::new()
+        .append(true)
+        .open(sst.join("000001.log"))?
+        .write_all(&[9, 0, 0])?;
+    let mut engine = open(dir.path())?;
+    engine.set(b"a".to_vec(), b"2".to_vec())?;
+    engine.del(b"b".to_vec())?;
+    assert_eq!(wal_segment_names(&sst), vec!["000001.log", "000002.log"]);
+    crash(engine);
+
+    let mut engine = open(dir.path())?;
+    assert_eq!(engine.seq(), 4);
+    assert_eq!(engine.get(b"a")?, Some((3, b"2".to_vec())));
+    assert_eq!(engine.get(b"b")?, None);
+
+    // One flush makes every older segment obsolete.
+    engine.force_flush()?;
+    assert_eq!(wal_segment_names(&sst), vec!["000004.log"]);
+    assert_eq!(engine.manifest.log_number(), 4);
+    Ok(())
+}
+
+#[test]
+fn flushed_segments_are_not_replayed() -> Result<()> {
+    let dir = tempdir()?;
+    let sst = dir.path().join("sst");
+    let mut engine = open(dir.path())?;
+    engine.set(b"k".to_vec(), b"old".to_vec())?;
+    let first = fs::read(sst.join("000001.log"))?;
+    engine.force_flush()?;
+    engine.set(b"k".to_vec(), b"new".to_vec())?;
+    engine.force_flush()?;
+    crash(engine);
+
+    // A crash right after the manifest update left the first segment
+    // behind. Its record is in an SSTable, under a newer version of the key.
+    fs::write(sst.join("000001.log"), &first)?;
+
+    let mut engine = open(dir.path())?;
+    assert_eq!(engine.get(b"k")?, Some((2, b"new".to_vec())));
+    assert!(!sst.join("000001.log").exists(), "swept on open");
+    assert!(engine.delete_obsolete_files()?.is_empty());
+    Ok(())
+}
+
+// --------------------- Legacy WAL ---------------------
+
+#[test]
+fn legacy_wal_is_replayed_until_flushed() -> Result<()> {
+    let dir = tempdir()?;
+    let sst = dir.path().join("sst");
+    let legacy = dir.path().join("wal.log");
+    let mut writer = WalWriter::create(&legacy, false)?;
+    writer.append(&WalRecord::Put {
+        seq: 7,
+        key: b"k".to_vec(),
+        value: b"v".to_vec(),
+    })?;
+    drop(writer);
+
+    let engine = open(dir.path())?;
+    assert_eq!(engine.get(b"k")?, Some((7, b"v".to_vec())));
+    assert!(
+        legacy.exists(),
+        "still needed until the memtable is flushed"
+    );
+    assert_eq!(wal_segment_names(&sst), vec!["000001.log"]);
+    crash(engine);
+
+    let mut engine = open(dir.path())?;
+    assert_eq!(engine.get(b"k")?, Some((7, b"v".to_vec())));
+    engine.force_flush()?;
+    assert!(!legacy.exists());
+
+    let engine = open(dir.path())?;
+    assert_eq!(engine.get(b"k")?, Some((7, b"v".to_vec())));
+    Ok(())
+}
+
+// --------------------- Recovery modes ---------------------
+
+fn open_with_mode(dir: &Path, mode: WalRecoveryMode) -> Result<Engine> {
+    let mut engine = Engine::open_with_wal_recovery_mode(
+        dir.join("wal.log"),
+        dir.join("sst"),
+        usize::MAX,
+        false,
+        mode,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    Ok(engine)
+}
+
+/// Writes `a` and `b` to segment 1 and `c` to segment 2, then flips a byte
+/// of the record for `b`. Returns the byte offset of that record.
+fn corrupt_middle_of_first_segment(dir: &Path) -> Result<u64> {
+    let sst = dir.join("sst");
+    let segment = sst.join("000001.log");
+    let mut engine = open(dir)?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    let defect = fs::metadata(&segment)?.len();
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    crash(engine);
+
+    // A torn tail makes the next engine start segment 2.
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&segment)?
+        .write_all(&[9, 0])?;
+    let mut engine = open(dir)?;
+    engine.set(b"c".to_vec(), b"3".to_vec())?;
+    assert_eq!(wal_segment_names(&sst), vec!["000001.log", "000002.log"]);
+    crash(engine);
+
+    let mut bytes = fs::read(&segment)?;
+    bytes[defect as usize + 10] ^= 0xFF;
+    fs::write(&segment, bytes)?;
+    Ok(defect)
+}
+
+#[test]
+fn clean_recovery_reports_nothing() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open(dir.path())?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    crash(engine);
+
+    let engine = open_with_mode(dir.path(), WalRecoveryMode::AbsoluteConsistency)?;
+    assert!(engine.wal_recovery_reports().is_empty());
+    assert_eq!(engine.get(b"a")?, Some((1, b"1".to_vec())));
+    Ok(())
+}
+
+#[test]
+fn default_mode_drops_a_corrupt_last_record() -> Result<()> {
+    let dir = tempdir()?;
+    let segment = dir.path().join("sst").join("000001.log");
+    let mut engine = open(dir.path())?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    let defect = fs::metadata(&segment)?.len();
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    crash(engine);
+    let mut bytes = fs::read(&segment)?;
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xFF;
+    fs::write(&segment, &bytes)?;
+
+    assert!(open_with_mode(dir.path(), WalRecoveryMode::AbsoluteConsistency).is_err());
+
+    let engine = open(dir.path())?;
+    assert_eq!(engine.get(b"a")?, Some((1, b"1".to_vec())));
+    assert_eq!(engine.get(b"b")?, None);
+    let reports = engine.wal_recovery_reports();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].0, segment);
+    assert_eq!(reports[0].1.records_discarded, 1);
+    assert_eq!(reports[0].1.first_defect_offset, Some(defect));
+    Ok(())
+}
+
+#[test]
+fn corruption_before_the_tail_fails_strict_modes() -> Result<()> {
+    let dir = tempdir()?;
+    corrupt_middle_of_first_segment(dir.path())?;
+
+    for mode in [
+        WalRecoveryMode::AbsoluteConsistency,
+        WalRecoveryMode::TolerateCorruptedTail,
+    ] {
+        let err = open_with_mode(dir.path(), mode).unwrap_err();
+        assert!(format!("{:#}", err).contains("000001.log"), "{:#}", err);
+    }
+    Ok(())
+}
+
+#[test]
+fn point_in_time_keeps_the_prefix_and_seals_it() -> Result<()> {
+    let dir = tempdir()?;
+    let sst = dir.path().join("sst");
+    let defect = corrupt_middle_of_first_segment(dir.path())?;
+
+    let mut engine = open_with_mode(dir.path(), WalRecoveryMode::PointInTime)?;
+    assert_eq!(engine.get(b"a")?, Some((1, b"1".to_vec())));
+    assert_eq!(engine.get(b"b")?, None);
+    assert_eq!(engine.get(b"c")?, None, "after the defect");
+
+    let reports = engine.wal_recovery_reports();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].1.first_defect_offset, Some(defect));
+    assert!(reports[0].1.stopped_early);
+    assert_eq!(reports[1].0, sst.join("000002.log"));
+    assert_eq!(reports[1].1.records_discarded, 1);
+
+    // The prefix was flushed and the damaged segments are gone, so writes
+    // made now survive a crash even with the strictest mode.
+    assert_eq!(engine.l0_sstable_count(), 1);
+    assert!(!sst.join("000001.log").exists());
+    assert!(!sst.join("000002.log").exists());
+    engine.set(b"d".to_vec(), b"4".to_vec())?;
+    crash(engine);
+
+    let engine = open_with_mode(dir.path(), WalRecoveryMode::AbsoluteConsistency)?;
+    assert_eq!(engine.get(b"a")?, Some((1, b"1".to_vec())));
+    assert!(engine.get(b"d")?.is_some());
+    Ok(())
+}
+
+#[test]
+fn skip_any_corrupted_keeps_every_valid_record() -> Result<()> {
+    let dir = tempdir()?;
+    let defect = corrupt_middle_of_first_segment(dir.path())?;
+
+    let engine = open_with_mode(dir.path(), WalRecoveryMode::SkipAnyCorrupted)?;
+    assert_eq!(engine.get(b"a")?, Some((1, b"1".to_vec())));
+    assert_eq!(engine.get(b"b")?, None);
+    assert_eq!(engine.get(b"c")?, Some((3, b"3".to_vec())));
+
+    let reports = engine.wal_recovery_reports();
+    assert_eq!(reports.len(), 1);
+    let report = &reports[0].1;
+    assert_eq!(report.records_replayed, 1);
+    assert_eq!(report.records_discarded, 2, "corrupt record and torn tail");
+    assert_eq!(report.first_defect_offset, Some(defect));
+    assert!(!report.stopped_early);
+    Ok(())
+}
+
+// --------------------- Format versions ---------------------
+
+#[test]
+fn v1_segment_is_appended_to_in_v1() -> Result<()> {
+    let dir = tempdir()?;
+    let sst = dir.path().join("sst");
+    let mut engine = open(dir.path())?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    crash(engine);
+
+    // Strip the header, as a segment written before it existed.
+    let segment = sst.join("000001.log");
+    let bytes = fs::read(&segment)?;
+    fs::write(&segment, &bytes[wal::WAL_HEADER_LEN as usize..])?;
+
+    let mut engine = open(dir.path())?;
+    assert_eq!(engine.get(b"a")?, Some((1, b"1".to_vec())));
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    assert_eq!(wal_segment_names(&sst), vec!["000001.log"]);
+    crash(engine);
+
+    let mut reader = wal::WalReader::open(&segment)?;
+    let mut seqs = Vec::new();
+    reader.replay(|r| seqs.push(r.seq()))?;
+    assert_eq!(seqs, vec![1, 2]);
+    assert_eq!(reader.format_version(), Some(1));
+    Ok(())
+}
+
+#[test]
+fn batch_records_are_replayed() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open(dir.path())?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    crash(engine);
+
+    let mut writer = WalWriter::create(dir.path().join("sst").join("000001.log"), false)?;
+    writer.append(&WalRecord::Batch {
+        records: vec![
+            WalRecord::Del {
+                seq: 2,
+                key: b"a".to_vec(),
+            },
+            WalRecord::Put {
+                seq: 3,
+                key: b"b".to_vec(),
+                value: b"2".to_vec(),
+            },
+        ],
+    })?;
+    drop(writer);
+
+    let engine = open(dir.path())?;
+    assert_eq!(engine.seq(), 3);
+    assert_eq!(engine.get(b"a")?, None);
+    assert_eq!(engine.get(b"b")?, Some((3, b"2".to_vec())));
+    Ok(())
+}
+
+#[test]
+fn unsupported_record_type_fails_recovery() -> Result<()> {
+    let dir = tempdir()?;
+    crash(open(dir.path())?);
+
+    let mut writer = WalWriter::create(dir.path().join("sst").join("000001.log"), false)?;
+    writer.append(&WalRecord::Merge {
+        seq: 1,
+        key: b"k".to_vec(),
+        operand: b"+1".to_vec(),
+    })?;
+    drop(writer);
+
+    let err = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        usize::MAX,
+        false,
+    )
+    .unwrap_err();
+    assert!(format!("{:#}", err).contains("Merge"), "{:#}", err);
+    Ok(())
+}
+
+// --------------------- Sync policies ---------------------
+
+#[test]
+fn every_write_is_durable_immediately() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open(dir.path())?;
+    engine.set_wal_sync_policy(WalSyncPolicy::EveryWrite)?;
+
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.del(b"a".to_vec())?;
+    assert_eq!(engine.durable_seq(), 2);
+    assert_eq!(engine.stats().durable_seq, 2);
+    Ok(())
+}
+
+#[test]
+fn never_lags_until_synced_or_flushed() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open(dir.path())?;
+    assert_eq!(engine.wal_sync_policy(), WalSyncPolicy::Never);
+
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    assert_eq!(engine.durable_seq(), 0);
+
+    engine.sync_wal()?;
+    assert_eq!(engine.durable_seq(), 2);
+
+    engine.set(b"c".to_vec(), b"3".to_vec())?;
+    assert_eq!(engine.durable_seq(), 2);
+    engine.flush()?;
+    assert_eq!(engine.durable_seq(), 3);
+    Ok(())
+}
+
+#[test]
+fn every_n_bytes_syncs_in_batches() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open(dir.path())?;
+    engine.set_wal_sync_policy(WalSyncPolicy::EveryNBytes(1024))?;
+
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    assert_eq!(engine.durable_seq(), 0);
+    engine.set(b"b".to_vec(), vec![b'x'; 2048])?;
+    assert_eq!(engine.durable_seq(), 2);
+    Ok(())
+}
+
+#[test]
+fn interval_policy_catches_up_in_the_background() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open(dir.path())?;
+    engine.set_wal_sync_policy(WalSyncPolicy::EveryInterval(Duration::from_millis(5)))?;
+
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while engine.durable_seq() < 1 {
+        assert!(Instant::now() < deadline, "background sync never happened");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    Ok(())
+}
+
+#[test]
+fn policy_carries_over_to_new_segments() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open(dir.path())?;
+    engine.set_wal_sync_policy(WalSyncPolicy::EveryWrite)?;
+
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.flush()?;
+    engine.set(b"b".to_vec(), b"2".to_vec())?;
+    assert_eq!(engine.durable_seq(), 2);
+    assert_eq!(engine.stats().wal_syncs, 2);
+    Ok(())
+}
+
+#[test]
+fn read_only_engine_cannot_sync_the_wal() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open(dir.path())?;
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    drop(engine);
+
+    let mut reader = Engine::open_read_only(dir.path().join("wal.log"), dir.path().join("sst"))?;
+    assert_eq!(reader.durable_seq(), 1);
+    assert!(reader.sync_wal().is_err());
+    Ok(())
+}
+
+// --------------------- Compression ---------------------
+
+#[test]
+fn compressed_wal_is_smaller_and_replays() -> Result<()> {
+    let value = br#"{"user":"alice","roles":["admin","editor"],"active":true}"#.repeat(20);
+    let mut sizes = Vec::new();
+    for compression in [WalCompression::None, WalCompression::Lz4] {
+        let dir = tempdir()?;
+        let mut engine = open(dir.path())?;
+        engine.set_wal_compression(compression);
+        assert_eq!(engine.wal_compression(), compression);
+        for i in 0..10u32 {
+            engine.set(format!("doc{}", i).into_bytes(), value.clone())?;
+        }
+        sizes.push(engine.stats().wal_bytes);
+        crash(engine);
+
+        let engine = open(dir.path())?;
+        assert_eq!(engine.get(b"doc7")?, Some((8, value.clone())));
+    }
+    assert!(sizes[1] * 4 < sizes[0], "{:?}", sizes);
+    Ok(())
+}
+
+#[test]
+fn compression_carries_over_to_new_segments() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open(dir.path())?;
+    engine.set_wal_compression(WalCompression::Lz4);
+    engine.set(b"a".to_vec(), b"1".to_vec())?;
+    engine.flush()?;
+
+    let value = b"abcdefgh".repeat(100);
+    engine.set(b"b".to_vec(), value.clone())?;
+    let segment = dir.path().join("sst").join(wal_filename(engine.wal_number));
+    assert!(fs::metadata(&segment)?.len() < 200);
+    crash(engine);
+
+    let engine = open(dir.path())?;
+    assert_eq!(engine.get(b"b")?, Some((2, value)));
+    Ok(())
+}
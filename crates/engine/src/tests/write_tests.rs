@@ -1,7 +1,8 @@
-use super::helpers::count_sst_files;
+use super::helpers::{count_sst_files, wal_segment_names};
 use crate::*;
 use anyhow::Result;
 use std::fs;
+use std::sync::Arc;
 use tempfile::tempdir;
 
 // --------------------- Basic set / get / del ---------------------
@@ -341,12 +342,13 @@ fn set_rejects_oversized_key() -> Result<()> {
 // --------------------- Flush mechanics ---------------------
 
 #[test]
-fn flush_writes_sstable_and_truncates_wal() -> Result<()> {
+fn flush_writes_sstable_and_rotates_wal() -> Result<()> {
     let dir = tempdir()?;
     let wal_path = dir.path().join("wal.log");
     let sst_dir = dir.path().join("sst");
 
     let mut engine = Engine::new(&wal_path, &sst_dir, 1, true)?;
+    assert_eq!(wal_segment_names(&sst_dir), vec!["000001.log"]);
     engine.set(b"key1".to_vec(), b"value1".to_vec())?;
 
     assert!(
@@ -354,8 +356,15 @@ fn flush_writes_sstable_and_truncates_wal() -> Result<()> {
         "expected at least one .sst file"
     );
 
-    let wal_meta = fs::metadata(&wal_path)?;
-    assert_eq!(wal_meta.len(), 0, "expected wal to be truncated to 0 bytes");
+    // The flushed segment is gone; writes go to a new one holding only
+    // its file header.
+    assert_eq!(wal_segment_names(&sst_dir), vec!["000003.log"]);
+    assert_eq!(engine.manifest.log_number(), 3);
+    assert_eq!(
+        fs::metadata(sst_dir.join("000003.log"))?.len(),
+        wal::WAL_HEADER_LEN
+    );
+    assert!(!wal_path.exists(), "no single-file WAL is written");
     Ok(())
 }
 
@@ -424,3 +433,209 @@ fn tombstone_in_sstable_shadows_older_value() -> Result<()> {
     assert!(engine.get(b"k")?.is_none());
     Ok(())
 }
+
+// --------------------- Rate limiting ---------------------
+
+#[test]
+fn rate_limiter_throttles_sstable_writes_but_not_wal() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1024 * 1024,
+        false,
+    )?;
+    engine.set_l0_compaction_trigger(0);
+    let limiter = Arc::new(RateLimiter::new(0));
+    engine.set_rate_limiter(Some(limiter.clone()));
+
+    for i in 0..50u32 {
+        engine.set(i.to_be_bytes().to_vec(), vec![b'x'; 100])?;
+    }
+    assert_eq!(limiter.total_bytes(), 0, "WAL appends bypass the limiter");
+
+    engine.force_flush()?;
+    let flushed = engine.stats().bytes_flushed;
+    assert_eq!(limiter.total_bytes(), flushed);
+
+    engine.set(b"k".to_vec(), b"v".to_vec())?;
+    engine.force_flush()?;
+    engine.compact()?;
+    let stats = engine.stats();
+    assert_eq!(
+        limiter.total_bytes(),
+        stats.bytes_flushed + stats.bytes_compacted
+    );
+    Ok(())
+}
+
+#[test]
+fn rate_limiter_budget_applies_to_flush() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = Engine::new(
+        dir.path().join("wal.log"),
+        dir.path().join("sst"),
+        1024 * 1024,
+        false,
+    )?;
+    let limiter = Arc::new(RateLimiter::new(0));
+    engine.set_rate_limiter(Some(limiter.clone()));
+    for i in 0..200u32 {
+        engine.set(i.to_be_bytes().to_vec(), vec![b'x'; 100])?;
+    }
+
+    // ~25 KiB of SSTable at 100 KiB/s (10 KiB burst) takes well over 100 ms.
+    limiter.set_bytes_per_sec(100 * 1024);
+    let start = std::time::Instant::now();
+    engine.force_flush()?;
+    assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+    assert!(limiter.total_wait_micros() > 0);
+
+    engine.set_rate_limiter(None);
+    assert!(engine.rate_limiter().is_none());
+    Ok(())
+}
+
+// --------------------- Write options & batches ---------------------
+
+fn open_unsynced(dir: &std::path::Path) -> Result<Engine> {
+    let mut engine = Engine::new(dir.join("wal.log"), dir.join("sst"), usize::MAX, false)?;
+    engine.set_l0_compaction_trigger(0);
+    Ok(engine)
+}
+
+#[test]
+fn sync_option_makes_a_single_write_durable() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open_unsynced(dir.path())?;
+
+    engine.set(b"cache".to_vec(), b"1".to_vec())?;
+    assert_eq!(engine.durable_seq(), 0);
+    let sync = WriteOptions {
+        sync: true,
+        ..WriteOptions::default()
+    };
+    engine.set_opt(b"critical".to_vec(), b"2".to_vec(), &sync)?;
+    assert_eq!(engine.durable_seq(), 2);
+    engine.del_opt(b"cache".to_vec(), &sync)?;
+    assert_eq!(engine.durable_seq(), 3);
+    Ok(())
+}
+
+#[test]
+fn writes_without_wal_are_lost_on_crash() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open_unsynced(dir.path())?;
+    let no_wal = WriteOptions {
+        disable_wal: true,
+        ..WriteOptions::default()
+    };
+
+    engine.set(b"logged".to_vec(), b"1".to_vec())?;
+    engine.set_opt(b"cache".to_vec(), b"2".to_vec(), &no_wal)?;
+    assert_eq!(engine.get(b"cache")?, Some((2, b"2".to_vec())));
+    std::mem::forget(engine);
+
+    let engine = open_unsynced(dir.path())?;
+    assert_eq!(engine.get(b"logged")?, Some((1, b"1".to_vec())));
+    assert_eq!(engine.get(b"cache")?, None);
+    Ok(())
+}
+
+#[test]
+fn writes_without_wal_survive_a_flush() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open_unsynced(dir.path())?;
+    let no_wal = WriteOptions {
+        disable_wal: true,
+        ..WriteOptions::default()
+    };
+
+    engine.set_opt(b"a".to_vec(), b"1".to_vec(), &no_wal)?;
+    engine.set_opt(b"b".to_vec(), b"2".to_vec(), &no_wal)?;
+    engine.del_opt(b"a".to_vec(), &no_wal)?;
+    engine.force_flush()?;
+    std::mem::forget(engine);
+
+    let engine = open_unsynced(dir.path())?;
+    assert_eq!(engine.seq(), 3);
+    assert_eq!(engine.get(b"a")?, None);
+    assert_eq!(engine.get(b"b")?, Some((2, b"2".to_vec())));
+    Ok(())
+}
+
+#[test]
+fn sync_without_wal_is_rejected() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open_unsynced(dir.path())?;
+    let opts = WriteOptions {
+        sync: true,
+        disable_wal: true,
+    };
+
+    assert!(engine.set_opt(b"k".to_vec(), b"v".to_vec(), &opts).is_err());
+    assert!(engine.del_opt(b"k".to_vec(), &opts).is_err());
+    assert_eq!(engine.seq(), 0);
+    Ok(())
+}
+
+#[test]
+fn batch_is_applied_with_consecutive_seqs_and_replayed() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open_unsynced(dir.path())?;
+    engine.set(b"old".to_vec(), b"0".to_vec())?;
+
+    let mut batch = WriteBatch::new();
+    batch
+        .put(b"a".to_vec(), b"1".to_vec())
+        .delete(b"old".to_vec())
+        .put(b"b".to_vec(), b"2".to_vec());
+    assert_eq!(batch.len(), 3);
+    engine.write(batch)?;
+    assert_eq!(engine.seq(), 4);
+    std::mem::forget(engine);
+
+    let engine = open_unsynced(dir.path())?;
+    assert_eq!(engine.seq(), 4);
+    assert_eq!(engine.get(b"a")?, Some((2, b"1".to_vec())));
+    assert_eq!(engine.get(b"old")?, None);
+    assert_eq!(engine.get(b"b")?, Some((4, b"2".to_vec())));
+    assert_eq!(engine.stats().sets, 0, "replay is not counted as writes");
+    Ok(())
+}
+
+#[test]
+fn invalid_batch_applies_nothing() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open_unsynced(dir.path())?;
+
+    let mut batch = WriteBatch::new();
+    batch.put(b"a".to_vec(), b"1".to_vec()).delete(Vec::new());
+    assert!(engine.write(batch).is_err());
+    assert_eq!(engine.seq(), 0);
+    assert_eq!(engine.get(b"a")?, None);
+
+    engine.write(WriteBatch::new())?;
+    assert_eq!(engine.seq(), 0);
+    Ok(())
+}
+
+#[test]
+fn synced_batch_is_durable() -> Result<()> {
+    let dir = tempdir()?;
+    let mut engine = open_unsynced(dir.path())?;
+
+    let mut batch = WriteBatch::new();
+    batch.put(b"a".to_vec(), b"1".to_vec());
+    batch.put(b"b".to_vec(), b"2".to_vec());
+    engine.write_opt(
+        batch,
+        &WriteOptions {
+            sync: true,
+            ..WriteOptions::default()
+        },
+    )?;
+    assert_eq!(engine.durable_seq(), 2);
+    assert_eq!(engine.stats().sets, 2);
+    Ok(())
+}
@@ -1,15 +1,124 @@
-/// Write path: `set()`, `del()`, `force_flush()`, and the internal `flush()`.
+/// Write path: `set()`, `del()`, `write()` and their `_opt` variants,
+/// `force_flush()`, and the internal `flush()`.
 ///
 /// All mutations flow through this module. Each write is first appended to the
 /// WAL for durability, then applied to the in-memory Memtable. When the
 /// Memtable exceeds the configured flush threshold, it is persisted to a new
-/// SSTable on disk.
+/// SSTable on disk, and the WAL segments holding its records are deleted.
 use anyhow::Result;
-use std::fs::OpenOptions;
-use std::time::{SystemTime, UNIX_EPOCH};
-use wal::{WalRecord, WalWriter};
+use encryption::KeyProvider;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use wal::{WalRecord, WalSyncPolicy, WalWriter};
 
-use crate::{Engine, SSTableReader, SSTableWriter, MAX_KEY_SIZE, MAX_VALUE_SIZE};
+use crate::events::{
+    BackgroundJob, FlushJobInfo, TableFileInfo, TableFileReason, WalTruncatedInfo,
+};
+use crate::manifest::SstMeta;
+use crate::recovery::{live_wal_segments, open_sstable};
+use crate::stats::Metrics;
+use crate::{wal_filename, Engine, SSTableWriter, MAX_KEY_SIZE, MAX_VALUE_SIZE};
+
+/// Per-write durability options for [`Engine::set_opt`], [`Engine::del_opt`]
+/// and [`Engine::write_opt`].
+///
+/// The default follows the engine's [`WalSyncPolicy`](crate::WalSyncPolicy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Forces the WAL to disk before the write returns, whatever the sync
+    /// policy.
+    pub sync: bool,
+    /// Skips the WAL. Such a write is **lost on crash** unless a flush
+    /// persists it to an SSTable first; it is also invisible to read-only
+    /// instances tailing the WAL until then, and not covered by
+    /// [`Engine::durable_seq`]. Meant for data that can be rebuilt.
+    pub disable_wal: bool,
+}
+
+impl WriteOptions {
+    fn validate(&self) -> Result<()> {
+        anyhow::ensure!(
+            !(self.sync && self.disable_wal),
+            "WriteOptions: sync requires the WAL (disable_wal is set)"
+        );
+        Ok(())
+    }
+}
+
+/// A group of puts and deletes applied atomically by [`Engine::write_opt`].
+///
+/// The batch is logged as a single WAL record, so after a crash either all
+/// of it or none of it is recovered. Operations get consecutive sequence
+/// numbers in insertion order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BatchOp {
+    Put { key: Vec<u8>, value: Vec<u8> },
+    Del { key: Vec<u8> },
+}
+
+impl WriteBatch {
+    /// Creates an empty batch.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a put of `key` to `value`.
+    pub fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> &mut Self {
+        self.ops.push(BatchOp::Put { key, value });
+        self
+    }
+
+    /// Adds a delete of `key`.
+    pub fn delete(&mut self, key: Vec<u8>) -> &mut Self {
+        self.ops.push(BatchOp::Del { key });
+        self
+    }
+
+    /// Number of operations in the batch.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns `true` if the batch holds no operations.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Removes every operation, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.ops.clear();
+    }
+}
+
+fn check_key(key: &[u8]) -> Result<()> {
+    anyhow::ensure!(!key.is_empty(), "key must not be empty");
+    anyhow::ensure!(
+        key.len() <= MAX_KEY_SIZE,
+        "key too large: {} bytes (max {})",
+        key.len(),
+        MAX_KEY_SIZE
+    );
+    Ok(())
+}
+
+fn check_value(value: &[u8]) -> Result<()> {
+    anyhow::ensure!(
+        value.len() <= MAX_VALUE_SIZE,
+        "value too large: {} bytes (max {})",
+        value.len(),
+        MAX_VALUE_SIZE
+    );
+    Ok(())
+}
 
 impl Engine {
     /// Inserts a key-value pair (the `SET` command).
@@ -18,41 +127,45 @@ impl Engine {
     /// Memtable. If the Memtable exceeds the flush threshold, it is
     /// automatically flushed to a new SSTable.
     pub fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
-        anyhow::ensure!(!key.is_empty(), "key must not be empty");
-        anyhow::ensure!(
-            key.len() <= MAX_KEY_SIZE,
-            "key too large: {} bytes (max {})",
-            key.len(),
-            MAX_KEY_SIZE
-        );
-        anyhow::ensure!(
-            value.len() <= MAX_VALUE_SIZE,
-            "value too large: {} bytes (max {})",
-            value.len(),
-            MAX_VALUE_SIZE
-        );
+        self.set_opt(key, value, &WriteOptions::default())
+    }
 
-        self.seq = self
-            .seq
-            .checked_add(1)
-            .ok_or_else(|| anyhow::anyhow!("sequence number overflow (u64::MAX reached)"))?;
-        let seq = self.seq;
+    /// [`set`](Engine::set) with per-write [`WriteOptions`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key or value is invalid, the options
+    /// combine `sync` with `disable_wal`, the engine is read-only, or the
+    /// WAL append fails.
+    pub fn set_opt(&mut self, key: Vec<u8>, value: Vec<u8>, opts: &WriteOptions) -> Result<()> {
+        self.ensure_writable("set")?;
+        let start = Instant::now();
+        opts.validate()?;
+        check_key(&key)?;
+        check_value(&value)?;
+
+        let seq = self.next_seqs(1)?;
 
         // Append to WAL first
-        self.wal_writer.append(&WalRecord::Put {
-            seq,
-            key: key.clone(),
-            value: value.clone(),
-        })?;
+        if !opts.disable_wal {
+            self.log(
+                &WalRecord::Put {
+                    seq,
+                    key: key.clone(),
+                    value: value.clone(),
+                },
+                opts,
+            )?;
+        }
 
         // Apply to memtable
         self.mem.put(key, value, seq);
 
         // Maybe flush memtable to SSTable
-        if self.mem.approx_size() >= self.flush_threshold {
-            self.flush()?;
-        }
+        self.maybe_flush()?;
 
+        Metrics::add(&self.metrics.sets, 1);
+        self.metrics.set_latency.record_duration(start.elapsed());
         Ok(())
     }
 
@@ -61,103 +174,340 @@ impl Engine {
     /// A tombstone record is appended to the WAL and inserted into the
     /// Memtable. The tombstone shadows any older value in SSTables.
     pub fn del(&mut self, key: Vec<u8>) -> Result<()> {
-        anyhow::ensure!(!key.is_empty(), "key must not be empty");
-        anyhow::ensure!(
-            key.len() <= MAX_KEY_SIZE,
-            "key too large: {} bytes (max {})",
-            key.len(),
-            MAX_KEY_SIZE
-        );
+        self.del_opt(key, &WriteOptions::default())
+    }
 
-        self.seq = self
-            .seq
-            .checked_add(1)
-            .ok_or_else(|| anyhow::anyhow!("sequence number overflow (u64::MAX reached)"))?;
-        let seq = self.seq;
+    /// [`del`](Engine::del) with per-write [`WriteOptions`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`set_opt`](Engine::set_opt).
+    pub fn del_opt(&mut self, key: Vec<u8>, opts: &WriteOptions) -> Result<()> {
+        self.ensure_writable("del")?;
+        let start = Instant::now();
+        opts.validate()?;
+        check_key(&key)?;
+
+        let seq = self.next_seqs(1)?;
 
-        self.wal_writer.append(&WalRecord::Del {
-            seq,
-            key: key.clone(),
-        })?;
+        if !opts.disable_wal {
+            self.log(
+                &WalRecord::Del {
+                    seq,
+                    key: key.clone(),
+                },
+                opts,
+            )?;
+        }
 
         self.mem.delete(key, seq);
 
-        if self.mem.approx_size() >= self.flush_threshold {
-            self.flush()?;
+        self.maybe_flush()?;
+
+        Metrics::add(&self.metrics.deletes, 1);
+        self.metrics.delete_latency.record_duration(start.elapsed());
+        Ok(())
+    }
+
+    /// Applies `batch` atomically with default [`WriteOptions`].
+    pub fn write(&mut self, batch: WriteBatch) -> Result<()> {
+        self.write_opt(batch, &WriteOptions::default())
+    }
+
+    /// Applies every operation of `batch` atomically: all of them are
+    /// validated first, logged as one WAL record and then inserted into the
+    /// Memtable. An empty batch is a no-op.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`set_opt`](Engine::set_opt); if any operation is invalid,
+    /// none is applied.
+    pub fn write_opt(&mut self, batch: WriteBatch, opts: &WriteOptions) -> Result<()> {
+        self.ensure_writable("write")?;
+        let start = Instant::now();
+        opts.validate()?;
+        for op in &batch.ops {
+            match op {
+                BatchOp::Put { key, value } => {
+                    check_key(key)?;
+                    check_value(value)?;
+                }
+                BatchOp::Del { key } => check_key(key)?,
+            }
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let first = self.next_seqs(batch.len() as u64)?;
+
+        if !opts.disable_wal {
+            let records = batch
+                .ops
+                .iter()
+                .zip(first..)
+                .map(|(op, seq)| match op {
+                    BatchOp::Put { key, value } => WalRecord::Put {
+                        seq,
+                        key: key.clone(),
+                        value: value.clone(),
+                    },
+                    BatchOp::Del { key } => WalRecord::Del {
+                        seq,
+                        key: key.clone(),
+                    },
+                })
+                .collect();
+            self.log(&WalRecord::Batch { records }, opts)?;
         }
 
+        let (mut sets, mut deletes) = (0, 0);
+        for (op, seq) in batch.ops.into_iter().zip(first..) {
+            match op {
+                BatchOp::Put { key, value } => {
+                    self.mem.put(key, value, seq);
+                    sets += 1;
+                }
+                BatchOp::Del { key } => {
+                    self.mem.delete(key, seq);
+                    deletes += 1;
+                }
+            }
+        }
+
+        self.maybe_flush()?;
+
+        Metrics::add(&self.metrics.sets, sets);
+        Metrics::add(&self.metrics.deletes, deletes);
+        self.metrics.batch_latency.record_duration(start.elapsed());
+        Ok(())
+    }
+
+    /// Reserves `n` consecutive sequence numbers and returns the first.
+    fn next_seqs(&mut self, n: u64) -> Result<u64> {
+        self.seq = self
+            .seq
+            .checked_add(n)
+            .ok_or_else(|| anyhow::anyhow!("sequence number overflow (u64::MAX reached)"))?;
+        Ok(self.seq - n + 1)
+    }
+
+    /// Appends `record` to the WAL, syncing it if `opts` asks for it.
+    fn log(&mut self, record: &WalRecord, opts: &WriteOptions) -> Result<()> {
+        let writer = self.wal_writer_mut()?;
+        writer.append(record)?;
+        if opts.sync {
+            writer.sync_to_disk()?;
+        }
         Ok(())
     }
 
     /// Forces a flush of the current Memtable to a new SSTable.
     ///
-    /// This is a no-op if the memtable is empty. After flushing, the WAL is
-    /// truncated and the memtable is reset. If auto-compaction is enabled and
+    /// This is a no-op if the memtable is empty. After flushing, the WAL
+    /// segments holding the flushed records are deleted and the memtable is
+    /// reset. If auto-compaction is enabled and
     /// the L0 count reaches the trigger, compaction runs automatically.
     ///
     /// # Errors
     ///
     /// Returns an error on I/O failure during SSTable write, manifest update,
-    /// or WAL truncation.
+    /// or WAL rotation, or if the engine is read-only.
     pub fn force_flush(&mut self) -> Result<()> {
+        self.ensure_writable("flush")?;
         if self.mem.is_empty() {
             return Ok(());
         }
         self.flush()
     }
 
+    /// Forces every write so far to disk, whatever the WAL sync policy.
+    /// Afterwards [`Engine::durable_seq`] equals [`Engine::seq`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the engine is read-only or the sync fails.
+    pub fn sync_wal(&mut self) -> Result<()> {
+        self.ensure_writable("sync_wal")?;
+        self.wal_writer_mut()?.sync_to_disk()?;
+        self.persisted_seq = self.seq;
+        Ok(())
+    }
+
+    /// Flushes the memtable if it has reached the flush threshold.
+    ///
+    /// The flush (and any compaction it triggers) runs inline, blocking the
+    /// foreground write that crossed the threshold; this is recorded as a
+    /// write stall.
+    fn maybe_flush(&mut self) -> Result<()> {
+        if self.mem.approx_size() < self.flush_threshold {
+            return Ok(());
+        }
+        let start = Instant::now();
+        self.flush()?;
+        Metrics::add(&self.metrics.write_stalls, 1);
+        Metrics::add(
+            &self.metrics.write_stall_micros,
+            start.elapsed().as_micros() as u64,
+        );
+        Ok(())
+    }
+
     /// Internal flush implementation. Callers should use [`force_flush`] for
     /// the public API or rely on the automatic flush in `set`/`del`.
     ///
     /// # Steps
     ///
-    /// 1. Generate a unique filename: `sst-{seq}-{timestamp_ms}.sst`.
-    /// 2. Write the SSTable via [`SSTableWriter::write_from_memtable`]
+    /// 1. Allocate a file number from the manifest for the filename
+    ///    (`000042.sst`, see [`next_sst_name`](Engine::next_sst_name)).
+    /// 2. Seal the memtable: start a new WAL segment for later writes.
+    /// 3. Write the SSTable via [`SSTableWriter::write_from_memtable_with_options`]
     ///    (atomic temp + rename).
-    /// 3. Update the manifest atomically.
-    /// 4. Truncate the WAL to zero bytes.
-    /// 5. Create a fresh [`WalWriter`] in append mode.
+    /// 4. Update the manifest atomically, recording the last sequence number
+    ///    and the new segment as the oldest live one.
+    /// 5. Delete the older WAL segments.
     /// 6. Replace the Memtable with an empty one.
     /// 7. Open the new SSTable and insert it at position 0 (newest).
-    /// 8. Trigger auto-compaction if the L0 count reaches the threshold.
+    /// 8. Run leveled compactions while L0 or any level is over budget.
     pub(crate) fn flush(&mut self) -> Result<()> {
-        // choose filename using current seq and timestamp so it's monotonic
-        let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        self.ensure_writable("flush")?;
+
+        if let Err(e) = self.flush_memtable() {
+            self.notify_background_error(BackgroundJob::Flush, &e);
+            return Err(e);
+        }
+
+        // Auto-compaction: push data down the levels while L0 has reached
+        // the trigger threshold or a deeper level exceeds its target size.
+        // This keeps read amplification bounded without requiring the caller
+        // to manually invoke compact().
+        self.maybe_compact()
+    }
 
-        let sst_name = format!("sst-{:020}-{}.sst", self.seq, ts);
+    /// Steps 1-7 of [`flush`](Engine::flush): writes the memtable to a new
+    /// L0 SSTable, updates the manifest, and deletes the sealed WAL
+    /// segments, notifying event listeners along the way.
+    fn flush_memtable(&mut self) -> Result<()> {
+        // allocate a fresh file number; never reused, even after a crash
+        let sst_name = self.next_sst_name();
         let sst_path = self.sst_dir.join(&sst_name);
 
+        let mut info = FlushJobInfo {
+            sst_name: sst_name.clone(),
+            path: sst_path.clone(),
+            entries: self.mem.len(),
+            file_size: 0,
+            max_seq: self.seq,
+        };
+        self.notify(|l| l.on_flush_begin(&info));
+
+        // Seal the memtable. Its records live in the segments before the new
+        // one, which stay live until the manifest records the flush; if the
+        // flush fails they are replayed on restart together with the new one.
+        let sealed = live_wal_segments(&self.sst_dir, &self.wal_path, self.manifest.log_number())?;
+        self.rotate_wal()?;
+
         // write sstable (this writes to temp and rename inside)
-        SSTableWriter::write_from_memtable(&sst_path, &self.mem)?;
+        let min_seq = self.mem.iter().map(|(_, e)| e.seq).min().unwrap_or(0);
+        SSTableWriter::write_from_memtable_with_options(
+            &sst_path,
+            &self.mem,
+            &self.sst_write_options(),
+        )?;
+        info.file_size = std::fs::metadata(&sst_path)?.len();
+        self.notify(|l| {
+            l.on_table_file_created(&TableFileInfo {
+                path: sst_path.clone(),
+                file_size: info.file_size,
+                reason: TableFileReason::Flush,
+            })
+        });
 
         // Record the new SSTable in the manifest and persist atomically.
-        self.manifest.add(sst_name, 0);
-        self.manifest.save()?;
-
-        // Successfully wrote SSTable and manifest; now safely truncate the WAL.
-        let _f = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&self.wal_path)?;
+        let reader = open_sstable(&sst_path, self.key_provider.as_ref())?;
+        self.manifest.add(SstMeta::from_reader(&reader, 0, min_seq));
+        self.manifest.set_log_number(self.wal_number);
+        self.save_manifest()?;
+        // the flushed records no longer depend on the WAL being synced
+        self.persisted_seq = self.persisted_seq.max(info.max_seq);
 
-        // create a fresh WalWriter (append mode)
-        self.wal_writer = WalWriter::create(&self.wal_path, self.wal_sync)?;
+        // Successfully wrote SSTable and manifest; now safely delete the
+        // sealed segments. One that cannot be deleted is obsolete and left
+        // for delete_obsolete_files().
+        for (number, path) in sealed {
+            if number >= self.wal_number {
+                continue;
+            }
+            let bytes_truncated = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if std::fs::remove_file(&path).is_ok() {
+                self.notify(|l| {
+                    l.on_wal_truncated(&WalTruncatedInfo {
+                        wal_path: path.clone(),
+                        bytes_truncated,
+                    })
+                });
+            }
+        }
 
         // reset memtable (reuses existing allocation)
         self.mem.clear();
 
-        let reader = SSTableReader::open(&sst_path)?;
-        self.l0_sstables.insert(0, reader);
+        Metrics::add(&self.metrics.flushes, 1);
+        Metrics::add(&self.metrics.bytes_flushed, reader.file_size());
+        self.levels[0].insert(0, reader);
+
+        self.notify(|l| l.on_flush_completed(&info));
+        Ok(())
+    }
 
-        // Auto-compaction: if the L0 count has reached the trigger threshold,
-        // merge all L0 + L1 SSTables into a single L1 SSTable. This keeps
-        // read amplification bounded without requiring the caller to manually
-        // invoke compact().
-        if self.l0_compaction_trigger > 0 && self.l0_sstables.len() >= self.l0_compaction_trigger {
-            self.compact()?;
+    /// Makes the state recovered by a replay that stopped early durable, so
+    /// the damaged segments and those after them are never replayed again.
+    pub(crate) fn seal_recovered_wal(&mut self) -> Result<()> {
+        if !self.mem.is_empty() {
+            return self.flush();
         }
+        // Nothing to flush: the segment just opened for appends becomes the
+        // oldest live one, and delete_obsolete_files() removes the others.
+        self.manifest.set_log_number(self.wal_number);
+        self.save_manifest()
+    }
 
+    /// Starts a new WAL segment and switches appends to it, keeping the old
+    /// writer's counters for stats().
+    fn rotate_wal(&mut self) -> Result<()> {
+        let number = self.manifest.allocate_file_number();
+        let mut writer = create_wal_writer(
+            &self.sst_dir.join(wal_filename(number)),
+            self.wal_sync_policy,
+            self.key_provider.as_ref(),
+        )?;
+        writer.set_compression(self.wal_compression);
+        if let Some(old) = self.wal_writer.replace(writer) {
+            Metrics::add(&self.metrics.retired_wal_bytes, old.bytes_written());
+            Metrics::add(&self.metrics.retired_wal_syncs, old.sync_count());
+        }
+        self.wal_number = number;
         Ok(())
     }
+
+    /// Returns the WAL writer, or an error if the engine is read-only.
+    fn wal_writer_mut(&mut self) -> Result<&mut WalWriter> {
+        self.wal_writer
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("engine is opened in read-only mode"))
+    }
+}
+
+/// Opens a WAL segment for appending; a new segment is encrypted if `keys`
+/// are given.
+pub(crate) fn create_wal_writer(
+    path: &Path,
+    policy: WalSyncPolicy,
+    keys: Option<&Arc<dyn KeyProvider>>,
+) -> Result<WalWriter> {
+    let writer = match keys {
+        Some(keys) => WalWriter::create_encrypted(path, policy, keys.clone())?,
+        None => WalWriter::create_with_policy(path, policy)?,
+    };
+    Ok(writer)
 }
@@ -20,8 +20,29 @@
 //!
 //! v3 also adds a CRC32 checksum per data record for end-to-end integrity.
 //!
+//! ## v4 footer (36 bytes) - magic `SST4` (`0x5353_5434`)
+//!
+//! ```text
+//! [tombstone_count: u64 LE][max_seq: u64 LE][bloom_offset: u64 LE][index_offset: u64 LE][magic: u32 LE]
+//! ```
+//!
+//! v4 records how many data records are tombstones; the data, bloom and
+//! index sections are unchanged from v3.
+//!
 //! The reader detects the version by reading the last 4 bytes (magic) first,
 //! then seeking back to read the appropriate footer size.
+//!
+//! ## Encrypted files
+//!
+//! ```text
+//! [magic: "RSSTENC1"][encryption header: 48 bytes][block_size: u32 LE][blocks]
+//! ```
+//!
+//! The blocks hold a complete plaintext SSTable (any of the versions above)
+//! encrypted with [`encryption::BlockWriter`]; all offsets in it are
+//! plaintext offsets. No plaintext SSTable starts with the magic: bytes 4..8
+//! would be a key length (v3+) or bytes 0..4 one (v1/v2), far above the
+//! 64 KiB limit.
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::io::{Read, Result as IoResult, Seek, SeekFrom, Write};
@@ -38,6 +59,12 @@ pub const SSTABLE_MAGIC_V2: u32 = 0x5353_5432;
 /// `max_seq` in the footer for O(1) sequence-number recovery.
 pub const SSTABLE_MAGIC_V3: u32 = 0x5353_5433;
 
+/// Magic number identifying SSTable v4 files (ASCII "SST4").
+///
+/// v4 adds the number of tombstones to the footer so that compaction can
+/// find delete-heavy files without reading them.
+pub const SSTABLE_MAGIC_V4: u32 = 0x5353_5434;
+
 /// Size of the v1 footer in bytes: 8 (`index_offset`) + 4 (`magic`).
 pub const FOOTER_BYTES_V1: u64 = 8 + 4;
 
@@ -47,9 +74,19 @@ pub const FOOTER_BYTES_V2: u64 = 8 + 8 + 4;
 /// Size of the v3 footer in bytes: 8 (`max_seq`) + 8 (`bloom_offset`) + 8 (`index_offset`) + 4 (`magic`).
 pub const FOOTER_BYTES_V3: u64 = 8 + 8 + 8 + 4;
 
+/// Size of the v4 footer in bytes: 8 (`tombstone_count`) + the v3 footer.
+pub const FOOTER_BYTES_V4: u64 = 8 + FOOTER_BYTES_V3;
+
 /// Backwards-compatible alias used by existing code.
 pub const SSTABLE_MAGIC: u32 = SSTABLE_MAGIC_V1;
 
+/// Magic bytes at the start of an encrypted SSTable.
+pub const SSTABLE_MAGIC_ENCRYPTED: [u8; 8] = *b"RSSTENC1";
+
+/// Size of the header of an encrypted SSTable: magic, encryption header and
+/// block size.
+pub const ENCRYPTED_HEADER_BYTES: u64 = 8 + encryption::HEADER_LEN as u64 + 4;
+
 /// Backwards-compatible alias used by existing code.
 pub const FOOTER_BYTES: u64 = FOOTER_BYTES_V1;
 
@@ -71,9 +108,28 @@ pub fn write_footer_v2<W: Write>(w: &mut W, bloom_offset: u64, index_offset: u64
     Ok(())
 }
 
+/// Writes a v4 SSTable footer to `w`.
+///
+/// Layout: `[tombstone_count: u64][max_seq: u64][bloom_offset: u64][index_offset: u64][magic: u32 = "SST4"]`
+pub fn write_footer_v4<W: Write>(
+    w: &mut W,
+    tombstone_count: u64,
+    max_seq: u64,
+    bloom_offset: u64,
+    index_offset: u64,
+) -> IoResult<()> {
+    w.write_u64::<LittleEndian>(tombstone_count)?;
+    w.write_u64::<LittleEndian>(max_seq)?;
+    w.write_u64::<LittleEndian>(bloom_offset)?;
+    w.write_u64::<LittleEndian>(index_offset)?;
+    w.write_u32::<LittleEndian>(SSTABLE_MAGIC_V4)?;
+    Ok(())
+}
+
 /// Writes a v3 SSTable footer to `w`.
 ///
 /// Layout: `[max_seq: u64][bloom_offset: u64][index_offset: u64][magic: u32 = "SST3"]`
+#[allow(dead_code)]
 pub fn write_footer_v3<W: Write>(
     w: &mut W,
     max_seq: u64,
@@ -111,6 +167,13 @@ pub enum Footer {
         bloom_offset: u64,
         index_offset: u64,
     },
+    /// v4: adds the tombstone count.
+    V4 {
+        tombstone_count: u64,
+        max_seq: u64,
+        bloom_offset: u64,
+        index_offset: u64,
+    },
 }
 
 impl Footer {
@@ -121,6 +184,7 @@ impl Footer {
             Footer::V1 { index_offset } => *index_offset,
             Footer::V2 { index_offset, .. } => *index_offset,
             Footer::V3 { index_offset, .. } => *index_offset,
+            Footer::V4 { index_offset, .. } => *index_offset,
         }
     }
 
@@ -131,6 +195,7 @@ impl Footer {
             Footer::V1 { .. } => None,
             Footer::V2 { bloom_offset, .. } => Some(*bloom_offset),
             Footer::V3 { bloom_offset, .. } => Some(*bloom_offset),
+            Footer::V4 { bloom_offset, .. } => Some(*bloom_offset),
         }
     }
 
@@ -142,14 +207,26 @@ impl Footer {
     pub fn max_seq(&self) -> Option<u64> {
         match self {
             Footer::V1 { .. } | Footer::V2 { .. } => None,
-            Footer::V3 { max_seq, .. } => Some(*max_seq),
+            Footer::V3 { max_seq, .. } | Footer::V4 { max_seq, .. } => Some(*max_seq),
         }
     }
 
-    /// Returns `true` if this is a v3 SSTable (has per-record CRC32).
+    /// Returns the number of tombstones stored in the footer (v4+), or
+    /// `None` for older files.
+    #[must_use]
+    pub fn tombstone_count(&self) -> Option<u64> {
+        match self {
+            Footer::V4 {
+                tombstone_count, ..
+            } => Some(*tombstone_count),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this is a v3+ SSTable (has per-record CRC32).
     #[must_use]
     pub fn has_checksums(&self) -> bool {
-        matches!(self, Footer::V3 { .. })
+        matches!(self, Footer::V3 { .. } | Footer::V4 { .. })
     }
 
     /// Returns the magic number for this footer version.
@@ -160,6 +237,7 @@ impl Footer {
             Footer::V1 { .. } => SSTABLE_MAGIC_V1,
             Footer::V2 { .. } => SSTABLE_MAGIC_V2,
             Footer::V3 { .. } => SSTABLE_MAGIC_V3,
+            Footer::V4 { .. } => SSTABLE_MAGIC_V4,
         }
     }
 
@@ -170,11 +248,12 @@ impl Footer {
             Footer::V1 { .. } => FOOTER_BYTES_V1,
             Footer::V2 { .. } => FOOTER_BYTES_V2,
             Footer::V3 { .. } => FOOTER_BYTES_V3,
+            Footer::V4 { .. } => FOOTER_BYTES_V4,
         }
     }
 }
 
-/// Reads the SSTable footer from `r`, auto-detecting v1 or v2.
+/// Reads the SSTable footer from `r`, auto-detecting v1 through v4.
 /// Strategy: read the last 4 bytes to determine the magic, then seek back
 /// to read the full footer for that version.
 pub fn read_footer_versioned<R: Read + Seek>(r: &mut R) -> IoResult<Footer> {
@@ -192,6 +271,26 @@ pub fn read_footer_versioned<R: Read + Seek>(r: &mut R) -> IoResult<Footer> {
     let magic = r.read_u32::<LittleEndian>()?;
 
     match magic {
+        SSTABLE_MAGIC_V4 => {
+            if filesize < FOOTER_BYTES_V4 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "file too small for v4 footer",
+                ));
+            }
+            r.seek(SeekFrom::End(-(FOOTER_BYTES_V4 as i64)))?;
+            let tombstone_count = r.read_u64::<LittleEndian>()?;
+            let max_seq = r.read_u64::<LittleEndian>()?;
+            let bloom_offset = r.read_u64::<LittleEndian>()?;
+            let index_offset = r.read_u64::<LittleEndian>()?;
+            let _magic = r.read_u32::<LittleEndian>()?;
+            Ok(Footer::V4 {
+                tombstone_count,
+                max_seq,
+                bloom_offset,
+                index_offset,
+            })
+        }
         SSTABLE_MAGIC_V3 => {
             if filesize < FOOTER_BYTES_V3 {
                 return Err(io::Error::new(
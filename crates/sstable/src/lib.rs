@@ -7,7 +7,7 @@
 //! read-many* — once created they are never modified (only replaced during
 //! compaction).
 //!
-//! ## File layout (v3 – current)
+//! ## File layout (v4 – current)
 //!
 //! ```text
 //! ┌───────────────────────────────────────────────────────────────┐
@@ -33,17 +33,19 @@
 //! │                                                               │
 //! │ ... repeated for each entry ...                                │
 //! ├───────────────────────────────────────────────────────────────┤
-//! │ FOOTER (always last 28 bytes)                                  │
+//! │ FOOTER (always last 36 bytes)                                  │
 //! │                                                               │
-//! │ max_seq (u64 LE) | bloom_offset (u64 LE)                       │
-//! │ index_offset (u64 LE) | magic (u32 LE) "SST3"                 │
+//! │ tombstone_count (u64 LE) | max_seq (u64 LE)                    │
+//! │ bloom_offset (u64 LE) | index_offset (u64 LE)                  │
+//! │ magic (u32 LE) "SST4"                                          │
 //! └───────────────────────────────────────────────────────────────┘
 //! ```
 //!
-//! All integers are little-endian. The magic value `0x5353_5433` ("SST3")
-//! identifies v3. The reader also supports v1 files (magic `SST1`, 12-byte
-//! footer, no bloom/CRC) and v2 files (magic `SST2`, 20-byte footer, bloom
-//! but no CRC) for backward compatibility.
+//! All integers are little-endian. The magic value `0x5353_5434` ("SST4")
+//! identifies v4. The reader also supports v1 files (magic `SST1`, 12-byte
+//! footer, no bloom/CRC), v2 files (magic `SST2`, 20-byte footer, bloom
+//! but no CRC) and v3 files (magic `SST3`, 28-byte footer, no tombstone
+//! count) for backward compatibility.
 //!
 //! ## Version history
 //!
@@ -52,19 +54,31 @@
 //! | v1      | `SST1`| 12 B   | Basic DATA + INDEX                |
 //! | v2      | `SST2`| 20 B   | + Bloom filter section             |
 //! | v3      | `SST3`| 28 B   | + Per-record CRC32, max_seq in footer |
+//! | v4      | `SST4`| 36 B   | + Tombstone count in footer        |
+//!
+//! ## Encryption
+//!
+//! With a [`KeyProvider`](encryption::KeyProvider) in
+//! [`SSTableWriteOptions`], the whole file above is encrypted in blocks
+//! behind a small header naming the key (see the `format` module). Reading
+//! it needs the provider in [`SSTableReadOptions`]; a missing or wrong key
+//! fails [`SSTableReader::open_with_options`] with an
+//! [`EncryptionError`](encryption::EncryptionError).
 
 mod format;
 mod merge;
+mod rate_limiter;
 mod reader;
 mod writer;
 
 pub use format::{
-    FOOTER_BYTES, FOOTER_BYTES_V2, FOOTER_BYTES_V3, SSTABLE_MAGIC, SSTABLE_MAGIC_V2,
-    SSTABLE_MAGIC_V3,
+    ENCRYPTED_HEADER_BYTES, FOOTER_BYTES, FOOTER_BYTES_V2, FOOTER_BYTES_V3, FOOTER_BYTES_V4,
+    SSTABLE_MAGIC, SSTABLE_MAGIC_ENCRYPTED, SSTABLE_MAGIC_V2, SSTABLE_MAGIC_V3, SSTABLE_MAGIC_V4,
 };
 pub use merge::MergeIterator;
-pub use reader::SSTableReader;
-pub use writer::SSTableWriter;
+pub use rate_limiter::{RateLimiter, REFILL_BURST};
+pub use reader::{BloomStats, SSTableReadOptions, SSTableReader};
+pub use writer::{SSTableWriteOptions, SSTableWriter};
 
 #[cfg(test)]
 mod tests;
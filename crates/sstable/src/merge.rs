@@ -57,7 +57,7 @@ impl Ord for HeapEntry {
 /// sequence number. The iterator is lazy — it reads one key at a time from
 /// each source SSTable.
 pub struct MergeIterator<'a> {
-    readers: &'a [SSTableReader],
+    readers: Vec<&'a SSTableReader>,
     /// Per-reader: sorted keys remaining to be yielded.
     key_iters: Vec<std::vec::IntoIter<Vec<u8>>>,
     heap: BinaryHeap<HeapEntry>,
@@ -70,11 +70,29 @@ impl<'a> MergeIterator<'a> {
     /// in-memory index). The first key from each reader is pushed onto a
     /// min-heap.
     pub fn new(readers: &'a [SSTableReader]) -> Self {
+        Self::from_refs(readers.iter().collect())
+    }
+
+    /// Creates a merge iterator over borrowed readers that need not live in
+    /// one slice (e.g. a few files picked from several levels).
+    pub fn from_refs(readers: Vec<&'a SSTableReader>) -> Self {
+        Self::from_refs_in_range(readers, &[], &[])
+    }
+
+    /// Like [`from_refs`](Self::from_refs), but only yields keys in
+    /// `[start, end)`. An empty `start` or `end` leaves that side unbounded.
+    ///
+    /// Merges over disjoint ranges of the same readers can run concurrently
+    /// (e.g. one per compaction worker thread).
+    pub fn from_refs_in_range(readers: Vec<&'a SSTableReader>, start: &[u8], end: &[u8]) -> Self {
         let mut key_iters: Vec<std::vec::IntoIter<Vec<u8>>> = Vec::with_capacity(readers.len());
         let mut heap = BinaryHeap::new();
 
         for (i, reader) in readers.iter().enumerate() {
-            let keys: Vec<Vec<u8>> = reader.keys().map(|k| k.to_vec()).collect();
+            let keys: Vec<Vec<u8>> = reader
+                .keys_in_range(start, end)
+                .map(|k| k.to_vec())
+                .collect();
             let mut iter = keys.into_iter();
             if let Some(first_key) = iter.next() {
                 heap.push(HeapEntry {
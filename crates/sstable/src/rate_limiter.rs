@@ -0,0 +1,126 @@
+//! Token-bucket I/O rate limiter for SSTable writes.
+//!
+//! Flushes and compactions write SSTables at full disk speed, which can
+//! starve foreground reads. A [`RateLimiter`] caps the combined write
+//! throughput of every [`SSTableWriter`](crate::SSTableWriter) sharing it
+//! (via `Arc`) to a bytes-per-second budget.
+//!
+//! Tokens (bytes) refill continuously at the configured rate, up to a burst
+//! of [`REFILL_BURST`] worth of tokens. A request larger than the available
+//! balance is granted immediately but drives the balance negative; the caller
+//! then sleeps until the debt is repaid. Later callers see the debt and wait
+//! their turn, so the long-run throughput never exceeds the budget.
+//!
+//! The budget can be changed at any time with
+//! [`set_bytes_per_sec`](RateLimiter::set_bytes_per_sec); a budget of `0`
+//! disables limiting.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Time window of tokens that may accumulate while the limiter is idle.
+pub const REFILL_BURST: Duration = Duration::from_millis(100);
+
+/// Mutable bucket state, guarded by a mutex.
+#[derive(Debug)]
+struct Bucket {
+    /// Available bytes. Negative while callers are paying off a large request.
+    available: f64,
+    last_refill: Instant,
+}
+
+/// A thread-safe token bucket limiting bytes per second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: AtomicU64,
+    bucket: Mutex<Bucket>,
+    total_bytes: AtomicU64,
+    total_wait_micros: AtomicU64,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `bytes_per_sec` bytes per second.
+    /// `0` means unlimited.
+    #[must_use]
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: AtomicU64::new(bytes_per_sec),
+            bucket: Mutex::new(Bucket {
+                available: burst_bytes(bytes_per_sec),
+                last_refill: Instant::now(),
+            }),
+            total_bytes: AtomicU64::new(0),
+            total_wait_micros: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the current budget in bytes per second (`0` = unlimited).
+    #[must_use]
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec.load(Ordering::Relaxed)
+    }
+
+    /// Changes the budget. Takes effect for the next request; callers
+    /// already sleeping finish their current wait.
+    pub fn set_bytes_per_sec(&self, bytes_per_sec: u64) {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        self.refill(&mut bucket);
+        self.bytes_per_sec.store(bytes_per_sec, Ordering::Relaxed);
+        bucket.available = bucket.available.min(burst_bytes(bytes_per_sec));
+    }
+
+    /// Total bytes that have passed through the limiter.
+    #[must_use]
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Total time callers have spent blocked, in microseconds.
+    #[must_use]
+    pub fn total_wait_micros(&self) -> u64 {
+        self.total_wait_micros.load(Ordering::Relaxed)
+    }
+
+    /// Takes `bytes` tokens, blocking the calling thread until the budget
+    /// allows them. Returns immediately when the limiter is unlimited.
+    pub fn request(&self, bytes: u64) {
+        self.total_bytes.fetch_add(bytes, Ordering::Relaxed);
+        let rate = self.bytes_per_sec();
+        if rate == 0 || bytes == 0 {
+            return;
+        }
+
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            self.refill(&mut bucket);
+            bucket.available -= bytes as f64;
+            if bucket.available >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.available / rate as f64)
+        };
+
+        std::thread::sleep(wait);
+        self.total_wait_micros
+            .fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Adds the tokens accumulated since the last refill, capped at the burst.
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let rate = self.bytes_per_sec();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.last_refill = now;
+        if rate == 0 {
+            bucket.available = 0.0;
+            return;
+        }
+        bucket.available = (bucket.available + elapsed * rate as f64).min(burst_bytes(rate));
+    }
+}
+
+/// Maximum number of tokens the bucket holds at `bytes_per_sec`.
+fn burst_bytes(bytes_per_sec: u64) -> f64 {
+    bytes_per_sec as f64 * REFILL_BURST.as_secs_f64()
+}
@@ -2,20 +2,94 @@ use anyhow::{bail, Result};
 use bloom::BloomFilter;
 use byteorder::{LittleEndian, ReadBytesExt};
 use crc32fast::Hasher as Crc32;
+use encryption::{BlockReader, FileCipher, KeyProvider};
 use memtable::ValueEntry;
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::ops::Bound;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
-use crate::format::{read_footer_versioned, Footer, FOOTER_BYTES_V1};
+use crate::format::{
+    read_footer_versioned, Footer, ENCRYPTED_HEADER_BYTES, FOOTER_BYTES_V1, SSTABLE_MAGIC_ENCRYPTED,
+};
 
 /// Maximum key size we'll allocate during reads (64 KiB). Prevents OOM on corrupt files.
 const MAX_KEY_BYTES: usize = 64 * 1024;
 /// Maximum value size we'll allocate during reads (10 MiB). Prevents OOM on corrupt files.
 const MAX_VALUE_BYTES: usize = 10 * 1024 * 1024;
 
+/// Point-in-time snapshot of a reader's bloom filter counters.
+///
+/// Every [`SSTableReader::get`] on a file with a bloom filter counts as
+/// either a **hit** (the filter said "maybe present") or a **miss** (the
+/// filter said "definitely absent" and the lookup was skipped). A hit whose
+/// key turns out not to be in the index is also counted as a
+/// **false positive**.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BloomStats {
+    /// Lookups the filter let through ("maybe present").
+    pub hits: u64,
+    /// Lookups the filter rejected ("definitely absent").
+    pub misses: u64,
+    /// Hits for keys that were not actually in the SSTable.
+    pub false_positives: u64,
+}
+
+/// Options controlling how an SSTable is opened.
+#[derive(Debug, Clone, Default)]
+pub struct SSTableReadOptions {
+    /// Keys for encrypted files. Plain files are read without them.
+    pub key_provider: Option<Arc<dyn KeyProvider>>,
+}
+
+/// The open file: read directly, or decrypted block by block.
+enum SstFile {
+    Plain(File),
+    Encrypted(BlockReader<File>),
+}
+
+impl SstFile {
+    /// Opens `f` (of `filesize` bytes), returning it with the size of the
+    /// (plaintext) SSTable it holds.
+    fn open(mut f: File, filesize: u64, keys: Option<&dyn KeyProvider>) -> Result<(Self, u64)> {
+        let mut magic = [0u8; 8];
+        if filesize >= ENCRYPTED_HEADER_BYTES {
+            f.read_exact(&mut magic)?;
+        }
+        if magic != SSTABLE_MAGIC_ENCRYPTED {
+            return Ok((SstFile::Plain(f), filesize));
+        }
+        let mut header = [0u8; encryption::HEADER_LEN];
+        f.read_exact(&mut header)?;
+        let cipher = FileCipher::open(&header, keys)?;
+        let block_size = f.read_u32::<LittleEndian>()? as usize;
+        let reader = BlockReader::new(f, cipher, ENCRYPTED_HEADER_BYTES, block_size)?;
+        let len = reader.len();
+        Ok((SstFile::Encrypted(reader), len))
+    }
+}
+
+impl Read for SstFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            SstFile::Plain(f) => f.read(buf),
+            SstFile::Encrypted(r) => r.read(buf),
+        }
+    }
+}
+
+impl Seek for SstFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            SstFile::Plain(f) => f.seek(pos),
+            SstFile::Encrypted(r) => r.seek(pos),
+        }
+    }
+}
+
 /// Reads an SSTable file for point lookups.
 ///
 /// On [`open`](SSTableReader::open) the entire **index** is loaded into memory
@@ -27,19 +101,30 @@ const MAX_VALUE_BYTES: usize = 10 * 1024 * 1024;
 /// reference.
 ///
 /// Point lookups require only a single seek + read per call (no file open/close).
+///
+/// Encrypted files are opened with
+/// [`open_with_options`](SSTableReader::open_with_options) and decrypted
+/// transparently.
 pub struct SSTableReader {
-    /// Path to the `.sst` file on disk (kept for diagnostics).
-    #[allow(dead_code)]
+    /// Path to the `.sst` file on disk.
     path: PathBuf,
     /// In-memory index mapping each key to its byte offset in the data section.
     index: BTreeMap<Vec<u8>, u64>,
     /// Optional bloom filter (present for v2+ SSTables).
     bloom: Option<BloomFilter>,
     /// Persistent file handle, wrapped in Mutex for interior mutability.
-    file: Mutex<BufReader<File>>,
+    file: Mutex<BufReader<SstFile>>,
     /// Parsed footer — used to determine version-specific read behaviour
     /// (e.g. whether to verify CRC32 on reads, or to expose max_seq).
     footer: Footer,
+    /// Size of the file in bytes.
+    file_size: u64,
+    /// Id of the key the file is encrypted with, if it is.
+    key_id: Option<u32>,
+    /// Bloom filter counters (see [`BloomStats`]).
+    bloom_hits: AtomicU64,
+    bloom_misses: AtomicU64,
+    bloom_false_positives: AtomicU64,
 }
 
 impl SSTableReader {
@@ -54,13 +139,29 @@ impl SSTableReader {
     ///
     /// # Errors
     ///
-    /// Returns an error if the file is too small, the magic is wrong, or any
-    /// I/O operation fails.
+    /// Returns an error if the file is too small, the magic is wrong, the
+    /// file is encrypted, or any I/O operation fails.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with_options(path, &SSTableReadOptions::default())
+    }
+
+    /// Like [`open`](SSTableReader::open), with explicit read options (e.g.
+    /// the keys of an encrypted file).
+    ///
+    /// # Errors
+    ///
+    /// As [`open`](SSTableReader::open); an encrypted file whose key is
+    /// missing or wrong fails with an
+    /// [`EncryptionError`](encryption::EncryptionError).
+    pub fn open_with_options<P: AsRef<Path>>(path: P, opts: &SSTableReadOptions) -> Result<Self> {
         let path_buf = path.as_ref().to_path_buf();
-        let mut f = File::open(&path_buf)?;
-        let metadata = f.metadata()?;
-        let filesize = metadata.len();
+        let file = File::open(&path_buf)?;
+        let file_size = file.metadata()?.len();
+        let (mut f, filesize) = SstFile::open(file, file_size, opts.key_provider.as_deref())?;
+        let key_id = match &f {
+            SstFile::Plain(_) => None,
+            SstFile::Encrypted(r) => Some(r.key_id()),
+        };
 
         if filesize < FOOTER_BYTES_V1 {
             bail!("sstable file too small");
@@ -113,6 +214,11 @@ impl SSTableReader {
             bloom,
             file: Mutex::new(BufReader::new(f)),
             footer,
+            file_size,
+            key_id,
+            bloom_hits: AtomicU64::new(0),
+            bloom_misses: AtomicU64::new(0),
+            bloom_false_positives: AtomicU64::new(0),
         })
     }
 
@@ -136,13 +242,20 @@ impl SSTableReader {
         // Fast path: bloom filter says "definitely not here"
         if let Some(ref bf) = self.bloom {
             if !bf.may_contain(key) {
+                self.bloom_misses.fetch_add(1, Ordering::Relaxed);
                 return Ok(None);
             }
+            self.bloom_hits.fetch_add(1, Ordering::Relaxed);
         }
 
         let offset = match self.index.get(key) {
             Some(&o) => o,
-            None => return Ok(None),
+            None => {
+                if self.bloom.is_some() {
+                    self.bloom_false_positives.fetch_add(1, Ordering::Relaxed);
+                }
+                return Ok(None);
+            }
         };
 
         let has_crc = self.footer.has_checksums();
@@ -222,6 +335,37 @@ impl SSTableReader {
         Ok(Some(ValueEntry { seq, value }))
     }
 
+    /// Returns the path of the `.sst` file this reader was opened from.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the size of the SSTable file in bytes.
+    #[must_use]
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Returns the id of the key the file is encrypted with, or `None` if it
+    /// is not encrypted.
+    #[must_use]
+    pub fn key_id(&self) -> Option<u32> {
+        self.key_id
+    }
+
+    /// Returns a snapshot of this reader's bloom filter counters.
+    ///
+    /// All counters stay at zero for v1 files, which have no bloom filter.
+    #[must_use]
+    pub fn bloom_stats(&self) -> BloomStats {
+        BloomStats {
+            hits: self.bloom_hits.load(Ordering::Relaxed),
+            misses: self.bloom_misses.load(Ordering::Relaxed),
+            false_positives: self.bloom_false_positives.load(Ordering::Relaxed),
+        }
+    }
+
     /// Returns `true` if this SSTable has a bloom filter loaded (v2+ format).
     #[must_use]
     pub fn has_bloom(&self) -> bool {
@@ -237,6 +381,13 @@ impl SSTableReader {
         self.footer.max_seq()
     }
 
+    /// Returns the number of tombstones in the SSTable, as recorded in the
+    /// footer (v4+). Returns `None` for older files.
+    #[must_use]
+    pub fn tombstone_count(&self) -> Option<u64> {
+        self.footer.tombstone_count()
+    }
+
     /// Returns `true` if this SSTable has per-record CRC32 checksums (v3+).
     #[must_use]
     pub fn has_checksums(&self) -> bool {
@@ -255,6 +406,18 @@ impl SSTableReader {
         self.index.is_empty()
     }
 
+    /// Returns the smallest key in the SSTable, or `None` if it is empty.
+    #[must_use]
+    pub fn smallest_key(&self) -> Option<&[u8]> {
+        self.index.keys().next().map(|k| k.as_slice())
+    }
+
+    /// Returns the largest key in the SSTable, or `None` if it is empty.
+    #[must_use]
+    pub fn largest_key(&self) -> Option<&[u8]> {
+        self.index.keys().next_back().map(|k| k.as_slice())
+    }
+
     /// Returns an iterator over all keys in the in-memory index.
     ///
     /// Keys are yielded in ascending sorted order (guaranteed by `BTreeMap`).
@@ -263,4 +426,17 @@ impl SSTableReader {
     pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
         self.index.keys().map(|k| k.as_slice())
     }
+
+    /// Returns an iterator over the keys in `[start, end)`, in ascending
+    /// order. An empty `start` or `end` leaves that side unbounded.
+    pub fn keys_in_range<'a>(
+        &'a self,
+        start: &'a [u8],
+        end: &'a [u8],
+    ) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.index
+            .range::<[u8], _>((Bound::Included(start), Bound::Unbounded))
+            .map(|(k, _)| k.as_slice())
+            .take_while(move |k| end.is_empty() || *k < end)
+    }
 }
@@ -0,0 +1,127 @@
+use crate::*;
+use anyhow::Result;
+use encryption::{EncryptionError, Key, KeyProvider, StaticKeyProvider, KEY_LEN};
+use memtable::{Memtable, ValueEntry};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn keys(id: u32, byte: u8) -> Arc<dyn KeyProvider> {
+    Arc::new(StaticKeyProvider::new(id, Key::new([byte; KEY_LEN])))
+}
+
+fn write_opts(keys: Arc<dyn KeyProvider>) -> SSTableWriteOptions {
+    SSTableWriteOptions {
+        key_provider: Some(keys),
+        ..Default::default()
+    }
+}
+
+fn read_opts(keys: Arc<dyn KeyProvider>) -> SSTableReadOptions {
+    SSTableReadOptions {
+        key_provider: Some(keys),
+    }
+}
+
+fn encryption_error(err: &anyhow::Error) -> Option<&EncryptionError> {
+    err.downcast_ref::<EncryptionError>()
+}
+
+// -------------------- Encrypted files --------------------
+
+#[test]
+fn encrypted_sstable_roundtrips_across_many_blocks() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("enc.sst");
+    let entries: Vec<(Vec<u8>, ValueEntry)> = (0..2_000u64)
+        .map(|i| {
+            let value = (i % 10 != 0).then(|| format!("secret-value-{i}").into_bytes());
+            (
+                format!("key{i:05}").into_bytes(),
+                ValueEntry { seq: i + 1, value },
+            )
+        })
+        .collect();
+    SSTableWriter::write_from_iterator_with_options(
+        &path,
+        entries.len(),
+        entries.clone().into_iter(),
+        &write_opts(keys(3, 1)),
+    )?;
+
+    let raw = std::fs::read(&path)?;
+    assert_eq!(&raw[..8], &SSTABLE_MAGIC_ENCRYPTED);
+    assert!(!raw.windows(6).any(|w| w == b"secret"));
+    assert!(!raw.windows(3).any(|w| w == b"key"));
+
+    let reader = SSTableReader::open_with_options(&path, &read_opts(keys(3, 1)))?;
+    assert_eq!(reader.key_id(), Some(3));
+    assert_eq!(reader.len(), entries.len());
+    assert_eq!(reader.file_size(), raw.len() as u64);
+    assert_eq!(reader.max_seq(), Some(2_000));
+    assert_eq!(reader.tombstone_count(), Some(200));
+    for (key, entry) in &entries {
+        assert_eq!(reader.get(key)?.as_ref(), Some(entry));
+    }
+    assert_eq!(reader.get(b"missing")?, None);
+    Ok(())
+}
+
+#[test]
+fn plain_files_open_with_keys_configured() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("plain.sst");
+    let mut mem = Memtable::new();
+    mem.put(b"a".to_vec(), b"apple".to_vec(), 1);
+    SSTableWriter::write_from_memtable(&path, &mem)?;
+
+    let reader = SSTableReader::open_with_options(&path, &read_opts(keys(1, 1)))?;
+    assert_eq!(reader.key_id(), None);
+    assert_eq!(reader.get(b"a")?.unwrap().value, Some(b"apple".to_vec()));
+    Ok(())
+}
+
+#[test]
+fn encrypted_sstable_needs_the_right_key() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("enc.sst");
+    let mut mem = Memtable::new();
+    mem.put(b"a".to_vec(), b"apple".to_vec(), 1);
+    SSTableWriter::write_from_memtable_with_options(&path, &mem, &write_opts(keys(1, 1)))?;
+
+    let err = SSTableReader::open(&path).err().unwrap();
+    assert!(matches!(
+        encryption_error(&err),
+        Some(EncryptionError::NoKeyProvider(1))
+    ));
+    let err = SSTableReader::open_with_options(&path, &read_opts(keys(1, 2)))
+        .err()
+        .unwrap();
+    assert!(matches!(
+        encryption_error(&err),
+        Some(EncryptionError::WrongKey(1))
+    ));
+
+    // a retired key still opens old files
+    let rotated: Arc<dyn KeyProvider> = Arc::new(
+        StaticKeyProvider::new(2, Key::new([2; KEY_LEN])).with_key(1, Key::new([1; KEY_LEN])),
+    );
+    let reader = SSTableReader::open_with_options(&path, &read_opts(rotated))?;
+    assert_eq!(reader.get(b"a")?.unwrap().value, Some(b"apple".to_vec()));
+    Ok(())
+}
+
+#[test]
+fn tampered_encrypted_sstable_fails_to_open() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("enc.sst");
+    let mut mem = Memtable::new();
+    mem.put(b"a".to_vec(), b"apple".to_vec(), 1);
+    SSTableWriter::write_from_memtable_with_options(&path, &mem, &write_opts(keys(1, 1)))?;
+
+    let mut raw = std::fs::read(&path)?;
+    let at = ENCRYPTED_HEADER_BYTES as usize + encryption::NONCE_LEN;
+    raw[at] ^= 1;
+    std::fs::write(&path, &raw)?;
+    assert!(SSTableReader::open_with_options(&path, &read_opts(keys(1, 1))).is_err());
+    Ok(())
+}
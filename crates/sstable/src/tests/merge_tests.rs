@@ -260,3 +260,43 @@ fn merge_tombstone_wins_over_older_value() -> Result<()> {
 
     Ok(())
 }
+
+// -------------------- Key ranges --------------------
+
+#[test]
+fn merge_in_range_yields_only_keys_in_range() -> Result<()> {
+    let dir = tempdir()?;
+    let r1 = write_and_open(
+        dir.path(),
+        "a.sst",
+        &[
+            (b"a", Some(b"1"), 1),
+            (b"c", Some(b"3"), 3),
+            (b"e", Some(b"5"), 5),
+        ],
+    )?;
+    let r2 = write_and_open(
+        dir.path(),
+        "b.sst",
+        &[
+            (b"b", Some(b"2"), 2),
+            (b"c", Some(b"new"), 6),
+            (b"d", None, 4),
+        ],
+    )?;
+    let keys = |start: &[u8], end: &[u8]| -> Result<Vec<Vec<u8>>> {
+        let mut iter = MergeIterator::from_refs_in_range(vec![&r1, &r2], start, end);
+        Ok(iter.collect_all()?.into_iter().map(|(k, _)| k).collect())
+    };
+
+    assert_eq!(keys(b"b", b"d")?, vec![b"b".to_vec(), b"c".to_vec()]);
+    assert_eq!(keys(b"", b"b")?, vec![b"a".to_vec()]);
+    assert_eq!(keys(b"d", b"")?, vec![b"d".to_vec(), b"e".to_vec()]);
+    assert_eq!(keys(b"", b"")?.len(), 5);
+    assert!(keys(b"x", b"")?.is_empty());
+
+    let mut iter = MergeIterator::from_refs_in_range(vec![&r1, &r2], b"c", b"d");
+    let (_, entry) = iter.next_entry()?.unwrap();
+    assert_eq!(entry.value, Some(b"new".to_vec()), "newest version wins");
+    Ok(())
+}
@@ -1,3 +1,5 @@
+mod encryption_tests;
 mod merge_tests;
+mod rate_limiter_tests;
 mod reader_tests;
 mod writer_tests;
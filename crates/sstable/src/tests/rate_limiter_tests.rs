@@ -0,0 +1,67 @@
+use crate::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// --------------------- RateLimiter ---------------------
+
+#[test]
+fn unlimited_limiter_never_blocks() {
+    let limiter = RateLimiter::new(0);
+    let start = Instant::now();
+    for _ in 0..1000 {
+        limiter.request(1024 * 1024);
+    }
+    assert!(start.elapsed() < Duration::from_millis(100));
+    assert_eq!(limiter.total_bytes(), 1000 * 1024 * 1024);
+    assert_eq!(limiter.total_wait_micros(), 0);
+}
+
+#[test]
+fn limiter_enforces_budget() {
+    // 100 KiB/s with a 10 KiB burst: 30 KiB needs at least ~200 ms.
+    let limiter = RateLimiter::new(100 * 1024);
+    let start = Instant::now();
+    for _ in 0..30 {
+        limiter.request(1024);
+    }
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(180), "took {:?}", elapsed);
+    assert!(limiter.total_wait_micros() > 0);
+}
+
+#[test]
+fn budget_can_change_at_runtime() {
+    let limiter = RateLimiter::new(1024);
+    assert_eq!(limiter.bytes_per_sec(), 1024);
+
+    limiter.set_bytes_per_sec(0);
+    let start = Instant::now();
+    limiter.request(10 * 1024 * 1024);
+    assert!(start.elapsed() < Duration::from_millis(50));
+
+    limiter.set_bytes_per_sec(1024 * 1024);
+    assert_eq!(limiter.bytes_per_sec(), 1024 * 1024);
+}
+
+#[test]
+fn limiter_is_shared_across_threads() {
+    // Four threads writing 10 KiB each at 200 KiB/s share one budget.
+    let limiter = Arc::new(RateLimiter::new(200 * 1024));
+    let start = Instant::now();
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let limiter = limiter.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    limiter.request(1024);
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+    // 40 KiB minus the 20 KiB burst at 200 KiB/s is ~100 ms.
+    assert!(start.elapsed() >= Duration::from_millis(80));
+    assert_eq!(limiter.total_bytes(), 40 * 1024);
+}
@@ -246,3 +246,96 @@ fn multiple_gets_same_reader() -> Result<()> {
 
     Ok(())
 }
+
+// -------------------- Bloom stats & file size --------------------
+
+#[test]
+fn bloom_stats_count_hits_and_misses() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("stats.sst");
+    SSTableWriter::write_from_memtable(&path, &make_sample_memtable())?;
+    let reader = SSTableReader::open(&path)?;
+    assert_eq!(reader.bloom_stats(), BloomStats::default());
+
+    reader.get(b"a")?;
+    reader.get(b"b")?;
+    for i in 0..100u32 {
+        reader.get(format!("missing-{}", i).as_bytes())?;
+    }
+
+    let stats = reader.bloom_stats();
+    assert_eq!(stats.hits + stats.misses, 102);
+    assert!(stats.hits >= 2);
+    assert_eq!(stats.false_positives, stats.hits - 2);
+    Ok(())
+}
+
+#[test]
+fn file_size_matches_disk() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("size.sst");
+    SSTableWriter::write_from_memtable(&path, &make_sample_memtable())?;
+    let reader = SSTableReader::open(&path)?;
+    assert_eq!(reader.file_size(), std::fs::metadata(&path)?.len());
+    assert_eq!(reader.path(), path.as_path());
+    Ok(())
+}
+
+// -------------------- Key range --------------------
+
+#[test]
+fn smallest_and_largest_key() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("range.sst");
+    SSTableWriter::write_from_memtable(&path, &make_sample_memtable())?;
+    let reader = SSTableReader::open(&path)?;
+    assert_eq!(reader.smallest_key(), Some(&b"a"[..]));
+    // The tombstone for "d" counts towards the range.
+    assert_eq!(reader.largest_key(), Some(&b"d"[..]));
+    Ok(())
+}
+
+// -------------------- Tombstone count --------------------
+
+#[test]
+fn tombstone_count_from_footer() -> Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("tomb.sst");
+    SSTableWriter::write_from_memtable(&path, &make_sample_memtable())?;
+    let reader = SSTableReader::open(&path)?;
+    assert_eq!(reader.tombstone_count(), Some(1));
+    assert!(reader.has_checksums());
+    Ok(())
+}
+
+#[test]
+fn v3_sstable_has_no_tombstone_count() -> Result<()> {
+    use crate::format::{read_footer_versioned, write_footer_v3};
+    use std::io::Write;
+
+    let dir = tempdir()?;
+    let path = dir.path().join("v3.sst");
+    SSTableWriter::write_from_memtable(&path, &make_sample_memtable())?;
+
+    // Swap the v4 footer for the equivalent v3 one.
+    let footer = read_footer_versioned(&mut std::fs::File::open(&path)?)?;
+    let len = std::fs::metadata(&path)?.len();
+    let file = std::fs::OpenOptions::new().write(true).open(&path)?;
+    file.set_len(len - FOOTER_BYTES_V4)?;
+    let mut file = std::io::BufWriter::new(file);
+    std::io::Seek::seek(&mut file, std::io::SeekFrom::End(0))?;
+    write_footer_v3(
+        &mut file,
+        footer.max_seq().unwrap(),
+        footer.bloom_offset().unwrap(),
+        footer.index_offset(),
+    )?;
+    file.flush()?;
+    drop(file);
+
+    let reader = SSTableReader::open(&path)?;
+    assert_eq!(reader.tombstone_count(), None);
+    assert_eq!(reader.max_seq(), Some(4));
+    assert_eq!(reader.get(b"d")?.unwrap().value, None);
+    Ok(())
+}
@@ -1,9 +1,10 @@
-use crate::format::{read_footer_versioned, Footer, SSTABLE_MAGIC_V3};
+use crate::format::{read_footer_versioned, Footer, SSTABLE_MAGIC_V4};
 use crate::*;
 use anyhow::Result;
 use memtable::Memtable;
 use std::io::Read;
 use std::io::Seek;
+use std::sync::Arc;
 use tempfile::tempdir;
 
 fn make_sample_memtable() -> Memtable {
@@ -35,7 +36,7 @@ fn write_empty_memtable_is_rejected() {
 }
 
 #[test]
-fn write_and_inspect_sstable_v3_footer() -> Result<()> {
+fn write_and_inspect_sstable_v4_footer() -> Result<()> {
     let dir = tempdir()?;
     let path = dir.path().join("test.sst");
 
@@ -46,20 +47,23 @@ fn write_and_inspect_sstable_v3_footer() -> Result<()> {
     let meta = std::fs::metadata(&path)?;
     assert!(meta.len() > 0);
 
-    // Read versioned footer and verify it's v3
+    // Read versioned footer and verify it's v4
     let mut f = std::fs::File::open(&path)?;
     let filesize = f.metadata()?.len();
-    assert!(filesize >= 28, "file too small to contain v3 footer");
+    assert!(filesize >= 36, "file too small to contain v4 footer");
 
     let footer = read_footer_versioned(&mut f)?;
-    assert_eq!(footer.magic(), SSTABLE_MAGIC_V3);
+    assert_eq!(footer.magic(), SSTABLE_MAGIC_V4);
 
     match &footer {
-        Footer::V3 {
+        Footer::V4 {
+            tombstone_count,
             max_seq,
             bloom_offset,
             index_offset,
         } => {
+            // one tombstone ("d") in our sample memtable
+            assert_eq!(*tombstone_count, 1);
             // max_seq should be 4 (highest seq in our sample memtable)
             assert_eq!(*max_seq, 4);
             // bloom_offset must be before index_offset
@@ -67,7 +71,7 @@ fn write_and_inspect_sstable_v3_footer() -> Result<()> {
             // index_offset must point inside file
             assert!(*index_offset < filesize);
         }
-        _ => panic!("expected v3 Footer"),
+        _ => panic!("expected v4 Footer"),
     }
 
     // Read a few first bytes to ensure data was written (smoke)
@@ -78,3 +82,33 @@ fn write_and_inspect_sstable_v3_footer() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn write_with_rate_limiter_charges_file_bytes() -> Result<()> {
+    let dir = tempdir()?;
+    let limiter = Arc::new(RateLimiter::new(0));
+    let opts = SSTableWriteOptions {
+        rate_limiter: Some(limiter.clone()),
+        ..Default::default()
+    };
+
+    let mem = make_sample_memtable();
+    let path = dir.path().join("limited.sst");
+    SSTableWriter::write_from_memtable_with_options(&path, &mem, &opts)?;
+    let first = std::fs::metadata(&path)?.len();
+    assert_eq!(limiter.total_bytes(), first);
+
+    let path2 = dir.path().join("limited2.sst");
+    let entries = mem.iter().map(|(k, v)| (k.to_vec(), v.clone()));
+    SSTableWriter::write_from_iterator_with_options(&path2, mem.len(), entries, &opts)?;
+    assert_eq!(
+        limiter.total_bytes(),
+        first + std::fs::metadata(&path2)?.len()
+    );
+
+    // The output is identical to an unthrottled write.
+    let reader = SSTableReader::open(&path2)?;
+    assert_eq!(reader.get(b"a")?.unwrap().value, Some(b"apple".to_vec()));
+    assert_eq!(reader.get(b"d")?.unwrap().value, None);
+    Ok(())
+}
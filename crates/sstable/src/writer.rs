@@ -2,20 +2,121 @@ use anyhow::Result;
 use bloom::BloomFilter;
 use byteorder::{LittleEndian, WriteBytesExt};
 use crc32fast::Hasher as Crc32;
+use encryption::{BlockWriter, FileCipher, KeyProvider, DEFAULT_BLOCK_SIZE};
 use memtable::{Memtable, ValueEntry};
-use std::fs::{rename, OpenOptions};
-use std::io::{BufWriter, Seek, Write};
+use std::fs::{rename, File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::sync::Arc;
 
-use crate::format::write_footer_v3;
+use crate::format::{write_footer_v4, SSTABLE_MAGIC_ENCRYPTED};
+use crate::RateLimiter;
 
 /// Default bloom filter false positive rate (1%).
 const BLOOM_FPR: f64 = 0.01;
 
+/// Options controlling how an SSTable is written.
+#[derive(Debug, Clone, Default)]
+pub struct SSTableWriteOptions {
+    /// Throttles the bytes written to the file. Share one limiter between
+    /// flushes and compactions to cap their combined write throughput.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Encrypts the file with the provider's current key.
+    pub key_provider: Option<Arc<dyn KeyProvider>>,
+}
+
+/// A file whose writes are charged to an optional [`RateLimiter`].
+///
+/// Sits underneath the `BufWriter`, so the limiter is asked for tokens once
+/// per buffer flush rather than once per record.
+struct ThrottledFile {
+    file: File,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl Write for ThrottledFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.file.write(buf)?;
+        if let Some(limiter) = &self.limiter {
+            limiter.request(n as u64);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for ThrottledFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+/// Where the SSTable bytes go: straight to the file, or through the block
+/// cipher. Positions are plaintext positions either way.
+enum Sink {
+    Plain(ThrottledFile),
+    Encrypted(BlockWriter<ThrottledFile>),
+}
+
+impl Sink {
+    fn new(mut file: ThrottledFile, keys: Option<&dyn KeyProvider>) -> std::io::Result<Self> {
+        let Some(keys) = keys else {
+            return Ok(Sink::Plain(file));
+        };
+        let (cipher, header) = FileCipher::create(keys)?;
+        file.write_all(&SSTABLE_MAGIC_ENCRYPTED)?;
+        file.write_all(&header)?;
+        file.write_u32::<LittleEndian>(DEFAULT_BLOCK_SIZE as u32)?;
+        Ok(Sink::Encrypted(BlockWriter::new(
+            file,
+            cipher,
+            DEFAULT_BLOCK_SIZE,
+        )))
+    }
+
+    /// Writes any pending encrypted block and returns the file.
+    fn finish(self) -> std::io::Result<File> {
+        match self {
+            Sink::Plain(inner) => Ok(inner.file),
+            Sink::Encrypted(writer) => Ok(writer.finish()?.file),
+        }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Sink::Plain(inner) => inner.write(buf),
+            Sink::Encrypted(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Sink::Plain(inner) => inner.flush(),
+            Sink::Encrypted(writer) => writer.flush(),
+        }
+    }
+}
+
+impl Seek for Sink {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Sink::Plain(inner) => inner.seek(pos),
+            Sink::Encrypted(writer) => writer.seek(pos),
+        }
+    }
+}
+
 /// Writes a [`Memtable`] to disk as an immutable SSTable file.
 ///
-/// The writer is stateless — all work happens inside the single static method
-/// [`write_from_memtable`](SSTableWriter::write_from_memtable). The write is
+/// The writer is stateless — all work happens inside the static methods
+/// [`write_from_memtable`](SSTableWriter::write_from_memtable) and
+/// [`write_from_iterator`](SSTableWriter::write_from_iterator) (plus their
+/// `_with_options` variants taking [`SSTableWriteOptions`]). The write is
 /// crash-safe: data is first written to a temporary file, fsynced, and then
 /// atomically renamed to the final path.
 pub struct SSTableWriter {}
@@ -23,13 +124,13 @@ pub struct SSTableWriter {}
 impl SSTableWriter {
     /// Flushes `mem` to a new SSTable file at `path`.
     ///
-    /// # File Layout (v3)
+    /// # File Layout (v4)
     ///
     /// ```text
     /// [DATA]  repeated: crc32(u32) | key_len(u32) | key | seq(u64) | present(u8) | [val_len(u32) | val]
     /// [BLOOM] serialized BloomFilter (num_bits + num_hashes + bits)
     /// [INDEX] repeated: key_len(u32) | key | data_offset(u64)
-    /// [FOOTER] max_seq(u64) | bloom_offset(u64) | index_offset(u64) | magic(u32 = "SST3")
+    /// [FOOTER] tombstone_count(u64) | max_seq(u64) | bloom_offset(u64) | index_offset(u64) | magic(u32 = "SST4")
     /// ```
     ///
     /// The CRC32 covers everything after itself in the record (key_len through
@@ -46,11 +147,25 @@ impl SSTableWriter {
     /// Returns an error if the memtable is empty (writing an empty SSTable is
     /// not useful and likely indicates a logic bug) or on any I/O failure.
     pub fn write_from_memtable(path: &Path, mem: &Memtable) -> Result<()> {
+        Self::write_from_memtable_with_options(path, mem, &SSTableWriteOptions::default())
+    }
+
+    /// Like [`write_from_memtable`](SSTableWriter::write_from_memtable), with
+    /// explicit write options (e.g. a rate limiter).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the memtable is empty or on any I/O failure.
+    pub fn write_from_memtable_with_options(
+        path: &Path,
+        mem: &Memtable,
+        opts: &SSTableWriteOptions,
+    ) -> Result<()> {
         if mem.is_empty() {
             anyhow::bail!("refusing to write an empty SSTable (empty memtable)");
         }
         let iter = mem.iter().map(|(k, v)| (k.to_vec(), v.clone()));
-        Self::write_internal(path, mem.len(), iter)
+        Self::write_internal(path, mem.len(), iter, opts)
     }
 
     /// Writes an SSTable from an iterator of `(key, ValueEntry)` pairs.
@@ -76,7 +191,30 @@ impl SSTableWriter {
     where
         I: Iterator<Item = (Vec<u8>, ValueEntry)>,
     {
-        Self::write_internal(path, expected_count.max(1), iter)
+        Self::write_from_iterator_with_options(
+            path,
+            expected_count,
+            iter,
+            &SSTableWriteOptions::default(),
+        )
+    }
+
+    /// Like [`write_from_iterator`](SSTableWriter::write_from_iterator), with
+    /// explicit write options (e.g. a rate limiter).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the iterator yields zero entries or on I/O failure.
+    pub fn write_from_iterator_with_options<I>(
+        path: &Path,
+        expected_count: usize,
+        iter: I,
+        opts: &SSTableWriteOptions,
+    ) -> Result<()>
+    where
+        I: Iterator<Item = (Vec<u8>, ValueEntry)>,
+    {
+        Self::write_internal(path, expected_count.max(1), iter, opts)
     }
 
     /// Internal write implementation shared by both `write_from_memtable` and
@@ -84,7 +222,12 @@ impl SSTableWriter {
     ///
     /// Accepts any iterator of `(Vec<u8>, ValueEntry)` pairs. The iterator
     /// must yield entries in ascending key order.
-    fn write_internal<I>(path: &Path, expected_count: usize, iter: I) -> Result<()>
+    fn write_internal<I>(
+        path: &Path,
+        expected_count: usize,
+        iter: I,
+        opts: &SSTableWriteOptions,
+    ) -> Result<()>
     where
         I: Iterator<Item = (Vec<u8>, ValueEntry)>,
     {
@@ -95,7 +238,14 @@ impl SSTableWriter {
             .write(true)
             .truncate(true)
             .open(&tmp_path)?;
-        let mut file = BufWriter::new(raw_file);
+        let sink = Sink::new(
+            ThrottledFile {
+                file: raw_file,
+                limiter: opts.rate_limiter.clone(),
+            },
+            opts.key_provider.as_deref(),
+        )?;
+        let mut file = BufWriter::new(sink);
 
         // Build bloom filter from all keys
         let mut bloom = BloomFilter::new(expected_count.max(1), BLOOM_FPR);
@@ -103,8 +253,9 @@ impl SSTableWriter {
         // Keep an in-memory index: (key, offset)
         let mut index: Vec<(Vec<u8>, u64)> = Vec::new();
 
-        // Track max sequence number for the v3 footer.
+        // Track max sequence number and tombstone count for the footer.
         let mut max_seq: u64 = 0;
+        let mut tombstone_count: u64 = 0;
 
         // Reusable buffer for computing per-record CRC32 checksums.
         let mut record_buf: Vec<u8> = Vec::with_capacity(256);
@@ -126,6 +277,7 @@ impl SSTableWriter {
                 }
                 None => {
                     record_buf.write_u8(0)?;
+                    tombstone_count += 1;
                 }
             }
 
@@ -166,12 +318,22 @@ impl SSTableWriter {
             file.write_u64::<LittleEndian>(*data_offset)?;
         }
 
-        // Write v3 FOOTER (max_seq + bloom_offset + index_offset + magic)
-        write_footer_v3(&mut file, max_seq, bloom_offset, index_offset)?;
+        // Write v4 FOOTER (tombstone_count + max_seq + bloom_offset +
+        // index_offset + magic)
+        write_footer_v4(
+            &mut file,
+            tombstone_count,
+            max_seq,
+            bloom_offset,
+            index_offset,
+        )?;
 
         // Flush BufWriter, then sync the underlying file
         file.flush()?;
-        file.into_inner()?.sync_all()?;
+        file.into_inner()
+            .map_err(|e| e.into_error())?
+            .finish()?
+            .sync_all()?;
 
         // Atomically move into place
         rename(&tmp_path, path)?;
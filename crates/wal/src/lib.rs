@@ -7,16 +7,75 @@
 //! restart the WAL is replayed to reconstruct the memtable, guaranteeing that
 //! no acknowledged write is lost.
 //!
-//! ## Binary Record Format
+//! ## File Format
+//!
+//! A WAL file starts with an 8-byte header, followed by frames:
 //!
 //! ```text
+//! [magic: "RWAL"][version: u32 LE]
+//! [record_len: u32 LE][crc32: u32 LE][body ...]
 //! [record_len: u32 LE][crc32: u32 LE][body ...]
+//! ...
+//! ```
+//!
+//! `record_len` includes the 4-byte CRC but **not** itself. The CRC covers
+//! the body as stored.
+//!
+//! Since version 3 the top four bits of `record_len` are frame flags and
+//! the length is in the low 28 bits. [`FRAME_FLAG_LZ4`] marks a body stored
+//! compressed as `[raw_len: u32][LZ4 block]` (see [`WalCompression`]); it is
+//! decompressed after the CRC check.
+//!
+//! ### Encryption
+//!
+//! A log written with a [`KeyProvider`] (see
+//! [`WalWriter::create_encrypted`]) starts with a different magic and the
+//! encryption header naming the key:
+//!
+//! ```text
+//! [magic: "RWAE"][version: u32 LE][encryption header: 48 bytes]
 //! ```
 //!
-//! Body (Put): `[seq: u64][op=0: u8][key_len: u32][key][val_len: u32][value]`
-//! Body (Del): `[seq: u64][op=1: u8][key_len: u32][key]`
+//! Every frame of it has [`FRAME_FLAG_ENCRYPTED`] set and stores its body
+//! (compressed first, if at all) sealed with ChaCha20-Poly1305. The frame
+//! flags and the frame's byte offset in the file are authenticated too, so
+//! frames cannot be reordered, duplicated or dropped (except from the end,
+//! which looks like a torn tail) without failing. The CRC covers the sealed
+//! bytes, so
+//! damaged frames are handled by the recovery modes without the key; a
+//! frame that passes its CRC but fails authentication is corrupt. Reading
+//! an encrypted log needs [`WalReader::with_key_provider`]; a missing or
+//! wrong key fails with [`WalError::Encryption`].
+//!
+//! Body: `[seq: u64][type: u8][payload]`, where `type` is a [`RecordType`]
+//! code and byte strings in the payload are `[len: u32][bytes]`:
 //!
-//! `record_len` includes the 4-byte CRC but **not** itself.
+//! | Type | Code | Payload |
+//! |------|------|---------|
+//! | Put | 0 | `[key][value]` |
+//! | Del | 1 | `[key]` |
+//! | Batch | 2 | `[count: u32]` then `count` × `[len: u32][body]` |
+//! | RangeDel | 3 | `[start][end]` |
+//! | Merge | 4 | `[key][operand]` |
+//! | ColumnFamily | 5 | `[cf_id: u32][body]` |
+//! | PutWithTtl | 6 | `[key][value][expires_at_ms: u64]` |
+//!
+//! Codes with the high bit set ([`RecordType::IGNORABLE`]) mark records a
+//! reader may skip if it does not know them; any other unknown code fails
+//! with [`WalError::UnsupportedRecordType`].
+//!
+//! ### Version 1
+//!
+//! Logs written before the header was introduced have no header and only
+//! Put and Del records, with the same frame and body layout. They are
+//! recognized by their first four bytes, which can never spell the magic
+//! (as a record length, `"RWAL"` exceeds the maximum record size), and stay
+//! readable. [`WalWriter`] keeps appending to them in version 1.
+//!
+//! ### Version 2
+//!
+//! Frames have no flags, so records are never compressed. [`WalWriter`]
+//! keeps appending to version 2 logs uncompressed.
 //!
 //! ## Example
 //!
@@ -33,21 +92,68 @@
 //!
 //! let mut r = WalReader::open("wal.log").unwrap();
 //! r.replay(|rec| println!("{:?}", rec)).unwrap();
+//!
+//! // or pull records one at a time, with their positions
+//! let r = WalReader::open("wal.log").unwrap();
+//! for item in r {
+//!     let (pos, rec) = item.unwrap();
+//!     println!("seq {} at byte {}: {:?}", pos.seq, pos.offset, rec);
+//! }
 //! ```
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use crc32fast::Hasher as Crc32;
+use encryption::{EncryptionError, FileCipher, KeyProvider};
+use std::borrow::Cow;
 use std::fs::{File, OpenOptions};
-use std::io::{self, BufReader, Read, Write};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use thiserror::Error;
 
-/// A single WAL record representing either a key-value insertion or a deletion.
+/// Magic bytes at the start of every WAL file with a header (version 2 on).
+pub const WAL_MAGIC: [u8; 4] = *b"RWAL";
+
+/// File format version written by [`WalWriter`] to new files.
+pub const WAL_FORMAT_VERSION: u32 = 3;
+
+/// Frame flag (in the top bits of `record_len`) of a compressed body.
+pub const FRAME_FLAG_LZ4: u32 = 1 << 31;
+
+/// Frame flag of an encrypted body; set on every frame of an encrypted log.
+pub const FRAME_FLAG_ENCRYPTED: u32 = 1 << 30;
+
+/// Bits of `record_len` reserved for frame flags, from version 3 on.
+const FRAME_FLAGS: u32 = 0xF000_0000;
+
+/// Largest frame a reader accepts, CRC included.
+const MAX_RECORD_SIZE: u32 = 64 * 1024 * 1024; // 64MB safety cap
+
+/// Bodies shorter than this are never compressed.
+const MIN_COMPRESS_LEN: usize = 64;
+
+/// Size of the file header: magic plus version.
+pub const WAL_HEADER_LEN: u64 = 8;
+
+/// Magic bytes at the start of an encrypted WAL file.
+pub const WAL_MAGIC_ENCRYPTED: [u8; 4] = *b"RWAE";
+
+/// Size of the header of an encrypted WAL file: magic, version and the
+/// encryption header.
+pub const WAL_ENCRYPTED_HEADER_LEN: u64 = WAL_HEADER_LEN + encryption::HEADER_LEN as u64;
+
+/// A single WAL record.
 ///
 /// Each record carries a monotonically increasing **sequence number** that the
-/// engine uses for ordering, conflict resolution, and (in later phases) snapshot reads.
+/// engine uses for ordering, conflict resolution, and snapshot reads. New
+/// record types may be added, so matches outside this crate need a wildcard
+/// arm.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum WalRecord {
     /// A key-value insertion.
     Put {
@@ -65,6 +171,136 @@ pub enum WalRecord {
         /// The key to delete.
         key: Vec<u8>,
     },
+    /// Records written as one frame, so they are recovered all or nothing.
+    Batch {
+        /// The records, in the order they are applied.
+        records: Vec<WalRecord>,
+    },
+    /// Deletion of every key in `start..end`.
+    RangeDel {
+        /// Sequence number assigned by the engine.
+        seq: u64,
+        /// First key deleted.
+        start: Vec<u8>,
+        /// First key after the range (exclusive).
+        end: Vec<u8>,
+    },
+    /// A merge operand to combine with the key's current value.
+    Merge {
+        /// Sequence number assigned by the engine.
+        seq: u64,
+        /// The key to merge into.
+        key: Vec<u8>,
+        /// The operand.
+        operand: Vec<u8>,
+    },
+    /// A record that applies to column family `cf_id`.
+    ColumnFamily {
+        /// Column family id.
+        cf_id: u32,
+        /// The record.
+        record: Box<WalRecord>,
+    },
+    /// A key-value insertion that expires.
+    PutWithTtl {
+        /// Sequence number assigned by the engine.
+        seq: u64,
+        /// The lookup key.
+        key: Vec<u8>,
+        /// The payload value.
+        value: Vec<u8>,
+        /// Expiry time in milliseconds since the Unix epoch.
+        expires_at_ms: u64,
+    },
+}
+
+impl WalRecord {
+    /// Returns the record's type.
+    #[must_use]
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            WalRecord::Put { .. } => RecordType::Put,
+            WalRecord::Del { .. } => RecordType::Del,
+            WalRecord::Batch { .. } => RecordType::Batch,
+            WalRecord::RangeDel { .. } => RecordType::RangeDel,
+            WalRecord::Merge { .. } => RecordType::Merge,
+            WalRecord::ColumnFamily { .. } => RecordType::ColumnFamily,
+            WalRecord::PutWithTtl { .. } => RecordType::PutWithTtl,
+        }
+    }
+
+    /// Returns the record's sequence number; for a batch, the highest one
+    /// (`0` if it is empty).
+    #[must_use]
+    pub fn seq(&self) -> u64 {
+        match self {
+            WalRecord::Put { seq, .. }
+            | WalRecord::Del { seq, .. }
+            | WalRecord::RangeDel { seq, .. }
+            | WalRecord::Merge { seq, .. }
+            | WalRecord::PutWithTtl { seq, .. } => *seq,
+            WalRecord::Batch { records } => records.iter().map(WalRecord::seq).max().unwrap_or(0),
+            WalRecord::ColumnFamily { record, .. } => record.seq(),
+        }
+    }
+}
+
+/// Registry of record type codes stored in the body of each frame.
+///
+/// Codes are never reused. A new type that older readers may safely skip
+/// gets a code with [`IGNORABLE`](RecordType::IGNORABLE) set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum RecordType {
+    /// [`WalRecord::Put`]. Also valid in version 1 logs.
+    Put = 0,
+    /// [`WalRecord::Del`]. Also valid in version 1 logs.
+    Del = 1,
+    /// [`WalRecord::Batch`].
+    Batch = 2,
+    /// [`WalRecord::RangeDel`].
+    RangeDel = 3,
+    /// [`WalRecord::Merge`].
+    Merge = 4,
+    /// [`WalRecord::ColumnFamily`].
+    ColumnFamily = 5,
+    /// [`WalRecord::PutWithTtl`].
+    PutWithTtl = 6,
+}
+
+impl RecordType {
+    /// Flag bit of codes that readers which do not know them may skip.
+    pub const IGNORABLE: u8 = 0x80;
+
+    /// Returns the type with code `code`, or `None` if it is unknown.
+    #[must_use]
+    pub fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            0 => RecordType::Put,
+            1 => RecordType::Del,
+            2 => RecordType::Batch,
+            3 => RecordType::RangeDel,
+            4 => RecordType::Merge,
+            5 => RecordType::ColumnFamily,
+            6 => RecordType::PutWithTtl,
+            _ => return None,
+        })
+    }
+
+    /// Returns the code stored on disk.
+    #[must_use]
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the first file format version that supports the type.
+    #[must_use]
+    pub fn min_version(self) -> u32 {
+        match self {
+            RecordType::Put | RecordType::Del => 1,
+            _ => 2,
+        }
+    }
 }
 
 /// Errors that can occur during WAL operations.
@@ -74,49 +310,408 @@ pub enum WalError {
     #[error("io error: {0}")]
     Io(#[from] io::Error),
 
-    /// A record failed CRC validation or contained an unknown op code.
+    /// A record failed CRC validation or could not be decoded.
     #[error("corrupt record")]
     Corrupt,
+
+    /// The file header names a format version this reader does not know.
+    #[error("unsupported WAL format version {0}")]
+    UnsupportedVersion(u32),
+
+    /// A record type this reader does not know and may not skip, or one
+    /// the file's format version cannot store.
+    #[error("unsupported WAL record type {0}")]
+    UnsupportedRecordType(u8),
+
+    /// The log is encrypted and cannot be read or appended to with the
+    /// configured keys.
+    #[error("{0}")]
+    Encryption(#[from] EncryptionError),
+}
+
+/// How [`WalReader::replay_with_mode`] handles damaged records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalRecoveryMode {
+    /// Fail on any defect, including a record torn by a crash.
+    AbsoluteConsistency,
+    /// Drop a damaged last record (torn or failing its CRC); fail on a
+    /// defect anywhere else.
+    #[default]
+    TolerateCorruptedTail,
+    /// Stop at the first defect and keep every record before it.
+    PointInTime,
+    /// Skip damaged records and keep going. A record whose length field is
+    /// damaged cannot be stepped over, so the rest of the log is skipped.
+    SkipAnyCorrupted,
+}
+
+/// What a replay kept and what it discarded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WalRecoveryReport {
+    /// Records passed to the callback.
+    pub records_replayed: u64,
+    /// Damaged records that were dropped or skipped.
+    pub records_discarded: u64,
+    /// Bytes not replayed: damaged records and, when replay stopped at a
+    /// defect, everything after it.
+    pub bytes_discarded: u64,
+    /// Byte offset of the first damaged record, if any.
+    pub first_defect_offset: Option<u64>,
+    /// `true` if replay stopped at a defect ([`WalRecoveryMode::PointInTime`])
+    /// and valid records after it may have been discarded.
+    pub stopped_early: bool,
+}
+
+impl WalRecoveryReport {
+    /// Returns `true` if nothing was discarded.
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.records_discarded == 0
+    }
+}
+
+/// When [`WalWriter`] calls `fsync` to make appended records durable.
+///
+/// Records are written to the file (the OS page cache) on every append
+/// either way; the policy only decides when they are forced to disk, and
+/// so how many acknowledged records a power failure can lose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalSyncPolicy {
+    /// `fsync` after every append. Nothing acknowledged is lost.
+    #[default]
+    EveryWrite,
+    /// `fsync` once at least this many bytes were appended since the last
+    /// sync. At most that many bytes of records can be lost.
+    EveryNBytes(u64),
+    /// `fsync` from a background thread at this interval, if anything was
+    /// appended. At most one interval of records can be lost.
+    EveryInterval(Duration),
+    /// Never `fsync`, except on [`WalWriter::sync_to_disk`].
+    Never,
+}
+
+/// Per-record compression applied by [`WalWriter::append`].
+///
+/// Only bodies of at least 64 bytes are compressed, and only when that
+/// makes them smaller; each frame says whether it is compressed, so a log
+/// may mix both and the setting can change at any time. Readers decompress
+/// transparently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalCompression {
+    /// Store every body as is.
+    #[default]
+    None,
+    /// LZ4 block compression (pure Rust, no native library).
+    Lz4,
 }
 
 /// Append-only WAL writer.
 ///
 /// Records are serialized into an in-memory buffer, CRC-checksummed, and then
-/// written to the underlying file in a single `write_all` call. When `sync` is
-/// `true`, every append is followed by `sync_all()` (fsync) to guarantee the
-/// record is durable on disk before the call returns.
+/// written to the underlying file in a single `write_all` call. The writer's
+/// [`WalSyncPolicy`] decides when appended records are forced to disk with
+/// `sync_all()` (fsync); [`durable_seq`](WalWriter::durable_seq) tells which
+/// ones are.
 pub struct WalWriter {
-    file: File,
-    sync: bool,
+    /// File and durability state, shared with the background syncer.
+    shared: Arc<SyncState>,
+    policy: WalSyncPolicy,
+    /// Format version of the file being appended to.
+    version: u32,
     /// Reusable scratch buffer to avoid allocation on every append.
     buf: Vec<u8>,
+    compression: WalCompression,
+    /// Reusable output buffer of the compressor.
+    compressed: Vec<u8>,
+    /// Set if the file is encrypted.
+    cipher: Option<FileCipher>,
+    /// Length of the file: the offset of the next frame, which encrypted
+    /// frames are bound to.
+    file_len: u64,
+    /// Total bytes appended by this writer, file header included.
+    bytes_written: u64,
+    /// Bytes appended since the last sync, for [`WalSyncPolicy::EveryNBytes`].
+    unsynced_bytes: u64,
+    /// Background thread for [`WalSyncPolicy::EveryInterval`].
+    syncer: Option<Syncer>,
+}
+
+/// State shared by a [`WalWriter`] and its background syncer.
+struct SyncState {
+    file: File,
+    /// Highest sequence number appended.
+    written_seq: AtomicU64,
+    /// Highest sequence number known to be on disk.
+    durable_seq: AtomicU64,
+    /// `true` if records were appended since the last sync.
+    dirty: AtomicBool,
+    /// Number of `sync_all` calls issued.
+    sync_count: AtomicU64,
+    /// Failure of a background sync, returned by the next append.
+    error: Mutex<Option<io::Error>>,
+}
+
+impl SyncState {
+    /// Forces everything appended so far to disk.
+    fn sync(&self) -> io::Result<()> {
+        // Clear the flag first: a record appended from here on leaves it
+        // set for the next sync. Appends write the file before publishing
+        // their seq, so every seq loaded below is covered by this sync.
+        self.dirty.store(false, Ordering::Release);
+        let seq = self.written_seq.load(Ordering::Acquire);
+        if let Err(e) = self.file.sync_all() {
+            self.dirty.store(true, Ordering::Release);
+            return Err(e);
+        }
+        self.sync_count.fetch_add(1, Ordering::Relaxed);
+        self.durable_seq.fetch_max(seq, Ordering::AcqRel);
+        Ok(())
+    }
+}
+
+/// Handle of the background syncer thread. Dropping the sender stops it.
+struct Syncer {
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl Syncer {
+    /// Starts a thread that syncs `state` every `interval` while records
+    /// are being appended, and once more when it is stopped.
+    fn spawn(state: Arc<SyncState>, interval: Duration) -> io::Result<Self> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("wal-syncer".into())
+            .spawn(move || loop {
+                let stopping = !matches!(
+                    stopped.recv_timeout(interval),
+                    Err(mpsc::RecvTimeoutError::Timeout)
+                );
+                if state.dirty.load(Ordering::Acquire) {
+                    if let Err(e) = state.sync() {
+                        *state.error.lock().unwrap_or_else(|p| p.into_inner()) = Some(e);
+                    }
+                }
+                if stopping {
+                    return;
+                }
+            })?;
+        Ok(Self { stop, thread })
+    }
+
+    /// Stops the thread after its final sync.
+    fn stop(self) {
+        drop(self.stop);
+        let _ = self.thread.join();
+    }
 }
 
 impl WalWriter {
     /// Opens (or creates) a WAL file in append mode.
     ///
+    /// Shorthand for [`create_with_policy`](WalWriter::create_with_policy)
+    /// with [`WalSyncPolicy::EveryWrite`] if `sync` is true and
+    /// [`WalSyncPolicy::Never`] otherwise.
+    ///
     /// # Arguments
     ///
     /// * `path` - file system path for the WAL (created if it does not exist).
     /// * `sync` - if true, every `append` call is followed by `fsync`.
+    ///
+    /// # Errors
+    ///
+    /// See [`create_with_policy`](WalWriter::create_with_policy).
     pub fn create<P: AsRef<Path>>(path: P, sync: bool) -> Result<Self, WalError> {
-        let file = OpenOptions::new()
+        let policy = if sync {
+            WalSyncPolicy::EveryWrite
+        } else {
+            WalSyncPolicy::Never
+        };
+        Self::create_with_policy(path, policy)
+    }
+
+    /// Opens (or creates) a WAL file in append mode, syncing it as `policy`
+    /// prescribes.
+    ///
+    /// A new (or empty) file gets a [`WAL_FORMAT_VERSION`] header. An
+    /// existing file keeps its format version, so records appended to a
+    /// version 1 log stay readable by older readers.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WalError::Io` if the file cannot be opened or its header
+    /// written, or the syncer thread cannot be started,
+    /// `WalError::UnsupportedVersion` if the file was written in a newer
+    /// format, and `WalError::Encryption` if it is encrypted.
+    pub fn create_with_policy<P: AsRef<Path>>(
+        path: P,
+        policy: WalSyncPolicy,
+    ) -> Result<Self, WalError> {
+        Self::open(path.as_ref(), policy, None)
+    }
+
+    /// Like [`create_with_policy`](WalWriter::create_with_policy), but a
+    /// new (or empty) file is encrypted with the current key of `keys`.
+    ///
+    /// An existing file keeps its format: an encrypted one is appended to
+    /// with the key its header names (which `keys` must still have), a
+    /// plain one stays plain.
+    ///
+    /// # Errors
+    ///
+    /// As [`create_with_policy`](WalWriter::create_with_policy), plus
+    /// `WalError::Encryption` if the key is missing or wrong.
+    pub fn create_encrypted<P: AsRef<Path>>(
+        path: P,
+        policy: WalSyncPolicy,
+        keys: Arc<dyn KeyProvider>,
+    ) -> Result<Self, WalError> {
+        Self::open(path.as_ref(), policy, Some(keys))
+    }
+
+    fn open(
+        path: &Path,
+        policy: WalSyncPolicy,
+        keys: Option<Arc<dyn KeyProvider>>,
+    ) -> Result<Self, WalError> {
+        let mut file = OpenOptions::new()
             .create(true)
             .append(true)
             .read(true)
             .open(path)?;
-        Ok(Self {
-            file,
-            sync,
+
+        let mut head = [0u8; WAL_ENCRYPTED_HEADER_LEN as usize];
+        let n = read_full(&mut file, &mut head)?;
+        let mut cipher = None;
+        let version = match parse_header(&head[..n])? {
+            Header::Version(version) => Some(version),
+            Header::Encrypted(version) => {
+                let keys = keys.as_deref();
+                cipher = Some(FileCipher::open(&head[WAL_HEADER_LEN as usize..n], keys)?);
+                Some(version)
+            }
+            Header::Missing => Some(1),
+            // Empty, or a header torn by a crash: there are no records.
+            Header::Empty | Header::Torn => None,
+        };
+
+        let mut bytes_written = 0;
+        if version.is_none() {
+            file.set_len(0)?;
+            let mut header = Vec::with_capacity(WAL_ENCRYPTED_HEADER_LEN as usize);
+            match keys.as_deref() {
+                Some(keys) => {
+                    let (new_cipher, encryption_header) = FileCipher::create(keys)?;
+                    header.extend_from_slice(&WAL_MAGIC_ENCRYPTED);
+                    header.extend_from_slice(&WAL_FORMAT_VERSION.to_le_bytes());
+                    header.extend_from_slice(&encryption_header);
+                    cipher = Some(new_cipher);
+                }
+                None => {
+                    header.extend_from_slice(&WAL_MAGIC);
+                    header.extend_from_slice(&WAL_FORMAT_VERSION.to_le_bytes());
+                }
+            }
+            file.write_all(&header)?;
+            bytes_written = header.len() as u64;
+        }
+        let file_len = file.metadata()?.len();
+
+        let mut writer = Self {
+            shared: Arc::new(SyncState {
+                file,
+                written_seq: AtomicU64::new(0),
+                durable_seq: AtomicU64::new(0),
+                dirty: AtomicBool::new(bytes_written > 0),
+                sync_count: AtomicU64::new(0),
+                error: Mutex::new(None),
+            }),
+            policy: WalSyncPolicy::Never,
+            version: version.unwrap_or(WAL_FORMAT_VERSION),
             buf: Vec::with_capacity(256),
-        })
+            compression: WalCompression::None,
+            compressed: Vec::new(),
+            cipher,
+            file_len,
+            bytes_written,
+            unsynced_bytes: bytes_written,
+            syncer: None,
+        };
+        writer.set_sync_policy(policy)?;
+        Ok(writer)
     }
 
-    /// Serializes `record` and appends it to the WAL file.
+    /// Returns the format version of the file being appended to.
+    #[must_use]
+    pub fn format_version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the sync policy.
+    #[must_use]
+    pub fn sync_policy(&self) -> WalSyncPolicy {
+        self.policy
+    }
+
+    /// Returns the id of the key the file is encrypted with, or `None` if
+    /// it is not encrypted.
+    #[must_use]
+    pub fn key_id(&self) -> Option<u32> {
+        self.cipher.as_ref().map(FileCipher::key_id)
+    }
+
+    /// Returns the compression applied to appended records.
+    #[must_use]
+    pub fn compression(&self) -> WalCompression {
+        self.compression
+    }
+
+    /// Compresses records appended from now on with `compression`. Has no
+    /// effect on logs older than version 3, whose frames cannot say that
+    /// they are compressed.
+    pub fn set_compression(&mut self, compression: WalCompression) {
+        self.compression = compression;
+    }
+
+    /// Switches to `policy`, starting or stopping the background syncer as
+    /// needed. Leaving [`WalSyncPolicy::EveryInterval`] syncs once more.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WalError::Io` if the syncer thread cannot be started.
+    pub fn set_sync_policy(&mut self, policy: WalSyncPolicy) -> Result<(), WalError> {
+        if let Some(syncer) = self.syncer.take() {
+            syncer.stop();
+        }
+        if let WalSyncPolicy::EveryInterval(interval) = policy {
+            self.syncer = Some(Syncer::spawn(Arc::clone(&self.shared), interval)?);
+        }
+        self.policy = policy;
+        Ok(())
+    }
+
+    /// Serializes `record` and appends it to the WAL file, then syncs if
+    /// the policy calls for it.
     ///
     /// Layout:
-    /// [record_len: u32 LE][crc32: u32 LE][body bytes...]
+    /// [flags | record_len: u32 LE][crc32: u32 LE][body bytes...]
+    ///
+    /// The body is compressed first if the writer's [`WalCompression`]
+    /// calls for it, then encrypted if the file is.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WalError::UnsupportedRecordType` if the file's format
+    /// version cannot store the record, and `WalError::Io` on I/O errors,
+    /// including a failed background sync since the last append.
     pub fn append(&mut self, record: &WalRecord) -> Result<(), WalError> {
+        if let Some(ty) = unsupported_type(record, self.version) {
+            return Err(WalError::UnsupportedRecordType(ty.code()));
+        }
+        if let Some(e) = self.take_sync_error() {
+            return Err(e.into());
+        }
+
         // Reuse the internal buffer — clear but keep the allocation
         self.buf.clear();
 
@@ -124,21 +719,13 @@ impl WalWriter {
         self.buf.extend_from_slice(&[0u8; 8]);
 
         // Write body into buf starting at offset 8
-        match record {
-            WalRecord::Put { seq, key, value } => {
-                self.buf.write_u64::<LittleEndian>(*seq)?;
-                self.buf.write_u8(0)?; // op = put
-                self.buf.write_u32::<LittleEndian>(key.len() as u32)?;
-                self.buf.extend_from_slice(key);
-                self.buf.write_u32::<LittleEndian>(value.len() as u32)?;
-                self.buf.extend_from_slice(value);
-            }
-            WalRecord::Del { seq, key } => {
-                self.buf.write_u64::<LittleEndian>(*seq)?;
-                self.buf.write_u8(1)?; // op = del
-                self.buf.write_u32::<LittleEndian>(key.len() as u32)?;
-                self.buf.extend_from_slice(key);
-            }
+        encode_body(&mut self.buf, record)?;
+        let mut flags = self.compress_body();
+        if let Some(cipher) = &self.cipher {
+            flags |= FRAME_FLAG_ENCRYPTED;
+            let sealed = cipher.seal(&frame_aad(flags, self.file_len), &self.buf[8..])?;
+            self.buf.truncate(8);
+            self.buf.extend_from_slice(&sealed);
         }
 
         // Body is buf[8..]
@@ -151,39 +738,142 @@ impl WalWriter {
 
         // record_len = body.len() + 4 (CRC), must fit in u32
         let record_len = (body.len() as u64) + 4;
-        if record_len > (u32::MAX as u64) {
+        if record_len > u64::from(MAX_RECORD_SIZE) {
             return Err(WalError::Io(io::Error::new(
                 io::ErrorKind::InvalidInput,
-                "WAL record too large (exceeds u32::MAX bytes)",
+                "WAL record too large (exceeds 64 MiB)",
             )));
         }
 
         // Fill in the 8-byte header: record_len(u32) + crc(u32)
-        let header = (record_len as u32).to_le_bytes();
+        let header = (record_len as u32 | flags).to_le_bytes();
         let crc_bytes = crc.to_le_bytes();
         self.buf[0..4].copy_from_slice(&header);
         self.buf[4..8].copy_from_slice(&crc_bytes);
 
         // Single write call for the entire frame
-        self.file.write_all(&self.buf)?;
-        self.file.flush()?;
+        let state = &*self.shared;
+        if let Err(e) = (&state.file).write_all(&self.buf) {
+            // part of the frame may have been written
+            self.file_len = state.file.metadata().map_or(self.file_len, |m| m.len());
+            return Err(e.into());
+        }
+        state.written_seq.fetch_max(record.seq(), Ordering::AcqRel);
+        state.dirty.store(true, Ordering::Release);
+        self.file_len += self.buf.len() as u64;
+        self.bytes_written += self.buf.len() as u64;
+        self.unsynced_bytes += self.buf.len() as u64;
 
-        if self.sync {
-            self.file.sync_all()?;
+        let sync = match self.policy {
+            WalSyncPolicy::EveryWrite => true,
+            WalSyncPolicy::EveryNBytes(n) => self.unsynced_bytes >= n,
+            WalSyncPolicy::EveryInterval(_) | WalSyncPolicy::Never => false,
+        };
+        if sync {
+            self.sync_to_disk()?;
         }
 
         Ok(())
     }
 
-    /// Forces all buffered data to be written to disk via `sync_all()`.
+    /// Replaces the body in `buf[8..]` by its compressed form if the
+    /// compression setting and format version allow it and it saves space.
+    /// Returns the frame flags.
+    fn compress_body(&mut self) -> u32 {
+        let raw_len = self.buf.len() - 8;
+        if self.compression == WalCompression::None
+            || self.version < 3
+            || raw_len < MIN_COMPRESS_LEN
+        {
+            return 0;
+        }
+        self.compressed
+            .resize(4 + lz4_flex::block::get_maximum_output_size(raw_len), 0);
+        let Ok(n) = lz4_flex::block::compress_into(&self.buf[8..], &mut self.compressed[4..])
+        else {
+            return 0;
+        };
+        if 4 + n >= raw_len {
+            return 0;
+        }
+        self.compressed[..4].copy_from_slice(&(raw_len as u32).to_le_bytes());
+        self.buf.truncate(8);
+        self.buf.extend_from_slice(&self.compressed[..4 + n]);
+        FRAME_FLAG_LZ4
+    }
+
+    /// Forces all appended records to disk via `sync_all()`, whatever the
+    /// policy.
     ///
-    /// Useful when `sync` is `false` (batched mode) and the caller wants to
-    /// ensure durability at a specific point (e.g., before acknowledging a batch).
+    /// Useful with [`WalSyncPolicy::Never`] when the caller wants to ensure
+    /// durability at a specific point (e.g., before acknowledging a batch).
     pub fn sync_to_disk(&mut self) -> Result<(), WalError> {
-        self.file.flush()?;
-        self.file.sync_all()?;
+        self.shared.sync()?;
+        self.unsynced_bytes = 0;
         Ok(())
     }
+
+    /// Returns the highest sequence number appended by this writer (`0` if
+    /// none).
+    #[must_use]
+    pub fn written_seq(&self) -> u64 {
+        self.shared.written_seq.load(Ordering::Acquire)
+    }
+
+    /// Returns the highest sequence number this writer knows to be on disk
+    /// (`0` if none). Every record appended by this writer with a sequence
+    /// number up to it survives a power failure.
+    #[must_use]
+    pub fn durable_seq(&self) -> u64 {
+        self.shared.durable_seq.load(Ordering::Acquire)
+    }
+
+    /// Returns the total number of bytes (file and frame headers included)
+    /// appended by this writer since it was created.
+    #[must_use]
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Returns the number of `fsync` calls issued by this writer since it
+    /// was created, by the policy, the background syncer and
+    /// [`sync_to_disk`](Self::sync_to_disk).
+    #[must_use]
+    pub fn sync_count(&self) -> u64 {
+        self.shared.sync_count.load(Ordering::Relaxed)
+    }
+
+    /// Takes the error of a failed background sync, if any.
+    fn take_sync_error(&self) -> Option<io::Error> {
+        self.shared
+            .error
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .take()
+    }
+}
+
+impl Drop for WalWriter {
+    fn drop(&mut self) {
+        if let Some(syncer) = self.syncer.take() {
+            syncer.stop();
+        }
+    }
+}
+
+/// Where a record sits in the log.
+///
+/// Positions are returned by the [`WalReader`] iterator. Saving one and
+/// passing it to [`WalReader::resume_after`] later continues with the next
+/// record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WalPosition {
+    /// Byte offset of the record's frame.
+    pub offset: u64,
+    /// Byte offset just past the record's frame, where the next one starts.
+    pub next_offset: u64,
+    /// The record's sequence number (the highest one, for a batch).
+    pub seq: u64,
 }
 
 /// Sequential WAL reader that yields valid records.
@@ -191,11 +881,33 @@ impl WalWriter {
 /// The reader is generic over any `Read` implementor, allowing it to be used
 /// with real files (`WalReader<File>`) or in-memory buffers for testing.
 ///
+/// Records can be pulled one at a time, since the reader is an [`Iterator`]
+/// over `(position, record)` pairs, or pushed to a callback with
+/// [`replay`](WalReader::replay) or
+/// [`replay_with_mode`](WalReader::replay_with_mode).
+///
 /// During replay, each record's CRC32 is verified. A truncated tail record
 /// (e.g., from a crash mid-write) is treated as a clean EOF — all fully-written
 /// records before it are still returned.
+///
+/// The reader tracks the byte offset just past the last complete record it
+/// has replayed (see [`position`](WalReader::position)). Tailing readers can
+/// remember that offset and later resume from it with
+/// [`seek_to`](WalReader::seek_to).
 pub struct WalReader<R: Read> {
-    rdr: BufReader<R>,
+    rdr: BufReader<Unread<R>>,
+    /// Byte offset just past the last complete record.
+    pos: u64,
+    /// Format version, once the file header has been read.
+    version: Option<u32>,
+    /// Reusable frame buffer for the iterator.
+    body: Vec<u8>,
+    /// Set once the iterator has returned an error; cleared by a seek.
+    failed: bool,
+    /// Keys for an encrypted log.
+    keys: Option<Arc<dyn KeyProvider>>,
+    /// Set once the header of an encrypted log has been read.
+    cipher: Option<FileCipher>,
 }
 
 impl WalReader<File> {
@@ -205,7 +917,13 @@ impl WalReader<File> {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<WalReader<File>, WalError> {
         let f = File::open(path)?;
         Ok(WalReader {
-            rdr: BufReader::new(f),
+            rdr: BufReader::new(Unread::new(f)),
+            pos: 0,
+            version: None,
+            body: Vec::new(),
+            failed: false,
+            keys: None,
+            cipher: None,
         })
     }
 }
@@ -216,10 +934,48 @@ impl<R: Read> WalReader<R> {
     /// Useful for unit tests that supply an in-memory buffer (e.g., `Cursor<Vec<u8>>`).
     pub fn from_reader(reader: R) -> Self {
         WalReader {
-            rdr: BufReader::new(reader),
+            rdr: BufReader::new(Unread::new(reader)),
+            pos: 0,
+            version: None,
+            body: Vec::new(),
+            failed: false,
+            keys: None,
+            cipher: None,
         }
     }
 
+    /// Supplies the keys needed to read an encrypted log. Plain logs are
+    /// read as before.
+    #[must_use]
+    pub fn with_key_provider(mut self, keys: Arc<dyn KeyProvider>) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Returns the id of the key the log is encrypted with, or `None` if it
+    /// is not encrypted or its header has not been read yet.
+    #[must_use]
+    pub fn key_id(&self) -> Option<u32> {
+        self.cipher.as_ref().map(FileCipher::key_id)
+    }
+
+    /// Returns the byte offset just past the last complete record replayed.
+    ///
+    /// A truncated tail record is not counted, so the position always points
+    /// at a record boundary. Replaying again from this offset (after the
+    /// writer has appended more data) yields exactly the new records.
+    #[must_use]
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Returns the file's format version, or `None` if the header has not
+    /// been read yet (nothing was replayed, or the file is empty).
+    #[must_use]
+    pub fn format_version(&self) -> Option<u32> {
+        self.version
+    }
+
     /// Replays every valid record in the WAL, calling `apply` for each one.
     ///
     /// # Termination
@@ -228,88 +984,632 @@ impl<R: Read> WalReader<R> {
     /// - **Truncated tail** (partial record at end, e.g., crash mid-write) ->
     ///   returns `Ok(())` after yielding all complete records before it.
     /// - **CRC mismatch** -> returns `Err(WalError::Corrupt)`.
-    /// - **Unknown op code** -> returns `Err(WalError::Corrupt)`.
+    /// - **Unknown record type** -> skipped if it is ignorable, otherwise
+    ///   returns `Err(WalError::UnsupportedRecordType(..))` (or
+    ///   `Err(WalError::Corrupt)` in a version 1 log).
+    /// - **Unknown format version** -> returns
+    ///   `Err(WalError::UnsupportedVersion(..))`.
+    /// - **Encrypted log without the right key** -> returns
+    ///   `Err(WalError::Encryption(..))`.
     /// - **I/O error** -> returns `Err(WalError::Io(...))`.
+    ///
+    /// See [`replay_with_mode`](WalReader::replay_with_mode) for other ways
+    /// of handling damaged records.
     pub fn replay<F>(&mut self, mut apply: F) -> Result<(), WalError>
     where
         F: FnMut(WalRecord),
     {
-        // Reusable buffer to avoid allocation per record
-        let mut body = Vec::with_capacity(256);
+        for item in self.by_ref() {
+            let (_, record) = item?;
+            apply(record);
+        }
+        Ok(())
+    }
 
+    /// Replays every valid record in the WAL, calling `apply` for each one,
+    /// and handles damaged records as `mode` prescribes. Returns what was
+    /// replayed and what was discarded.
+    ///
+    /// A damaged record is *at the tail* if nothing follows it: either the
+    /// file ends inside it (a write torn by a crash) or it fails its CRC and
+    /// ends exactly at the end of the file. Discarded records never advance
+    /// [`position`](WalReader::position).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(WalError::Corrupt)` for a defect `mode` does not
+    /// tolerate, and `Err(WalError::Io(...))` on I/O errors. A record type
+    /// or format version this reader does not know is not a defect and
+    /// fails in every mode, as in [`replay`](WalReader::replay).
+    pub fn replay_with_mode<F>(
+        &mut self,
+        mode: WalRecoveryMode,
+        mut apply: F,
+    ) -> Result<WalRecoveryReport, WalError>
+    where
+        F: FnMut(WalRecord),
+    {
+        let mut report = WalRecoveryReport::default();
+        let mut body = Vec::with_capacity(256);
+        let mut offset = self.pos;
         loop {
-            // read record_len
-            let record_len = match self.rdr.read_u32::<LittleEndian>() {
-                Ok(v) => v,
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
-                Err(e) => return Err(WalError::Io(e)),
+            let (consumed, skippable, at_tail) = match self.read_frame(offset, &mut body)? {
+                Frame::Record(record, frame_len) => {
+                    // any frames skipped before this one are consumed too
+                    offset += frame_len;
+                    self.pos = offset;
+                    report.records_replayed += 1;
+                    apply(record);
+                    continue;
+                }
+                Frame::Skipped(len) => {
+                    offset += len;
+                    self.pos = offset;
+                    continue;
+                }
+                Frame::End => return Ok(report),
+                Frame::Truncated { bytes } => (bytes, false, true),
+                Frame::Corrupt {
+                    consumed,
+                    skippable,
+                    at_tail,
+                } => (consumed, skippable, at_tail),
             };
 
-            // record_len includes CRC (4 bytes) but not itself
-            // Reject absurd sizes -> corruption
-            const MAX_RECORD_SIZE: u32 = 64 * 1024 * 1024; // 64MB safety cap
-            if record_len <= 4 || record_len > MAX_RECORD_SIZE {
+            report.first_defect_offset.get_or_insert(offset);
+            let tolerated = match mode {
+                WalRecoveryMode::AbsoluteConsistency => false,
+                WalRecoveryMode::TolerateCorruptedTail => at_tail,
+                WalRecoveryMode::PointInTime | WalRecoveryMode::SkipAnyCorrupted => true,
+            };
+            if !tolerated {
                 return Err(WalError::Corrupt);
             }
+            report.records_discarded += 1;
+            report.bytes_discarded += consumed;
 
-            // read crc (handle truncated tail)
-            let crc = match self.rdr.read_u32::<LittleEndian>() {
-                Ok(v) => v,
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
-                Err(e) => return Err(WalError::Io(e)),
-            };
+            if at_tail {
+                return Ok(report);
+            }
+            if skippable && mode == WalRecoveryMode::SkipAnyCorrupted {
+                offset += consumed;
+                continue;
+            }
+            // Everything from here on is discarded.
+            report.bytes_discarded += io::copy(&mut self.rdr, &mut io::sink())?;
+            report.stopped_early = mode == WalRecoveryMode::PointInTime;
+            return Ok(report);
+        }
+    }
 
-            // read body (record_len - 4 bytes), reusing the buffer
-            let body_len = (record_len - 4) as usize;
-            body.clear();
-            body.resize(body_len, 0);
-            match self.rdr.read_exact(&mut body) {
-                Ok(()) => {}
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
-                    // truncated tail — treat as EOF
-                    return Ok(());
+    /// Reads the next frame, which starts at byte `offset`, leaving `pos`
+    /// untouched. The file header is read first and returned as skipped
+    /// bytes.
+    fn read_frame(&mut self, offset: u64, body: &mut Vec<u8>) -> Result<Frame, WalError> {
+        let version = match self.version {
+            Some(version) => version,
+            None => match self.load_header()? {
+                (Header::Empty, _) => return Ok(Frame::End),
+                (Header::Torn, len) => return Ok(Frame::Truncated { bytes: len }),
+                (Header::Missing, _) => 1,
+                (Header::Version(_) | Header::Encrypted(_), len) => {
+                    return Ok(Frame::Skipped(len));
                 }
-                Err(e) => return Err(WalError::Io(e)),
-            }
+            },
+        };
 
-            // verify crc (only after we've successfully read the full body)
-            let mut hasher = Crc32::new();
-            hasher.update(&body);
-            if hasher.finalize() != crc {
-                return Err(WalError::Corrupt);
+        // read record_len and, from version 3 on, the frame flags above it
+        let mut len_bytes = [0u8; 4];
+        let mut record_len = match read_full(&mut self.rdr, &mut len_bytes)? {
+            0 => return Ok(Frame::End),
+            4 => u32::from_le_bytes(len_bytes),
+            n => return Ok(Frame::Truncated { bytes: n as u64 }),
+        };
+        let flags = if version >= 3 {
+            record_len & FRAME_FLAGS
+        } else {
+            0
+        };
+        record_len &= !flags;
+
+        // record_len includes CRC (4 bytes) but not itself
+        // Reject absurd sizes or unknown flags -> corruption (and the frame
+        // cannot be skipped)
+        let known_flags = FRAME_FLAG_LZ4 | FRAME_FLAG_ENCRYPTED;
+        if record_len <= 4 || record_len > MAX_RECORD_SIZE || flags & !known_flags != 0 {
+            let at_tail = self.at_eof()?;
+            return Ok(Frame::Corrupt {
+                consumed: 4,
+                skippable: false,
+                at_tail,
+            });
+        }
+        let frame_len = 4 + record_len as u64;
+
+        // read crc + body, reusing the buffer (handle truncated tail)
+        let body_len = (record_len - 4) as usize;
+        body.clear();
+        body.resize(4 + body_len, 0);
+        let read = read_full(&mut self.rdr, body)?;
+        if read < body.len() {
+            return Ok(Frame::Truncated {
+                bytes: 4 + read as u64,
+            });
+        }
+        let crc = u32::from_le_bytes([body[0], body[1], body[2], body[3]]);
+        let body = &body[4..];
+
+        // verify crc (only after we've successfully read the full body)
+        let mut hasher = Crc32::new();
+        hasher.update(body);
+        let stored = if hasher.finalize() != crc {
+            None
+        } else {
+            // every frame of an encrypted log is encrypted, and only those
+            match (&self.cipher, flags & FRAME_FLAG_ENCRYPTED != 0) {
+                (Some(cipher), true) => cipher
+                    .open_sealed(&frame_aad(flags, offset), body)
+                    .ok()
+                    .map(Cow::Owned),
+                (None, false) => Some(Cow::Borrowed(body)),
+                _ => None,
             }
+        };
+        let decoded = stored.and_then(|body| {
+            if flags & FRAME_FLAG_LZ4 != 0 {
+                decompress(&body).and_then(|raw| decode_body(&raw, version))
+            } else {
+                decode_body(&body, version)
+            }
+        });
+        match decoded {
+            Some(Decoded::Record(record)) => Ok(Frame::Record(record, frame_len)),
+            Some(Decoded::Ignored) => Ok(Frame::Skipped(frame_len)),
+            Some(Decoded::Unsupported(code)) => Err(WalError::UnsupportedRecordType(code)),
+            None => Ok(Frame::Corrupt {
+                consumed: frame_len,
+                skippable: true,
+                at_tail: self.at_eof()?,
+            }),
+        }
+    }
 
-            // parse body (single read)
-            let mut br = &body[..];
-            let seq = br.read_u64::<LittleEndian>()?;
-            let op = br.read_u8()?;
-            let key_len = br.read_u32::<LittleEndian>()? as usize;
-            if key_len > body_len {
-                return Err(WalError::Corrupt);
+    /// Parses the file header at the current position (the start of the
+    /// file), recording the format version and, for an encrypted log, its
+    /// cipher. Returns the header and its length. A complete header is
+    /// consumed; any other bytes read are left to read again.
+    fn load_header(&mut self) -> Result<(Header, u64), WalError> {
+        // Nothing is buffered at the start of the file, so the header can
+        // be read from the source directly, however short its reads are.
+        debug_assert!(self.rdr.buffer().is_empty());
+        let source = self.rdr.get_mut();
+        let mut buf = [0u8; WAL_ENCRYPTED_HEADER_LEN as usize];
+        let n = read_full(source, &mut buf)?;
+        let head = &buf[..n];
+        let header = parse_header(head)?;
+        let len = match header {
+            Header::Version(version) => {
+                self.version = Some(version);
+                WAL_HEADER_LEN
             }
-            let mut key = vec![0u8; key_len];
-            br.read_exact(&mut key)?;
-
-            match op {
-                0 => {
-                    let val_len = br.read_u32::<LittleEndian>()? as usize;
-                    if val_len > body_len {
-                        return Err(WalError::Corrupt);
-                    }
-                    let mut val = vec![0u8; val_len];
-                    br.read_exact(&mut val)?;
-                    apply(WalRecord::Put {
-                        seq,
-                        key,
-                        value: val,
-                    });
+            Header::Encrypted(version) => {
+                let encryption_header = &head[WAL_HEADER_LEN as usize..];
+                self.cipher = Some(FileCipher::open(encryption_header, self.keys.as_deref())?);
+                self.version = Some(version);
+                WAL_ENCRYPTED_HEADER_LEN
+            }
+            Header::Missing => {
+                self.version = Some(1);
+                0
+            }
+            Header::Empty | Header::Torn => head.len() as u64,
+        };
+        let consumed = match header {
+            Header::Version(_) | Header::Encrypted(_) => len as usize,
+            Header::Empty | Header::Missing | Header::Torn => 0,
+        };
+        self.rdr.get_mut().unread(&head[consumed..]);
+        Ok((header, len))
+    }
+
+    /// Returns `true` if no bytes are left to read.
+    fn at_eof(&mut self) -> Result<bool, WalError> {
+        Ok(self.rdr.fill_buf()?.is_empty())
+    }
+}
+
+/// Yields each valid record with its position, with the same termination
+/// rules as [`WalReader::replay`]: `None` at the end of the log or at a
+/// truncated tail, and one `Err` for a damaged or unsupported record, after
+/// which the iterator stays exhausted until the reader is repositioned.
+///
+/// After `None`, records appended later are returned by further calls to
+/// `next`, unless the log ended in a truncated record: its bytes were
+/// consumed, so [`seek_to`](WalReader::seek_to) the
+/// [`position`](WalReader::position) first.
+impl<R: Read> Iterator for WalReader<R> {
+    type Item = Result<(WalPosition, WalRecord), WalError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let mut body = std::mem::take(&mut self.body);
+        let item = loop {
+            match self.read_frame(self.pos, &mut body) {
+                Ok(Frame::Record(record, frame_len)) => {
+                    let position = WalPosition {
+                        offset: self.pos,
+                        next_offset: self.pos + frame_len,
+                        seq: record.seq(),
+                    };
+                    self.pos = position.next_offset;
+                    break Some(Ok((position, record)));
                 }
-                1 => {
-                    apply(WalRecord::Del { seq, key });
+                Ok(Frame::Skipped(len)) => self.pos += len,
+                Ok(Frame::End | Frame::Truncated { .. }) => break None,
+                Ok(Frame::Corrupt { .. }) => break Some(Err(WalError::Corrupt)),
+                Err(e) => break Some(Err(e)),
+            }
+        };
+        self.body = body;
+        self.failed = matches!(item, Some(Err(_)));
+        item
+    }
+}
+
+/// One frame as read from the log.
+enum Frame {
+    /// A valid record and the size of its frame.
+    Record(WalRecord, u64),
+    /// Bytes holding no record to apply: the file header, or a record of
+    /// an unknown type that may be ignored.
+    Skipped(u64),
+    /// Clean end of the log.
+    End,
+    /// The log ends inside a frame after `bytes` bytes of it.
+    Truncated { bytes: u64 },
+    /// A bad frame after `consumed` bytes of it were read. It is
+    /// `skippable` unless the length field itself is bad; `at_tail` is
+    /// `true` if nothing follows it.
+    Corrupt {
+        consumed: u64,
+        skippable: bool,
+        at_tail: bool,
+    },
+}
+
+/// What the first bytes of a file say about its format.
+enum Header {
+    /// The file is empty.
+    Empty,
+    /// A version 1 log, which has no header.
+    Missing,
+    /// A header cut short by a crash right after the file was created.
+    Torn,
+    /// A complete header.
+    Version(u32),
+    /// A complete header of an encrypted log.
+    Encrypted(u32),
+}
+
+/// Classifies a file by its first bytes (at least
+/// [`WAL_ENCRYPTED_HEADER_LEN`] of them, unless the file is shorter).
+fn parse_header(head: &[u8]) -> Result<Header, WalError> {
+    let magic_len = head.len().min(WAL_MAGIC.len());
+    if head.is_empty() {
+        return Ok(Header::Empty);
+    }
+    let plain = head[..magic_len] == WAL_MAGIC[..magic_len];
+    let encrypted = head[..magic_len] == WAL_MAGIC_ENCRYPTED[..magic_len];
+    if !plain && !encrypted {
+        return Ok(Header::Missing);
+    }
+    let header_len = if encrypted && magic_len == WAL_MAGIC.len() {
+        WAL_ENCRYPTED_HEADER_LEN
+    } else {
+        WAL_HEADER_LEN
+    };
+    if (head.len() as u64) < header_len {
+        return Ok(Header::Torn);
+    }
+    let version = u32::from_le_bytes([head[4], head[5], head[6], head[7]]);
+    if !plain {
+        // encryption came with version 3
+        if !(3..=WAL_FORMAT_VERSION).contains(&version) {
+            return Err(WalError::UnsupportedVersion(version));
+        }
+        return Ok(Header::Encrypted(version));
+    }
+    if !(2..=WAL_FORMAT_VERSION).contains(&version) {
+        return Err(WalError::UnsupportedVersion(version));
+    }
+    Ok(Header::Version(version))
+}
+
+/// Returns the associated data an encrypted frame at byte `offset` is
+/// sealed with: its flags and its offset.
+fn frame_aad(flags: u32, offset: u64) -> [u8; 12] {
+    let mut aad = [0u8; 12];
+    aad[..4].copy_from_slice(&flags.to_le_bytes());
+    aad[4..].copy_from_slice(&offset.to_le_bytes());
+    aad
+}
+
+/// Returns the first record type in `record` that a version `version` log
+/// cannot store.
+fn unsupported_type(record: &WalRecord, version: u32) -> Option<RecordType> {
+    let ty = record.record_type();
+    if ty.min_version() > version {
+        return Some(ty);
+    }
+    match record {
+        WalRecord::Batch { records } => records.iter().find_map(|r| unsupported_type(r, version)),
+        WalRecord::ColumnFamily { record, .. } => unsupported_type(record, version),
+        _ => None,
+    }
+}
+
+/// Appends the body of `record` to `buf`.
+fn encode_body(buf: &mut Vec<u8>, record: &WalRecord) -> io::Result<()> {
+    buf.write_u64::<LittleEndian>(record.seq())?;
+    buf.write_u8(record.record_type().code())?;
+    match record {
+        WalRecord::Put { key, value, .. } => {
+            write_bytes(buf, key)?;
+            write_bytes(buf, value)?;
+        }
+        WalRecord::Del { key, .. } => write_bytes(buf, key)?,
+        WalRecord::Batch { records } => {
+            buf.write_u32::<LittleEndian>(records.len() as u32)?;
+            for record in records {
+                // length-prefixed nested body, the length filled in after
+                let start = buf.len();
+                buf.extend_from_slice(&[0u8; 4]);
+                encode_body(buf, record)?;
+                let len = (buf.len() - start - 4) as u32;
+                buf[start..start + 4].copy_from_slice(&len.to_le_bytes());
+            }
+        }
+        WalRecord::RangeDel { start, end, .. } => {
+            write_bytes(buf, start)?;
+            write_bytes(buf, end)?;
+        }
+        WalRecord::Merge { key, operand, .. } => {
+            write_bytes(buf, key)?;
+            write_bytes(buf, operand)?;
+        }
+        WalRecord::ColumnFamily { cf_id, record } => {
+            buf.write_u32::<LittleEndian>(*cf_id)?;
+            encode_body(buf, record)?;
+        }
+        WalRecord::PutWithTtl {
+            key,
+            value,
+            expires_at_ms,
+            ..
+        } => {
+            write_bytes(buf, key)?;
+            write_bytes(buf, value)?;
+            buf.write_u64::<LittleEndian>(*expires_at_ms)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a `[len: u32][bytes]` field.
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) -> io::Result<()> {
+    buf.write_u32::<LittleEndian>(bytes.len() as u32)?;
+    buf.extend_from_slice(bytes);
+    Ok(())
+}
+
+/// A record body as understood by this reader.
+enum Decoded {
+    Record(WalRecord),
+    /// An unknown type flagged [`RecordType::IGNORABLE`].
+    Ignored,
+    /// An unknown type that may not be ignored.
+    Unsupported(u8),
+}
+
+/// Parses a record body of a version `version` log, or returns `None` if
+/// it is malformed.
+fn decode_body(body: &[u8], version: u32) -> Option<Decoded> {
+    let mut br = body;
+    let seq = br.read_u64::<LittleEndian>().ok()?;
+    let code = br.read_u8().ok()?;
+    let ty = match RecordType::from_code(code) {
+        Some(ty) if ty.min_version() <= version => ty,
+        // version 1 logs only ever held puts and deletes
+        _ if version == 1 => return None,
+        _ if code & RecordType::IGNORABLE != 0 => return Some(Decoded::Ignored),
+        _ => return Some(Decoded::Unsupported(code)),
+    };
+    let record = match ty {
+        RecordType::Put => WalRecord::Put {
+            seq,
+            key: read_bytes(&mut br)?,
+            value: read_bytes(&mut br)?,
+        },
+        RecordType::Del => WalRecord::Del {
+            seq,
+            key: read_bytes(&mut br)?,
+        },
+        RecordType::Batch => {
+            let count = br.read_u32::<LittleEndian>().ok()?;
+            let mut records = Vec::new();
+            for _ in 0..count {
+                match decode_body(read_slice(&mut br)?, version)? {
+                    Decoded::Record(record) => records.push(record),
+                    Decoded::Ignored => {}
+                    unsupported @ Decoded::Unsupported(_) => return Some(unsupported),
                 }
-                _ => return Err(WalError::Corrupt),
             }
+            WalRecord::Batch { records }
         }
+        RecordType::RangeDel => WalRecord::RangeDel {
+            seq,
+            start: read_bytes(&mut br)?,
+            end: read_bytes(&mut br)?,
+        },
+        RecordType::Merge => WalRecord::Merge {
+            seq,
+            key: read_bytes(&mut br)?,
+            operand: read_bytes(&mut br)?,
+        },
+        RecordType::ColumnFamily => {
+            let cf_id = br.read_u32::<LittleEndian>().ok()?;
+            match decode_body(br, version)? {
+                Decoded::Record(record) => WalRecord::ColumnFamily {
+                    cf_id,
+                    record: Box::new(record),
+                },
+                other => return Some(other),
+            }
+        }
+        RecordType::PutWithTtl => WalRecord::PutWithTtl {
+            seq,
+            key: read_bytes(&mut br)?,
+            value: read_bytes(&mut br)?,
+            expires_at_ms: br.read_u64::<LittleEndian>().ok()?,
+        },
+    };
+    Some(Decoded::Record(record))
+}
+
+/// Decompresses a `[raw_len: u32][LZ4 block]` body, or returns `None` if
+/// it is malformed.
+fn decompress(stored: &[u8]) -> Option<Vec<u8>> {
+    let mut br = stored;
+    let raw_len = br.read_u32::<LittleEndian>().ok()?;
+    if raw_len > MAX_RECORD_SIZE {
+        return None;
+    }
+    let mut raw = vec![0u8; raw_len as usize];
+    match lz4_flex::block::decompress_into(br, &mut raw) {
+        Ok(n) if n == raw.len() => Some(raw),
+        _ => None,
+    }
+}
+
+/// Reads a `[len: u32][bytes]` field.
+fn read_bytes(br: &mut &[u8]) -> Option<Vec<u8>> {
+    read_slice(br).map(<[u8]>::to_vec)
+}
+
+/// Reads a `[len: u32][bytes]` field without copying it.
+fn read_slice<'a>(br: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = br.read_u32::<LittleEndian>().ok()? as usize;
+    if len > br.len() {
+        return None;
+    }
+    let (bytes, rest) = br.split_at(len);
+    *br = rest;
+    Some(bytes)
+}
+
+/// A reader that returns bytes put back with [`unread`](Unread::unread)
+/// before those of its source. Lets [`WalReader`] read the file header in
+/// full and hand back what follows it.
+struct Unread<R> {
+    inner: R,
+    head: Vec<u8>,
+    /// Bytes of `head` already returned.
+    pos: usize,
+}
+
+impl<R> Unread<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            head: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Puts `bytes` back in front of the unread ones.
+    fn unread(&mut self, bytes: &[u8]) {
+        self.head.drain(..self.pos);
+        self.head.splice(0..0, bytes.iter().copied());
+        self.pos = 0;
+    }
+}
+
+impl<R: Read> Read for Unread<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.head.len() {
+            return self.inner.read(buf);
+        }
+        let n = buf.len().min(self.head.len() - self.pos);
+        buf[..n].copy_from_slice(&self.head[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for Unread<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pending = (self.head.len() - self.pos) as i64;
+        let pos = match pos {
+            SeekFrom::Current(n) => SeekFrom::Current(n - pending),
+            pos => pos,
+        };
+        self.head.clear();
+        self.pos = 0;
+        self.inner.seek(pos)
+    }
+}
+
+/// Reads until `buf` is full or the reader is exhausted, returning the
+/// number of bytes read.
+fn read_full<R: Read>(rdr: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match rdr.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+impl<R: Read + Seek> WalReader<R> {
+    /// Repositions the reader at `offset`, which must be a record boundary
+    /// previously returned by [`position`](WalReader::position) (or `0`).
+    ///
+    /// Subsequent calls to [`replay`](WalReader::replay) start from there.
+    /// The file header is read first if it has not been yet, since records
+    /// are decoded according to the format version.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WalError::Io` if seeking fails, and
+    /// `WalError::UnsupportedVersion` for a header this reader does not know.
+    pub fn seek_to(&mut self, offset: u64) -> Result<(), WalError> {
+        if offset == 0 {
+            self.version = None;
+            self.cipher = None;
+        } else if self.version.is_none() {
+            self.rdr.seek(SeekFrom::Start(0))?;
+            self.load_header()?;
+        }
+        self.rdr.seek(SeekFrom::Start(offset))?;
+        self.pos = offset;
+        self.failed = false;
+        Ok(())
+    }
+
+    /// Repositions the reader just past the record at `position`, so
+    /// iteration continues with the record after it.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`seek_to`](WalReader::seek_to).
+    pub fn resume_after(&mut self, position: &WalPosition) -> Result<(), WalError> {
+        self.seek_to(position.next_offset)
     }
 }
 
@@ -1,6 +1,7 @@
 use super::*;
 use std::fs;
 use std::io::Cursor;
+use std::time::Duration;
 use tempfile::tempdir;
 // use anyhow::Result;
 
@@ -443,3 +444,1045 @@ fn interleaved_puts_and_dels() {
     assert_eq!(del_count, 334);
     assert_eq!(put_count, 666);
 }
+
+// -------------------- Position tracking & resume --------------------
+
+#[test]
+fn position_tracks_complete_records() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+
+    {
+        let mut w = WalWriter::create(&path, false).unwrap();
+        w.append(&make_put(1, b"a", b"1")).unwrap();
+        w.append(&make_del(2, b"a")).unwrap();
+    }
+
+    let mut reader = WalReader::open(&path).unwrap();
+    assert_eq!(reader.position(), 0);
+    reader.replay(|_| {}).unwrap();
+    assert_eq!(reader.position(), fs::metadata(&path).unwrap().len());
+}
+
+#[test]
+fn position_excludes_truncated_tail() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+
+    {
+        let mut w = WalWriter::create(&path, false).unwrap();
+        w.append(&make_put(1, b"a", b"1")).unwrap();
+    }
+    let complete_len = fs::metadata(&path).unwrap().len();
+
+    // Append half of a second record.
+    {
+        let mut w = WalWriter::create(&path, false).unwrap();
+        w.append(&make_put(2, b"b", b"2")).unwrap();
+    }
+    let data = fs::read(&path).unwrap();
+    fs::write(&path, &data[..data.len() - 3]).unwrap();
+
+    let mut reader = WalReader::open(&path).unwrap();
+    let mut count = 0;
+    reader.replay(|_| count += 1).unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(reader.position(), complete_len);
+}
+
+#[test]
+fn seek_to_resumes_after_new_appends() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+
+    let mut w = WalWriter::create(&path, false).unwrap();
+    w.append(&make_put(1, b"a", b"1")).unwrap();
+
+    let mut reader = WalReader::open(&path).unwrap();
+    reader.replay(|_| {}).unwrap();
+    let resume_at = reader.position();
+
+    w.append(&make_put(2, b"b", b"2")).unwrap();
+    w.append(&make_del(3, b"a")).unwrap();
+
+    let mut reader = WalReader::open(&path).unwrap();
+    reader.seek_to(resume_at).unwrap();
+    let mut recs = Vec::new();
+    reader.replay(|r| recs.push(r)).unwrap();
+    assert_eq!(recs, vec![make_put(2, b"b", b"2"), make_del(3, b"a")]);
+    assert_eq!(reader.position(), fs::metadata(&path).unwrap().len());
+}
+
+// -------------------- Writer counters --------------------
+
+#[test]
+fn writer_counts_bytes_and_syncs() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+
+    let mut w = WalWriter::create(&path, true).unwrap();
+    w.append(&make_put(1, b"k", b"v")).unwrap();
+    w.append(&make_del(2, b"k")).unwrap();
+    assert_eq!(w.bytes_written(), fs::metadata(&path).unwrap().len());
+    assert_eq!(w.sync_count(), 2);
+
+    let mut w = WalWriter::create(&path, false).unwrap();
+    w.append(&make_put(3, b"k", b"v")).unwrap();
+    assert_eq!(w.sync_count(), 0);
+    w.sync_to_disk().unwrap();
+    assert_eq!(w.sync_count(), 1);
+}
+
+// -------------------- Recovery modes --------------------
+
+/// Encodes three records and returns the log plus each frame's offset.
+fn three_record_log() -> (Vec<u8>, Vec<usize>) {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut offsets = Vec::new();
+    let mut w = WalWriter::create(&path, false).unwrap();
+    for (seq, key) in [(1, b"a"), (2, b"b"), (3, b"c")] {
+        offsets.push(w.bytes_written() as usize);
+        w.append(&make_put(seq, key, b"v")).unwrap();
+    }
+    (fs::read(&path).unwrap(), offsets)
+}
+
+fn replay_mode(
+    data: &[u8],
+    mode: WalRecoveryMode,
+) -> Result<(Vec<u64>, WalRecoveryReport, u64), WalError> {
+    let mut reader = WalReader::from_reader(Cursor::new(data.to_vec()));
+    let mut seqs = Vec::new();
+    let report = reader.replay_with_mode(mode, |r| seqs.push(r.seq()))?;
+    Ok((seqs, report, reader.position()))
+}
+
+const ALL_MODES: [WalRecoveryMode; 4] = [
+    WalRecoveryMode::AbsoluteConsistency,
+    WalRecoveryMode::TolerateCorruptedTail,
+    WalRecoveryMode::PointInTime,
+    WalRecoveryMode::SkipAnyCorrupted,
+];
+
+#[test]
+fn clean_log_replays_fully_in_every_mode() {
+    let (data, _) = three_record_log();
+    for mode in ALL_MODES {
+        let (seqs, report, pos) = replay_mode(&data, mode).unwrap();
+        assert_eq!(seqs, vec![1, 2, 3], "{:?}", mode);
+        assert!(report.is_clean());
+        assert_eq!(report.records_replayed, 3);
+        assert_eq!(pos, data.len() as u64);
+    }
+}
+
+#[test]
+fn torn_tail_handling_per_mode() {
+    let (data, offsets) = three_record_log();
+    let torn = &data[..data.len() - 2];
+    let dropped = (torn.len() - offsets[2]) as u64;
+
+    assert!(matches!(
+        replay_mode(torn, WalRecoveryMode::AbsoluteConsistency),
+        Err(WalError::Corrupt)
+    ));
+    for mode in &ALL_MODES[1..] {
+        let (seqs, report, pos) = replay_mode(torn, *mode).unwrap();
+        assert_eq!(seqs, vec![1, 2], "{:?}", mode);
+        assert_eq!(report.records_discarded, 1);
+        assert_eq!(report.bytes_discarded, dropped);
+        assert_eq!(report.first_defect_offset, Some(offsets[2] as u64));
+        assert!(!report.stopped_early);
+        assert_eq!(pos, offsets[2] as u64);
+    }
+}
+
+#[test]
+fn corrupt_last_record_is_dropped_as_tail() {
+    let (mut data, offsets) = three_record_log();
+    let last = data.len() - 1;
+    data[last] ^= 0xFF;
+
+    assert!(replay_mode(&data, WalRecoveryMode::AbsoluteConsistency).is_err());
+    let (seqs, report, pos) = replay_mode(&data, WalRecoveryMode::TolerateCorruptedTail).unwrap();
+    assert_eq!(seqs, vec![1, 2]);
+    assert_eq!(report.bytes_discarded, (data.len() - offsets[2]) as u64);
+    assert_eq!(pos, offsets[2] as u64);
+
+    // replay() keeps treating a CRC mismatch as corruption
+    assert!(matches!(replay_from_bytes(&data), Err(WalError::Corrupt)));
+}
+
+#[test]
+fn corrupt_middle_record_handling_per_mode() {
+    let (mut data, offsets) = three_record_log();
+    data[offsets[2] - 1] ^= 0xFF; // last byte of the second record
+    let second_len = (offsets[2] - offsets[1]) as u64;
+
+    for mode in [
+        WalRecoveryMode::AbsoluteConsistency,
+        WalRecoveryMode::TolerateCorruptedTail,
+    ] {
+        assert!(matches!(replay_mode(&data, mode), Err(WalError::Corrupt)));
+    }
+
+    let (seqs, report, pos) = replay_mode(&data, WalRecoveryMode::PointInTime).unwrap();
+    assert_eq!(seqs, vec![1]);
+    assert_eq!(report.records_discarded, 1);
+    assert_eq!(report.bytes_discarded, (data.len() - offsets[1]) as u64);
+    assert_eq!(report.first_defect_offset, Some(offsets[1] as u64));
+    assert!(report.stopped_early);
+    assert_eq!(pos, offsets[1] as u64);
+
+    let (seqs, report, pos) = replay_mode(&data, WalRecoveryMode::SkipAnyCorrupted).unwrap();
+    assert_eq!(seqs, vec![1, 3]);
+    assert_eq!(report.records_replayed, 2);
+    assert_eq!(report.records_discarded, 1);
+    assert_eq!(report.bytes_discarded, second_len);
+    assert!(!report.stopped_early);
+    assert_eq!(pos, data.len() as u64);
+}
+
+#[test]
+fn damaged_length_cannot_be_skipped() {
+    let (mut data, offsets) = three_record_log();
+    data[offsets[1]..offsets[1] + 4].copy_from_slice(&0u32.to_le_bytes());
+
+    let (seqs, report, _) = replay_mode(&data, WalRecoveryMode::SkipAnyCorrupted).unwrap();
+    assert_eq!(seqs, vec![1]);
+    assert_eq!(report.bytes_discarded, (data.len() - offsets[1]) as u64);
+}
+
+#[test]
+fn partial_length_field_is_a_torn_tail() {
+    let (data, offsets) = three_record_log();
+    let torn = &data[..offsets[2] + 2];
+    assert!(replay_mode(torn, WalRecoveryMode::AbsoluteConsistency).is_err());
+    let (seqs, report, _) = replay_mode(torn, WalRecoveryMode::TolerateCorruptedTail).unwrap();
+    assert_eq!(seqs, vec![1, 2]);
+    assert_eq!(report.bytes_discarded, 2);
+}
+
+// -------------------- File header & record types --------------------
+
+/// Frames `body` as `[record_len][crc][body]`.
+fn frame(body: &[u8]) -> Vec<u8> {
+    let mut hasher = Crc32::new();
+    hasher.update(body);
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&((body.len() + 4) as u32).to_le_bytes());
+    bytes.extend_from_slice(&hasher.finalize().to_le_bytes());
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+fn header(version: u32) -> Vec<u8> {
+    let mut bytes = WAL_MAGIC.to_vec();
+    bytes.extend_from_slice(&version.to_le_bytes());
+    bytes
+}
+
+/// Body of a record with type `code` and no payload.
+fn bare_body(seq: u64, code: u8) -> Vec<u8> {
+    let mut body = seq.to_le_bytes().to_vec();
+    body.push(code);
+    body
+}
+
+fn body_of(record: &WalRecord) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_body(&mut body, record).unwrap();
+    body
+}
+
+#[test]
+fn new_file_starts_with_header() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+
+    let w = WalWriter::create(&path, false).unwrap();
+    assert_eq!(w.format_version(), WAL_FORMAT_VERSION);
+    assert_eq!(fs::read(&path).unwrap(), header(WAL_FORMAT_VERSION));
+    drop(w);
+
+    let mut reader = WalReader::open(&path).unwrap();
+    assert_eq!(reader.format_version(), None);
+    reader.replay(|_| panic!("no records")).unwrap();
+    assert_eq!(reader.format_version(), Some(WAL_FORMAT_VERSION));
+    assert_eq!(reader.position(), WAL_HEADER_LEN);
+}
+
+#[test]
+fn v1_log_stays_readable_and_appendable() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    fs::write(&path, frame(&body_of(&make_put(1, b"a", b"1")))).unwrap();
+
+    let mut w = WalWriter::create(&path, false).unwrap();
+    assert_eq!(w.format_version(), 1);
+    w.append(&make_del(2, b"a")).unwrap();
+    let batch = WalRecord::Batch {
+        records: vec![make_put(3, b"b", b"2")],
+    };
+    assert!(matches!(
+        w.append(&batch),
+        Err(WalError::UnsupportedRecordType(2))
+    ));
+    drop(w);
+
+    let mut reader = WalReader::open(&path).unwrap();
+    let mut recs = Vec::new();
+    reader.replay(|r| recs.push(r)).unwrap();
+    assert_eq!(recs, vec![make_put(1, b"a", b"1"), make_del(2, b"a")]);
+    assert_eq!(reader.format_version(), Some(1));
+    assert_eq!(reader.position(), fs::metadata(&path).unwrap().len());
+}
+
+#[test]
+fn v1_log_rejects_newer_record_types() {
+    let mut data = frame(&body_of(&make_put(1, b"a", b"1")));
+    data.extend(frame(&body_of(&WalRecord::Batch { records: vec![] })));
+    assert!(matches!(replay_from_bytes(&data), Err(WalError::Corrupt)));
+}
+
+#[test]
+fn every_record_type_roundtrips() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let records = vec![
+        make_put(1, b"k", b"v"),
+        make_del(2, b"k"),
+        WalRecord::Batch {
+            records: vec![
+                make_put(3, b"a", b"1"),
+                WalRecord::ColumnFamily {
+                    cf_id: 7,
+                    record: Box::new(make_del(4, b"b")),
+                },
+            ],
+        },
+        WalRecord::RangeDel {
+            seq: 5,
+            start: b"a".to_vec(),
+            end: b"m".to_vec(),
+        },
+        WalRecord::Merge {
+            seq: 6,
+            key: b"counter".to_vec(),
+            operand: b"+1".to_vec(),
+        },
+        WalRecord::ColumnFamily {
+            cf_id: 1,
+            record: Box::new(make_put(7, b"x", b"y")),
+        },
+        WalRecord::PutWithTtl {
+            seq: 8,
+            key: b"session".to_vec(),
+            value: b"token".to_vec(),
+            expires_at_ms: 1_700_000_000_000,
+        },
+    ];
+
+    let mut w = WalWriter::create(&path, false).unwrap();
+    for record in &records {
+        w.append(record).unwrap();
+    }
+    drop(w);
+
+    assert_eq!(replay_all(&path).unwrap(), records);
+    assert_eq!(records[2].seq(), 4, "a batch reports its highest seq");
+    for record in &records {
+        let ty = record.record_type();
+        assert_eq!(RecordType::from_code(ty.code()), Some(ty));
+    }
+}
+
+#[test]
+fn ignorable_unknown_records_are_skipped() {
+    let mut data = header(WAL_FORMAT_VERSION);
+    data.extend(frame(&body_of(&make_put(1, b"a", b"1"))));
+    data.extend(frame(&bare_body(2, RecordType::IGNORABLE | 0x05)));
+    // a batch holding an ignorable record and a delete
+    let mut batch = bare_body(4, RecordType::Batch.code());
+    batch.extend_from_slice(&2u32.to_le_bytes());
+    for nested in [
+        bare_body(3, RecordType::IGNORABLE),
+        body_of(&make_del(4, b"a")),
+    ] {
+        batch.extend_from_slice(&(nested.len() as u32).to_le_bytes());
+        batch.extend(nested);
+    }
+    data.extend(frame(&batch));
+
+    let mut reader = WalReader::from_reader(Cursor::new(data.clone()));
+    let mut recs = Vec::new();
+    reader.replay(|r| recs.push(r)).unwrap();
+    assert_eq!(
+        recs,
+        vec![
+            make_put(1, b"a", b"1"),
+            WalRecord::Batch {
+                records: vec![make_del(4, b"a")]
+            },
+        ]
+    );
+    assert_eq!(reader.position(), data.len() as u64);
+}
+
+#[test]
+fn unknown_record_type_is_unsupported() {
+    let mut data = header(WAL_FORMAT_VERSION);
+    data.extend(frame(&body_of(&make_put(1, b"a", b"1"))));
+    data.extend(frame(&bare_body(2, 0x70)));
+    assert!(matches!(
+        replay_from_bytes(&data),
+        Err(WalError::UnsupportedRecordType(0x70))
+    ));
+
+    // not a defect: no recovery mode skips it
+    for mode in ALL_MODES {
+        assert!(matches!(
+            replay_mode(&data, mode),
+            Err(WalError::UnsupportedRecordType(0x70))
+        ));
+    }
+}
+
+#[test]
+fn newer_format_version_is_rejected() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    fs::write(&path, header(WAL_FORMAT_VERSION + 1)).unwrap();
+
+    assert!(matches!(
+        replay_all(&path),
+        Err(WalError::UnsupportedVersion(v)) if v == WAL_FORMAT_VERSION + 1
+    ));
+    assert!(matches!(
+        WalWriter::create(&path, false),
+        Err(WalError::UnsupportedVersion(_))
+    ));
+}
+
+#[test]
+fn torn_header_is_an_empty_log() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    fs::write(&path, &WAL_MAGIC[..3]).unwrap();
+
+    assert!(replay_all(&path).unwrap().is_empty());
+    assert!(replay_mode(
+        &fs::read(&path).unwrap(),
+        WalRecoveryMode::AbsoluteConsistency
+    )
+    .is_err());
+
+    // The writer starts the file over.
+    let mut w = WalWriter::create(&path, false).unwrap();
+    w.append(&make_put(1, b"a", b"1")).unwrap();
+    drop(w);
+    assert_eq!(replay_all(&path).unwrap(), vec![make_put(1, b"a", b"1")]);
+}
+
+#[test]
+fn seek_to_reads_the_header_first() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create(&path, false).unwrap();
+    w.append(&make_put(1, b"a", b"1")).unwrap();
+    let resume_at = w.bytes_written();
+    w.append(&WalRecord::Batch {
+        records: vec![make_put(2, b"b", b"2")],
+    })
+    .unwrap();
+
+    let mut reader = WalReader::open(&path).unwrap();
+    reader.seek_to(resume_at).unwrap();
+    assert_eq!(reader.format_version(), Some(WAL_FORMAT_VERSION));
+    let mut recs = Vec::new();
+    reader.replay(|r| recs.push(r)).unwrap();
+    assert_eq!(recs.len(), 1);
+    assert_eq!(recs[0].seq(), 2);
+}
+
+/// A reader that returns at most one byte per read, like a slow pipe.
+struct OneByteReader<R>(R);
+
+impl<R: Read> Read for OneByteReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(1);
+        self.0.read(&mut buf[..n])
+    }
+}
+
+impl<R: Seek> Seek for OneByteReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+#[test]
+fn header_is_read_in_full_from_short_reads() {
+    let dir = tempdir().unwrap();
+    let plain = dir.path().join("plain.log");
+    let encrypted = dir.path().join("encrypted.log");
+    let records = vec![make_put(1, b"a", b"1"), make_del(2, b"a")];
+    let mut w = WalWriter::create(&plain, false).unwrap();
+    let mut e =
+        WalWriter::create_encrypted(&encrypted, WalSyncPolicy::Never, test_keys(1, 1)).unwrap();
+    for r in &records {
+        w.append(r).unwrap();
+        e.append(r).unwrap();
+    }
+    drop((w, e));
+    let v1: Vec<u8> = records.iter().flat_map(|r| frame(&body_of(r))).collect();
+
+    let replay = |data: Vec<u8>| {
+        let mut reader = WalReader::from_reader(OneByteReader(Cursor::new(data)))
+            .with_key_provider(test_keys(1, 1));
+        let mut recs = Vec::new();
+        reader
+            .replay(|r| recs.push(r))
+            .map(|()| (recs, reader.format_version()))
+    };
+    for (data, version) in [
+        (fs::read(&plain).unwrap(), WAL_FORMAT_VERSION),
+        (fs::read(&encrypted).unwrap(), WAL_FORMAT_VERSION),
+        (v1, 1),
+    ] {
+        assert_eq!(replay(data).unwrap(), (records.clone(), Some(version)));
+    }
+
+    // seeking reads the header the same way
+    let data = fs::read(&plain).unwrap();
+    let (second, _) = WalReader::from_reader(Cursor::new(data.clone()))
+        .nth(1)
+        .unwrap()
+        .unwrap();
+    let mut reader = WalReader::from_reader(OneByteReader(Cursor::new(data)));
+    reader.seek_to(second.offset).unwrap();
+    assert_eq!(reader.format_version(), Some(WAL_FORMAT_VERSION));
+    assert_eq!(reader.next().unwrap().unwrap().1, records[1]);
+}
+
+// -------------------- Iterator --------------------
+
+#[test]
+fn iterator_yields_positions_and_seqs() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create(&path, false).unwrap();
+    w.append(&make_put(1, b"a", b"1")).unwrap();
+    w.append(&make_del(2, b"a")).unwrap();
+    w.append(&WalRecord::Batch {
+        records: vec![make_put(3, b"b", b"2"), make_put(4, b"c", b"3")],
+    })
+    .unwrap();
+    drop(w);
+
+    let items: Vec<_> = WalReader::open(&path)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let positions: Vec<WalPosition> = items.iter().map(|(pos, _)| *pos).collect();
+    assert_eq!(
+        positions.iter().map(|p| p.seq).collect::<Vec<_>>(),
+        vec![1, 2, 4]
+    );
+    assert_eq!(positions[0].offset, WAL_HEADER_LEN);
+    for pair in positions.windows(2) {
+        assert_eq!(pair[0].next_offset, pair[1].offset);
+    }
+    assert_eq!(positions[2].next_offset, fs::metadata(&path).unwrap().len());
+    assert_eq!(items[1].1, make_del(2, b"a"));
+}
+
+#[test]
+fn iterator_stops_early_and_resumes_after_saved_position() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create(&path, false).unwrap();
+    for seq in 1..=5 {
+        w.append(&make_put(seq, format!("k{}", seq).as_bytes(), b"v"))
+            .unwrap();
+    }
+    drop(w);
+
+    let mut reader = WalReader::open(&path).unwrap();
+    let saved = reader.by_ref().take(2).last().unwrap().unwrap().0;
+    assert_eq!(saved.seq, 2);
+    assert_eq!(reader.position(), saved.next_offset);
+
+    let mut reader = WalReader::open(&path).unwrap();
+    reader.resume_after(&saved).unwrap();
+    let seqs: Vec<u64> = reader.map(|item| item.unwrap().0.seq).collect();
+    assert_eq!(seqs, vec![3, 4, 5]);
+
+    // seeking to the saved offset reads that record again
+    let mut reader = WalReader::open(&path).unwrap();
+    reader.seek_to(saved.offset).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap().0, saved);
+}
+
+#[test]
+fn iterator_returns_one_error_then_stops() {
+    let (mut data, offsets) = three_record_log();
+    data[offsets[2] - 1] ^= 0xFF; // corrupt the second record
+
+    let mut reader = WalReader::from_reader(Cursor::new(data));
+    assert_eq!(reader.next().unwrap().unwrap().0.seq, 1);
+    assert!(matches!(reader.next(), Some(Err(WalError::Corrupt))));
+    assert!(reader.next().is_none());
+    assert_eq!(reader.position(), offsets[1] as u64);
+}
+
+#[test]
+fn iterator_ends_at_truncated_tail() {
+    let (data, offsets) = three_record_log();
+    let mut reader = WalReader::from_reader(Cursor::new(data[..data.len() - 1].to_vec()));
+    assert_eq!(reader.by_ref().count(), 2);
+    assert_eq!(reader.position(), offsets[2] as u64);
+}
+
+#[test]
+fn iterator_picks_up_records_appended_later() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create(&path, false).unwrap();
+    w.append(&make_put(1, b"a", b"1")).unwrap();
+
+    let mut reader = WalReader::open(&path).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap().0.seq, 1);
+    assert!(reader.next().is_none());
+
+    w.append(&make_put(2, b"b", b"2")).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap().1, make_put(2, b"b", b"2"));
+    assert!(reader.next().is_none());
+}
+
+// -------------------- Sync policies --------------------
+
+#[test]
+fn every_write_makes_each_record_durable() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create_with_policy(&path, WalSyncPolicy::EveryWrite).unwrap();
+    assert_eq!(w.durable_seq(), 0);
+
+    w.append(&make_put(1, b"a", b"1")).unwrap();
+    assert_eq!(w.durable_seq(), 1);
+    w.append(&make_put(2, b"b", b"2")).unwrap();
+    assert_eq!(w.durable_seq(), 2);
+    assert_eq!(w.sync_count(), 2);
+}
+
+#[test]
+fn every_n_bytes_syncs_once_the_threshold_is_reached() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create_with_policy(&path, WalSyncPolicy::EveryNBytes(100)).unwrap();
+
+    // each frame is 8 + 8 + 1 + 4 + 1 + 4 + 20 = 46 bytes; the header counts too
+    let value = [b'x'; 20];
+    w.append(&make_put(1, b"a", &value)).unwrap();
+    assert_eq!(w.sync_count(), 0);
+    assert_eq!(w.durable_seq(), 0);
+    w.append(&make_put(2, b"b", &value)).unwrap();
+    assert_eq!(w.sync_count(), 1, "8 + 46 + 46 bytes reach the threshold");
+    assert_eq!(w.durable_seq(), 2);
+
+    w.append(&make_put(3, b"c", &value)).unwrap();
+    assert_eq!(w.durable_seq(), 2);
+    assert_eq!(w.written_seq(), 3);
+}
+
+#[test]
+fn every_interval_syncs_in_the_background() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let policy = WalSyncPolicy::EveryInterval(Duration::from_millis(5));
+    let mut w = WalWriter::create_with_policy(&path, policy).unwrap();
+    w.append(&make_put(1, b"a", b"1")).unwrap();
+    w.append(&make_put(2, b"b", b"2")).unwrap();
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while w.durable_seq() < 2 {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "syncer never caught up"
+        );
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(w.sync_count() >= 1);
+
+    // An idle writer is not synced again.
+    let syncs = w.sync_count();
+    std::thread::sleep(Duration::from_millis(30));
+    assert_eq!(w.sync_count(), syncs);
+}
+
+#[test]
+fn leaving_every_interval_syncs_once_more() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let policy = WalSyncPolicy::EveryInterval(Duration::from_secs(3600));
+    let mut w = WalWriter::create_with_policy(&path, policy).unwrap();
+    w.append(&make_put(1, b"a", b"1")).unwrap();
+    assert_eq!(w.durable_seq(), 0);
+
+    w.set_sync_policy(WalSyncPolicy::Never).unwrap();
+    assert_eq!(w.durable_seq(), 1);
+    assert_eq!(w.sync_policy(), WalSyncPolicy::Never);
+}
+
+#[test]
+fn never_syncs_only_on_request() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create_with_policy(&path, WalSyncPolicy::Never).unwrap();
+    w.append(&make_put(1, b"a", b"1")).unwrap();
+    w.append(&make_del(2, b"a")).unwrap();
+    assert_eq!(w.sync_count(), 0);
+    assert_eq!(w.durable_seq(), 0);
+
+    w.sync_to_disk().unwrap();
+    assert_eq!(w.durable_seq(), 2);
+    assert_eq!(replay_all(&path).unwrap().len(), 2);
+}
+
+// -------------------- Compression --------------------
+
+fn json_value(n: usize) -> Vec<u8> {
+    let mut value = b"[".to_vec();
+    for i in 0..n {
+        value.extend_from_slice(format!("{{\"id\":{},\"name\":\"user\"}},", i).as_bytes());
+    }
+    value.push(b']');
+    value
+}
+
+#[test]
+fn compressed_records_are_smaller_and_replay_transparently() {
+    let dir = tempdir().unwrap();
+    let plain = dir.path().join("plain.log");
+    let packed = dir.path().join("packed.log");
+    let records = vec![
+        make_put(1, b"doc", &json_value(50)),
+        make_put(2, b"small", b"1"),
+        WalRecord::Batch {
+            records: vec![make_put(3, b"a", &json_value(20)), make_del(4, b"b")],
+        },
+    ];
+
+    let mut w = WalWriter::create(&plain, false).unwrap();
+    for r in &records {
+        w.append(r).unwrap();
+    }
+    let mut w = WalWriter::create(&packed, false).unwrap();
+    w.set_compression(WalCompression::Lz4);
+    assert_eq!(w.compression(), WalCompression::Lz4);
+    for r in &records {
+        w.append(r).unwrap();
+    }
+    drop(w);
+
+    let plain_len = fs::metadata(&plain).unwrap().len();
+    let packed_len = fs::metadata(&packed).unwrap().len();
+    assert!(
+        packed_len * 2 < plain_len,
+        "{} vs {}",
+        packed_len,
+        plain_len
+    );
+    assert_eq!(replay_all(&packed).unwrap(), records);
+}
+
+#[test]
+fn small_and_incompressible_records_are_stored_plain() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    // a xorshift sequence does not compress
+    let mut x = 0x2545_f491_4f6c_dd1du64;
+    let noise: Vec<u8> = (0..256)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect();
+
+    let mut w = WalWriter::create(&path, false).unwrap();
+    w.set_compression(WalCompression::Lz4);
+    w.append(&make_put(1, b"k", b"v")).unwrap();
+    w.append(&make_put(2, b"n", &noise)).unwrap();
+    drop(w);
+
+    let data = fs::read(&path).unwrap();
+    let mut expected = header(WAL_FORMAT_VERSION);
+    expected.extend(frame(&body_of(&make_put(1, b"k", b"v"))));
+    expected.extend(frame(&body_of(&make_put(2, b"n", &noise))));
+    assert_eq!(data, expected);
+}
+
+#[test]
+fn compressed_frame_sets_the_flag_and_crc_covers_stored_bytes() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create(&path, false).unwrap();
+    w.set_compression(WalCompression::Lz4);
+    w.append(&make_put(1, b"doc", &json_value(50))).unwrap();
+    drop(w);
+
+    let mut data = fs::read(&path).unwrap();
+    let frame = &data[WAL_HEADER_LEN as usize..];
+    let len = u32::from_le_bytes(frame[0..4].try_into().unwrap());
+    assert_eq!(len & FRAME_FLAG_LZ4, FRAME_FLAG_LZ4);
+    let stored = &frame[8..];
+    assert_eq!((len & !FRAME_FLAG_LZ4) as usize, stored.len() + 4);
+    let crc = u32::from_le_bytes(frame[4..8].try_into().unwrap());
+    assert_eq!(crc, crc32fast::hash(stored));
+
+    // a flipped bit in the compressed bytes fails the CRC
+    let last = data.len() - 1;
+    data[last] ^= 0x01;
+    let mut reader = WalReader::from_reader(Cursor::new(data));
+    assert!(matches!(reader.replay(|_| {}), Err(WalError::Corrupt)));
+}
+
+#[test]
+fn undecompressable_body_is_corrupt() {
+    let stored = [&1000u32.to_le_bytes()[..], b"\xff\xff\xff"].concat();
+    let mut data = header(WAL_FORMAT_VERSION);
+    data.extend(((stored.len() as u32 + 4) | FRAME_FLAG_LZ4).to_le_bytes());
+    data.extend(crc32fast::hash(&stored).to_le_bytes());
+    data.extend(&stored);
+    data.extend(frame(&body_of(&make_put(2, b"a", b"1"))));
+
+    let mut reader = WalReader::from_reader(Cursor::new(data.clone()));
+    assert!(matches!(reader.replay(|_| {}), Err(WalError::Corrupt)));
+
+    let (seqs, report, _) = replay_mode(&data, WalRecoveryMode::SkipAnyCorrupted).unwrap();
+    assert_eq!(seqs, vec![2]);
+    assert_eq!(report.records_discarded, 1);
+}
+
+#[test]
+fn v2_log_is_appended_to_uncompressed() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    fs::write(&path, header(2)).unwrap();
+
+    let mut w = WalWriter::create(&path, false).unwrap();
+    assert_eq!(w.format_version(), 2);
+    w.set_compression(WalCompression::Lz4);
+    let record = make_put(1, b"doc", &json_value(50));
+    w.append(&record).unwrap();
+    drop(w);
+
+    let mut expected = header(2);
+    expected.extend(frame(&body_of(&record)));
+    assert_eq!(fs::read(&path).unwrap(), expected);
+    assert_eq!(replay_all(&path).unwrap(), vec![record]);
+}
+
+// -------------------- Encryption --------------------
+
+fn test_keys(id: u32, byte: u8) -> Arc<dyn KeyProvider> {
+    Arc::new(encryption::StaticKeyProvider::new(
+        id,
+        encryption::Key::new([byte; encryption::KEY_LEN]),
+    ))
+}
+
+fn replay_encrypted(
+    path: &std::path::Path,
+    keys: Arc<dyn KeyProvider>,
+) -> Result<Vec<WalRecord>, WalError> {
+    let mut reader = WalReader::open(path)?.with_key_provider(keys);
+    let mut recs = Vec::new();
+    reader.replay(|r| recs.push(r))?;
+    Ok(recs)
+}
+
+#[test]
+fn encrypted_log_roundtrips_and_hides_its_contents() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let records = vec![
+        make_put(1, b"secret-key", b"secret-value"),
+        make_del(2, b"secret-key"),
+        WalRecord::Batch {
+            records: vec![make_put(3, b"a", &json_value(20)), make_del(4, b"b")],
+        },
+    ];
+
+    let mut w = WalWriter::create_encrypted(&path, WalSyncPolicy::Never, test_keys(7, 1)).unwrap();
+    w.set_compression(WalCompression::Lz4);
+    assert_eq!(w.key_id(), Some(7));
+    for r in &records {
+        w.append(r).unwrap();
+    }
+    drop(w);
+
+    let data = fs::read(&path).unwrap();
+    assert_eq!(&data[..4], &WAL_MAGIC_ENCRYPTED);
+    assert_eq!(encryption::header_key_id(&data[8..]), Some(7));
+    assert!(!data.windows(6).any(|w| w == b"secret"));
+
+    let mut reader = WalReader::open(&path)
+        .unwrap()
+        .with_key_provider(test_keys(7, 1));
+    let mut recs = Vec::new();
+    reader.replay(|r| recs.push(r)).unwrap();
+    assert_eq!(recs, records);
+    assert_eq!(reader.key_id(), Some(7));
+}
+
+#[test]
+fn encrypted_log_needs_the_right_key() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create_encrypted(&path, WalSyncPolicy::Never, test_keys(1, 1)).unwrap();
+    w.append(&make_put(1, b"k", b"v")).unwrap();
+    drop(w);
+
+    assert!(matches!(
+        replay_all(&path),
+        Err(WalError::Encryption(EncryptionError::NoKeyProvider(1)))
+    ));
+    assert!(matches!(
+        replay_encrypted(&path, test_keys(1, 2)),
+        Err(WalError::Encryption(EncryptionError::WrongKey(1)))
+    ));
+    assert!(matches!(
+        replay_encrypted(&path, test_keys(2, 1)),
+        Err(WalError::Encryption(EncryptionError::UnknownKey(1)))
+    ));
+    assert!(matches!(
+        WalWriter::create(&path, false),
+        Err(WalError::Encryption(EncryptionError::NoKeyProvider(1)))
+    ));
+}
+
+#[test]
+fn reopened_logs_keep_their_format_and_key() {
+    let dir = tempdir().unwrap();
+    let encrypted = dir.path().join("encrypted.log");
+    let plain = dir.path().join("plain.log");
+
+    let mut w =
+        WalWriter::create_encrypted(&encrypted, WalSyncPolicy::Never, test_keys(1, 1)).unwrap();
+    w.append(&make_put(1, b"a", b"1")).unwrap();
+    drop(w);
+    // key 2 is now current, but the file stays under key 1
+    let rotated: Arc<dyn KeyProvider> = Arc::new(
+        encryption::StaticKeyProvider::new(2, encryption::Key::new([2; encryption::KEY_LEN]))
+            .with_key(1, encryption::Key::new([1; encryption::KEY_LEN])),
+    );
+    let mut w =
+        WalWriter::create_encrypted(&encrypted, WalSyncPolicy::Never, rotated.clone()).unwrap();
+    assert_eq!(w.key_id(), Some(1));
+    w.append(&make_put(2, b"b", b"2")).unwrap();
+    drop(w);
+    assert_eq!(
+        replay_encrypted(&encrypted, rotated).unwrap(),
+        vec![make_put(1, b"a", b"1"), make_put(2, b"b", b"2")]
+    );
+
+    WalWriter::create(&plain, false)
+        .unwrap()
+        .append(&make_put(1, b"a", b"1"))
+        .unwrap();
+    let w = WalWriter::create_encrypted(&plain, WalSyncPolicy::Never, test_keys(1, 1)).unwrap();
+    assert_eq!(w.key_id(), None);
+    drop(w);
+    assert_eq!(
+        replay_encrypted(&plain, test_keys(1, 1)).unwrap(),
+        vec![make_put(1, b"a", b"1")]
+    );
+}
+
+#[test]
+fn tampered_encrypted_frame_is_corrupt() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create_encrypted(&path, WalSyncPolicy::Never, test_keys(1, 1)).unwrap();
+    for seq in 1..=3 {
+        w.append(&make_put(seq, b"k", b"v")).unwrap();
+    }
+    drop(w);
+    let data = fs::read(&path).unwrap();
+    let frame_len = (data.len() - WAL_ENCRYPTED_HEADER_LEN as usize) / 3;
+
+    // flip a ciphertext byte of the middle frame and fix up its CRC, so only
+    // authentication catches it
+    let mut tampered = data.clone();
+    let start = WAL_ENCRYPTED_HEADER_LEN as usize + frame_len;
+    tampered[start + 8 + encryption::NONCE_LEN] ^= 1;
+    let crc = crc32fast::hash(&tampered[start + 8..start + frame_len]);
+    tampered[start + 4..start + 8].copy_from_slice(&crc.to_le_bytes());
+
+    let replay = |data: Vec<u8>, mode| {
+        let mut reader =
+            WalReader::from_reader(Cursor::new(data)).with_key_provider(test_keys(1, 1));
+        let mut seqs = Vec::new();
+        reader
+            .replay_with_mode(mode, |r| seqs.push(r.seq()))
+            .map(|_| seqs)
+    };
+    assert!(matches!(
+        replay(tampered.clone(), WalRecoveryMode::TolerateCorruptedTail),
+        Err(WalError::Corrupt)
+    ));
+    assert_eq!(
+        replay(tampered, WalRecoveryMode::SkipAnyCorrupted).unwrap(),
+        vec![1, 3]
+    );
+
+    // a torn encrypted header means an empty log
+    let torn = data[..WAL_HEADER_LEN as usize + 10].to_vec();
+    assert_eq!(
+        replay(torn, WalRecoveryMode::TolerateCorruptedTail).unwrap(),
+        vec![]
+    );
+}
+
+#[test]
+fn reordered_encrypted_frames_are_corrupt() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut w = WalWriter::create_encrypted(&path, WalSyncPolicy::Never, test_keys(1, 1)).unwrap();
+    for seq in 1..=3 {
+        w.append(&make_put(seq, b"k", b"v")).unwrap();
+    }
+    drop(w);
+    let data = fs::read(&path).unwrap();
+    let header = WAL_ENCRYPTED_HEADER_LEN as usize;
+    let frame_len = (data.len() - header) / 3;
+    let frames: Vec<&[u8]> = data[header..].chunks(frame_len).collect();
+
+    // every frame is intact, with a valid CRC, but at the wrong offset
+    let swapped = [&data[..header], frames[1], frames[0], frames[2]].concat();
+    let duplicated = [&data[..header], frames[0], frames[0], frames[1]].concat();
+    let dropped = [&data[..header], frames[0], frames[2]].concat();
+    for data in [swapped, duplicated, dropped] {
+        let mut reader =
+            WalReader::from_reader(Cursor::new(data)).with_key_provider(test_keys(1, 1));
+        assert!(matches!(reader.replay(|_| {}), Err(WalError::Corrupt)));
+    }
+}
+
+#[test]
+fn plain_frame_in_encrypted_log_is_corrupt() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    let w = WalWriter::create_encrypted(&path, WalSyncPolicy::Never, test_keys(1, 1)).unwrap();
+    drop(w);
+    let mut data = fs::read(&path).unwrap();
+    data.extend(frame(&body_of(&make_put(1, b"k", b"v"))));
+
+    let mut reader = WalReader::from_reader(Cursor::new(data)).with_key_provider(test_keys(1, 1));
+    assert!(matches!(reader.replay(|_| {}), Err(WalError::Corrupt)));
+}